@@ -0,0 +1,823 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::rc::Rc;
+
+use crate::first_pass::OperatorKind;
+use crate::interpreter::Value;
+use crate::lexer::Span;
+use crate::module::Loader;
+use crate::parser::{Expr, ExprKind, ImportKind, Number};
+
+// Variables live in numbered slots of the scope that defines them; an access names the
+// scope by how many scopes out it is. Jump targets are instruction indexes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Constant(u32),
+    Null,
+    Pop,
+    Array(u32),
+    Interpolate(u32),
+    GetVar(u16, u16, u32),
+    // a variable that is about to be called; unset is fine, the call falls back to a builtin
+    GetCallee(u16, u16),
+    SetVar(u16, u16),
+    GetConst(u16, u16, u32),
+    DefineConst(u16, u32),
+    // the built-in operators; ones a script defines or imports go through `Operator`
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    Tetrate,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Eq,
+    Range,
+    Neg,
+    Not,
+    Truthy,
+    Inc,
+    Operator(u32, u8),
+    Call(u32, u8),
+    Index,
+    Slice(bool, bool),
+    Jump(u32),
+    JumpIfFalse(u32),
+    PushScope(u16),
+    PopScope,
+    Closure(u32),
+    // a paused generator running a generator block's function
+    Generator(u32),
+    Return,
+    // hand the value to whatever resumed the generator and pause until it is resumed again
+    Yield,
+    Throw,
+    Rethrow,
+    Try(u32),
+    EndTry,
+    IterStart(u32, bool),
+    IterNext(u32),
+    IterEnd,
+    Fail(u32),
+}
+
+impl Op {
+    fn binary(op: &str) -> Option<Op> {
+        Some(match op {
+            "+" => Op::Add,
+            "-" => Op::Sub,
+            "*" => Op::Mul,
+            "/" => Op::Div,
+            "^" => Op::Pow,
+            "^^" => Op::Tetrate,
+            "<" => Op::Lt,
+            ">" => Op::Gt,
+            "≤" => Op::Le,
+            "≥" => Op::Ge,
+            "=" => Op::Eq,
+            ".." => Op::Range,
+            _ => return None,
+        })
+    }
+
+    fn unary(op: &str) -> Option<Op> {
+        Some(match op {
+            "-" => Op::Neg,
+            "!" => Op::Not,
+            "?" => Op::Truthy,
+            "++" => Op::Inc,
+            _ => return None,
+        })
+    }
+
+    // how a built-in operator is written, which is what its errors mention
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Op::Add => "+",
+            Op::Sub | Op::Neg => "-",
+            Op::Mul => "*",
+            Op::Div => "/",
+            Op::Pow => "^",
+            Op::Tetrate => "^^",
+            Op::Lt => "<",
+            Op::Gt => ">",
+            Op::Le => "≤",
+            Op::Ge => "≥",
+            Op::Eq => "=",
+            Op::Range => "..",
+            Op::Not => "!",
+            Op::Truthy => "?",
+            Op::Inc => "++",
+            _ => unreachable!("{:?} is not a built-in operator", self),
+        }
+    }
+}
+
+pub struct Chunk {
+    pub name: String,
+    pub arity: usize,
+    // calling it makes a paused generator instead of running it
+    pub generator: bool,
+    pub slots: u16,
+    pub code: Vec<Op>,
+    pub spans: Vec<Span>,
+    pub constants: Vec<Value>,
+    pub names: Vec<String>,
+}
+
+impl Chunk {
+    fn new(name: &str, arity: usize) -> Self {
+        Chunk {
+            name: name.to_string(),
+            arity,
+            generator: false,
+            slots: 0,
+            code: Vec::new(),
+            spans: Vec::new(),
+            constants: Vec::new(),
+            names: Vec::new(),
+        }
+    }
+}
+
+pub enum OperatorSource {
+    Local(u32),
+    Imported(usize, String),
+}
+
+pub struct Program {
+    pub main: Rc<Chunk>,
+    pub functions: Vec<Rc<Chunk>>,
+    pub operators: Vec<(String, OperatorSource)>,
+    // (module, kind, name, global slot) for every imported variable and constant
+    pub imports: Vec<(usize, ImportKind, String, u16)>,
+    pub exports: Vec<(ImportKind, String, u16)>,
+}
+
+#[derive(Default)]
+struct Scope {
+    vars: HashMap<String, u16>,
+    consts: HashMap<String, u16>,
+    size: u16,
+}
+
+// what has to be undone, innermost last, when a jump or return leaves the code that set it up
+#[derive(Clone, Copy)]
+enum Cleanup<'a> {
+    Scope,
+    Handler,
+    Iter,
+    Finally(&'a Expr, usize),
+}
+
+struct Loop {
+    label: Option<String>,
+    break_height: usize,
+    continue_height: usize,
+    continue_addr: usize,
+    breaks: Vec<usize>,
+}
+
+struct Builder<'a> {
+    chunk: Chunk,
+    cleanups: Vec<Cleanup<'a>>,
+    loops: Vec<Loop>,
+}
+
+struct Compiler<'a> {
+    builders: Vec<Builder<'a>>,
+    scopes: Vec<Scope>,
+    functions: Vec<Option<Rc<Chunk>>>,
+    operators: HashMap<String, (OperatorKind, u32)>,
+}
+
+pub fn compile(loader: &Loader, id: usize) -> Program {
+    let module = &loader.modules[&id];
+    let mut compiler = Compiler {
+        builders: vec![Builder { chunk: Chunk::new("main", 0), cleanups: Vec::new(), loops: Vec::new() }],
+        scopes: vec![Scope::default()],
+        functions: Vec::new(),
+        operators: HashMap::new(),
+    };
+
+    let mut sources = Vec::new();
+    let mut locals: Vec<_> = module.operators.values().collect();
+    locals.sort_by_key(|op| op.body.span.start);
+    for op in &locals {
+        compiler.operators.insert(op.op.clone(), (op.kind.clone(), sources.len() as u32));
+        sources.push((op.op.clone(), OperatorSource::Local(0)));
+    }
+
+    let mut imports = Vec::new();
+    for (import, dep) in &module.imports {
+        let available = &loader.modules[dep];
+        let names = import.names.as_ref().unwrap_or(&available.exports);
+        for name in names {
+            match name.kind {
+                ImportKind::Variable | ImportKind::Const => {
+                    let constant = name.kind == ImportKind::Const;
+                    let slot = compiler.declare(&name.name, constant);
+                    imports.push((*dep, name.kind, name.name.clone(), slot));
+                }
+                ImportKind::Operator => {
+                    if compiler.operators.contains_key(&name.name) {
+                        continue;
+                    }
+                    if let Some(def) = available.exported_ops.iter().find(|d| d.op == name.name) {
+                        compiler.operators.insert(name.name.clone(), (def.kind.clone(), sources.len() as u32));
+                        sources.push((name.name.clone(), OperatorSource::Imported(*dep, name.name.clone())));
+                    }
+                }
+            }
+        }
+    }
+
+    match &module.ast.kind {
+        ExprKind::Block(items) => compiler.compile_items(items),
+        _ => compiler.compile(&module.ast),
+    }
+    compiler.emit(Op::Return, module.ast.span);
+
+    // operator bodies run in the module's global scope, after everything in it is known
+    for (i, op) in locals.iter().enumerate() {
+        let index = compiler.functions.len();
+        compiler.functions.push(None);
+        let chunk = compiler.compile_function(&op.op, &op.params, &op.body);
+        compiler.functions[index] = Some(chunk);
+        sources[i].1 = OperatorSource::Local(index as u32);
+    }
+
+    let globals = &compiler.scopes[0];
+    let exports = module
+        .exports
+        .iter()
+        .filter_map(|name| {
+            let slot = match name.kind {
+                ImportKind::Variable => globals.vars.get(&name.name),
+                ImportKind::Const => globals.consts.get(&name.name),
+                ImportKind::Operator => None,
+            };
+            slot.map(|slot| (name.kind, name.name.clone(), *slot))
+        })
+        .collect();
+
+    let mut main = compiler.builders.pop().unwrap().chunk;
+    main.slots = compiler.scopes[0].size;
+    Program {
+        main: Rc::new(main),
+        functions: compiler.functions.into_iter().map(|f| f.unwrap()).collect(),
+        operators: sources,
+        imports,
+        exports,
+    }
+}
+
+impl<'a> Compiler<'a> {
+    fn builder(&mut self) -> &mut Builder<'a> {
+        self.builders.last_mut().unwrap()
+    }
+
+    fn emit(&mut self, op: Op, span: Span) -> usize {
+        let chunk = &mut self.builder().chunk;
+        chunk.code.push(op);
+        chunk.spans.push(span);
+        chunk.code.len() - 1
+    }
+
+    fn here(&mut self) -> usize {
+        self.builder().chunk.code.len()
+    }
+
+    fn patch(&mut self, at: usize) {
+        let target = self.here() as u32;
+        let code = &mut self.builder().chunk.code;
+        code[at] = match code[at] {
+            Op::Jump(_) => Op::Jump(target),
+            Op::JumpIfFalse(_) => Op::JumpIfFalse(target),
+            Op::Try(_) => Op::Try(target),
+            Op::IterNext(_) => Op::IterNext(target),
+            other => other,
+        };
+    }
+
+    fn constant(&mut self, value: Value, span: Span) {
+        let constants = &mut self.builder().chunk.constants;
+        constants.push(value);
+        let index = constants.len() as u32 - 1;
+        self.emit(Op::Constant(index), span);
+    }
+
+    fn name(&mut self, name: &str) -> u32 {
+        let names = &mut self.builder().chunk.names;
+        match names.iter().position(|n| n == name) {
+            Some(i) => i as u32,
+            None => {
+                names.push(name.to_string());
+                names.len() as u32 - 1
+            }
+        }
+    }
+
+    fn fail(&mut self, message: String, span: Span) {
+        let name = self.name(&message);
+        self.emit(Op::Fail(name), span);
+    }
+
+    fn resolve(&self, name: &str, constant: bool) -> Option<(u16, u16)> {
+        self.scopes.iter().rev().enumerate().find_map(|(depth, scope)| {
+            let table = if constant { &scope.consts } else { &scope.vars };
+            table.get(name).map(|slot| (depth as u16, *slot))
+        })
+    }
+
+    fn declare(&mut self, name: &str, constant: bool) -> u16 {
+        let scope = self.scopes.last_mut().unwrap();
+        let table = if constant { &mut scope.consts } else { &mut scope.vars };
+        if let Some(slot) = table.get(name) {
+            return *slot;
+        }
+        table.insert(name.to_string(), scope.size);
+        scope.size += 1;
+        scope.size - 1
+    }
+
+    fn push_scope(&mut self, span: Span) -> usize {
+        self.scopes.push(Scope::default());
+        self.builder().cleanups.push(Cleanup::Scope);
+        self.emit(Op::PushScope(0), span)
+    }
+
+    fn pop_scope(&mut self, at: usize, span: Span) {
+        let size = self.scopes.pop().unwrap().size;
+        self.builder().cleanups.pop();
+        self.emit(Op::PopScope, span);
+        self.builder().chunk.code[at] = Op::PushScope(size);
+    }
+
+    // undo everything above `height`; a `finally` is compiled in place, as if the
+    // scopes and handlers above it were already gone
+    fn emit_cleanups(&mut self, height: usize, span: Span) {
+        let mut i = self.builder().cleanups.len();
+        while i > height {
+            i -= 1;
+            match self.builder().cleanups[i] {
+                Cleanup::Scope => {
+                    self.emit(Op::PopScope, span);
+                }
+                Cleanup::Handler => {
+                    self.emit(Op::EndTry, span);
+                }
+                Cleanup::Iter => {
+                    self.emit(Op::IterEnd, span);
+                }
+                Cleanup::Finally(body, depth) => {
+                    let cleanups = self.builder().cleanups.split_off(i);
+                    let scopes = self.scopes.split_off(depth);
+                    self.compile(body);
+                    self.emit(Op::Pop, span);
+                    self.scopes.extend(scopes);
+                    self.builder().cleanups.extend(cleanups);
+                }
+            }
+        }
+    }
+
+    fn compile_function(&mut self, name: &str, params: &[String], body: &'a Expr) -> Rc<Chunk> {
+        let mut chunk = Chunk::new(name, params.len());
+        chunk.generator = body.contains_yield();
+        self.builders.push(Builder { chunk, cleanups: Vec::new(), loops: Vec::new() });
+        self.scopes.push(Scope::default());
+        for param in params {
+            self.declare(param, false);
+        }
+        match &body.kind {
+            ExprKind::Block(items) => self.compile_items(items),
+            _ => self.compile(body),
+        }
+        self.emit(Op::Return, body.span);
+
+        let size = self.scopes.pop().unwrap().size;
+        let mut chunk = self.builders.pop().unwrap().chunk;
+        chunk.slots = size;
+        Rc::new(chunk)
+    }
+
+    // railways get their slot up front so earlier code and each other can call them;
+    // their bodies are compiled once the rest of the block has declared its names
+    fn compile_items(&mut self, items: &'a [Expr]) {
+        let function = |item: &'a Expr| -> Option<&'a Expr> {
+            let inner = match &item.kind {
+                ExprKind::Export(inner) => inner,
+                _ => item,
+            };
+            matches!(inner.kind, ExprKind::Function { .. }).then_some(inner)
+        };
+
+        for item in items {
+            if let Some(ExprKind::Function { name, .. }) = function(item).map(|f| &f.kind) {
+                self.declare(name, false);
+            }
+        }
+
+        if items.is_empty() {
+            self.emit(Op::Null, Span::default());
+        }
+
+        let mut pending = Vec::new();
+        for (i, item) in items.iter().enumerate() {
+            match function(item) {
+                Some(f) => {
+                    let index = self.functions.len();
+                    self.functions.push(None);
+                    pending.push((index, f));
+                    self.define_function(index, f);
+                }
+                None => self.compile(item),
+            }
+            if i + 1 < items.len() {
+                self.emit(Op::Pop, item.span);
+            }
+        }
+
+        for (index, f) in pending {
+            if let ExprKind::Function { name, params, body } = &f.kind {
+                let chunk = self.compile_function(name, params, body);
+                self.functions[index] = Some(chunk);
+            }
+        }
+    }
+
+    fn define_function(&mut self, index: usize, f: &Expr) {
+        if let ExprKind::Function { name, .. } = &f.kind {
+            let slot = self.declare(name, false);
+            self.emit(Op::Closure(index as u32), f.span);
+            self.emit(Op::SetVar(0, slot), f.span);
+            self.emit(Op::Null, f.span);
+        }
+    }
+
+    fn compile_in_scope(&mut self, expr: &'a Expr) {
+        match &expr.kind {
+            ExprKind::Block(items) => self.compile_items(items),
+            _ => self.compile(expr),
+        }
+    }
+
+    fn find_loop(&self, label: &Option<String>) -> Option<usize> {
+        let loops = &self.builders.last().unwrap().loops;
+        loops.iter().rposition(|l| label.is_none() || l.label == *label)
+    }
+
+    fn compile_loop_body(&mut self, label: &Option<String>, continue_addr: usize, iter: bool, body: impl FnOnce(&mut Self)) -> Vec<usize> {
+        let height = self.builder().cleanups.len() - usize::from(iter);
+        self.builder().loops.push(Loop {
+            label: label.clone(),
+            break_height: height,
+            continue_height: height + usize::from(iter),
+            continue_addr,
+            breaks: Vec::new(),
+        });
+        body(self);
+        self.builder().loops.pop().unwrap().breaks
+    }
+
+    fn compile(&mut self, expr: &'a Expr) {
+        let span = expr.span;
+        match &expr.kind {
+            ExprKind::String(s) => self.constant(Value::Str(s.clone()), span),
+
+            ExprKind::Number(Number::Int(n)) => self.constant(Value::Int(*n), span),
+
+            ExprKind::Number(Number::Float(n)) => self.constant(Value::Float(*n), span),
+
+            ExprKind::Interpolated(parts) => {
+                for part in parts {
+                    self.compile(part);
+                }
+                self.emit(Op::Interpolate(parts.len() as u32), span);
+            }
+
+            ExprKind::Array(items) => {
+                for item in items {
+                    self.compile(item);
+                }
+                self.emit(Op::Array(items.len() as u32), span);
+            }
+
+            ExprKind::Variable(name) => match self.resolve(name, false) {
+                Some((depth, slot)) => {
+                    let name = self.name(name);
+                    self.emit(Op::GetVar(depth, slot, name), span);
+                }
+                None => self.fail(format!("undefined variable `£{}`", name), span),
+            },
+
+            ExprKind::Const(name) => match self.resolve(name, true) {
+                Some((depth, slot)) => {
+                    let name = self.name(name);
+                    self.emit(Op::GetConst(depth, slot, name), span);
+                }
+                None => self.fail(format!("undefined constant `${}`", name), span),
+            },
+
+            ExprKind::Binary { left, op, right } => {
+                self.compile(left);
+                self.compile(right);
+                match self.operators.get(op) {
+                    Some((OperatorKind::Binary, index)) => {
+                        let index = *index;
+                        self.emit(Op::Operator(index, 2), span);
+                    }
+                    _ => match Op::binary(op) {
+                        Some(code) => {
+                            self.emit(code, span);
+                        }
+                        None => self.fail(format!("unknown binary operator `{}`", op), span),
+                    },
+                }
+            }
+
+            ExprKind::Unary { oper, op } => {
+                self.compile(oper);
+                match self.operators.get(op) {
+                    Some((kind, index)) if *kind != OperatorKind::Binary => {
+                        let index = *index;
+                        self.emit(Op::Operator(index, 1), span);
+                    }
+                    _ => match Op::unary(op) {
+                        Some(code) => {
+                            self.emit(code, span);
+                        }
+                        None => self.fail(format!("unknown unary operator `{}`", op), span),
+                    },
+                }
+            }
+
+            ExprKind::Func { name, args } => {
+                match self.resolve(name, false) {
+                    Some((depth, slot)) => self.emit(Op::GetCallee(depth, slot), span),
+                    None => self.emit(Op::Null, span),
+                };
+                for arg in args {
+                    self.compile(arg);
+                }
+                let name = self.name(name);
+                self.emit(Op::Call(name, args.len() as u8), span);
+            }
+
+            ExprKind::Index { target, index } => {
+                self.compile(target);
+                self.compile(index);
+                self.emit(Op::Index, span);
+            }
+
+            ExprKind::Slice { target, from, to } => {
+                self.compile(target);
+                for bound in [from, to].into_iter().flatten() {
+                    self.compile(bound);
+                }
+                self.emit(Op::Slice(from.is_some(), to.is_some()), span);
+            }
+
+            ExprKind::If { cond, then, else_then } => {
+                self.compile(cond);
+                let to_else = self.emit(Op::JumpIfFalse(0), span);
+                self.compile(then);
+                let to_end = self.emit(Op::Jump(0), span);
+                self.patch(to_else);
+                self.compile(else_then);
+                self.patch(to_end);
+            }
+
+            ExprKind::While { cond, then, else_then, label } => {
+                let start = self.here();
+                self.compile(cond);
+                let exit = self.emit(Op::JumpIfFalse(0), span);
+                let breaks = self.compile_loop_body(label, start, false, |c| {
+                    c.compile(then);
+                    c.emit(Op::Pop, span);
+                    c.emit(Op::Jump(start as u32), span);
+                });
+                self.patch(exit);
+                self.compile(else_then);
+                self.emit(Op::Pop, span);
+                for at in breaks {
+                    self.patch(at);
+                }
+                self.emit(Op::Null, span);
+            }
+
+            ExprKind::For { iter, var, source, then, else_then, label } => {
+                if let Some(source) = source {
+                    self.compile(source);
+                }
+                self.emit(Op::IterStart(*iter as u32, source.is_some()), span);
+                self.builder().cleanups.push(Cleanup::Iter);
+                let next = self.emit(Op::IterNext(0), span);
+                let breaks = self.compile_loop_body(label, next, true, |c| {
+                    let at = c.push_scope(span);
+                    let slot = c.declare(var, false);
+                    c.emit(Op::SetVar(0, slot), span);
+                    c.compile_in_scope(then);
+                    c.emit(Op::Pop, span);
+                    c.pop_scope(at, span);
+                    c.emit(Op::Jump(next as u32), span);
+                });
+                // running out pops the iterator itself
+                self.builder().cleanups.pop();
+                self.patch(next);
+                self.compile(else_then);
+                self.emit(Op::Pop, span);
+                for at in breaks {
+                    self.patch(at);
+                }
+                self.emit(Op::Null, span);
+            }
+
+            ExprKind::Define { var, constant, val } => {
+                self.compile(val);
+                if *constant {
+                    let slot = self.declare(var, true);
+                    let name = self.name(var);
+                    self.emit(Op::DefineConst(slot, name), span);
+                } else {
+                    let (depth, slot) = match self.resolve(var, false) {
+                        Some(found) => found,
+                        None => (0, self.declare(var, false)),
+                    };
+                    self.emit(Op::SetVar(depth, slot), span);
+                }
+                self.emit(Op::Null, span);
+            }
+
+            ExprKind::Function { name, params, body } => {
+                let index = self.functions.len();
+                self.functions.push(None);
+                self.define_function(index, expr);
+                let chunk = self.compile_function(name, params, body);
+                self.functions[index] = Some(chunk);
+            }
+
+            ExprKind::Return(value) => {
+                match value {
+                    Some(value) => self.compile(value),
+                    None => {
+                        self.emit(Op::Null, span);
+                    }
+                }
+                if self.builders.len() == 1 {
+                    self.fail("`return` outside of a function".to_string(), span);
+                } else {
+                    self.emit_cleanups(0, span);
+                    self.emit(Op::Return, span);
+                }
+            }
+
+            ExprKind::Import(_) => {
+                self.emit(Op::Null, span);
+            }
+
+            ExprKind::Export(inner) => self.compile(inner),
+
+            ExprKind::Try { attempt, binding, catch, finally } => {
+                let depth = self.scopes.len();
+                if let Some(finally) = finally {
+                    self.builder().cleanups.push(Cleanup::Finally(finally, depth));
+                }
+                let handler = self.emit(Op::Try(0), span);
+                self.builder().cleanups.push(Cleanup::Handler);
+                self.compile(attempt);
+                self.builder().cleanups.pop();
+                self.emit(Op::EndTry, span);
+                if let Some(finally) = finally {
+                    self.builder().cleanups.pop();
+                    self.compile(finally);
+                    self.emit(Op::Pop, span);
+                }
+                let mut ends = vec![self.emit(Op::Jump(0), span)];
+
+                // the error that was raised is on the stack here
+                self.patch(handler);
+                if let Some(catch) = catch {
+                    let mut rethrow = None;
+                    if let Some(finally) = finally {
+                        self.builder().cleanups.push(Cleanup::Finally(finally, depth));
+                        rethrow = Some(self.emit(Op::Try(0), span));
+                        self.builder().cleanups.push(Cleanup::Handler);
+                    }
+
+                    let at = self.push_scope(span);
+                    match binding {
+                        Some(name) => {
+                            let slot = self.declare(name, false);
+                            self.emit(Op::SetVar(0, slot), span);
+                        }
+                        None => {
+                            self.emit(Op::Pop, span);
+                        }
+                    }
+                    self.compile_in_scope(catch);
+                    self.pop_scope(at, span);
+
+                    if let (Some(finally), Some(rethrow)) = (finally, rethrow) {
+                        self.builder().cleanups.pop();
+                        self.emit(Op::EndTry, span);
+                        self.builder().cleanups.pop();
+                        self.compile(finally);
+                        self.emit(Op::Pop, span);
+                        ends.push(self.emit(Op::Jump(0), span));
+                        self.patch(rethrow);
+                    }
+                }
+                if let Some(finally) = finally {
+                    self.compile(finally);
+                    self.emit(Op::Pop, span);
+                    self.emit(Op::Rethrow, span);
+                }
+                for at in ends {
+                    self.patch(at);
+                }
+            }
+
+            ExprKind::Throw(value) => {
+                self.compile(value);
+                self.emit(Op::Throw, span);
+            }
+
+            // resuming leaves `nothing` as the value of the `anywho` itself
+            ExprKind::Yield(inner) => {
+                self.compile(inner);
+                self.emit(Op::Yield, span);
+            }
+
+            // the block becomes a function of its own, started as soon as it is reached
+            ExprKind::Generator(body) => {
+                let index = self.functions.len();
+                self.functions.push(None);
+                let chunk = self.compile_function("block", &[], body);
+                self.functions[index] = Some(chunk);
+                self.emit(Op::Generator(index as u32), span);
+            }
+
+            ExprKind::Break(label) | ExprKind::Continue(label) => {
+                let is_break = matches!(expr.kind, ExprKind::Break(_));
+                let Some(index) = self.find_loop(label) else {
+                    self.fail("loop control outside of a loop".to_string(), span);
+                    return;
+                };
+                let (height, target) = {
+                    let l = &self.builder().loops[index];
+                    if is_break { (l.break_height, None) } else { (l.continue_height, Some(l.continue_addr)) }
+                };
+                self.emit_cleanups(height, span);
+                match target {
+                    Some(addr) => {
+                        self.emit(Op::Jump(addr as u32), span);
+                    }
+                    None => {
+                        let at = self.emit(Op::Jump(0), span);
+                        self.builder().loops[index].breaks.push(at);
+                    }
+                }
+            }
+
+            ExprKind::Error => self.fail("cannot run code that failed to parse".to_string(), span),
+
+            ExprKind::Block(items) => {
+                if items.is_empty() {
+                    self.emit(Op::Null, span);
+                    return;
+                }
+                let at = self.push_scope(span);
+                self.compile_items(items);
+                self.pop_scope(at, span);
+            }
+        }
+    }
+}
+
+pub fn disassemble(chunk: &Chunk) -> String {
+    let mut out = format!("== {} ({} param(s), {} slot(s)) ==\n", chunk.name, chunk.arity, chunk.slots);
+    let mut last_line = 0;
+    for (i, op) in chunk.code.iter().enumerate() {
+        let span = chunk.spans[i];
+        let line = if span.line == last_line { "   |".to_string() } else { format!("{:4}", span.line) };
+        last_line = span.line;
+
+        let name = |n: &u32| chunk.names[*n as usize].as_str();
+        let detail = match op {
+            Op::Constant(c) => format!("Constant {} ({})", c, chunk.constants[*c as usize]),
+            Op::GetVar(d, s, n) => format!("GetVar {} {} (£{})", d, s, name(n)),
+            Op::GetConst(d, s, n) => format!("GetConst {} {} (${})", d, s, name(n)),
+            Op::DefineConst(s, n) => format!("DefineConst {} (${})", s, name(n)),
+            Op::Call(n, argc) => format!("Call {} {}", name(n), argc),
+            Op::Fail(n) => format!("Fail ({})", name(n)),
+            other => format!("{:?}", other).replace('(', " ").replace(", ", " ").replace(')', ""),
+        };
+        let _ = writeln!(out, "{:04} {} {}", i, line, detail);
+    }
+    out
+}
@@ -0,0 +1,1191 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::process::Command;
+
+use crate::diagnostics::Diagnostic;
+use crate::first_pass::OperatorKind;
+use crate::lexer::Span;
+use crate::module::{Loader, Module};
+use crate::parser::{Expr, ExprKind, ImportKind, Number};
+use crate::resolver::DefKind;
+
+const RUNTIME: &str = include_str!("c_runtime.c");
+
+// functions and blocks are told apart by where their node is in the tree
+type Node = *const Expr;
+
+// where each definition of a module lives in the C program
+#[derive(Default)]
+struct Storage {
+    // definitions at the top of the module become C globals
+    globals: HashSet<usize>,
+    // definitions a nested railway reaches live in heap cells it can share
+    cells: HashSet<usize>,
+    // the definitions each function declares
+    owned: HashMap<Node, Vec<usize>>,
+    // the cells each railway takes from the functions around it, in the order they are passed
+    free: HashMap<Node, Vec<usize>>,
+    // the cells each block makes when it is entered
+    made: HashMap<Node, Vec<usize>>,
+    // functions with a `try` in them, whose locals have to survive a `longjmp`
+    catching: HashSet<Node>,
+}
+
+fn definitions(module: &Module) -> HashMap<(Span, String), usize> {
+    module
+        .resolution
+        .definitions
+        .iter()
+        .enumerate()
+        .filter(|(_, def)| def.kind != DefKind::Import)
+        .map(|(id, def)| ((def.span, def.name.clone()), id))
+        .collect()
+}
+
+struct Scan<'a> {
+    module: &'a Module,
+    defs: &'a HashMap<(Span, String), usize>,
+    root: Node,
+    functions: Vec<Node>,
+    blocks: Vec<Node>,
+    owner: HashMap<usize, Node>,
+    home: HashMap<usize, Node>,
+    uses: Vec<(usize, Vec<Node>)>,
+    storage: Storage,
+}
+
+impl Scan<'_> {
+    fn define(&mut self, span: Span, name: &str, in_block: bool) {
+        let Some(&id) = self.defs.get(&(span, name.to_string())) else { return };
+        let function = *self.functions.last().unwrap();
+        self.owner.insert(id, function);
+        self.storage.owned.entry(function).or_default().push(id);
+        if in_block {
+            let block = *self.blocks.last().unwrap();
+            self.home.insert(id, block);
+            if self.functions.len() == 1 && block == self.root {
+                self.storage.globals.insert(id);
+            }
+        }
+    }
+
+    fn use_at(&mut self, span: Span) {
+        if let Some(&id) = self.module.resolution.uses.get(&span) {
+            self.uses.push((id, self.functions.clone()));
+        }
+    }
+
+    fn function(&mut self, node: Node, params: &[String], span: Span, body: &Expr) {
+        self.functions.push(node);
+        for param in params {
+            self.define(span, param, false);
+        }
+        self.visit(body);
+        self.functions.pop();
+    }
+
+    fn visit(&mut self, expr: &Expr) {
+        let node = expr as Node;
+        match &expr.kind {
+            ExprKind::Variable(_) | ExprKind::Const(_) => self.use_at(expr.span),
+
+            ExprKind::Func { args, .. } => {
+                for arg in args {
+                    self.visit(arg);
+                }
+                self.use_at(expr.span);
+            }
+
+            ExprKind::Block(items) => {
+                self.blocks.push(node);
+                for item in items {
+                    self.visit(item);
+                }
+                self.blocks.pop();
+            }
+
+            ExprKind::Define { var, val, .. } => {
+                self.visit(val);
+                if self.module.resolution.uses.contains_key(&expr.span) {
+                    self.use_at(expr.span);
+                } else {
+                    self.define(expr.span, var, true);
+                }
+            }
+
+            ExprKind::Function { name, params, body } => {
+                self.define(expr.span, name, true);
+                self.function(node, params, expr.span, body);
+            }
+
+            ExprKind::Generator(body) => self.function(node, &[], expr.span, body),
+
+            ExprKind::For { var, source, then, else_then, .. } => {
+                if let Some(source) = source {
+                    self.visit(source);
+                }
+                self.define(expr.span, var, false);
+                self.visit(then);
+                self.visit(else_then);
+            }
+
+            ExprKind::Try { attempt, binding, catch, finally } => {
+                self.storage.catching.insert(*self.functions.last().unwrap());
+                self.visit(attempt);
+                if let Some(catch) = catch {
+                    if let Some(name) = binding {
+                        self.define(catch.span, name, false);
+                    }
+                    self.visit(catch);
+                }
+                if let Some(finally) = finally {
+                    self.visit(finally);
+                }
+            }
+
+            _ => {
+                for child in expr.children() {
+                    self.visit(child);
+                }
+            }
+        }
+    }
+}
+
+fn storage(module: &Module, defs: &HashMap<(Span, String), usize>) -> Storage {
+    let root = &module.ast as Node;
+    let mut scan = Scan {
+        module,
+        defs,
+        root,
+        functions: vec![root],
+        blocks: vec![root],
+        owner: HashMap::new(),
+        home: HashMap::new(),
+        uses: Vec::new(),
+        storage: Storage::default(),
+    };
+    for (id, def) in module.resolution.definitions.iter().enumerate() {
+        if def.kind == DefKind::Import {
+            scan.storage.globals.insert(id);
+        }
+    }
+
+    match &module.ast.kind {
+        ExprKind::Block(items) => {
+            for item in items {
+                scan.visit(item);
+            }
+        }
+        _ => scan.visit(&module.ast),
+    }
+    for op in module.operators.values() {
+        scan.function(&op.body as Node, &op.params, op.body.span, &op.body);
+    }
+
+    // a definition used from a function other than its own is captured by every function in between
+    let mut storage = scan.storage;
+    for (id, chain) in &scan.uses {
+        if storage.globals.contains(id) {
+            continue;
+        }
+        let Some(owner) = scan.owner.get(id) else { continue };
+        let Some(at) = chain.iter().position(|f| f == owner) else { continue };
+        if at + 1 < chain.len() {
+            storage.cells.insert(*id);
+            for function in &chain[at + 1..] {
+                let free = storage.free.entry(*function).or_default();
+                if !free.contains(id) {
+                    free.push(*id);
+                }
+            }
+        }
+    }
+    let mut cells: Vec<usize> = storage.cells.iter().copied().collect();
+    cells.sort();
+    for id in cells {
+        if let Some(block) = scan.home.get(&id) {
+            storage.made.entry(*block).or_default().push(id);
+        }
+    }
+    storage
+}
+
+fn c_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for b in s.bytes() {
+        match b {
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            // `??` starts a trigraph in C99
+            b'?' => out.push_str("\\?"),
+            b'\n' => out.push_str("\\n"),
+            b'\t' => out.push_str("\\t"),
+            b' '..=b'~' => out.push(b as char),
+            _ => out.push_str(&format!("\\{:03o}", b)),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn ident(name: &str) -> String {
+    name.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect()
+}
+
+fn is_empty(expr: &Expr) -> bool {
+    matches!(&expr.kind, ExprKind::Block(items) if items.is_empty())
+}
+
+fn builtin_binary(op: &str) -> Option<&'static str> {
+    Some(match op {
+        "+" => "vita_add",
+        "-" => "vita_sub",
+        "*" => "vita_mul",
+        "/" => "vita_div",
+        "^" => "vita_pow",
+        "^^" => "vita_tetrate",
+        "<" => "vita_lt",
+        ">" => "vita_gt",
+        "≤" => "vita_le",
+        "≥" => "vita_ge",
+        ".." => "vita_range",
+        "=" => "vita_eq",
+        _ => return None,
+    })
+}
+
+fn builtin_unary(op: &str) -> Option<&'static str> {
+    Some(match op {
+        "!" => "vita_not",
+        "?" => "vita_truth",
+        "-" => "vita_neg",
+        "++" => "vita_inc",
+        _ => return None,
+    })
+}
+
+// where the value of a statement goes
+#[derive(Clone)]
+enum Dest {
+    Discard,
+    Assign(String),
+    Return,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Role {
+    Module,
+    Railway,
+    Operator,
+    // a railway with `anywho` in it, which runs to its next `anywho` each time it is resumed
+    Generator,
+}
+
+// what has to happen before jumping out of a `try`
+enum Cleanup<'a> {
+    Handler(String),
+    Finally(&'a Expr),
+}
+
+struct Loop {
+    label: Option<String>,
+    next: String,
+    exit: String,
+    cleanups: usize,
+    continued: bool,
+    broken: bool,
+}
+
+enum Local {
+    Value,
+    Cell,
+    Other(&'static str),
+}
+
+struct Function<'a> {
+    node: Node,
+    role: Role,
+    locals: Vec<(Local, String)>,
+    lines: Vec<String>,
+    indent: usize,
+    counter: usize,
+    loops: Vec<Loop>,
+    cleanups: Vec<Cleanup<'a>>,
+    // the `anywho`s of a generator, numbered from 1 in the order they are written
+    resumes: usize,
+}
+
+impl Function<'_> {
+    fn render(&self, head: &str, catching: bool) -> String {
+        let volatile = if catching { "volatile " } else { "" };
+        let mut out = format!("{} {{\n", head);
+        // a generator returns at every `anywho`, so its locals are kept in a frame of its own in between
+        let kept = self.role == Role::Generator && !self.locals.is_empty();
+        if kept {
+            out.push_str("    struct frame {\n");
+            for (local, name) in &self.locals {
+                match local {
+                    Local::Value => out.push_str(&format!("        Value {};\n", name)),
+                    Local::Cell => out.push_str(&format!("        Value *{};\n", name)),
+                    Local::Other(ty) => out.push_str(&format!("        {} {};\n", ty, name)),
+                }
+            }
+            out.push_str("    } *frame = g->frame;\n");
+            out.push_str("    if (!frame) frame = g->frame = vita_zalloc(sizeof *frame);\n");
+        }
+        for (local, name) in &self.locals {
+            let init = |fresh: &str| if kept { format!("frame->{}", name) } else { fresh.to_string() };
+            match local {
+                Local::Value => out.push_str(&format!("    {}Value {} = {};\n", volatile, name, init("UNDEF"))),
+                Local::Cell => out.push_str(&format!("    Value *{}{} = {};\n", volatile, name, init("NULL"))),
+                Local::Other(ty) if kept => out.push_str(&format!("    {} {} = {};\n", ty, name, init(""))),
+                Local::Other(ty) => out.push_str(&format!("    {} {};\n", ty, name)),
+            }
+        }
+        if self.resumes > 0 {
+            out.push_str("    switch (g->at) {\n");
+            for i in 1..=self.resumes {
+                out.push_str(&format!("    case {}: goto resume_{};\n", i, i));
+            }
+            out.push_str("    }\n");
+        }
+        for line in &self.lines {
+            out.push_str(line);
+            out.push('\n');
+        }
+        if self.resumes > 0 {
+            out.push_str("suspend:\n");
+            if kept {
+                for (_, name) in &self.locals {
+                    out.push_str(&format!("    frame->{} = {};\n", name, name));
+                }
+            }
+            out.push_str("    return 1;\n");
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+#[derive(Default)]
+struct Exports {
+    vars: HashMap<(bool, String), String>,
+    operators: HashMap<String, (OperatorKind, String)>,
+}
+
+#[derive(Default)]
+struct Program {
+    strings: Vec<String>,
+    string_ids: HashMap<String, usize>,
+    globals: Vec<String>,
+    prototypes: Vec<String>,
+    functions: Vec<String>,
+    files: HashMap<usize, usize>,
+    exports: HashMap<usize, Exports>,
+    railways: usize,
+}
+
+struct Compiler<'a, 'p> {
+    module: &'a Module,
+    id: usize,
+    defs: HashMap<(Span, String), usize>,
+    storage: Storage,
+    operators: HashMap<String, (OperatorKind, String)>,
+    consts: Vec<HashSet<String>>,
+    functions: Vec<Function<'a>>,
+    program: &'p mut Program,
+}
+
+impl<'a> Compiler<'a, '_> {
+    fn current(&mut self) -> &mut Function<'a> {
+        self.functions.last_mut().unwrap()
+    }
+
+    fn line(&mut self, text: impl AsRef<str>) {
+        let f = self.current();
+        let line = format!("{}{}", "    ".repeat(f.indent), text.as_ref());
+        f.lines.push(line);
+    }
+
+    fn open(&mut self, text: impl AsRef<str>) {
+        self.line(text);
+        self.current().indent += 1;
+    }
+
+    fn close(&mut self, text: &str) {
+        self.current().indent -= 1;
+        self.line(text);
+    }
+
+    fn reopen(&mut self, text: &str) {
+        self.close(text);
+        self.current().indent += 1;
+    }
+
+    fn fresh(&mut self, prefix: &str) -> String {
+        let f = self.current();
+        f.counter += 1;
+        format!("{}{}", prefix, f.counter)
+    }
+
+    fn local(&mut self, local: Local, prefix: &str) -> String {
+        let name = self.fresh(prefix);
+        self.current().locals.push((local, name.clone()));
+        name
+    }
+
+    fn temp(&mut self, value: String) -> String {
+        let temp = self.local(Local::Value, "t");
+        self.line(format!("{} = {};", temp, value));
+        temp
+    }
+
+    fn at(&self, span: Span) -> String {
+        let file = self.program.files.get(&span.file).copied().unwrap_or(0);
+        format!("AT({}, {}, {})", file, span.line, span.col)
+    }
+
+    fn string(&mut self, s: &str) -> String {
+        let next = self.program.strings.len();
+        let id = *self.program.string_ids.entry(s.to_string()).or_insert(next);
+        if id == next {
+            self.program.strings.push(s.to_string());
+        }
+        format!("S[{}]", id)
+    }
+
+    fn fail(&mut self, message: &str, span: Span) {
+        let line = format!("vita_fail({}, \"%s\", {});", self.at(span), c_string(message));
+        self.line(line);
+    }
+
+    fn def(&self, span: Span, name: &str) -> Option<usize> {
+        self.defs.get(&(span, name.to_string())).copied()
+    }
+
+    fn global(&self, id: usize) -> String {
+        format!("g{}_{}_{}", self.id, id, ident(&self.module.resolution.definitions[id].name))
+    }
+
+    fn cell(&self, id: usize) -> String {
+        format!("c{}_{}", id, ident(&self.module.resolution.definitions[id].name))
+    }
+
+    // the C lvalue holding a definition
+    fn place(&self, id: usize) -> String {
+        if self.storage.globals.contains(&id) {
+            self.global(id)
+        } else if self.storage.cells.contains(&id) {
+            format!("(*{})", self.cell(id))
+        } else {
+            format!("v{}_{}", id, ident(&self.module.resolution.definitions[id].name))
+        }
+    }
+
+    // reading a name checks it has been given a value unless nothing could have run before that
+    fn read(&self, id: usize, span: Span) -> String {
+        let def = &self.module.resolution.definitions[id];
+        let plain = !self.storage.globals.contains(&id) && !self.storage.cells.contains(&id) && def.kind != DefKind::Function;
+        if plain {
+            return self.place(id);
+        }
+        let sigil = if def.kind == DefKind::Const { "$" } else { "£" };
+        format!("vita_get({}, {}, {})", self.place(id), c_string(&format!("{}{}", sigil, def.name)), self.at(span))
+    }
+
+    // give a definition its first value; cells for parameters, loop variables and catch bindings are made here
+    fn bind(&mut self, id: usize, value: &str, fresh: bool) {
+        if fresh && self.storage.cells.contains(&id) {
+            let line = format!("{} = vita_cell();", self.cell(id));
+            self.line(line);
+        }
+        let line = format!("{} = {};", self.place(id), value);
+        self.line(line);
+    }
+
+    fn user_operator(&self, op: &str, binary: bool) -> Option<String> {
+        self.operators
+            .get(op)
+            .filter(|(kind, _)| (*kind == OperatorKind::Binary) == binary)
+            .map(|(_, name)| name.clone())
+    }
+
+    fn values(items: &[String]) -> String {
+        if items.is_empty() {
+            "NULL".to_string()
+        } else {
+            format!("(Value[]){{{}}}", items.join(", "))
+        }
+    }
+
+    // every operand is saved in a temporary first, since C leaves the order of arguments open
+    fn expr(&mut self, expr: &'a Expr) -> String {
+        let span = expr.span;
+        let value = match &expr.kind {
+            ExprKind::String(s) => return self.string(s),
+
+            ExprKind::Number(Number::Int(i64::MIN)) => return "vita_int(INT64_MIN)".to_string(),
+
+            ExprKind::Number(Number::Int(n)) => return format!("vita_int({})", n),
+
+            ExprKind::Number(Number::Float(n)) => return format!("vita_float({:?})", n),
+
+            ExprKind::Variable(_) | ExprKind::Const(_) => match self.module.resolution.uses.get(&span) {
+                Some(&id) => self.read(id, span),
+                None => {
+                    self.fail("cannot run code that failed to parse", span);
+                    return "NIL".to_string();
+                }
+            },
+
+            ExprKind::Interpolated(parts) => {
+                let parts: Vec<String> = parts.iter().map(|p| self.expr(p)).collect();
+                format!("vita_interpolate({}, {})", parts.len(), Self::values(&parts))
+            }
+
+            ExprKind::Array(items) => {
+                let items: Vec<String> = items.iter().map(|i| self.expr(i)).collect();
+                format!("vita_array({}, {})", items.len(), Self::values(&items))
+            }
+
+            ExprKind::Binary { left, op, right } => {
+                let (l, r) = (self.expr(left), self.expr(right));
+                let at = self.at(span);
+                match (self.user_operator(op, true), builtin_binary(op)) {
+                    (Some(f), _) => format!("vita_apply({}, {}, (Value[]){{{}, {}}}, {})", f, c_string(op), l, r, at),
+                    (None, Some(f)) => format!("{}({}, {}, {})", f, l, r, at),
+                    (None, None) => format!("vita_unknown_binary({}, {}, {}, {})", c_string(op), l, r, at),
+                }
+            }
+
+            ExprKind::Unary { oper, op } => {
+                let v = self.expr(oper);
+                let at = self.at(span);
+                match (self.user_operator(op, false), builtin_unary(op)) {
+                    (Some(f), _) => format!("vita_apply({}, {}, (Value[]){{{}}}, {})", f, c_string(op), v, at),
+                    (None, Some(f)) => format!("{}({}, {})", f, v, at),
+                    (None, None) => format!("vita_unknown_unary({}, {}, {})", c_string(op), v, at),
+                }
+            }
+
+            ExprKind::Func { name, args } => {
+                let args: Vec<String> = args.iter().map(|a| self.expr(a)).collect();
+                let at = self.at(span);
+                match self.module.resolution.uses.get(&span) {
+                    // a railway not defined yet is still undefined here, which falls back to the builtin
+                    Some(&id) => format!("vita_call({}, {}, {}, {}, {})", self.place(id), c_string(name), args.len(), Self::values(&args), at),
+                    None => format!("vita_builtin({}, {}, {}, {})", c_string(name), args.len(), Self::values(&args), at),
+                }
+            }
+
+            ExprKind::Index { target, index } => {
+                let (t, i) = (self.expr(target), self.expr(index));
+                format!("vita_index({}, {}, {})", t, i, self.at(span))
+            }
+
+            ExprKind::Slice { target, from, to } => {
+                let t = self.expr(target);
+                let from = from.as_ref().map_or("UNDEF".to_string(), |e| self.expr(e));
+                let to = to.as_ref().map_or("UNDEF".to_string(), |e| self.expr(e));
+                format!("vita_slice({}, {}, {}, {})", t, from, to, self.at(span))
+            }
+
+            _ => {
+                let temp = self.local(Local::Value, "t");
+                self.stmt(expr, &Dest::Assign(temp.clone()));
+                return temp;
+            }
+        };
+        self.temp(value)
+    }
+
+    fn finish(&mut self, value: &str, dest: &Dest) {
+        match dest {
+            Dest::Discard => {}
+            Dest::Assign(name) => self.line(format!("{} = {};", name, value)),
+            Dest::Return => self.line(format!("return {};", value)),
+        }
+    }
+
+    fn statements(&mut self, items: &'a [Expr], dest: &Dest) {
+        match items.split_last() {
+            Some((last, rest)) => {
+                for item in rest {
+                    self.stmt(item, &Dest::Discard);
+                }
+                self.stmt(last, dest);
+            }
+            None => self.finish("NIL", dest),
+        }
+    }
+
+    fn block(&mut self, node: &'a Expr, items: &'a [Expr], dest: &Dest) {
+        self.consts.push(HashSet::new());
+        for id in self.storage.made.get(&(node as Node)).cloned().unwrap_or_default() {
+            let line = format!("{} = vita_cell();", self.cell(id));
+            self.line(line);
+        }
+        self.statements(items, dest);
+        self.consts.pop();
+    }
+
+    // the contents of a block that already has braces of its own in the output
+    fn branch(&mut self, expr: &'a Expr, dest: &Dest) {
+        match &expr.kind {
+            ExprKind::Block(items) => self.block(expr, items, dest),
+            _ => {
+                self.consts.push(HashSet::new());
+                self.stmt(expr, dest);
+                self.consts.pop();
+            }
+        }
+    }
+
+    // run what every `try` being left needs, innermost first, down to `height`
+    fn unwind(&mut self, height: usize) {
+        let mut i = self.current().cleanups.len();
+        while i > height {
+            i -= 1;
+            let cleanup = match &self.current().cleanups[i] {
+                Cleanup::Handler(handler) => Err(handler.clone()),
+                Cleanup::Finally(finally) => Ok(*finally),
+            };
+            match cleanup {
+                Err(handler) => self.line(format!("vita_untry(&{});", handler)),
+                Ok(finally) => {
+                    let outer = self.current().cleanups.split_off(i);
+                    self.open("{");
+                    self.branch(finally, &Dest::Discard);
+                    self.close("}");
+                    self.current().cleanups.extend(outer);
+                }
+            }
+        }
+    }
+
+    fn target(&self, label: &Option<String>) -> Option<usize> {
+        let loops = &self.functions.last().unwrap().loops;
+        match label {
+            None => loops.len().checked_sub(1),
+            Some(label) => loops.iter().rposition(|l| l.label.as_ref() == Some(label)),
+        }
+    }
+
+    fn start_loop(&mut self, label: &Option<String>) {
+        let n = self.fresh("");
+        let cleanups = self.current().cleanups.len();
+        self.current().loops.push(Loop {
+            label: label.clone(),
+            next: format!("next_{}", n),
+            exit: format!("exit_{}", n),
+            cleanups,
+            continued: false,
+            broken: false,
+        });
+    }
+
+    fn loop_body(&mut self, then: &'a Expr) {
+        self.branch(then, &Dest::Discard);
+        let lp = self.current().loops.last().unwrap();
+        if lp.continued {
+            let line = format!("{}: ;", lp.next);
+            self.line(line);
+        }
+    }
+
+    fn end_loop(&mut self, else_then: &'a Expr, dest: &Dest) {
+        let lp = self.current().loops.pop().unwrap();
+        if !is_empty(else_then) {
+            self.branch(else_then, &Dest::Discard);
+        }
+        if lp.broken {
+            self.line(format!("{}: ;", lp.exit));
+        }
+        self.finish("NIL", dest);
+    }
+
+    fn function(&mut self, node: Node, role: Role, head: String, params: &[String], span: Span, body: &'a Expr) {
+        self.functions.push(Function {
+            node,
+            role,
+            locals: Vec::new(),
+            lines: Vec::new(),
+            indent: 1,
+            counter: 0,
+            loops: Vec::new(),
+            cleanups: Vec::new(),
+            resumes: 0,
+        });
+
+        for id in self.storage.owned.get(&node).cloned().unwrap_or_default() {
+            if self.storage.cells.contains(&id) {
+                let name = self.cell(id);
+                self.current().locals.push((Local::Cell, name));
+            } else if !self.storage.globals.contains(&id) {
+                let name = self.place(id);
+                self.current().locals.push((Local::Value, name));
+            }
+        }
+        // a generator is started with what it was called with, and has to return before it can pause
+        let (closure, args, dest, done) = match role {
+            Role::Generator => ("g->fn", "g->args", Dest::Discard, "return 0;"),
+            _ => ("self", "args", Dest::Return, "return NIL;"),
+        };
+        for (i, id) in self.storage.free.get(&node).cloned().unwrap_or_default().into_iter().enumerate() {
+            let name = self.cell(id);
+            self.current().locals.push((Local::Cell, name.clone()));
+            self.line(format!("{} = {}->cells[{}];", name, closure, i));
+        }
+        for (i, param) in params.iter().enumerate() {
+            if let Some(id) = self.def(span, param) {
+                self.bind(id, &format!("{}[{}]", args, i), true);
+            }
+        }
+
+        self.branch(body, &dest);
+        if !self.current().lines.last().is_some_and(|l| l.trim_start().starts_with("return ")) {
+            self.line(done);
+        }
+
+        let function = self.functions.pop().unwrap();
+        let catching = self.storage.catching.contains(&function.node);
+        self.program.prototypes.push(format!("{};", head));
+        self.program.functions.push(function.render(&head, catching));
+    }
+
+    // compile a railway and give back the closure over the cells it takes
+    fn closure(&mut self, expr: &'a Expr, name: &str, params: &[String], body: &'a Expr) -> String {
+        let node = expr as Node;
+        self.program.railways += 1;
+        let function = format!("f{}_{}", self.program.railways, ident(name));
+        let generator = body.contains_yield();
+        let (role, head, entry) = if generator {
+            (Role::Generator, format!("static int {}(Generator *g, Value *out)", function), format!("NULL, {}", function))
+        } else {
+            (Role::Railway, format!("static Value {}(Closure *self, Value *args)", function), format!("{}, NULL", function))
+        };
+        self.function(node, role, head, params, expr.span, body);
+
+        let cells: Vec<String> = self.storage.free.get(&node).cloned().unwrap_or_default().into_iter().map(|id| self.cell(id)).collect();
+        format!(
+            "vita_closure({}, {}, {}, {}, {})",
+            c_string(name),
+            params.len(),
+            entry,
+            cells.len(),
+            if cells.is_empty() { "NULL".to_string() } else { format!("(Value *[]){{{}}}", cells.join(", ")) }
+        )
+    }
+
+    fn stmt(&mut self, expr: &'a Expr, dest: &Dest) {
+        let span = expr.span;
+        match &expr.kind {
+            ExprKind::Block(items) => {
+                self.open("{");
+                self.block(expr, items, dest);
+                self.close("}");
+            }
+
+            ExprKind::If { cond, then, else_then } => {
+                let cond = self.expr(cond);
+                self.open(format!("if (vita_truthy({})) {{", cond));
+                self.branch(then, dest);
+                if !is_empty(else_then) || !matches!(dest, Dest::Discard) {
+                    self.reopen("} else {");
+                    self.branch(else_then, dest);
+                }
+                self.close("}");
+            }
+
+            ExprKind::While { cond, then, else_then, label } => {
+                self.start_loop(label);
+                self.open("for (;;) {");
+                let cond = self.expr(cond);
+                self.line(format!("if (!vita_truthy({})) break;", cond));
+                self.loop_body(then);
+                self.close("}");
+                self.end_loop(else_then, dest);
+            }
+
+            ExprKind::For { iter, var, source, then, else_then, label } => {
+                let it = self.local(Local::Other("Iter"), "i");
+                match source {
+                    Some(source) => {
+                        let source = self.expr(source);
+                        let line = format!("vita_iter_start(&{}, {}, {});", it, source, self.at(span));
+                        self.line(line);
+                    }
+                    None => self.line(format!("vita_iter_count(&{}, {});", it, iter)),
+                }
+                self.start_loop(label);
+                self.open(format!("while (vita_iter_next(&{})) {{", it));
+                if let Some(id) = self.def(span, var) {
+                    self.bind(id, &format!("{}.item", it), true);
+                }
+                self.loop_body(then);
+                self.close("}");
+                self.end_loop(else_then, dest);
+            }
+
+            ExprKind::Define { var, constant: true, val } => {
+                let value = self.expr(val);
+                if self.consts.iter().any(|scope| scope.contains(var)) {
+                    self.fail(&format!("cannot redefine constant `${}`", var), span);
+                    return;
+                }
+                self.consts.last_mut().unwrap().insert(var.clone());
+                if let Some(id) = self.def(span, var) {
+                    self.bind(id, &format!("vita_freeze({})", value), false);
+                }
+                self.finish("NIL", dest);
+            }
+
+            ExprKind::Define { var, val, .. } => {
+                let value = self.expr(val);
+                let id = match self.module.resolution.uses.get(&span) {
+                    Some(&id) => Some(id),
+                    None => self.def(span, var),
+                };
+                if let Some(id) = id {
+                    self.bind(id, &value, false);
+                }
+                self.finish("NIL", dest);
+            }
+
+            ExprKind::Function { name, params, body } => {
+                let closure = self.closure(expr, name, params, body);
+                if let Some(id) = self.def(span, name) {
+                    self.bind(id, &closure, false);
+                }
+                self.finish("NIL", dest);
+            }
+
+            // a block generator is a generator railway without parameters, started where it is written
+            ExprKind::Generator(body) => {
+                let closure = self.closure(expr, "block", &[], body);
+                let generator = self.temp(format!("vita_generator({}.as.fn, NULL)", closure));
+                self.finish(&generator, dest);
+            }
+
+            ExprKind::Return(value) => {
+                if self.current().role == Role::Module {
+                    self.fail("`return` outside of a function", span);
+                    return;
+                }
+                let value = match value {
+                    Some(value) => self.expr(value),
+                    None => "NIL".to_string(),
+                };
+                self.unwind(0);
+                if self.current().role == Role::Generator {
+                    self.line("return 0;");
+                } else {
+                    self.line(format!("return {};", value));
+                }
+            }
+
+            ExprKind::Throw(value) => {
+                let value = self.expr(value);
+                let line = format!("vita_throw_value({}, {});", value, self.at(span));
+                self.line(line);
+            }
+
+            ExprKind::Break(label) | ExprKind::Continue(label) => {
+                let is_break = matches!(expr.kind, ExprKind::Break(_));
+                match self.target(label) {
+                    Some(i) => {
+                        let height = self.current().loops[i].cleanups;
+                        self.unwind(height);
+                        let lp = &mut self.current().loops[i];
+                        let target = if is_break {
+                            lp.broken = true;
+                            lp.exit.clone()
+                        } else {
+                            lp.continued = true;
+                            lp.next.clone()
+                        };
+                        self.line(format!("goto {};", target));
+                    }
+                    None => {
+                        self.unwind(0);
+                        match self.current().role {
+                            Role::Module if is_break => self.fail("`jump off the bandwagon` outside of a loop", span),
+                            Role::Module => self.fail("`get back to work boy` outside of a loop", span),
+                            Role::Generator => {
+                                self.line("vita_escaped(1);");
+                                self.line("return 0;");
+                            }
+                            role => self.line(format!("return vita_escaped({});", (role == Role::Railway) as u8)),
+                        }
+                    }
+                }
+            }
+
+            ExprKind::Try { attempt, binding, catch, finally } => {
+                // a value being returned waits until the `finally` has run
+                let (dest, result) = match dest {
+                    Dest::Return => {
+                        let temp = self.local(Local::Value, "t");
+                        (Dest::Assign(temp.clone()), Some(temp))
+                    }
+                    other => (other.clone(), None),
+                };
+                let handler = self.local(Local::Other("Handler"), "h");
+                self.line(format!("vita_try(&{});", handler));
+                self.open(format!("if (!setjmp({}.buf)) {{", handler));
+                if let Some(finally) = finally {
+                    self.current().cleanups.push(Cleanup::Finally(finally));
+                }
+                self.current().cleanups.push(Cleanup::Handler(handler.clone()));
+                self.branch(attempt, &dest);
+                self.current().cleanups.pop();
+                self.line(format!("vita_untry(&{});", handler));
+                self.reopen("} else {");
+
+                // an error out of the `catch` still runs the `finally` before going on
+                let mut failed = handler.clone();
+                if let Some(catch) = catch {
+                    if finally.is_some() {
+                        failed = self.local(Local::Other("Handler"), "h");
+                        self.line(format!("vita_try(&{});", failed));
+                        self.open(format!("if (!setjmp({}.buf)) {{", failed));
+                        self.current().cleanups.push(Cleanup::Handler(failed.clone()));
+                    }
+                    self.consts.push(HashSet::new());
+                    if let Some(id) = binding.as_ref().and_then(|name| self.def(catch.span, name)) {
+                        self.bind(id, &format!("vita_error_value({}.error)", handler), true);
+                    }
+                    self.branch(catch, &dest);
+                    self.consts.pop();
+                    if finally.is_some() {
+                        self.current().cleanups.pop();
+                        self.line(format!("vita_untry(&{});", failed));
+                        self.reopen("} else {");
+                    }
+                }
+                if let Some(finally) = finally {
+                    self.current().cleanups.pop();
+                    self.branch(finally, &Dest::Discard);
+                }
+                if catch.is_none() || finally.is_some() {
+                    self.line(format!("vita_throw({}.error);", failed));
+                }
+                if catch.is_some() && finally.is_some() {
+                    self.close("}");
+                }
+                self.close("}");
+                if let Some(finally) = finally {
+                    self.branch(finally, &Dest::Discard);
+                }
+                if let Some(result) = result {
+                    self.line(format!("return {};", result));
+                }
+            }
+
+            // hand the value back and carry on from just after it when resumed
+            ExprKind::Yield(value) => {
+                let value = self.expr(value);
+                let f = self.current();
+                f.resumes += 1;
+                let resume = f.resumes;
+                self.line(format!("*out = {};", value));
+                self.line(format!("g->at = {};", resume));
+                self.line("goto suspend;");
+                self.line(format!("resume_{}: ;", resume));
+                self.finish("NIL", dest);
+            }
+
+            ExprKind::Export(inner) => self.stmt(inner, dest),
+
+            ExprKind::Import(_) => self.finish("NIL", dest),
+
+            ExprKind::Error => self.fail("cannot run code that failed to parse", span),
+
+            _ => {
+                let value = self.expr(expr);
+                self.finish(&value, dest);
+            }
+        }
+    }
+}
+
+fn module(loader: &Loader, id: usize, program: &mut Program) {
+    let module = &loader.modules[&id];
+    let defs = definitions(module);
+    let storage = storage(module, &defs);
+    let mut c = Compiler {
+        module,
+        id,
+        defs,
+        storage,
+        operators: HashMap::new(),
+        consts: vec![HashSet::new()],
+        functions: Vec::new(),
+        program,
+    };
+
+    let mut globals: Vec<usize> = c.storage.globals.iter().copied().collect();
+    globals.sort();
+    for global in globals {
+        let line = format!("static Value {};", c.global(global));
+        c.program.globals.push(line);
+    }
+
+    // the module's own operators win over imported ones of the same name, as in the interpreter
+    let mut locals: Vec<_> = module.operators.values().collect();
+    locals.sort_by_key(|op| op.body.span.start);
+    for (i, op) in locals.iter().enumerate() {
+        c.operators.insert(op.op.clone(), (op.kind.clone(), format!("op{}_{}", id, i)));
+    }
+
+    c.functions.push(Function {
+        node: &module.ast,
+        role: Role::Module,
+        locals: Vec::new(),
+        lines: Vec::new(),
+        indent: 1,
+        counter: 0,
+        loops: Vec::new(),
+        cleanups: Vec::new(),
+        resumes: 0,
+    });
+
+    let mut imported = module.resolution.definitions.iter().enumerate().filter(|(_, d)| d.kind == DefKind::Import).map(|(i, _)| i);
+    for (import, dep) in &module.imports {
+        let available = &loader.modules[dep];
+        let names = import.names.as_ref().unwrap_or(&available.exports);
+        for name in names {
+            let exports = &c.program.exports[dep];
+            match name.kind {
+                ImportKind::Variable | ImportKind::Const => {
+                    let constant = name.kind == ImportKind::Const;
+                    let source = exports.vars.get(&(constant, name.name.clone())).cloned();
+                    let (Some(local), Some(source)) = (imported.next(), source) else { continue };
+                    let line = format!("{} = {};", c.global(local), source);
+                    if constant {
+                        c.consts[0].insert(name.name.clone());
+                    }
+                    c.line(line);
+                }
+                ImportKind::Operator => {
+                    if c.operators.contains_key(&name.name) {
+                        continue;
+                    }
+                    if let Some(op) = exports.operators.get(&name.name).cloned() {
+                        c.operators.insert(name.name.clone(), op);
+                    }
+                }
+            }
+        }
+    }
+
+    c.current().locals.push((Local::Value, "value".to_string()));
+    for id in c.storage.owned.get(&(&module.ast as Node)).cloned().unwrap_or_default() {
+        if c.storage.cells.contains(&id) {
+            let name = c.cell(id);
+            c.current().locals.push((Local::Cell, name));
+        } else if !c.storage.globals.contains(&id) {
+            let name = c.place(id);
+            c.current().locals.push((Local::Value, name));
+        }
+    }
+    match &module.ast.kind {
+        ExprKind::Block(items) => c.statements(items, &Dest::Assign("value".to_string())),
+        _ => c.stmt(&module.ast, &Dest::Assign("value".to_string())),
+    }
+    c.line("return value;");
+    let main = c.functions.pop().unwrap();
+
+    for (i, op) in locals.iter().enumerate() {
+        let head = format!("static Value op{}_{}(Value *args)", id, i);
+        c.function(&op.body as Node, Role::Operator, head, &op.params, op.body.span, &op.body);
+    }
+
+    let head = format!("static Value module_{}(void)", id);
+    let catching = c.storage.catching.contains(&(&module.ast as Node));
+    c.program.prototypes.push(format!("{};", head));
+    c.program.functions.push(main.render(&head, catching));
+
+    let mut exports = Exports::default();
+    if let ExprKind::Block(items) = &module.ast.kind {
+        for item in items {
+            let ExprKind::Export(inner) = &item.kind else { continue };
+            let (name, constant) = match &inner.kind {
+                ExprKind::Define { var, constant, .. } => (var, *constant),
+                ExprKind::Function { name, .. } => (name, false),
+                _ => continue,
+            };
+            let def = match module.resolution.uses.get(&inner.span) {
+                Some(&def) => Some(def),
+                None => c.def(inner.span, name),
+            };
+            if let Some(def) = def {
+                exports.vars.insert((constant, name.clone()), c.global(def));
+            }
+        }
+    }
+    for name in &module.exports {
+        if let (ImportKind::Operator, Some(op)) = (&name.kind, c.operators.get(&name.name)) {
+            exports.operators.insert(name.name.clone(), op.clone());
+        }
+    }
+    c.program.exports.insert(id, exports);
+}
+
+// C99 for the entry module and everything it imports, in one translation unit with the runtime
+pub fn generate(loader: &Loader, entry: usize) -> String {
+    let ids = loader.order(entry);
+    let mut program = Program { files: ids.iter().enumerate().map(|(i, id)| (*id, i)).collect(), ..Default::default() };
+    for &id in &ids {
+        module(loader, id, &mut program);
+    }
+
+    let mut out = String::from(RUNTIME);
+    out.push_str(&format!("\nstatic Value S[{}];\n", program.strings.len().max(1)));
+    for global in &program.globals {
+        out.push_str(&format!("{}\n", global));
+    }
+    out.push('\n');
+    for prototype in &program.prototypes {
+        out.push_str(&format!("{}\n", prototype));
+    }
+    for function in &program.functions {
+        out.push_str(&format!("\n{}", function));
+    }
+
+    let sources: Vec<String> = ids.iter().map(|id| c_string(&loader.sources[*id].name)).collect();
+    out.push_str("\nint main(void) {\n");
+    out.push_str(&format!("    static const char *sources[] = {{{}}};\n", sources.join(", ")));
+    out.push_str("    vita_sources = sources;\n");
+    for (i, s) in program.strings.iter().enumerate() {
+        out.push_str(&format!("    S[{}] = vita_str_lit({}, {});\n", i, c_string(s), s.len()));
+    }
+    for &id in &ids {
+        if id == entry {
+            out.push_str(&format!("    vita_print_result(module_{}());\n", id));
+        } else {
+            out.push_str(&format!("    module_{}();\n", id));
+        }
+    }
+    out.push_str("    return 0;\n}\n");
+    out
+}
+
+// hand the generated C to the system compiler; `$CC` picks a different one than `cc`
+pub fn compile(source: &Path, output: &Path) -> Result<(), Diagnostic> {
+    let compiler = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let result = Command::new(&compiler)
+        .args(["-std=c99", "-O2", "-o"])
+        .arg(output)
+        .arg(source)
+        .arg("-lm")
+        .output();
+    match result {
+        Ok(result) if result.status.success() => Ok(()),
+        Ok(result) => Err(Diagnostic::error("E0803", format!("`{}` could not compile `{}`", compiler, source.display()))
+            .with_note(String::from_utf8_lossy(&result.stderr).trim_end().to_string())),
+        Err(e) => Err(Diagnostic::error("E0803", format!("cannot run the C compiler `{}`: {}", compiler, e))
+            .with_note("set `CC` to the C compiler to use")),
+    }
+}
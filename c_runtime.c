@@ -0,0 +1,1103 @@
+/* runtime for programs built by `vita build`; values and errors behave as they do in the interpreter */
+#include <inttypes.h>
+#include <math.h>
+#include <setjmp.h>
+#include <stdarg.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#define MAX_CALL_DEPTH 1000
+
+typedef enum { T_UNDEF, T_NULL, T_BOOL, T_INT, T_FLOAT, T_STR, T_ARRAY, T_RANGE, T_FUNC, T_GEN, T_ERROR } Tag;
+
+typedef struct Str {
+    size_t len;
+    char data[];
+} Str;
+
+typedef struct Value {
+    Tag tag;
+    union {
+        int b;
+        int64_t i;
+        double f;
+        Str *s;
+        struct Array *a;
+        struct { int64_t from, to; } r;
+        struct Closure *fn;
+        struct Generator *g;
+        struct Error *e;
+    } as;
+} Value;
+
+typedef struct Array {
+    Value *items;
+    size_t len, cap;
+    int frozen;
+} Array;
+
+typedef struct Closure {
+    const char *name;
+    int arity;
+    Value (*fn)(struct Closure *self, Value *args);
+    /* set instead of `fn` for a generator; calling it makes a generator and this runs it to its next `anywho` */
+    int (*resume)(struct Generator *g, Value *out);
+    Value **cells;
+} Closure;
+
+/* where in which source an expression is; `file` indexes `vita_sources` */
+typedef struct Loc {
+    int file, line, col;
+} Loc;
+
+typedef struct Frame {
+    const char *name;
+    Loc at;
+} Frame;
+
+typedef struct Error {
+    Str *kind;
+    Str *message;
+    Loc at;
+    Frame *trace;
+    size_t trace_len, trace_cap;
+} Error;
+
+typedef struct Handler {
+    jmp_buf buf;
+    struct Handler *prev;
+    size_t frames;
+    int depth;
+    Error *error;
+} Handler;
+
+/* a paused generator is the `anywho` to carry on from and the locals its body keeps in `frame` */
+typedef struct Generator {
+    const char *name;
+    Closure *fn;
+    Value *args;
+    enum { GEN_READY, GEN_RUNNING, GEN_DONE } state;
+    int at;
+    void *frame;
+} Generator;
+
+typedef struct Iter {
+    int kind;
+    Value source;
+    int64_t index, count;
+    Value item;
+    Loc at;
+} Iter;
+
+#define AT(f, l, c) ((Loc){f, l, c})
+#define NIL ((Value){.tag = T_NULL})
+#define UNDEF ((Value){.tag = T_UNDEF})
+
+static const char **vita_sources;
+static Handler *vita_handlers;
+static Frame *vita_frames;
+static size_t vita_nframes, vita_frames_cap;
+static int vita_depth;
+
+static void *vita_alloc(size_t size) {
+    void *p = malloc(size);
+    if (!p) {
+        fputs("out of memory\n", stderr);
+        exit(1);
+    }
+    return p;
+}
+
+static void *vita_zalloc(size_t size) {
+    return memset(vita_alloc(size), 0, size);
+}
+
+/* growable text, used for printing values */
+typedef struct Buf {
+    char *data;
+    size_t len, cap;
+} Buf;
+
+static void buf_put(Buf *b, const char *s, size_t n) {
+    if (b->len + n + 1 > b->cap) {
+        b->cap = (b->len + n + 1) * 2;
+        b->data = realloc(b->data, b->cap);
+        if (!b->data) {
+            fputs("out of memory\n", stderr);
+            exit(1);
+        }
+    }
+    memcpy(b->data + b->len, s, n);
+    b->len += n;
+    b->data[b->len] = '\0';
+}
+
+static void buf_puts(Buf *b, const char *s) {
+    buf_put(b, s, strlen(s));
+}
+
+static void buf_printf(Buf *b, const char *fmt, ...) {
+    char tmp[512];
+    va_list args;
+    va_start(args, fmt);
+    int n = vsnprintf(tmp, sizeof tmp, fmt, args);
+    va_end(args);
+    if (n >= (int)sizeof tmp) {
+        char *big = vita_alloc((size_t)n + 1);
+        va_start(args, fmt);
+        vsnprintf(big, (size_t)n + 1, fmt, args);
+        va_end(args);
+        buf_put(b, big, (size_t)n);
+        free(big);
+        return;
+    }
+    buf_put(b, tmp, (size_t)n);
+}
+
+static Str *str_new(const char *data, size_t len) {
+    Str *s = vita_alloc(sizeof(Str) + len + 1);
+    s->len = len;
+    memcpy(s->data, data, len);
+    s->data[len] = '\0';
+    return s;
+}
+
+static Value vita_int(int64_t i) {
+    Value v = {.tag = T_INT};
+    v.as.i = i;
+    return v;
+}
+
+static Value vita_float(double f) {
+    Value v = {.tag = T_FLOAT};
+    v.as.f = f;
+    return v;
+}
+
+static Value vita_bool(int b) {
+    Value v = {.tag = T_BOOL};
+    v.as.b = b != 0;
+    return v;
+}
+
+static Value vita_str(const char *data, size_t len) {
+    Value v = {.tag = T_STR};
+    v.as.s = str_new(data, len);
+    return v;
+}
+
+static Value vita_array(size_t n, const Value *items) {
+    Array *a = vita_alloc(sizeof(Array));
+    a->len = n;
+    a->cap = n < 4 ? 4 : n;
+    a->items = vita_alloc(a->cap * sizeof(Value));
+    if (n) memcpy(a->items, items, n * sizeof(Value));
+    a->frozen = 0;
+    Value v = {.tag = T_ARRAY};
+    v.as.a = a;
+    return v;
+}
+
+static void array_push(Array *a, Value item) {
+    if (a->len == a->cap) {
+        a->cap *= 2;
+        a->items = realloc(a->items, a->cap * sizeof(Value));
+        if (!a->items) {
+            fputs("out of memory\n", stderr);
+            exit(1);
+        }
+    }
+    a->items[a->len++] = item;
+}
+
+static Value *vita_cell(void) {
+    Value *cell = vita_alloc(sizeof(Value));
+    cell->tag = T_UNDEF;
+    return cell;
+}
+
+static Value vita_closure(const char *name, int arity, Value (*fn)(Closure *, Value *), int (*resume)(struct Generator *, Value *), int ncells, Value **cells) {
+    Closure *c = vita_alloc(sizeof(Closure));
+    c->name = name;
+    c->arity = arity;
+    c->fn = fn;
+    c->resume = resume;
+    c->cells = NULL;
+    if (ncells) {
+        c->cells = vita_alloc((size_t)ncells * sizeof(Value *));
+        memcpy(c->cells, cells, (size_t)ncells * sizeof(Value *));
+    }
+    Value v = {.tag = T_FUNC};
+    v.as.fn = c;
+    return v;
+}
+
+static const char *type_name(Value v) {
+    switch (v.tag) {
+    case T_NULL: return "nothing";
+    case T_BOOL: return "bool";
+    case T_INT:
+    case T_FLOAT: return "number";
+    case T_STR: return "string";
+    case T_ARRAY: return "array";
+    case T_RANGE: return "range";
+    case T_FUNC: return "function";
+    case T_GEN: return "generator";
+    case T_ERROR: return "error";
+    default: return "nothing";
+    }
+}
+
+static int vita_truthy(Value v) {
+    switch (v.tag) {
+    case T_BOOL: return v.as.b;
+    case T_INT: return v.as.i != 0;
+    case T_FLOAT: return v.as.f != 0.0;
+    case T_STR: return v.as.s->len > 0;
+    case T_ARRAY: return v.as.a->len > 0;
+    case T_RANGE: return v.as.r.from < v.as.r.to;
+    case T_FUNC:
+    case T_GEN:
+    case T_ERROR: return 1;
+    default: return 0;
+    }
+}
+
+/* floats print the way Rust's `{:?}` does: the shortest digits that read back the same,
+   always with a fraction or an exponent */
+static void show_float(Buf *b, double x) {
+    if (isnan(x)) {
+        buf_puts(b, "NaN");
+        return;
+    }
+    if (isinf(x)) {
+        buf_puts(b, x > 0 ? "inf" : "-inf");
+        return;
+    }
+    if (x == 0) {
+        buf_puts(b, signbit(x) ? "-0.0" : "0.0");
+        return;
+    }
+
+    char tmp[64];
+    for (int prec = 1; prec <= 17; prec++) {
+        snprintf(tmp, sizeof tmp, "%.*e", prec - 1, x);
+        if (strtod(tmp, NULL) == x) break;
+    }
+
+    const char *p = tmp;
+    if (*p == '-') {
+        buf_puts(b, "-");
+        p++;
+    }
+    char digits[32];
+    int nd = 0;
+    for (; *p && *p != 'e'; p++) {
+        if (*p != '.') digits[nd++] = *p;
+    }
+    int exp = atoi(p + 1);
+    while (nd > 1 && digits[nd - 1] == '0') nd--;
+
+    double ax = fabs(x);
+    if (ax >= 1e-4 && ax < 1e16) {
+        if (exp >= 0) {
+            for (int i = 0; i <= exp; i++) buf_put(b, i < nd ? &digits[i] : "0", 1);
+            buf_puts(b, ".");
+            if (nd > exp + 1) buf_put(b, digits + exp + 1, (size_t)(nd - exp - 1));
+            else buf_puts(b, "0");
+        } else {
+            buf_puts(b, "0.");
+            for (int i = 0; i < -exp - 1; i++) buf_puts(b, "0");
+            buf_put(b, digits, (size_t)nd);
+        }
+    } else {
+        buf_put(b, digits, 1);
+        if (nd > 1) {
+            buf_puts(b, ".");
+            buf_put(b, digits + 1, (size_t)(nd - 1));
+        }
+        buf_printf(b, "e%d", exp);
+    }
+}
+
+static void show(Buf *b, Value v) {
+    switch (v.tag) {
+    case T_BOOL: buf_puts(b, v.as.b ? "true" : "false"); break;
+    case T_INT: buf_printf(b, "%" PRId64, v.as.i); break;
+    case T_FLOAT: show_float(b, v.as.f); break;
+    case T_STR: buf_put(b, v.as.s->data, v.as.s->len); break;
+    case T_ARRAY:
+        buf_puts(b, "[");
+        for (size_t i = 0; i < v.as.a->len; i++) {
+            if (i > 0) buf_puts(b, ", ");
+            show(b, v.as.a->items[i]);
+        }
+        buf_puts(b, "]");
+        break;
+    case T_RANGE: buf_printf(b, "%" PRId64 "..%" PRId64, v.as.r.from, v.as.r.to); break;
+    case T_FUNC: buf_printf(b, "<railway %s>", v.as.fn->name); break;
+    case T_GEN: buf_printf(b, "<generator %s>", v.as.g->name); break;
+    case T_ERROR:
+        buf_put(b, v.as.e->kind->data, v.as.e->kind->len);
+        buf_puts(b, ": ");
+        buf_put(b, v.as.e->message->data, v.as.e->message->len);
+        break;
+    default: buf_puts(b, "nothing"); break;
+    }
+}
+
+static Str *show_str(Value v) {
+    Buf b = {0};
+    show(&b, v);
+    Str *s = str_new(b.data ? b.data : "", b.len);
+    free(b.data);
+    return s;
+}
+
+/* errors */
+
+static int same_frame(Frame a, Frame b) {
+    return strcmp(a.name, b.name) == 0 && a.at.file == b.at.file && a.at.line == b.at.line && a.at.col == b.at.col;
+}
+
+static void report(Error *e) {
+    fflush(stdout);
+    if (strcmp(e->kind->data, "RuntimeError") == 0) {
+        fprintf(stderr, "error[E0301]: %s\n", e->message->data);
+    } else {
+        fprintf(stderr, "error[E0301]: %s: %s\n", e->kind->data, e->message->data);
+    }
+    fprintf(stderr, " --> %s:%d:%d\n", vita_sources[e->at.file], e->at.line, e->at.col);
+    for (size_t i = 0; i < e->trace_len;) {
+        Frame f = e->trace[i];
+        size_t repeats = 0;
+        for (i++; i < e->trace_len && same_frame(e->trace[i], f); i++) repeats++;
+        fprintf(stderr, "  = note: in `%s`, called at %d:%d\n", f.name, f.at.line, f.at.col);
+        if (repeats > 0) fprintf(stderr, "  = note: \342\200\246 repeated %zu more time%s\n", repeats, repeats == 1 ? "" : "s");
+    }
+}
+
+static void trace_push(Error *e, Frame frame) {
+    if (e->trace_len == e->trace_cap) {
+        e->trace_cap = e->trace_cap ? e->trace_cap * 2 : 8;
+        e->trace = realloc(e->trace, e->trace_cap * sizeof(Frame));
+        if (!e->trace) {
+            fputs("out of memory\n", stderr);
+            exit(1);
+        }
+    }
+    e->trace[e->trace_len++] = frame;
+}
+
+/* the error passes every call still on the stack on its way out; the ones under the handler
+   that catches it are taken off again, since it never got that far */
+static void vita_throw(Error *e) {
+    for (size_t i = vita_nframes; i > 0; i--) trace_push(e, vita_frames[i - 1]);
+    Handler *h = vita_handlers;
+    if (!h) {
+        report(e);
+        exit(1);
+    }
+    vita_handlers = h->prev;
+    e->trace_len -= h->frames;
+    vita_nframes = h->frames;
+    vita_depth = h->depth;
+    h->error = e;
+    longjmp(h->buf, 1);
+}
+
+static Error *error_new(const char *kind, Str *message, Loc at) {
+    Error *e = vita_alloc(sizeof(Error));
+    e->kind = str_new(kind, strlen(kind));
+    e->message = message;
+    e->at = at;
+    e->trace = NULL;
+    e->trace_len = e->trace_cap = 0;
+    return e;
+}
+
+static void vita_fail(Loc at, const char *fmt, ...) {
+    char tmp[1024];
+    va_list args;
+    va_start(args, fmt);
+    vsnprintf(tmp, sizeof tmp, fmt, args);
+    va_end(args);
+    vita_throw(error_new("RuntimeError", str_new(tmp, strlen(tmp)), at));
+}
+
+static void vita_throw_value(Value v, Loc at) {
+    if (v.tag == T_ERROR) {
+        Error *e = vita_alloc(sizeof(Error));
+        *e = *v.as.e;
+        e->trace = NULL;
+        e->trace_cap = 0;
+        e->trace_len = 0;
+        for (size_t i = 0; i < v.as.e->trace_len; i++) trace_push(e, v.as.e->trace[i]);
+        vita_throw(e);
+    }
+    if (v.tag == T_ARRAY && v.as.a->len == 2 && v.as.a->items[0].tag == T_STR) {
+        Error *e = error_new(v.as.a->items[0].as.s->data, show_str(v.as.a->items[1]), at);
+        vita_throw(e);
+    }
+    vita_throw(error_new("Error", show_str(v), at));
+}
+
+static Value vita_error_value(Error *e) {
+    Value v = {.tag = T_ERROR};
+    v.as.e = e;
+    return v;
+}
+
+#define vita_try(h) ((h)->prev = vita_handlers, (h)->frames = vita_nframes, (h)->depth = vita_depth, vita_handlers = (h))
+
+static void vita_untry(Handler *h) {
+    vita_handlers = h->prev;
+}
+
+static Value vita_get(Value v, const char *name, Loc at) {
+    if (v.tag == T_UNDEF) {
+        vita_fail(at, "undefined %s `%s`", name[0] == '$' ? "constant" : "variable", name);
+    }
+    return v;
+}
+
+/* arithmetic */
+
+static const char *overflow(const char *op) {
+    static char message[64];
+    snprintf(message, sizeof message, "arithmetic overflow in `%s`", op);
+    return message;
+}
+
+static const char *no_real_result(const char *op) {
+    static char message[64];
+    snprintf(message, sizeof message, "`%s` has no real result", op);
+    return message;
+}
+
+static int add_overflows(int64_t a, int64_t b, int64_t *r) {
+    if ((b > 0 && a > INT64_MAX - b) || (b < 0 && a < INT64_MIN - b)) return 1;
+    *r = a + b;
+    return 0;
+}
+
+static int sub_overflows(int64_t a, int64_t b, int64_t *r) {
+    if ((b < 0 && a > INT64_MAX + b) || (b > 0 && a < INT64_MIN + b)) return 1;
+    *r = a - b;
+    return 0;
+}
+
+static int mul_overflows(int64_t a, int64_t b, int64_t *r) {
+    if (a > 0) {
+        if (b > 0 ? a > INT64_MAX / b : b < INT64_MIN / a) return 1;
+    } else if (a < 0) {
+        if (b > 0 ? a < INT64_MIN / b : b < INT64_MAX / a) return 1;
+    }
+    *r = a * b;
+    return 0;
+}
+
+/* same steps as Rust's `checked_pow` */
+static int pow_overflows(int64_t base, uint32_t exp, int64_t *r) {
+    int64_t acc = 1;
+    if (exp == 0) {
+        *r = 1;
+        return 0;
+    }
+    while (exp > 1) {
+        if (exp & 1) {
+            if (mul_overflows(acc, base, &acc)) return 1;
+        }
+        exp /= 2;
+        if (mul_overflows(base, base, &base)) return 1;
+    }
+    return mul_overflows(acc, base, r);
+}
+
+static int as_float(Value v, double *f) {
+    if (v.tag == T_INT) *f = (double)v.as.i;
+    else if (v.tag == T_FLOAT) *f = v.as.f;
+    else return 0;
+    return 1;
+}
+
+static Value checked_float(const char *op, double f, Loc at) {
+    if (isnan(f)) vita_fail(at, "%s", no_real_result(op));
+    if (!isfinite(f)) vita_fail(at, "%s", overflow(op));
+    return vita_float(f);
+}
+
+static Value tetrate_int(int64_t base, int64_t height, Loc at) {
+    if (height < 0) vita_fail(at, "cannot tetrate to a negative height");
+    if ((base == 0 || base == -1) && height > 3) height = 2 + height % 2;
+    int64_t result = 1;
+    for (int64_t i = 0; i < height; i++) {
+        int64_t next;
+        if (result < 0 || result > UINT32_MAX || pow_overflows(base, (uint32_t)result, &next)) {
+            vita_fail(at, "%s", overflow("^^"));
+        }
+        if (next == result) break;
+        result = next;
+    }
+    return vita_int(result);
+}
+
+static Value tetrate_float(double base, double height, Loc at) {
+    if (height != trunc(height)) vita_fail(at, "tetration height must be a whole number");
+    if (height < 0) vita_fail(at, "cannot tetrate to a negative height");
+    double result = 1.0;
+    for (int64_t i = 0; i < (int64_t)height; i++) {
+        double next = pow(base, result);
+        if (isnan(next)) vita_fail(at, "%s", no_real_result("^^"));
+        if (!isfinite(next)) vita_fail(at, "%s", overflow("^^"));
+        if (next == result) break;
+        result = next;
+    }
+    return vita_float(result);
+}
+
+static Value arithmetic(const char *op, Value l, Value r, Loc at) {
+    if (l.tag == T_INT && r.tag == T_INT) {
+        int64_t a = l.as.i, b = r.as.i, out;
+        int failed = 1;
+        switch (op[0]) {
+        case '+': failed = add_overflows(a, b, &out); break;
+        case '-': failed = sub_overflows(a, b, &out); break;
+        case '*': failed = mul_overflows(a, b, &out); break;
+        case '/':
+            if (b == 0) vita_fail(at, "division by zero");
+            if (b != -1 && a % b != 0) return checked_float(op, (double)a / (double)b, at);
+            failed = a == INT64_MIN && b == -1;
+            if (!failed) out = a / b;
+            break;
+        case '^':
+            if (op[1] == '^') return tetrate_int(a, b, at);
+            if (b < 0) {
+                if (a == 0) vita_fail(at, "division by zero");
+                return checked_float(op, pow((double)a, (double)b), at);
+            }
+            failed = b > UINT32_MAX || pow_overflows(a, (uint32_t)b, &out);
+            break;
+        }
+        if (failed) vita_fail(at, "%s", overflow(op));
+        return vita_int(out);
+    }
+
+    double a, b;
+    if (!as_float(l, &a) || !as_float(r, &b)) {
+        vita_fail(at, "cannot apply `%s` to %s and %s", op, type_name(l), type_name(r));
+    }
+    if (strcmp(op, "+") == 0) return checked_float(op, a + b, at);
+    if (strcmp(op, "-") == 0) return checked_float(op, a - b, at);
+    if (strcmp(op, "*") == 0) return checked_float(op, a * b, at);
+    if (strcmp(op, "/") == 0) {
+        if (b == 0.0) vita_fail(at, "division by zero");
+        return checked_float(op, a / b, at);
+    }
+    if (strcmp(op, "^") == 0) {
+        if (a == 0.0 && b < 0.0) vita_fail(at, "division by zero");
+        return checked_float(op, pow(a, b), at);
+    }
+    if (strcmp(op, "^^") == 0) return tetrate_float(a, b, at);
+    vita_fail(at, "unknown binary operator `%s`", op);
+    return NIL;
+}
+
+static Value concat(Array *a, Array *b) {
+    Value v = vita_array(a->len, a->items);
+    for (size_t i = 0; i < b->len; i++) array_push(v.as.a, b->items[i]);
+    return v;
+}
+
+static Value vita_add(Value l, Value r, Loc at) {
+    if (l.tag == T_STR && r.tag == T_STR) {
+        Value v = {.tag = T_STR};
+        v.as.s = vita_alloc(sizeof(Str) + l.as.s->len + r.as.s->len + 1);
+        v.as.s->len = l.as.s->len + r.as.s->len;
+        memcpy(v.as.s->data, l.as.s->data, l.as.s->len);
+        memcpy(v.as.s->data + l.as.s->len, r.as.s->data, r.as.s->len + 1);
+        return v;
+    }
+    if (l.tag == T_ARRAY && r.tag == T_ARRAY) return concat(l.as.a, r.as.a);
+    return arithmetic("+", l, r, at);
+}
+
+static Value vita_sub(Value l, Value r, Loc at) { return arithmetic("-", l, r, at); }
+static Value vita_mul(Value l, Value r, Loc at) { return arithmetic("*", l, r, at); }
+static Value vita_div(Value l, Value r, Loc at) { return arithmetic("/", l, r, at); }
+static Value vita_pow(Value l, Value r, Loc at) { return arithmetic("^", l, r, at); }
+static Value vita_tetrate(Value l, Value r, Loc at) { return arithmetic("^^", l, r, at); }
+
+/* -1, 0 or 1, or 2 when the two cannot be ordered (a NaN) */
+static int compare(const char *op, Value l, Value r, Loc at) {
+    if (l.tag == T_STR && r.tag == T_STR) {
+        size_t n = l.as.s->len < r.as.s->len ? l.as.s->len : r.as.s->len;
+        int c = memcmp(l.as.s->data, r.as.s->data, n);
+        if (c == 0) return l.as.s->len < r.as.s->len ? -1 : l.as.s->len > r.as.s->len;
+        return c < 0 ? -1 : 1;
+    }
+    if (l.tag == T_INT && r.tag == T_INT) return l.as.i < r.as.i ? -1 : l.as.i > r.as.i;
+    double a, b;
+    if (!as_float(l, &a) || !as_float(r, &b)) {
+        vita_fail(at, "cannot apply `%s` to %s and %s", op, type_name(l), type_name(r));
+    }
+    if (isnan(a) || isnan(b)) return 2;
+    return a < b ? -1 : a > b;
+}
+
+static Value vita_lt(Value l, Value r, Loc at) { int c = compare("<", l, r, at); return vita_bool(c == -1); }
+static Value vita_gt(Value l, Value r, Loc at) { int c = compare(">", l, r, at); return vita_bool(c == 1); }
+static Value vita_le(Value l, Value r, Loc at) { int c = compare("\342\211\244", l, r, at); return vita_bool(c == -1 || c == 0); }
+static Value vita_ge(Value l, Value r, Loc at) { int c = compare("\342\211\245", l, r, at); return vita_bool(c == 1 || c == 0); }
+
+static Value vita_range(Value l, Value r, Loc at) {
+    if (l.tag != T_INT || r.tag != T_INT) {
+        vita_fail(at, "cannot make a range from %s to %s", type_name(l), type_name(r));
+    }
+    Value v = {.tag = T_RANGE};
+    v.as.r.from = l.as.i;
+    v.as.r.to = r.as.i;
+    return v;
+}
+
+static int values_equal(Value l, Value r) {
+    if (l.tag == T_ARRAY && r.tag == T_ARRAY) {
+        if (l.as.a->len != r.as.a->len) return 0;
+        for (size_t i = 0; i < l.as.a->len; i++) {
+            if (!values_equal(l.as.a->items[i], r.as.a->items[i])) return 0;
+        }
+        return 1;
+    }
+    if (l.tag == r.tag) {
+        switch (l.tag) {
+        case T_NULL: return 1;
+        case T_BOOL: return l.as.b == r.as.b;
+        case T_INT: return l.as.i == r.as.i;
+        case T_STR: return l.as.s->len == r.as.s->len && memcmp(l.as.s->data, r.as.s->data, l.as.s->len) == 0;
+        case T_RANGE: return l.as.r.from == r.as.r.from && l.as.r.to == r.as.r.to;
+        case T_FUNC: return l.as.fn == r.as.fn;
+        case T_GEN: return l.as.g == r.as.g;
+        case T_ERROR: return l.as.e == r.as.e;
+        default: break;
+        }
+    }
+    double a, b;
+    return as_float(l, &a) && as_float(r, &b) && a == b;
+}
+
+static Value vita_eq(Value l, Value r, Loc at) {
+    (void)at;
+    return vita_bool(values_equal(l, r));
+}
+
+static Value vita_unknown_binary(const char *op, Value l, Value r, Loc at) {
+    (void)l;
+    (void)r;
+    vita_fail(at, "unknown binary operator `%s`", op);
+    return NIL;
+}
+
+static Value vita_not(Value v, Loc at) {
+    (void)at;
+    return vita_bool(!vita_truthy(v));
+}
+
+static Value vita_truth(Value v, Loc at) {
+    (void)at;
+    return vita_bool(vita_truthy(v));
+}
+
+static Value vita_neg(Value v, Loc at) {
+    if (v.tag == T_INT) {
+        if (v.as.i == INT64_MIN) vita_fail(at, "%s", overflow("-"));
+        return vita_int(-v.as.i);
+    }
+    if (v.tag == T_FLOAT) return vita_float(-v.as.f);
+    vita_fail(at, "cannot apply `-` to %s", type_name(v));
+    return NIL;
+}
+
+static Value vita_inc(Value v, Loc at) {
+    if (v.tag == T_INT) {
+        int64_t out;
+        if (add_overflows(v.as.i, 1, &out)) vita_fail(at, "%s", overflow("++"));
+        return vita_int(out);
+    }
+    if (v.tag == T_FLOAT) return checked_float("++", v.as.f + 1.0, at);
+    vita_fail(at, "cannot apply `++` to %s", type_name(v));
+    return NIL;
+}
+
+static Value vita_unknown_unary(const char *op, Value v, Loc at) {
+    (void)v;
+    vita_fail(at, "unknown unary operator `%s`", op);
+    return NIL;
+}
+
+/* strings are UTF-8 and indexed by character */
+
+static size_t char_count(Str *s) {
+    size_t n = 0;
+    for (size_t i = 0; i < s->len; i++) {
+        if (((unsigned char)s->data[i] & 0xC0) != 0x80) n++;
+    }
+    return n;
+}
+
+/* byte offset of character `index`, or the length for one past the end */
+static size_t char_offset(Str *s, size_t index) {
+    size_t n = 0;
+    for (size_t i = 0; i < s->len; i++) {
+        if (((unsigned char)s->data[i] & 0xC0) != 0x80) {
+            if (n == index) return i;
+            n++;
+        }
+    }
+    return s->len;
+}
+
+static Value substring(Str *s, size_t from, size_t to) {
+    size_t start = char_offset(s, from);
+    size_t end = char_offset(s, to);
+    return vita_str(s->data + start, end - start);
+}
+
+static int64_t as_index(Value v, Loc at) {
+    if (v.tag != T_INT) vita_fail(at, "cannot index with %s", type_name(v));
+    return v.as.i;
+}
+
+static int resolve_index(int64_t index, size_t len, size_t *out) {
+    int64_t resolved = index < 0 ? (int64_t)len + index : index;
+    if (resolved < 0 || resolved >= (int64_t)len) return 0;
+    *out = (size_t)resolved;
+    return 1;
+}
+
+static Value vita_index(Value target, Value index, Loc at) {
+    if (target.tag == T_ERROR && index.tag == T_STR) {
+        Error *e = target.as.e;
+        const char *field = index.as.s->data;
+        if (strcmp(field, "kind") == 0) return (Value){.tag = T_STR, .as.s = e->kind};
+        if (strcmp(field, "message") == 0) return (Value){.tag = T_STR, .as.s = e->message};
+        if (strcmp(field, "line") == 0) return vita_int(e->at.line);
+        if (strcmp(field, "column") == 0) return vita_int(e->at.col);
+        vita_fail(at, "errors have no `%s`; try `kind`, `message`, `line` or `column`", field);
+    }
+
+    int64_t i = as_index(index, at);
+    size_t resolved;
+    if (target.tag == T_ARRAY) {
+        if (!resolve_index(i, target.as.a->len, &resolved)) {
+            vita_fail(at, "index %" PRId64 " is out of range for an array of length %zu", i, target.as.a->len);
+        }
+        return target.as.a->items[resolved];
+    }
+    if (target.tag == T_STR) {
+        size_t len = char_count(target.as.s);
+        if (!resolve_index(i, len, &resolved)) {
+            vita_fail(at, "index %" PRId64 " is out of range for a string of length %zu", i, len);
+        }
+        return substring(target.as.s, resolved, resolved + 1);
+    }
+    vita_fail(at, "cannot index into %s", type_name(target));
+    return NIL;
+}
+
+static size_t slice_bound(Value v, size_t otherwise, size_t len, Loc at) {
+    if (v.tag == T_UNDEF) return otherwise;
+    int64_t i = as_index(v, at);
+    if (i < 0) i += (int64_t)len;
+    if (i < 0) return 0;
+    return (size_t)i > len ? len : (size_t)i;
+}
+
+/* a missing bound is passed as an undefined value */
+static Value vita_slice(Value target, Value from, Value to, Loc at) {
+    size_t len;
+    if (target.tag == T_ARRAY) len = target.as.a->len;
+    else if (target.tag == T_STR) len = char_count(target.as.s);
+    else {
+        vita_fail(at, "cannot slice %s", type_name(target));
+        return NIL;
+    }
+    size_t start = slice_bound(from, 0, len, at);
+    size_t end = slice_bound(to, len, len, at);
+    if (end < start) end = start;
+    if (target.tag == T_ARRAY) return vita_array(end - start, target.as.a->items + start);
+    return substring(target.as.s, start, end);
+}
+
+static Value vita_interpolate(int n, const Value *parts) {
+    Buf b = {0};
+    for (int i = 0; i < n; i++) show(&b, parts[i]);
+    Value v = vita_str(b.data ? b.data : "", b.len);
+    free(b.data);
+    return v;
+}
+
+static Value vita_freeze(Value v) {
+    if (v.tag == T_ARRAY && !v.as.a->frozen) {
+        v.as.a->frozen = 1;
+        for (size_t i = 0; i < v.as.a->len; i++) vita_freeze(v.as.a->items[i]);
+    }
+    return v;
+}
+
+static Value vita_echo(Value v) {
+    Buf b = {0};
+    show(&b, v);
+    buf_puts(&b, "\n");
+    fputs(b.data, stdout);
+    free(b.data);
+    return v;
+}
+
+/* calls */
+
+static int vita_resume(Generator *g, Value *out, Loc at);
+
+static void expect_args(const char *name, int argc, int count, Loc at) {
+    if (argc != count) vita_fail(at, "`%s` takes %d argument(s) but %d were given", name, count, argc);
+}
+
+static Value vita_builtin(const char *name, int argc, Value *args, Loc at) {
+    if ((strcmp(name, "push") == 0 || strcmp(name, "pop") == 0) && argc > 0 && args[0].tag == T_ARRAY && args[0].as.a->frozen) {
+        vita_fail(at, "cannot `%s` on an array held by a constant", name);
+    }
+    if (strcmp(name, "next") == 0) {
+        if (argc != 1) vita_fail(at, "`next` takes 1 argument(s) but %d were given", argc);
+        if (args[0].tag != T_GEN) vita_fail(at, "`next` expects a generator, not %s", type_name(args[0]));
+        Value item;
+        return vita_resume(args[0].as.g, &item, at) ? item : NIL;
+    }
+    if (strcmp(name, "print") == 0) {
+        Buf b = {0};
+        for (int i = 0; i < argc; i++) {
+            if (i > 0) buf_puts(&b, " ");
+            show(&b, args[i]);
+        }
+        buf_puts(&b, "\n");
+        fputs(b.data, stdout);
+        free(b.data);
+        return NIL;
+    }
+    if (strcmp(name, "len") == 0) {
+        expect_args(name, argc, 1, at);
+        Value v = args[0];
+        if (v.tag == T_ARRAY) return vita_int((int64_t)v.as.a->len);
+        if (v.tag == T_STR) return vita_int((int64_t)char_count(v.as.s));
+        if (v.tag == T_RANGE) {
+            int64_t n;
+            if (sub_overflows(v.as.r.to, v.as.r.from, &n)) n = v.as.r.to > v.as.r.from ? INT64_MAX : INT64_MIN;
+            return vita_int(n > 0 ? n : 0);
+        }
+        vita_fail(at, "`len` expects an array, a string or a range, not %s", type_name(v));
+    }
+    if (strcmp(name, "push") == 0) {
+        if (argc == 0) vita_fail(at, "`push` needs an array to push onto");
+        if (args[0].tag != T_ARRAY) vita_fail(at, "`push` expects an array, not %s", type_name(args[0]));
+        for (int i = 1; i < argc; i++) array_push(args[0].as.a, args[i]);
+        return args[0];
+    }
+    if (strcmp(name, "pop") == 0) {
+        expect_args(name, argc, 1, at);
+        if (args[0].tag != T_ARRAY) vita_fail(at, "`pop` expects an array, not %s", type_name(args[0]));
+        if (args[0].as.a->len == 0) vita_fail(at, "cannot `pop` from an empty array");
+        return args[0].as.a->items[--args[0].as.a->len];
+    }
+    if (strcmp(name, "concat") == 0) {
+        Value out = vita_array(0, NULL);
+        for (int i = 0; i < argc; i++) {
+            if (args[i].tag == T_ARRAY) {
+                for (size_t j = 0; j < args[i].as.a->len; j++) array_push(out.as.a, args[i].as.a->items[j]);
+            } else {
+                array_push(out.as.a, args[i]);
+            }
+        }
+        return out;
+    }
+    vita_fail(at, "unknown function `%s`", name);
+    return NIL;
+}
+
+static void frame_push(const char *name, Loc at) {
+    if (vita_nframes == vita_frames_cap) {
+        vita_frames_cap = vita_frames_cap ? vita_frames_cap * 2 : 64;
+        vita_frames = realloc(vita_frames, vita_frames_cap * sizeof(Frame));
+        if (!vita_frames) {
+            fputs("out of memory\n", stderr);
+            exit(1);
+        }
+    }
+    vita_frames[vita_nframes].name = name;
+    vita_frames[vita_nframes].at = at;
+    vita_nframes++;
+}
+
+/* generators */
+
+static Value vita_generator(Closure *f, Value *args) {
+    Generator *g = vita_alloc(sizeof(Generator));
+    g->name = f->name;
+    g->fn = f;
+    g->args = NULL;
+    if (f->arity) {
+        g->args = vita_alloc((size_t)f->arity * sizeof(Value));
+        memcpy(g->args, args, (size_t)f->arity * sizeof(Value));
+    }
+    g->state = GEN_READY;
+    g->at = 0;
+    g->frame = NULL;
+    Value v = {.tag = T_GEN};
+    v.as.g = g;
+    return v;
+}
+
+/* run a generator to its next `anywho`; false once it has finished. Failing finishes it too,
+   and the error goes on from here once the generator has been marked as done */
+static int vita_resume(Generator *g, Value *out, Loc at) {
+    if (g->state == GEN_DONE) return 0;
+    if (g->state == GEN_RUNNING) vita_fail(at, "generator `%s` is already running", g->name);
+    if (vita_depth >= MAX_CALL_DEPTH) vita_fail(at, "too many nested calls (last was to `%s`)", g->name);
+    Handler boundary;
+    vita_try(&boundary);
+    if (setjmp(boundary.buf)) {
+        g->state = GEN_DONE;
+        free(g->frame);
+        g->frame = NULL;
+        vita_throw(boundary.error);
+    }
+    g->state = GEN_RUNNING;
+    vita_depth++;
+    frame_push(g->name, at);
+    int more = g->fn->resume(g, out);
+    vita_nframes--;
+    vita_depth--;
+    vita_untry(&boundary);
+    if (!more) {
+        g->state = GEN_DONE;
+        free(g->frame);
+        g->frame = NULL;
+        return 0;
+    }
+    g->state = GEN_READY;
+    return 1;
+}
+
+/* a name bound to a railway calls it; anything else falls back to the builtin of that name */
+static Value vita_call(Value callee, const char *name, int argc, Value *args, Loc at) {
+    if (callee.tag != T_FUNC) return vita_builtin(name, argc, args, at);
+    Closure *f = callee.as.fn;
+    if (argc != f->arity) {
+        vita_fail(at, "`%s` takes %d argument(s) but %d were given", f->name, f->arity, argc);
+    }
+    if (vita_depth >= MAX_CALL_DEPTH) vita_fail(at, "too many nested calls (last was to `%s`)", f->name);
+    if (f->resume) return vita_generator(f, args);
+    vita_depth++;
+    frame_push(f->name, at);
+    Value result = f->fn(f, args);
+    vita_nframes--;
+    vita_depth--;
+    return result;
+}
+
+/* a `break` or `continue` that leaves the railway or operator it is in fails at the call */
+static Value vita_escaped(int railway) {
+    Frame frame = vita_frames[--vita_nframes];
+    if (railway) {
+        vita_depth--;
+        vita_fail(frame.at, "loop control escaped the body of `%s`", frame.name);
+    }
+    vita_fail(frame.at, "loop control escaped the body of operator `%s`", frame.name);
+    return NIL;
+}
+
+static Value vita_apply(Value (*op)(Value *), const char *name, Value *args, Loc at) {
+    frame_push(name, at);
+    Value result = op(args);
+    vita_nframes--;
+    return result;
+}
+
+/* `lolsie` sources; arrays are read live so pushing while looping extends the loop */
+
+enum { ITER_ARRAY, ITER_COUNT, ITER_RANGE, ITER_GEN };
+
+static void vita_iter_count(Iter *it, int64_t count) {
+    it->kind = ITER_COUNT;
+    it->index = 0;
+    it->count = count;
+}
+
+static void vita_iter_start(Iter *it, Value source, Loc at) {
+    it->index = 0;
+    it->at = at;
+    if (source.tag == T_STR) {
+        Str *s = source.as.s;
+        Value chars = vita_array(0, NULL);
+        size_t n = char_count(s);
+        for (size_t i = 0; i < n; i++) array_push(chars.as.a, substring(s, i, i + 1));
+        source = chars;
+    } else if (source.tag == T_FLOAT) {
+        double f = source.as.f;
+        if (f != trunc(f) || !(fabs(f) < 9223372036854775807.0)) {
+            Buf b = {0};
+            show_float(&b, f);
+            vita_fail(at, "cannot loop %s times", b.data);
+        }
+        source = vita_int((int64_t)f);
+    }
+
+    switch (source.tag) {
+    case T_ARRAY:
+        it->kind = ITER_ARRAY;
+        it->source = source;
+        break;
+    case T_INT:
+        vita_iter_count(it, source.as.i);
+        break;
+    case T_RANGE:
+        it->kind = ITER_RANGE;
+        it->source = source;
+        break;
+    case T_GEN:
+        it->kind = ITER_GEN;
+        it->source = source;
+        break;
+    default:
+        vita_fail(at, "cannot loop over %s", type_name(source));
+    }
+}
+
+static int vita_iter_next(Iter *it) {
+    int64_t i = it->index++;
+    switch (it->kind) {
+    case ITER_ARRAY:
+        if ((size_t)i >= it->source.as.a->len) return 0;
+        it->item = it->source.as.a->items[i];
+        return 1;
+    case ITER_COUNT:
+        if (i >= it->count) return 0;
+        it->item = vita_int(i);
+        return 1;
+    case ITER_GEN:
+        return vita_resume(it->source.as.g, &it->item, it->at);
+    default: {
+        int64_t item;
+        if (add_overflows(it->source.as.r.from, i, &item) || item >= it->source.as.r.to) return 0;
+        it->item = vita_int(item);
+        return 1;
+    }
+    }
+}
+
+static Value vita_str_lit(const char *data, size_t len) {
+    return vita_str(data, len);
+}
+
+static void vita_print_result(Value v) {
+    if (v.tag != T_NULL && v.tag != T_UNDEF) vita_echo(v);
+}
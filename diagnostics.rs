@@ -0,0 +1,167 @@
+use std::fmt;
+
+use crate::lexer::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    fn colour(&self) -> &'static str {
+        match self {
+            Severity::Error => "\x1b[31m",
+            Severity::Warning => "\x1b[33m",
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+    pub primary: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            code,
+            message: message.into(),
+            labels: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn error(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, code, message)
+    }
+
+    pub fn warning(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(Severity::Warning, code, message)
+    }
+
+    pub fn with_primary(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label { span, message: message.into(), primary: true });
+        self
+    }
+
+    pub fn with_secondary(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label { span, message: message.into(), primary: false });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    pub fn primary_span(&self) -> Option<Span> {
+        self.labels.iter().find(|l| l.primary).map(|l| l.span)
+    }
+
+    // colour is only for a terminal, so redirected output doesn't fill up with escape codes
+    pub fn render(&self, source: &str, file: &str, colour: bool) -> String {
+        let paint = |code: &'static str| if colour { code } else { "" };
+        let mut out = format!(
+            "{}{}[{}]{}: {}\n",
+            paint(self.severity.colour()),
+            self.severity,
+            self.code,
+            paint("\x1b[0m"),
+            self.message
+        );
+
+        let mut labels: Vec<&Label> = self.labels.iter().collect();
+        labels.sort_by_key(|l| (!l.primary, l.span.start));
+
+        let gutter = labels
+            .iter()
+            .map(|l| l.span.line.to_string().len())
+            .max()
+            .unwrap_or(1);
+        let pad = " ".repeat(gutter);
+
+        if let Some(first) = labels.first() {
+            out.push_str(&format!("{}--> {}:{}\n", pad, file, first.span));
+        }
+
+        labels.sort_by_key(|l| l.span.start);
+        let mut last_line = None;
+        for label in labels {
+            let start = label.span.start.min(source.len());
+            let line_start = source[..start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+            let line_end = source[line_start..].find('\n').map(|i| line_start + i).unwrap_or(source.len());
+            let line_text = source[line_start..line_end].trim_end_matches('\r');
+            let underline_end = label.span.end.clamp(start, line_end);
+            let width = source
+                .get(start..underline_end)
+                .map(|s| s.chars().count())
+                .unwrap_or(0)
+                .max(1);
+
+            let indent: String = source[line_start..start]
+                .chars()
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+            let marker = if label.primary { "^" } else { "-" };
+            let highlight = if label.primary { self.severity.colour() } else { "\x1b[34m" };
+
+            if last_line != Some(label.span.line) {
+                out.push_str(&format!("{} |\n", pad));
+                out.push_str(&format!("{:>width$} | {}\n", label.span.line, line_text, width = gutter));
+                last_line = Some(label.span.line);
+            }
+            out.push_str(&format!(
+                "{} | {}{}{} {}{}\n",
+                pad,
+                indent,
+                paint(highlight),
+                marker.repeat(width),
+                label.message,
+                paint("\x1b[0m")
+            ));
+        }
+
+        for note in &self.notes {
+            out.push_str(&format!("{} = note: {}\n", pad, note));
+        }
+
+        out
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.primary_span() {
+            Some(span) => write!(f, "{}[{}] at {}: {}", self.severity, self.code, span, self.message),
+            None => write!(f, "{}[{}]: {}", self.severity, self.code, self.message),
+        }
+    }
+}
+
+impl std::error::Error for Diagnostic {}
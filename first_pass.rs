@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 
-use crate::lexer::{self, TokenType, TokenValue};
+use crate::diagnostics::Diagnostic;
+use crate::lexer::{self, Span, TokenType, TokenValue};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum OperatorKind {
@@ -9,29 +10,61 @@ pub enum OperatorKind {
     Binary,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Associativity {
+    Left,
+    Right,
+    None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Precedence {
+    pub level: usize,
+    pub assoc: Associativity,
+}
+
+pub type PrecedenceMap = HashMap<String, Precedence>;
+
+impl OperatorKind {
+    pub fn params(&self) -> &'static [&'static str] {
+        match self {
+            OperatorKind::Prefix | OperatorKind::Postfix => &["x"],
+            OperatorKind::Binary => &["x", "y"],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct OperatorDef {
     pub op: String,
-    pub func: Option<String>,
+    pub func: Option<Vec<lexer::Token>>,
     pub precedence: usize,
     pub kind: OperatorKind,
+    pub assoc: Associativity,
+    pub span: Span,
+    pub exported: bool,
 }
 
 
-fn default_precedence_map() -> HashMap<String, usize> {
-    let mut precedence_map: HashMap<String, usize> = HashMap::new();
+fn default_precedence_map() -> PrecedenceMap {
+    let mut precedence_map: PrecedenceMap = HashMap::new();
+
+    let mut insert = |op: &str, level: usize, assoc: Associativity| {
+        precedence_map.insert(op.to_string(), Precedence { level, assoc });
+    };
 
-    precedence_map.insert("^^".to_string(), 4);
-    precedence_map.insert("^".to_string(), 3);
-    precedence_map.insert("*".to_string(), 2);
-    precedence_map.insert("/".to_string(), 2);
-    precedence_map.insert("+".to_string(), 1);
-    precedence_map.insert("-".to_string(), 1);
-    precedence_map.insert("<".to_string(), 0);
-    precedence_map.insert(">".to_string(), 0);
-    precedence_map.insert("=".to_string(), 0);
-    precedence_map.insert("≥".to_string(), 0);
-    precedence_map.insert("≤".to_string(), 0);
+    insert("^^", 4, Associativity::Right);
+    insert("^", 3, Associativity::Right);
+    insert("*", 2, Associativity::Left);
+    insert("/", 2, Associativity::Left);
+    insert("+", 1, Associativity::Left);
+    insert("-", 1, Associativity::Left);
+    insert("<", 0, Associativity::None);
+    insert(">", 0, Associativity::None);
+    insert("=", 0, Associativity::None);
+    insert("≥", 0, Associativity::None);
+    insert("≤", 0, Associativity::None);
+    insert("..", 0, Associativity::None);
 
     precedence_map
 }
@@ -41,20 +74,24 @@ fn default_precedence_map() -> HashMap<String, usize> {
 
 pub fn run(
     tokens: Vec<lexer::Token>,
-) -> (Vec<lexer::Token>, HashMap<String, usize>, Vec<OperatorDef>) {
+) -> Result<(Vec<lexer::Token>, PrecedenceMap, Vec<OperatorDef>), Diagnostic> {
     let mut filtered = Vec::with_capacity(tokens.len());
     let mut precedence_map = default_precedence_map();
     let mut defs = Vec::<OperatorDef>::new();
 
     let mut i = 0;
     while i < tokens.len() {
-        if let Some(op_def) = maybe_parse_op_def(&tokens, i) {
-            
-            precedence_map.insert(op_def.op.clone(), op_def.precedence);
+        let exported = tokens[i]._type == TokenType::Export;
+        let def_at = if exported { i + 1 } else { i };
+
+        if let Some((mut op_def, next)) = maybe_parse_op_def(&tokens, def_at)? {
+            op_def.exported = exported;
+
+            precedence_map.insert(op_def.op.clone(), Precedence { level: op_def.precedence, assoc: op_def.assoc });
             defs.push(op_def);
 
             
-            i = skip_until_end_of_assign(&tokens, i + 1);
+            i = next;
             continue;
         }
 
@@ -62,47 +99,94 @@ pub fn run(
         i += 1;
     }
 
-    (filtered, precedence_map, defs)
+    Ok((filtered, precedence_map, defs))
 }
 
-fn skip_until_end_of_assign(tokens: &[lexer::Token], mut i: usize) -> usize {
-    while i < tokens.len() {
-        if tokens[i]._type == TokenType::EndOfAssign {
-            return i + 1;
+fn collect_body(tokens: &[lexer::Token], mut cursor: usize) -> Result<(Vec<lexer::Token>, usize), Diagnostic> {
+    let open = &tokens[cursor];
+    let mut depth = 0usize;
+    let mut body = Vec::new();
+
+    while let Some(tok) = tokens.get(cursor) {
+        match tok._type {
+            TokenType::LeftCurly => depth += 1,
+            TokenType::RightCurly => depth -= 1,
+            TokenType::EOF => break,
+            _ => {}
+        }
+        body.push(tok.clone());
+        cursor += 1;
+
+        if depth == 0 {
+            return Ok((body, cursor));
         }
-        i += 1;
     }
-    i
+
+    Err(Diagnostic::error("E0103", "operator body is never closed")
+        .with_primary(open.span, "this `{` has no matching `}`"))
 }
 
-fn maybe_parse_op_def(tokens: &[lexer::Token], i: usize) -> Option<OperatorDef> {
+fn bind_params(body: Vec<lexer::Token>, kind: &OperatorKind) -> Vec<lexer::Token> {
+    let params = kind.params();
+    let eof_span = body.last().map(|t| t.span).unwrap_or_default();
+
+    let mut bound: Vec<lexer::Token> = body
+        .into_iter()
+        .map(|tok| match &tok.value {
+            Some(TokenValue::Str(s)) if tok._type == TokenType::String && params.contains(&s.as_str()) => {
+                lexer::Token::new(TokenType::Variable, tok.value.clone(), tok.span)
+            }
+            _ => tok,
+        })
+        .collect();
+
+    bound.push(lexer::Token::new(TokenType::EOF, Some(TokenValue::Str("EOF".to_string())), eof_span));
+    bound
+}
+
+fn expect_spec_token(tokens: &[lexer::Token], cursor: usize, ty: TokenType, what: &str) -> Result<(), Diagnostic> {
+    match tokens.get(cursor) {
+        Some(tok) if tok._type == ty => Ok(()),
+        Some(tok) => Err(Diagnostic::error("E0102", format!("malformed operator specification: expected {}", what))
+            .with_primary(tok.span, format!("found {}", tok._type.describe()))
+            .with_note("operators are defined as `{{body}, precedence, kind, associativity}`")),
+        None => Err(Diagnostic::error("E0102", "malformed operator specification")
+            .with_primary(tokens.last().map(|t| t.span).unwrap_or_default(), "the file ends here")),
+    }
+}
+
+fn maybe_parse_op_def(tokens: &[lexer::Token], i: usize) -> Result<Option<(OperatorDef, usize)>, Diagnostic> {
     
-    let t = tokens.get(i)?;
+    let t = match tokens.get(i) {
+        Some(t) => t,
+        None => return Ok(None),
+    };
     if t._type != TokenType::Define {
-        return None;
+        return Ok(None);
     }
 
-    let op_token = tokens.get(i + 1)?;
-    let assign_token = tokens.get(i + 2)?;
+    let (op_token, assign_token) = match (tokens.get(i + 1), tokens.get(i + 2)) {
+        (Some(op), Some(assign)) => (op, assign),
+        _ => return Ok(None),
+    };
     let op_is_name = matches!(op_token._type, TokenType::BinaryOperator);
     if !op_is_name || assign_token._type != TokenType::Assign {
-        return None;
+        return Ok(None);
     }
 
     let mut cursor = i + 3;
 
     
-    let (func, precedence, kind, _consumed) =
+    let (func, precedence, kind, assoc) =
         if matches!(tokens.get(cursor), Some(tok) if tok._type == TokenType::LeftCurly) {
             cursor += 1;
-            let func = match tokens.get(cursor) {
-                Some(lexer::Token {
-                    value: Some(TokenValue::Str(s)),
-                    ..
-                }) => Some(s.clone()),
-                _ => None,
+            let func = if matches!(tokens.get(cursor), Some(tok) if tok._type == TokenType::LeftCurly) {
+                let (body, next) = collect_body(tokens, cursor)?;
+                cursor = next;
+                Some(body)
+            } else {
+                None
             };
-            cursor += 1; 
 
             if matches!(tokens.get(cursor), Some(tok) if tok._type == TokenType::Comma) {
                 cursor += 1;
@@ -113,10 +197,28 @@ fn maybe_parse_op_def(tokens: &[lexer::Token], i: usize) -> Option<OperatorDef>
                     value: Some(TokenValue::Num(n)),
                     ..
                 }) => *n,
+                Some(lexer::Token {
+                    value: Some(TokenValue::Int(n)),
+                    span,
+                    ..
+                }) => match usize::try_from(*n) {
+                    Ok(n) => n,
+                    Err(_) => {
+                        return Err(Diagnostic::error("E0102", format!("invalid operator precedence `{}`", n))
+                            .with_primary(*span, "precedence cannot be negative"));
+                    }
+                },
                 Some(lexer::Token {
                     value: Some(TokenValue::Str(s)),
+                    span,
                     ..
-                }) => s.parse().unwrap_or(0),
+                }) => match s.parse() {
+                    Ok(n) => n,
+                    Err(_) => {
+                        return Err(Diagnostic::error("E0102", format!("invalid operator precedence `{}`", s))
+                            .with_primary(*span, "expected a whole number"));
+                    }
+                },
                 _ => 0,
             };
             cursor += 1;
@@ -142,17 +244,50 @@ fn maybe_parse_op_def(tokens: &[lexer::Token], i: usize) -> Option<OperatorDef>
                     value: Some(TokenValue::Str(s)),
                     ..
                 }) if s.eq_ignore_ascii_case("unary") => OperatorKind::Postfix,
+                Some(lexer::Token {
+                    _type: TokenType::String,
+                    value: Some(TokenValue::Str(s)),
+                    span,
+                }) => {
+                    return Err(Diagnostic::error("E0102", format!("unknown operator kind `{}`", s))
+                        .with_primary(*span, "expected `prefix`, `postfix`, `binary` or `unary`"));
+                }
                 _ => OperatorKind::Binary,
             };
-            cursor += 1; 
+            if matches!(tokens.get(cursor), Some(tok) if tok._type == TokenType::String) {
+                cursor += 1;
+            }
 
-            if matches!(tokens.get(cursor), Some(tok) if tok._type == TokenType::RightCurly) {
+            if matches!(tokens.get(cursor), Some(tok) if tok._type == TokenType::Comma) {
                 cursor += 1;
             }
 
-            (func, precedence, kind, cursor - (i + 3))
+            let assoc = match tokens.get(cursor) {
+                Some(lexer::Token {
+                    _type: TokenType::String,
+                    value: Some(TokenValue::Str(s)),
+                    span,
+                }) => {
+                    cursor += 1;
+                    match s.to_ascii_lowercase().as_str() {
+                        "left" => Associativity::Left,
+                        "right" => Associativity::Right,
+                        "none" => Associativity::None,
+                        _ => {
+                            return Err(Diagnostic::error("E0102", format!("unknown associativity `{}`", s))
+                                .with_primary(*span, "expected `left`, `right` or `none`"));
+                        }
+                    }
+                }
+                _ => Associativity::Left,
+            };
+
+            expect_spec_token(tokens, cursor, TokenType::RightCurly, "`}`")?;
+            cursor += 1;
+
+            (func.map(|body| bind_params(body, &kind)), precedence, kind, assoc)
         } else {
-            (None, 0, OperatorKind::Binary, 0)
+            (None, 0, OperatorKind::Binary, Associativity::Left)
         };
 
     
@@ -160,19 +295,26 @@ fn maybe_parse_op_def(tokens: &[lexer::Token], i: usize) -> Option<OperatorDef>
         if tokens[cursor]._type == TokenType::EndOfAssign {
             let op = match &op_token.value {
                 Some(TokenValue::Str(s)) => s.clone(),
-                _ => return None,
+                _ => return Ok(None),
             };
 
-            return Some(OperatorDef {
-                op,
-                func,
-                precedence,
-                kind,
-            });
+            return Ok(Some((
+                OperatorDef {
+                    op,
+                    func,
+                    precedence,
+                    kind,
+                    assoc,
+                    span: t.span.to(tokens[cursor].span),
+                    exported: false,
+                },
+                cursor + 1,
+            )));
         }
         cursor += 1;
     }
 
     
-    None
+    Err(Diagnostic::error("E0101", "operator definition is never closed")
+        .with_primary(t.span.to(op_token.span), "this definition is missing its `owners`"))
 }
@@ -0,0 +1,1331 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Deref;
+use std::rc::Rc;
+
+use crate::diagnostics::Diagnostic;
+use crate::lexer::Span;
+use crate::first_pass::OperatorKind;
+use crate::parser::{Expr, ExprKind, ImportKind, ImportName, Number, OperatorFn};
+
+#[derive(Debug, Clone)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Array(Rc<Array>),
+    Range(i64, i64),
+    Function(Rc<Closure>),
+    Compiled(Rc<crate::vm::Closure>),
+    Generator(Rc<RefCell<Generator>>),
+    CompiledGenerator(Rc<RefCell<crate::vm::Generator>>),
+    Error(Rc<RuntimeError>),
+}
+
+// once a `$` constant holds an array, neither it nor any array inside it can change
+#[derive(Debug, Default)]
+pub struct Array {
+    items: RefCell<Vec<Value>>,
+    frozen: Cell<bool>,
+}
+
+impl Deref for Array {
+    type Target = RefCell<Vec<Value>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.items
+    }
+}
+
+pub struct Closure {
+    name: String,
+    params: Vec<String>,
+    body: Rc<Expr>,
+    env: Env,
+    generator: bool,
+}
+
+impl fmt::Debug for Closure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Closure({}/{})", self.name, self.params.len())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum GenState {
+    Ready,
+    Running,
+    Done,
+}
+
+// A paused generator is a stack of frames, one per block or loop it is inside.
+// Frames point into the body by child index rather than by reference.
+struct Frame {
+    path: Vec<usize>,
+    env: Env,
+    progress: usize,
+    source: Option<Value>,
+}
+
+impl Frame {
+    fn new(path: Vec<usize>, env: Env) -> Self {
+        Self { path, env, progress: 0, source: None }
+    }
+}
+
+pub struct Generator {
+    name: String,
+    body: Rc<Expr>,
+    frames: Vec<Frame>,
+    state: GenState,
+}
+
+impl Generator {
+    fn new(name: String, body: Rc<Expr>, env: Env) -> Self {
+        Self { name, body, frames: vec![Frame::new(vec![], env)], state: GenState::Ready }
+    }
+}
+
+impl fmt::Debug for Generator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Generator({})", self.name)
+    }
+}
+
+enum Step {
+    Next,
+    Push(Frame),
+    Yield(Value),
+}
+
+fn child(expr: &Expr, index: usize) -> &Expr {
+    match (&expr.kind, index) {
+        (ExprKind::Block(items), i) => &items[i],
+        (ExprKind::If { then, .. } | ExprKind::While { then, .. } | ExprKind::For { then, .. }, 1) => then,
+        (ExprKind::If { else_then, .. } | ExprKind::While { else_then, .. } | ExprKind::For { else_then, .. }, 2) => else_then,
+        _ => expr,
+    }
+}
+
+fn node_at<'a>(root: &'a Expr, path: &[usize]) -> &'a Expr {
+    path.iter().fold(root, |expr, &i| child(expr, i))
+}
+
+fn child_path(path: &[usize], index: usize) -> Vec<usize> {
+    let mut path = path.to_vec();
+    path.push(index);
+    path
+}
+
+impl Value {
+    pub fn array(items: Vec<Value>) -> Self {
+        Value::Array(Rc::new(Array { items: RefCell::new(items), frozen: Cell::new(false) }))
+    }
+
+    pub fn freeze(&self) {
+        if let Value::Array(array) = self {
+            if !array.frozen.replace(true) {
+                for item in array.borrow().iter() {
+                    item.freeze();
+                }
+            }
+        }
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        matches!(self, Value::Array(array) if array.frozen.get())
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Null => "nothing",
+            Value::Bool(_) => "bool",
+            Value::Int(_) | Value::Float(_) => "number",
+            Value::Str(_) => "string",
+            Value::Array(_) => "array",
+            Value::Range(..) => "range",
+            Value::Function(_) | Value::Compiled(_) => "function",
+            Value::Generator(_) | Value::CompiledGenerator(_) => "generator",
+            Value::Error(_) => "error",
+        }
+    }
+
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Null => false,
+            Value::Bool(b) => *b,
+            Value::Int(n) => *n != 0,
+            Value::Float(n) => *n != 0.0,
+            Value::Str(s) => !s.is_empty(),
+            Value::Array(items) => !items.borrow().is_empty(),
+            Value::Range(from, to) => from < to,
+            Value::Function(_) | Value::Compiled(_) | Value::Generator(_) | Value::CompiledGenerator(_) | Value::Error(_) => true,
+        }
+    }
+
+    fn as_float(&self) -> Option<f64> {
+        match self {
+            Value::Int(n) => Some(*n as f64),
+            Value::Float(n) => Some(*n),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "nothing"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Float(n) => write!(f, "{:?}", n),
+            Value::Str(s) => write!(f, "{}", s),
+            Value::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Value::Range(from, to) => write!(f, "{}..{}", from, to),
+            Value::Function(func) => write!(f, "<railway {}>", func.name),
+            Value::Compiled(func) => write!(f, "<railway {}>", func.name()),
+            Value::Generator(gen) => write!(f, "<generator {}>", gen.borrow().name),
+            Value::CompiledGenerator(gen) => write!(f, "<generator {}>", gen.borrow().name()),
+            Value::Error(e) => write!(f, "{}: {}", e.kind, e.message),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RuntimeError {
+    pub kind: String,
+    pub message: String,
+    pub span: Span,
+    // innermost call first: the function that was running and where it was called from
+    pub trace: Vec<(String, Span)>,
+}
+
+impl RuntimeError {
+    pub fn new(message: impl Into<String>, span: Span) -> Self {
+        Self { kind: "RuntimeError".to_string(), message: message.into(), span, trace: Vec::new() }
+    }
+
+    pub fn thrown(value: Value, span: Span) -> Self {
+        let (kind, message) = match value {
+            Value::Error(e) => return (*e).clone(),
+            Value::Array(items) => match items.borrow().as_slice() {
+                [Value::Str(kind), message] => (kind.clone(), message.to_string()),
+                _ => ("Error".to_string(), Value::Array(items.clone()).to_string()),
+            },
+            other => ("Error".to_string(), other.to_string()),
+        };
+        Self { kind, message, span, trace: Vec::new() }
+    }
+
+    fn field(&self, name: &str) -> Option<Value> {
+        match name {
+            "kind" => Some(Value::Str(self.kind.clone())),
+            "message" => Some(Value::Str(self.message.clone())),
+            "line" => Some(Value::Int(self.span.line as i64)),
+            "column" => Some(Value::Int(self.span.col as i64)),
+            _ => None,
+        }
+    }
+}
+
+fn traced(result: Eval, name: &str, span: Span) -> Eval {
+    result.map_err(|signal| match signal {
+        Signal::Error(mut e) => {
+            e.trace.push((name.to_string(), span));
+            Signal::Error(e)
+        }
+        other => other,
+    })
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "runtime error at {}: {}", self.span, self.message)
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+impl From<RuntimeError> for Diagnostic {
+    fn from(e: RuntimeError) -> Self {
+        let message = if e.kind == "RuntimeError" {
+            e.message.clone()
+        } else {
+            format!("{}: {}", e.kind, e.message)
+        };
+        let mut diagnostic = Diagnostic::error("E0301", message).with_primary(e.span, "raised here");
+        // deep recursion would otherwise print the same call a thousand times
+        let mut frames = e.trace.iter().peekable();
+        while let Some(frame) = frames.next() {
+            let mut repeats = 0;
+            while frames.next_if_eq(&frame).is_some() {
+                repeats += 1;
+            }
+            diagnostic = diagnostic.with_note(format!("in `{}`, called at {}", frame.0, frame.1));
+            if repeats > 0 {
+                diagnostic = diagnostic.with_note(format!("… repeated {} more time{}", repeats, if repeats == 1 { "" } else { "s" }));
+            }
+        }
+        diagnostic
+    }
+}
+
+enum Signal {
+    Break(Option<String>, Span),
+    Continue(Option<String>, Span),
+    Return(Value, Span),
+    Error(RuntimeError),
+}
+
+enum LoopControl {
+    Next,
+    Exit,
+}
+
+// an unlabelled break/continue targets the innermost loop, a labelled one only its own loop
+fn loop_control(result: Eval, label: &Option<String>) -> Result<LoopControl, Signal> {
+    let targets = |target: &Option<String>| target.is_none() || target == label;
+    match result {
+        Ok(_) => Ok(LoopControl::Next),
+        Err(Signal::Break(target, _)) if targets(&target) => Ok(LoopControl::Exit),
+        Err(Signal::Continue(target, _)) if targets(&target) => Ok(LoopControl::Next),
+        Err(e) => Err(e),
+    }
+}
+
+impl From<RuntimeError> for Signal {
+    fn from(e: RuntimeError) -> Self {
+        Signal::Error(e)
+    }
+}
+
+type Eval = Result<Value, Signal>;
+
+#[derive(Default)]
+struct Scope {
+    vars: HashMap<String, Value>,
+    consts: HashMap<String, Value>,
+    parent: Option<Env>,
+}
+
+type Env = Rc<RefCell<Scope>>;
+
+fn child_env(parent: &Env) -> Env {
+    Rc::new(RefCell::new(Scope {
+        parent: Some(parent.clone()),
+        ..Default::default()
+    }))
+}
+
+fn lookup(env: &Env, name: &str, constant: bool) -> Option<Value> {
+    let scope = env.borrow();
+    let table = if constant { &scope.consts } else { &scope.vars };
+    match table.get(name) {
+        Some(v) => Some(v.clone()),
+        None => scope.parent.as_ref().and_then(|p| lookup(p, name, constant)),
+    }
+}
+
+fn assign(env: &Env, name: &str, value: Value) -> bool {
+    let mut scope = env.borrow_mut();
+    if let Some(slot) = scope.vars.get_mut(name) {
+        *slot = value;
+        return true;
+    }
+    match &scope.parent {
+        Some(p) => assign(p, name, value),
+        None => false,
+    }
+}
+
+pub const MAX_CALL_DEPTH: usize = 1000;
+
+// operator bodies run in the module that defined them, not the one using them
+#[derive(Clone)]
+struct UserOperator {
+    func: Rc<OperatorFn>,
+    env: Env,
+}
+
+#[derive(Clone, Default)]
+pub struct Exports {
+    vars: HashMap<String, Value>,
+    consts: HashMap<String, Value>,
+    operators: HashMap<String, UserOperator>,
+}
+
+pub struct Interpreter {
+    env: Env,
+    globals: Env,
+    operators: HashMap<String, UserOperator>,
+    depth: usize,
+}
+
+impl Interpreter {
+    pub fn new(operators: HashMap<String, OperatorFn>) -> Self {
+        let globals = Rc::new(RefCell::new(Scope::default()));
+        let operators = operators
+            .into_iter()
+            .map(|(k, v)| (k, UserOperator { func: Rc::new(v), env: globals.clone() }))
+            .collect();
+        Self {
+            env: globals.clone(),
+            globals,
+            operators,
+            depth: 0,
+        }
+    }
+
+    pub fn import(&mut self, exports: &Exports, names: Option<&[ImportName]>) {
+        let mut globals = self.globals.borrow_mut();
+        match names {
+            None => {
+                globals.vars.extend(exports.vars.iter().map(|(k, v)| (k.clone(), v.clone())));
+                globals.consts.extend(exports.consts.iter().map(|(k, v)| (k.clone(), v.clone())));
+                for (op, f) in &exports.operators {
+                    self.operators.entry(op.clone()).or_insert_with(|| f.clone());
+                }
+            }
+            Some(names) => {
+                for name in names {
+                    match name.kind {
+                        ImportKind::Variable => {
+                            if let Some(v) = exports.vars.get(&name.name) {
+                                globals.vars.insert(name.name.clone(), v.clone());
+                            }
+                        }
+                        ImportKind::Const => {
+                            if let Some(v) = exports.consts.get(&name.name) {
+                                globals.consts.insert(name.name.clone(), v.clone());
+                            }
+                        }
+                        ImportKind::Operator => {
+                            if let Some(f) = exports.operators.get(&name.name) {
+                                self.operators.entry(name.name.clone()).or_insert_with(|| f.clone());
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        drop(globals);
+
+        for value in exports.consts.values() {
+            value.freeze();
+        }
+    }
+
+    pub fn exports(&self, names: &[ImportName]) -> Exports {
+        let globals = self.globals.borrow();
+        let mut exports = Exports::default();
+        for name in names {
+            match name.kind {
+                ImportKind::Variable => {
+                    if let Some(v) = globals.vars.get(&name.name) {
+                        exports.vars.insert(name.name.clone(), v.clone());
+                    }
+                }
+                ImportKind::Const => {
+                    if let Some(v) = globals.consts.get(&name.name) {
+                        exports.consts.insert(name.name.clone(), v.clone());
+                    }
+                }
+                ImportKind::Operator => {
+                    if let Some(f) = self.operators.get(&name.name) {
+                        exports.operators.insert(name.name.clone(), f.clone());
+                    }
+                }
+            }
+        }
+        exports
+    }
+
+    fn user_operator(&self, op: &str, binary: bool) -> Option<UserOperator> {
+        self.operators
+            .get(op)
+            .filter(|f| (f.func.kind == OperatorKind::Binary) == binary)
+            .cloned()
+    }
+
+    fn call_operator(&mut self, op: UserOperator, args: Vec<Value>, span: Span) -> Eval {
+        let env = child_env(&op.env);
+        for (param, arg) in op.func.params.iter().zip(args) {
+            env.borrow_mut().vars.insert(param.clone(), arg);
+        }
+
+        let result = self.eval_in_scope(&op.func.body, env);
+        match traced(result, &op.func.op, span) {
+            Err(Signal::Return(v, _)) => Ok(v),
+            Err(Signal::Break(..) | Signal::Continue(..)) => Err(RuntimeError::new(
+                format!("loop control escaped the body of operator `{}`", op.func.op),
+                span,
+            )
+            .into()),
+            other => other,
+        }
+    }
+
+    fn call_function(&mut self, func: Rc<Closure>, args: Vec<Value>, span: Span) -> Eval {
+        if args.len() != func.params.len() {
+            return Err(RuntimeError::new(
+                format!("`{}` takes {} argument(s) but {} were given", func.name, func.params.len(), args.len()),
+                span,
+            )
+            .into());
+        }
+        if self.depth >= MAX_CALL_DEPTH {
+            return Err(RuntimeError::new(
+                format!("too many nested calls (last was to `{}`)", func.name),
+                span,
+            )
+            .into());
+        }
+
+        let env = child_env(&func.env);
+        for (param, arg) in func.params.iter().zip(args) {
+            env.borrow_mut().vars.insert(param.clone(), arg);
+        }
+
+        if func.generator {
+            let gen = Generator::new(func.name.clone(), func.body.clone(), env);
+            return Ok(Value::Generator(Rc::new(RefCell::new(gen))));
+        }
+
+        self.depth += 1;
+        let result = self.eval_in_scope(&func.body, env);
+        self.depth -= 1;
+
+        match traced(result, &func.name, span) {
+            Err(Signal::Return(v, _)) => Ok(v),
+            Err(Signal::Break(..) | Signal::Continue(..)) => Err(RuntimeError::new(
+                format!("loop control escaped the body of `{}`", func.name),
+                span,
+            )
+            .into()),
+            other => other,
+        }
+    }
+
+    fn resume(&mut self, gen: &Rc<RefCell<Generator>>, span: Span) -> Result<Option<Value>, Signal> {
+        let (body, mut frames) = {
+            let mut g = gen.borrow_mut();
+            match g.state {
+                GenState::Done => return Ok(None),
+                GenState::Running => {
+                    return Err(RuntimeError::new(format!("generator `{}` is already running", g.name), span).into());
+                }
+                GenState::Ready => {}
+            }
+            if self.depth >= MAX_CALL_DEPTH {
+                return Err(RuntimeError::new(format!("too many nested calls (last was to `{}`)", g.name), span).into());
+            }
+            g.state = GenState::Running;
+            (g.body.clone(), std::mem::take(&mut g.frames))
+        };
+
+        self.depth += 1;
+        let result = self.run_frames(&body, &mut frames);
+        self.depth -= 1;
+
+        let mut g = gen.borrow_mut();
+        g.state = if matches!(result, Ok(Some(_))) { GenState::Ready } else { GenState::Done };
+        g.frames = frames;
+        result.map_err(|signal| match signal {
+            Signal::Error(mut e) => {
+                e.trace.push((g.name.clone(), span));
+                Signal::Error(e)
+            }
+            other => other,
+        })
+    }
+
+    fn run_frames(&mut self, body: &Expr, frames: &mut Vec<Frame>) -> Result<Option<Value>, Signal> {
+        while let Some(frame) = frames.last_mut() {
+            let node = node_at(body, &frame.path);
+            let env = frame.env.clone();
+            let path = frame.path.clone();
+
+            let step = match &node.kind {
+                ExprKind::Block(items) => {
+                    if frame.progress == items.len() {
+                        frames.pop();
+                        continue;
+                    }
+                    let index = frame.progress;
+                    frame.progress += 1;
+                    self.step(&items[index], child_path(&path, index), env)
+                }
+
+                ExprKind::While { cond, else_then, .. } => match self.eval_in(cond, env.clone()) {
+                    Ok(v) if v.is_truthy() => Ok(Step::Push(Frame::new(child_path(&path, 1), child_env(&env)))),
+                    Ok(_) => {
+                        frames.pop();
+                        self.step(else_then, child_path(&path, 2), env)
+                    }
+                    Err(e) => Err(e),
+                },
+
+                ExprKind::For { iter, var, else_then, .. } => {
+                    let index = frame.progress;
+                    frame.progress += 1;
+                    match self.next_item(&frame.source, index, *iter, node.span) {
+                        Ok(Some(item)) => {
+                            let env = child_env(&env);
+                            env.borrow_mut().vars.insert(var.clone(), item);
+                            Ok(Step::Push(Frame::new(child_path(&path, 1), env)))
+                        }
+                        Ok(None) => {
+                            frames.pop();
+                            self.step(else_then, child_path(&path, 2), env)
+                        }
+                        Err(e) => Err(e),
+                    }
+                }
+
+                _ => {
+                    frames.pop();
+                    continue;
+                }
+            };
+
+            match step {
+                Ok(Step::Next) => {}
+                Ok(Step::Push(frame)) => frames.push(frame),
+                Ok(Step::Yield(value)) => return Ok(Some(value)),
+                Err(Signal::Return(..)) => {
+                    frames.clear();
+                    return Ok(None);
+                }
+                Err(signal @ (Signal::Break(..) | Signal::Continue(..))) => unwind(body, frames, signal)?,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn step(&mut self, stmt: &Expr, path: Vec<usize>, env: Env) -> Result<Step, Signal> {
+        if !stmt.contains_yield() {
+            self.eval_in(stmt, env)?;
+            return Ok(Step::Next);
+        }
+
+        match &stmt.kind {
+            ExprKind::Yield(inner) => Ok(Step::Yield(self.eval_in(inner, env)?)),
+
+            ExprKind::Block(_) => Ok(Step::Push(Frame::new(path, child_env(&env)))),
+
+            ExprKind::If { cond, then, else_then } => {
+                if self.eval_in(cond, env.clone())?.is_truthy() {
+                    self.step(then, child_path(&path, 1), env)
+                } else {
+                    self.step(else_then, child_path(&path, 2), env)
+                }
+            }
+
+            ExprKind::While { .. } => Ok(Step::Push(Frame::new(path, env))),
+
+            ExprKind::For { source, .. } => {
+                let saved = std::mem::replace(&mut self.env, env.clone());
+                let source = self.loop_source(source, stmt.span);
+                self.env = saved;
+                Ok(Step::Push(Frame { path, env, progress: 0, source: source? }))
+            }
+
+            _ => Err(RuntimeError::new("`anywho` cannot pause here", stmt.span).into()),
+        }
+    }
+
+    fn eval_in(&mut self, expr: &Expr, env: Env) -> Eval {
+        let saved = std::mem::replace(&mut self.env, env);
+        let result = self.eval(expr);
+        self.env = saved;
+        result
+    }
+
+    fn next_item(&mut self, source: &Option<Value>, index: usize, count: usize, span: Span) -> Result<Option<Value>, Signal> {
+        let index = index as i64;
+        match source {
+            Some(Value::Generator(gen)) => self.resume(gen, span),
+            Some(Value::Array(items)) => Ok(items.borrow().get(index as usize).cloned()),
+            Some(Value::Int(n)) => Ok((index < *n).then_some(Value::Int(index))),
+            Some(Value::Range(from, to)) => Ok(from.checked_add(index).filter(|i| i < to).map(Value::Int)),
+            Some(other) => Err(RuntimeError::new(format!("cannot loop over {}", other.type_name()), span).into()),
+            None => Ok((index < count as i64).then_some(Value::Int(index))),
+        }
+    }
+
+    fn loop_source(&mut self, source: &Option<Box<Expr>>, span: Span) -> Result<Option<Value>, Signal> {
+        let value = match source {
+            Some(e) => self.eval(e)?,
+            None => return Ok(None),
+        };
+        match value {
+            Value::Str(s) => Ok(Some(Value::array(s.chars().map(|c| Value::Str(c.to_string())).collect()))),
+            Value::Float(n) if n.fract() == 0.0 && n.abs() < i64::MAX as f64 => Ok(Some(Value::Int(n as i64))),
+            Value::Float(n) => Err(RuntimeError::new(format!("cannot loop {} times", n), span).into()),
+            other => Ok(Some(other)),
+        }
+    }
+
+    pub fn run(&mut self, program: &Expr) -> Result<Value, RuntimeError> {
+        let result = match &program.kind {
+            ExprKind::Block(exprs) => self.eval_sequence(exprs),
+            _ => self.eval(program),
+        };
+
+        match result {
+            Ok(v) => Ok(v),
+            Err(Signal::Error(e)) => Err(e),
+            Err(Signal::Break(_, span)) => Err(RuntimeError::new("`jump off the bandwagon` outside of a loop", span)),
+            Err(Signal::Continue(_, span)) => Err(RuntimeError::new("`get back to work boy` outside of a loop", span)),
+            Err(Signal::Return(_, span)) => Err(RuntimeError::new("`return` outside of a function", span)),
+        }
+    }
+
+    fn eval_sequence(&mut self, exprs: &[Expr]) -> Eval {
+        let mut last = Value::Null;
+        for expr in exprs {
+            last = self.eval(expr)?;
+        }
+        Ok(last)
+    }
+
+    fn eval_in_scope(&mut self, expr: &Expr, env: Env) -> Eval {
+        let saved = std::mem::replace(&mut self.env, env);
+        let result = match &expr.kind {
+            ExprKind::Block(exprs) => self.eval_sequence(exprs),
+            _ => self.eval(expr),
+        };
+        self.env = saved;
+        result
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Eval {
+        let span = expr.span;
+        match &expr.kind {
+            ExprKind::String(s) => Ok(Value::Str(s.clone())),
+
+            ExprKind::Interpolated(parts) => {
+                let mut out = String::new();
+                for part in parts {
+                    out.push_str(&self.eval(part)?.to_string());
+                }
+                Ok(Value::Str(out))
+            }
+
+            ExprKind::Number(Number::Int(n)) => Ok(Value::Int(*n)),
+
+            ExprKind::Number(Number::Float(n)) => Ok(Value::Float(*n)),
+
+            ExprKind::Array(items) => {
+                let mut values = Vec::with_capacity(items.len());
+                for item in items {
+                    values.push(self.eval(item)?);
+                }
+                Ok(Value::array(values))
+            }
+
+            ExprKind::Variable(name) => lookup(&self.env, name, false)
+                .ok_or_else(|| RuntimeError::new(format!("undefined variable `£{}`", name), span).into()),
+
+            ExprKind::Const(name) => lookup(&self.env, name, true)
+                .ok_or_else(|| RuntimeError::new(format!("undefined constant `${}`", name), span).into()),
+
+            ExprKind::Binary { left, op, right } => {
+                let l = self.eval(left)?;
+                let r = self.eval(right)?;
+                if let Some(f) = self.user_operator(op, true) {
+                    return self.call_operator(f, vec![l, r], span);
+                }
+                binary_op(op, &l, &r).map_err(|m| RuntimeError::new(m, span).into())
+            }
+
+            ExprKind::Unary { oper, op } => {
+                let v = self.eval(oper)?;
+                if let Some(f) = self.user_operator(op, false) {
+                    return self.call_operator(f, vec![v], span);
+                }
+                unary_op(op, &v).map_err(|m| RuntimeError::new(m, span).into())
+            }
+
+            ExprKind::Func { name, args } => {
+                let mut values = Vec::with_capacity(args.len());
+                for arg in args {
+                    values.push(self.eval(arg)?);
+                }
+                if let Some(Value::Function(func)) = lookup(&self.env, name, false) {
+                    return self.call_function(func, values, span);
+                }
+                if matches!(name.as_str(), "push" | "pop") && values.first().is_some_and(Value::is_frozen) {
+                    return Err(RuntimeError::new(format!("cannot `{}` on an array held by a constant", name), span).into());
+                }
+                if name == "next" {
+                    return match values.as_slice() {
+                        [Value::Generator(gen)] => Ok(self.resume(gen, span)?.unwrap_or(Value::Null)),
+                        [other] => Err(RuntimeError::new(format!("`next` expects a generator, not {}", other.type_name()), span).into()),
+                        _ => Err(RuntimeError::new(format!("`next` takes 1 argument(s) but {} were given", values.len()), span).into()),
+                    };
+                }
+                call_builtin(name, values).map_err(|m| RuntimeError::new(m, span).into())
+            }
+
+            ExprKind::Index { target, index } => {
+                let target = self.eval(target)?;
+                let index = self.eval(index)?;
+                index_value(&target, &index).map_err(|m| RuntimeError::new(m, span).into())
+            }
+
+            ExprKind::Slice { target, from, to } => {
+                let target = self.eval(target)?;
+                let from = match from {
+                    Some(e) => Some(self.eval(e)?),
+                    None => None,
+                };
+                let to = match to {
+                    Some(e) => Some(self.eval(e)?),
+                    None => None,
+                };
+                slice_value(&target, from.as_ref(), to.as_ref()).map_err(|m| RuntimeError::new(m, span).into())
+            }
+
+            ExprKind::If { cond, then, else_then } => {
+                if self.eval(cond)?.is_truthy() {
+                    self.eval(then)
+                } else {
+                    self.eval(else_then)
+                }
+            }
+
+            ExprKind::While { cond, then, else_then, label } => {
+                while self.eval(cond)?.is_truthy() {
+                    let result = self.eval(then);
+                    if let LoopControl::Exit = loop_control(result, label)? {
+                        return Ok(Value::Null);
+                    }
+                }
+                self.eval(else_then)?;
+                Ok(Value::Null)
+            }
+
+            ExprKind::For { iter, var, source, then, else_then, label } => {
+                let source = self.loop_source(source, span)?;
+                let mut index = 0;
+                while let Some(item) = self.next_item(&source, index, *iter, span)? {
+                    index += 1;
+                    let env = child_env(&self.env);
+                    env.borrow_mut().vars.insert(var.clone(), item);
+                    let result = self.eval_in_scope(then, env);
+                    if let LoopControl::Exit = loop_control(result, label)? {
+                        return Ok(Value::Null);
+                    }
+                }
+                self.eval(else_then)?;
+                Ok(Value::Null)
+            }
+
+            ExprKind::Define { var, constant, val } => {
+                let value = self.eval(val)?;
+                if *constant {
+                    if lookup(&self.env, var, true).is_some() {
+                        return Err(RuntimeError::new(format!("cannot redefine constant `${}`", var), span).into());
+                    }
+                    value.freeze();
+                    self.env.borrow_mut().consts.insert(var.clone(), value);
+                } else if !assign(&self.env, var, value.clone()) {
+                    self.env.borrow_mut().vars.insert(var.clone(), value);
+                }
+                Ok(Value::Null)
+            }
+
+            ExprKind::Function { name, params, body } => {
+                let func = Closure {
+                    name: name.clone(),
+                    params: params.clone(),
+                    body: body.clone(),
+                    env: self.env.clone(),
+                    generator: body.contains_yield(),
+                };
+                self.env.borrow_mut().vars.insert(name.clone(), Value::Function(Rc::new(func)));
+                Ok(Value::Null)
+            }
+
+            ExprKind::Return(value) => {
+                let value = match value {
+                    Some(e) => self.eval(e)?,
+                    None => Value::Null,
+                };
+                Err(Signal::Return(value, span))
+            }
+
+            // imports are bound before the module runs, see `module::run`
+            ExprKind::Import(_) => Ok(Value::Null),
+
+            ExprKind::Export(inner) => self.eval(inner),
+
+            ExprKind::Try { attempt, binding, catch, finally } => {
+                let result = match (self.eval(attempt), catch) {
+                    (Err(Signal::Error(e)), Some(catch)) => {
+                        let env = child_env(&self.env);
+                        if let Some(name) = binding {
+                            env.borrow_mut().vars.insert(name.clone(), Value::Error(Rc::new(e)));
+                        }
+                        self.eval_in_scope(catch, env)
+                    }
+                    (result, _) => result,
+                };
+
+                // cleanup runs however the attempt ended; if it fails itself, that failure wins
+                if let Some(finally) = finally {
+                    self.eval(finally)?;
+                }
+                result
+            }
+
+            ExprKind::Throw(value) => {
+                let value = self.eval(value)?;
+                Err(Signal::Error(RuntimeError::thrown(value, span)))
+            }
+
+            // generators pause on `anywho` themselves, and the semantic check rejects it anywhere else
+            ExprKind::Yield(_) => Err(RuntimeError::new("`anywho` outside of a generator", span).into()),
+
+            ExprKind::Generator(body) => {
+                let gen = Generator::new("block".to_string(), body.clone(), child_env(&self.env));
+                Ok(Value::Generator(Rc::new(RefCell::new(gen))))
+            }
+
+            ExprKind::Break(label) => Err(Signal::Break(label.clone(), span)),
+
+            ExprKind::Continue(label) => Err(Signal::Continue(label.clone(), span)),
+
+            ExprKind::Error => Err(RuntimeError::new("cannot run code that failed to parse", span).into()),
+
+            ExprKind::Block(exprs) => {
+                let env = child_env(&self.env);
+                let saved = std::mem::replace(&mut self.env, env);
+                let result = self.eval_sequence(exprs);
+                self.env = saved;
+                result
+            }
+        }
+    }
+}
+
+// pop frames up to the loop a break or continue is aimed at; a continue leaves that loop running
+fn unwind(body: &Expr, frames: &mut Vec<Frame>, signal: Signal) -> Result<(), Signal> {
+    let (target, is_break) = match &signal {
+        Signal::Break(target, _) => (target.clone(), true),
+        Signal::Continue(target, _) => (target.clone(), false),
+        _ => return Err(signal),
+    };
+
+    while let Some(frame) = frames.last() {
+        let label = match &node_at(body, &frame.path).kind {
+            ExprKind::While { label, .. } | ExprKind::For { label, .. } => Some(label),
+            _ => None,
+        };
+        if let Some(label) = label {
+            if target.is_none() || target == *label {
+                if is_break {
+                    frames.pop();
+                }
+                return Ok(());
+            }
+        }
+        frames.pop();
+    }
+
+    Err(signal)
+}
+
+enum Operands {
+    Ints(i64, i64),
+    Floats(f64, f64),
+}
+
+fn operands(op: &str, l: &Value, r: &Value) -> Result<Operands, String> {
+    match (l, r) {
+        (Value::Int(a), Value::Int(b)) => Ok(Operands::Ints(*a, *b)),
+        _ => match (l.as_float(), r.as_float()) {
+            (Some(a), Some(b)) => Ok(Operands::Floats(a, b)),
+            _ => Err(format!(
+                "cannot apply `{}` to {} and {}",
+                op,
+                l.type_name(),
+                r.type_name()
+            )),
+        },
+    }
+}
+
+fn overflow(op: &str) -> String {
+    format!("arithmetic overflow in `{}`", op)
+}
+
+// NaN comes from things like a negative number to a fractional power, not from running out of room
+fn no_real_result(op: &str) -> String {
+    format!("`{}` has no real result", op)
+}
+
+fn checked_float(op: &str, f: f64) -> Result<Value, String> {
+    if f.is_nan() {
+        Err(no_real_result(op))
+    } else if f.is_finite() {
+        Ok(Value::Float(f))
+    } else {
+        Err(overflow(op))
+    }
+}
+
+fn arithmetic(op: &str, l: &Value, r: &Value) -> Result<Value, String> {
+    match operands(op, l, r)? {
+        Operands::Ints(a, b) => {
+            let result = match op {
+                "+" => a.checked_add(b),
+                "-" => a.checked_sub(b),
+                "*" => a.checked_mul(b),
+                "/" => {
+                    if b == 0 {
+                        return Err("division by zero".to_string());
+                    }
+                    if a.checked_rem(b).is_some_and(|r| r != 0) {
+                        return checked_float(op, a as f64 / b as f64);
+                    }
+                    a.checked_div(b)
+                }
+                "^" => {
+                    if b < 0 {
+                        if a == 0 {
+                            return Err("division by zero".to_string());
+                        }
+                        return checked_float(op, (a as f64).powf(b as f64));
+                    }
+                    u32::try_from(b).ok().and_then(|b| a.checked_pow(b))
+                }
+                "^^" => return tetrate_int(a, b),
+                _ => None,
+            };
+            result.map(Value::Int).ok_or_else(|| overflow(op))
+        }
+        Operands::Floats(a, b) => match op {
+            "+" => checked_float(op, a + b),
+            "-" => checked_float(op, a - b),
+            "*" => checked_float(op, a * b),
+            "/" => {
+                if b == 0.0 {
+                    return Err("division by zero".to_string());
+                }
+                checked_float(op, a / b)
+            }
+            "^" => {
+                if a == 0.0 && b < 0.0 {
+                    return Err("division by zero".to_string());
+                }
+                checked_float(op, a.powf(b))
+            }
+            "^^" => tetrate_float(a, b),
+            _ => Err(format!("unknown binary operator `{}`", op)),
+        },
+    }
+}
+
+fn tetration_height(height: i64) -> Result<i64, String> {
+    if height < 0 {
+        return Err("cannot tetrate to a negative height".to_string());
+    }
+    Ok(height)
+}
+
+fn tetrate_int(base: i64, height: i64) -> Result<Value, String> {
+    let mut height = tetration_height(height)?;
+    if (base == 0 || base == -1) && height > 3 {
+        height = 2 + height % 2;
+    }
+
+    let mut result: i64 = 1;
+    for _ in 0..height {
+        let next = u32::try_from(result)
+            .ok()
+            .and_then(|e| base.checked_pow(e))
+            .ok_or_else(|| overflow("^^"))?;
+        if next == result {
+            break;
+        }
+        result = next;
+    }
+    Ok(Value::Int(result))
+}
+
+fn tetrate_float(base: f64, height: f64) -> Result<Value, String> {
+    if height.fract() != 0.0 {
+        return Err("tetration height must be a whole number".to_string());
+    }
+    let height = tetration_height(height as i64)?;
+
+    let mut result = 1.0;
+    for _ in 0..height {
+        let next = base.powf(result);
+        if next.is_nan() {
+            return Err(no_real_result("^^"));
+        }
+        if !next.is_finite() {
+            return Err(overflow("^^"));
+        }
+        if next == result {
+            break;
+        }
+        result = next;
+    }
+    Ok(Value::Float(result))
+}
+
+fn compare(op: &str, l: &Value, r: &Value) -> Result<Value, String> {
+    let ordering = match (l, r) {
+        (Value::Str(a), Value::Str(b)) => a.partial_cmp(b),
+        _ => match operands(op, l, r)? {
+            Operands::Ints(a, b) => a.partial_cmp(&b),
+            Operands::Floats(a, b) => a.partial_cmp(&b),
+        },
+    };
+
+    let ordering = match ordering {
+        Some(o) => o,
+        None => return Ok(Value::Bool(false)),
+    };
+
+    Ok(Value::Bool(match op {
+        "<" => ordering.is_lt(),
+        ">" => ordering.is_gt(),
+        "≤" => ordering.is_le(),
+        _ => ordering.is_ge(),
+    }))
+}
+
+fn values_equal(l: &Value, r: &Value) -> bool {
+    match (l, r) {
+        (Value::Null, Value::Null) => true,
+        (Value::Bool(a), Value::Bool(b)) => a == b,
+        (Value::Int(a), Value::Int(b)) => a == b,
+        (Value::Str(a), Value::Str(b)) => a == b,
+        (Value::Range(a, b), Value::Range(c, d)) => a == c && b == d,
+        (Value::Function(a), Value::Function(b)) => Rc::ptr_eq(a, b),
+        (Value::Compiled(a), Value::Compiled(b)) => Rc::ptr_eq(a, b),
+        (Value::Generator(a), Value::Generator(b)) => Rc::ptr_eq(a, b),
+        (Value::CompiledGenerator(a), Value::CompiledGenerator(b)) => Rc::ptr_eq(a, b),
+        (Value::Error(a), Value::Error(b)) => Rc::ptr_eq(a, b),
+        (Value::Array(a), Value::Array(b)) => {
+            let (a, b) = (a.borrow(), b.borrow());
+            a.len() == b.len() && a.iter().zip(b.iter()).all(|(x, y)| values_equal(x, y))
+        }
+        _ => match (l.as_float(), r.as_float()) {
+            (Some(a), Some(b)) => a == b,
+            _ => false,
+        },
+    }
+}
+
+pub fn binary_op(op: &str, l: &Value, r: &Value) -> Result<Value, String> {
+    match op {
+        "+" => match (l, r) {
+            (Value::Str(a), Value::Str(b)) => Ok(Value::Str(format!("{}{}", a, b))),
+            (Value::Array(a), Value::Array(b)) => Ok(concat(a, b)),
+            _ => arithmetic(op, l, r),
+        },
+        "-" | "*" | "/" | "^" | "^^" => arithmetic(op, l, r),
+        "<" | ">" | "≤" | "≥" => compare(op, l, r),
+        ".." => match (l, r) {
+            (Value::Int(from), Value::Int(to)) => Ok(Value::Range(*from, *to)),
+            _ => Err(format!("cannot make a range from {} to {}", l.type_name(), r.type_name())),
+        },
+        "=" => Ok(Value::Bool(values_equal(l, r))),
+        _ => Err(format!("unknown binary operator `{}`", op)),
+    }
+}
+
+pub fn unary_op(op: &str, v: &Value) -> Result<Value, String> {
+    match op {
+        "!" => Ok(Value::Bool(!v.is_truthy())),
+        "?" => Ok(Value::Bool(v.is_truthy())),
+        "-" => match v {
+            Value::Int(n) => n.checked_neg().map(Value::Int).ok_or_else(|| overflow("-")),
+            Value::Float(n) => Ok(Value::Float(-n)),
+            _ => Err(format!("cannot apply `-` to {}", v.type_name())),
+        },
+        "++" => match v {
+            Value::Int(n) => n.checked_add(1).map(Value::Int).ok_or_else(|| overflow("++")),
+            Value::Float(n) => checked_float("++", n + 1.0),
+            _ => Err(format!("cannot apply `++` to {}", v.type_name())),
+        },
+        _ => Err(format!("unknown unary operator `{}`", op)),
+    }
+}
+
+fn concat(a: &Array, b: &Array) -> Value {
+    let mut items = a.borrow().clone();
+    items.extend(b.borrow().iter().cloned());
+    Value::array(items)
+}
+
+fn as_index(index: &Value) -> Result<i64, String> {
+    match index {
+        Value::Int(n) => Ok(*n),
+        other => Err(format!("cannot index with {}", other.type_name())),
+    }
+}
+
+fn resolve_index(index: i64, len: usize) -> Option<usize> {
+    let resolved = if index < 0 { len as i64 + index } else { index };
+    if resolved >= 0 && (resolved as usize) < len {
+        Some(resolved as usize)
+    } else {
+        None
+    }
+}
+
+pub fn index_value(target: &Value, index: &Value) -> Result<Value, String> {
+    if let (Value::Error(e), Value::Str(name)) = (target, index) {
+        return e.field(name).ok_or_else(|| format!("errors have no `{}`; try `kind`, `message`, `line` or `column`", name));
+    }
+
+    let i = as_index(index)?;
+    match target {
+        Value::Array(items) => {
+            let items = items.borrow();
+            resolve_index(i, items.len())
+                .map(|i| items[i].clone())
+                .ok_or_else(|| format!("index {} is out of range for an array of length {}", i, items.len()))
+        }
+        Value::Str(s) => {
+            let chars: Vec<char> = s.chars().collect();
+            resolve_index(i, chars.len())
+                .map(|i| Value::Str(chars[i].to_string()))
+                .ok_or_else(|| format!("index {} is out of range for a string of length {}", i, chars.len()))
+        }
+        other => Err(format!("cannot index into {}", other.type_name())),
+    }
+}
+
+fn slice_bounds(from: Option<&Value>, to: Option<&Value>, len: usize) -> Result<(usize, usize), String> {
+    let clamp = |v: i64| -> usize {
+        let v = if v < 0 { len as i64 + v } else { v };
+        v.clamp(0, len as i64) as usize
+    };
+    let from = match from {
+        Some(v) => clamp(as_index(v)?),
+        None => 0,
+    };
+    let to = match to {
+        Some(v) => clamp(as_index(v)?),
+        None => len,
+    };
+    Ok((from, to.max(from)))
+}
+
+pub fn slice_value(target: &Value, from: Option<&Value>, to: Option<&Value>) -> Result<Value, String> {
+    match target {
+        Value::Array(items) => {
+            let items = items.borrow();
+            let (from, to) = slice_bounds(from, to, items.len())?;
+            Ok(Value::array(items[from..to].to_vec()))
+        }
+        Value::Str(s) => {
+            let chars: Vec<char> = s.chars().collect();
+            let (from, to) = slice_bounds(from, to, chars.len())?;
+            Ok(Value::Str(chars[from..to].iter().collect()))
+        }
+        other => Err(format!("cannot slice {}", other.type_name())),
+    }
+}
+
+fn expect_args(name: &str, args: &[Value], count: usize) -> Result<(), String> {
+    if args.len() != count {
+        return Err(format!("`{}` takes {} argument(s) but {} were given", name, count, args.len()));
+    }
+    Ok(())
+}
+
+pub const BUILTINS: &[&str] = &["print", "len", "push", "pop", "concat", "next"];
+
+#[cfg(not(test))]
+pub fn print_line(line: &str) {
+    println!("{}", line);
+}
+
+// test builds collect what scripts print per thread so outputs can be compared
+#[cfg(test)]
+thread_local! {
+    pub static OUTPUT: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+#[cfg(test)]
+pub fn print_line(line: &str) {
+    OUTPUT.with(|out| {
+        let mut out = out.borrow_mut();
+        out.push_str(line);
+        out.push('\n');
+    });
+}
+
+pub fn call_builtin(name: &str, args: Vec<Value>) -> Result<Value, String> {
+    match name {
+        "print" => {
+            let line: Vec<String> = args.iter().map(|v| v.to_string()).collect();
+            print_line(&line.join(" "));
+            Ok(Value::Null)
+        }
+        "len" => {
+            expect_args(name, &args, 1)?;
+            match &args[0] {
+                Value::Array(items) => Ok(Value::Int(items.borrow().len() as i64)),
+                Value::Str(s) => Ok(Value::Int(s.chars().count() as i64)),
+                Value::Range(from, to) => Ok(Value::Int(to.saturating_sub(*from).max(0))),
+                other => Err(format!("`len` expects an array, a string or a range, not {}", other.type_name())),
+            }
+        }
+        "push" => {
+            let mut args = args.into_iter();
+            match args.next() {
+                Some(Value::Array(items)) => {
+                    items.borrow_mut().extend(args);
+                    Ok(Value::Array(items))
+                }
+                Some(other) => Err(format!("`push` expects an array, not {}", other.type_name())),
+                None => Err("`push` needs an array to push onto".to_string()),
+            }
+        }
+        "pop" => {
+            expect_args(name, &args, 1)?;
+            match &args[0] {
+                Value::Array(items) => items
+                    .borrow_mut()
+                    .pop()
+                    .ok_or_else(|| "cannot `pop` from an empty array".to_string()),
+                other => Err(format!("`pop` expects an array, not {}", other.type_name())),
+            }
+        }
+        "concat" => {
+            let mut items = Vec::new();
+            for arg in &args {
+                match arg {
+                    Value::Array(a) => items.extend(a.borrow().iter().cloned()),
+                    other => items.push(other.clone()),
+                }
+            }
+            Ok(Value::array(items))
+        }
+        _ => Err(format!("unknown function `{}`", name)),
+    }
+}
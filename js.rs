@@ -0,0 +1,996 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use crate::first_pass::OperatorKind;
+use crate::lexer::Span;
+use crate::module::{Loader, Module};
+use crate::parser::{Expr, ExprKind, ImportKind, Number};
+
+const RUNTIME: &str = include_str!("js_runtime.js");
+
+pub struct Output {
+    pub code: String,
+    pub map: String,
+}
+
+// a piece of one generated line, remembering where each expression in it came from
+#[derive(Default)]
+struct Code {
+    text: String,
+    marks: Vec<(usize, Span)>,
+}
+
+impl Code {
+    fn new(text: impl Into<String>) -> Self {
+        Self { text: text.into(), marks: Vec::new() }
+    }
+
+    fn at(span: Span) -> Self {
+        Self { text: String::new(), marks: vec![(0, span)] }
+    }
+
+    fn push(&mut self, text: &str) {
+        self.text.push_str(text);
+    }
+
+    fn tap(mut self, text: &str) -> Self {
+        self.push(text);
+        self
+    }
+
+    fn append(&mut self, other: Code) {
+        let offset = self.text.len();
+        self.marks.extend(other.marks.into_iter().map(|(o, span)| (o + offset, span)));
+        self.text.push_str(&other.text);
+    }
+
+    fn list(&mut self, items: Vec<Code>) {
+        for (i, item) in items.into_iter().enumerate() {
+            if i > 0 {
+                self.push(", ");
+            }
+            self.append(item);
+        }
+    }
+}
+
+struct Mapping {
+    line: usize,
+    col: usize,
+    source: usize,
+    src_line: usize,
+    src_col: usize,
+}
+
+#[derive(Default)]
+struct Writer {
+    code: String,
+    line: usize,
+    indent: usize,
+    mappings: Vec<Mapping>,
+    // loader file id to its index in the source map's `sources`
+    files: HashMap<usize, usize>,
+}
+
+impl Writer {
+    fn raw(&mut self, text: &str) {
+        self.code.push_str(text);
+        self.line += text.matches('\n').count();
+    }
+
+    fn line(&mut self, code: Code) {
+        let col = self.indent * 2;
+        for (offset, span) in code.marks {
+            if let (Some(&source), true) = (self.files.get(&span.file), span.line > 0) {
+                self.mappings.push(Mapping {
+                    line: self.line,
+                    col: col + offset,
+                    source,
+                    src_line: span.line - 1,
+                    src_col: span.col.saturating_sub(1),
+                });
+            }
+        }
+        self.code.push_str(&"  ".repeat(self.indent));
+        self.code.push_str(&code.text);
+        self.code.push('\n');
+        self.line += 1;
+    }
+}
+
+// where the value of a statement goes
+enum Dest {
+    Discard,
+    Assign(String),
+    Return,
+}
+
+struct Loop {
+    label: Option<String>,
+    // the JS label on the loop itself, and on the block around it when it has an `else`
+    name: Option<String>,
+    exit: Option<String>,
+}
+
+struct Transpiler<'a> {
+    module: &'a Module,
+    out: &'a mut Writer,
+    operators: HashMap<String, (OperatorKind, String)>,
+    // JS block scopes, so a name is only declared once per block
+    declared: Vec<HashSet<String>>,
+    consts: Vec<HashSet<String>>,
+    loops: Vec<Loop>,
+    counter: usize,
+    function: bool,
+    generator: bool,
+}
+
+fn js_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            ' '..='~' => out.push(c),
+            _ => {
+                let mut units = [0u16; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    out.push_str(&format!("\\u{:04x}", unit));
+                }
+            }
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn var(name: &str) -> String {
+    format!("v_{}", name)
+}
+
+fn constant(name: &str) -> String {
+    format!("c_{}", name)
+}
+
+// the expression an `if` branch comes down to, if it is a single one
+fn branch_value(expr: &Expr) -> Option<Option<&Expr>> {
+    match &expr.kind {
+        ExprKind::Block(items) => match items.as_slice() {
+            [] => Some(None),
+            [item] => Some(Some(item)),
+            _ => None,
+        },
+        _ => Some(Some(expr)),
+    }
+}
+
+fn railway(item: &Expr) -> Option<&str> {
+    match &item.kind {
+        ExprKind::Function { name, .. } => Some(name),
+        ExprKind::Export(inner) => railway(inner),
+        _ => None,
+    }
+}
+
+impl Transpiler<'_> {
+    fn line(&mut self, code: Code) {
+        self.out.line(code);
+    }
+
+    fn open(&mut self, code: Code) {
+        self.out.line(code);
+        self.out.indent += 1;
+    }
+
+    fn close(&mut self, text: &str) {
+        self.out.indent -= 1;
+        self.out.line(Code::new(text));
+    }
+
+    fn reopen(&mut self, text: &str) {
+        self.close(text);
+        self.out.indent += 1;
+    }
+
+    fn fresh(&mut self, prefix: &str) -> String {
+        self.counter += 1;
+        format!("{}{}", prefix, self.counter)
+    }
+
+    fn at(&self, span: Span) -> String {
+        let source = self.out.files.get(&span.file).copied().unwrap_or(0);
+        format!("[{}, {}, {}]", source, span.line, span.col)
+    }
+
+    fn enter(&mut self) {
+        self.declared.push(HashSet::new());
+        self.consts.push(HashSet::new());
+    }
+
+    fn leave(&mut self) {
+        self.declared.pop();
+        self.consts.pop();
+    }
+
+    // `let` the first time a block sees a name, plain assignment after that
+    fn bind(&mut self, name: &str, assign: bool) -> String {
+        let scope = self.declared.last_mut().unwrap();
+        if assign || !scope.insert(name.to_string()) {
+            var(name)
+        } else {
+            format!("let {}", var(name))
+        }
+    }
+
+    fn is_simple(&self, expr: &Expr) -> bool {
+        match &expr.kind {
+            ExprKind::String(_) | ExprKind::Number(_) | ExprKind::Variable(_) | ExprKind::Const(_) => true,
+            ExprKind::Interpolated(items) | ExprKind::Array(items) | ExprKind::Func { args: items, .. } => {
+                items.iter().all(|e| self.is_simple(e))
+            }
+            ExprKind::Binary { left, right, .. } => self.is_simple(left) && self.is_simple(right),
+            ExprKind::Unary { oper, .. } => self.is_simple(oper),
+            ExprKind::Index { target, index } => self.is_simple(target) && self.is_simple(index),
+            ExprKind::Slice { target, from, to } => {
+                self.is_simple(target) && from.iter().chain(to.iter()).all(|e| self.is_simple(e))
+            }
+            ExprKind::If { cond, then, else_then } => {
+                let simple_branch = |e: &Expr| match branch_value(e) {
+                    Some(Some(e)) => self.is_simple(e),
+                    Some(None) => true,
+                    None => false,
+                };
+                self.is_simple(cond) && simple_branch(then) && simple_branch(else_then)
+            }
+            ExprKind::Yield(inner) => self.is_simple(inner),
+            _ => false,
+        }
+    }
+
+    fn user_operator(&self, op: &str, binary: bool) -> Option<&str> {
+        self.operators
+            .get(op)
+            .filter(|(kind, _)| (*kind == OperatorKind::Binary) == binary)
+            .map(|(_, name)| name.as_str())
+    }
+
+    // operands are evaluated left to right, so anything before one that needs statements is saved first
+    fn operands(&mut self, items: &[&Expr]) -> Vec<Code> {
+        let mut codes: Vec<Code> = Vec::new();
+        for item in items {
+            if !self.is_simple(item) {
+                for code in codes.iter_mut() {
+                    let temp = self.fresh("t");
+                    let mut line = Code::new(format!("const {} = ", temp));
+                    line.append(std::mem::replace(code, Code::new(temp)));
+                    line.push(";");
+                    self.line(line);
+                }
+            }
+            codes.push(self.expr(item));
+        }
+        codes
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Code {
+        let statement = match &expr.kind {
+            ExprKind::If { .. } => !self.is_simple(expr),
+            ExprKind::String(_) | ExprKind::Number(_) | ExprKind::Variable(_) | ExprKind::Const(_) => false,
+            ExprKind::Interpolated(_) | ExprKind::Array(_) | ExprKind::Func { .. } | ExprKind::Yield(_) => false,
+            ExprKind::Binary { .. } | ExprKind::Unary { .. } | ExprKind::Index { .. } | ExprKind::Slice { .. } => false,
+            _ => true,
+        };
+        if statement {
+            let temp = self.fresh("t");
+            self.line(Code::new(format!("let {};", temp)));
+            self.stmt(expr, &Dest::Assign(temp.clone()));
+            return Code::new(temp);
+        }
+
+        let mut code = Code::at(expr.span);
+        match &expr.kind {
+            ExprKind::String(s) => code.push(&js_string(s)),
+
+            ExprKind::Number(Number::Int(n)) => code.push(&format!("{}n", n)),
+
+            ExprKind::Number(Number::Float(n)) => code.push(&format!("{:?}", n)),
+
+            ExprKind::Variable(name) => code.push(&var(name)),
+
+            ExprKind::Const(name) => code.push(&constant(name)),
+
+            ExprKind::Interpolated(parts) => {
+                let parts = self.operands(&parts.iter().collect::<Vec<_>>());
+                code.push("$.interpolate([");
+                code.list(parts);
+                code.push("])");
+            }
+
+            ExprKind::Array(items) => {
+                let items = self.operands(&items.iter().collect::<Vec<_>>());
+                code.push("[");
+                code.list(items);
+                code.push("]");
+            }
+
+            ExprKind::Binary { left, op, right } => {
+                let mut operands = self.operands(&[&**left, &**right]).into_iter();
+                let (l, r) = (operands.next().unwrap(), operands.next().unwrap());
+                match self.user_operator(op, true).map(str::to_string) {
+                    Some(name) => {
+                        code.push(&format!("$.apply({}, [", name));
+                        code.list(vec![l, r]);
+                        code.push(&format!("], {})", self.at(expr.span)));
+                    }
+                    None => {
+                        code.push(&format!("$.binary({}, ", js_string(op)));
+                        code.list(vec![l, r]);
+                        code.push(&format!(", {})", self.at(expr.span)));
+                    }
+                }
+            }
+
+            ExprKind::Unary { oper, op } => {
+                let v = self.expr(oper);
+                match self.user_operator(op, false).map(str::to_string) {
+                    Some(name) => {
+                        code.push(&format!("$.apply({}, [", name));
+                        code.append(v);
+                        code.push(&format!("], {})", self.at(expr.span)));
+                    }
+                    None => {
+                        code.push(&format!("$.unary({}, ", js_string(op)));
+                        code.append(v);
+                        code.push(&format!(", {})", self.at(expr.span)));
+                    }
+                }
+            }
+
+            ExprKind::Func { name, args } => {
+                let args = self.operands(&args.iter().collect::<Vec<_>>());
+                if self.module.resolution.uses.contains_key(&expr.span) {
+                    code.push(&format!("$.call({}, {}, [", var(name), js_string(name)));
+                } else {
+                    code.push(&format!("$.builtin({}, [", js_string(name)));
+                }
+                code.list(args);
+                code.push(&format!("], {})", self.at(expr.span)));
+            }
+
+            ExprKind::Index { target, index } => {
+                let parts = self.operands(&[&**target, &**index]);
+                code.push("$.index(");
+                code.list(parts);
+                code.push(&format!(", {})", self.at(expr.span)));
+            }
+
+            ExprKind::Slice { target, from, to } => {
+                let mut items = vec![&**target];
+                items.extend(from.as_deref());
+                items.extend(to.as_deref());
+                let mut parts = self.operands(&items).into_iter();
+                code.push("$.slice(");
+                code.append(parts.next().unwrap());
+                for bound in [from, to] {
+                    code.push(", ");
+                    match bound {
+                        Some(_) => code.append(parts.next().unwrap()),
+                        None => code.push("undefined"),
+                    }
+                }
+                code.push(&format!(", {})", self.at(expr.span)));
+            }
+
+            ExprKind::If { cond, then, else_then } => {
+                let cond = self.expr(cond);
+                code.push("($.truthy(");
+                code.append(cond);
+                code.push(") ? ");
+                for (i, branch) in [then, else_then].into_iter().enumerate() {
+                    if i > 0 {
+                        code.push(" : ");
+                    }
+                    match branch_value(branch).flatten() {
+                        Some(e) => {
+                            let value = self.expr(e);
+                            code.append(value);
+                        }
+                        None => code.push("null"),
+                    }
+                }
+                code.push(")");
+            }
+
+            ExprKind::Yield(inner) => {
+                let value = self.expr(inner);
+                code.push("(yield ");
+                code.append(value);
+                code.push(")");
+            }
+
+            _ => unreachable!("statement compiled as an expression"),
+        }
+        code
+    }
+
+    fn finish(&mut self, value: Code, dest: &Dest) {
+        let mut line = Code::new(match dest {
+            Dest::Discard => String::new(),
+            Dest::Assign(name) => format!("{} = ", name),
+            Dest::Return => "return ".to_string(),
+        });
+        if matches!(dest, Dest::Discard) && value.text == "null" {
+            return;
+        }
+        line.append(value);
+        line.push(";");
+        self.line(line);
+    }
+
+    // the start of a statement whose value is written across several lines
+    fn prefix(dest: &Dest, span: Span) -> Code {
+        let mut code = Code::at(span);
+        match dest {
+            Dest::Discard => {}
+            Dest::Assign(name) => code.push(&format!("{} = ", name)),
+            Dest::Return => code.push("return "),
+        }
+        code
+    }
+
+    fn statements(&mut self, items: &[Expr], dest: &Dest) {
+        // railways can call ones defined later in the block, so every one gets its name up front
+        for name in items.iter().filter_map(railway) {
+            if self.declared.last_mut().unwrap().insert(name.to_string()) {
+                self.line(Code::new(format!("let {};", var(name))));
+            }
+        }
+
+        match items.split_last() {
+            Some((last, rest)) => {
+                for item in rest {
+                    self.stmt(item, &Dest::Discard);
+                }
+                self.stmt(last, dest);
+            }
+            None => self.finish(Code::new("null"), dest),
+        }
+    }
+
+    // the contents of a block that already has braces of its own in the output
+    fn body(&mut self, expr: &Expr, dest: &Dest) {
+        match &expr.kind {
+            ExprKind::Block(items) => self.statements(items, dest),
+            _ => self.stmt(expr, dest),
+        }
+    }
+
+    fn branch(&mut self, expr: &Expr, dest: &Dest) {
+        self.enter();
+        self.body(expr, dest);
+        self.leave();
+    }
+
+    fn is_empty(expr: &Expr) -> bool {
+        matches!(&expr.kind, ExprKind::Block(items) if items.is_empty())
+    }
+
+    fn function(&mut self, head: Code, params: &[String], body: &Expr, generator: bool) {
+        let loops = std::mem::take(&mut self.loops);
+        let function = std::mem::replace(&mut self.function, true);
+        let saved = std::mem::replace(&mut self.generator, generator);
+
+        self.open(head);
+        self.enter();
+        for param in params {
+            self.declared.last_mut().unwrap().insert(param.clone());
+        }
+        self.body(body, if generator { &Dest::Discard } else { &Dest::Return });
+        self.leave();
+
+        self.generator = saved;
+        self.function = function;
+        self.loops = loops;
+    }
+
+    fn loop_labels(&mut self, label: &Option<String>, else_then: &Expr) -> Loop {
+        let n = self.fresh("");
+        Loop {
+            label: label.clone(),
+            name: label.as_ref().map(|l| format!("{}_{}", l, n)),
+            exit: (!Self::is_empty(else_then)).then(|| format!("exit_{}", n)),
+        }
+    }
+
+    fn loop_head(&mut self, lp: &Loop, span: Span, head: Code) {
+        if let Some(exit) = &lp.exit {
+            self.open(Code::at(span).tap(&format!("{}: {{", exit)));
+        }
+        let mut code = Code::at(span);
+        if let Some(name) = &lp.name {
+            code.push(&format!("{}: ", name));
+        }
+        code.append(head);
+        self.open(code);
+    }
+
+    fn target(&self, label: &Option<String>) -> Option<usize> {
+        match label {
+            None => self.loops.len().checked_sub(1),
+            Some(label) => self.loops.iter().rposition(|l| l.label.as_ref() == Some(label)),
+        }
+    }
+
+    fn stmt(&mut self, expr: &Expr, dest: &Dest) {
+        let span = expr.span;
+        match &expr.kind {
+            ExprKind::Block(items) => {
+                self.open(Code::new("{"));
+                self.enter();
+                self.statements(items, dest);
+                self.leave();
+                self.close("}");
+            }
+
+            ExprKind::If { cond, then, else_then } => {
+                let cond = self.expr(cond);
+                let mut head = Code::at(span).tap("if ($.truthy(");
+                head.append(cond);
+                head.push(")) {");
+                self.open(head);
+                self.branch(then, dest);
+                if !Self::is_empty(else_then) || !matches!(dest, Dest::Discard) {
+                    self.reopen("} else {");
+                    self.branch(else_then, dest);
+                }
+                self.close("}");
+            }
+
+            ExprKind::While { cond, then, else_then, label } => {
+                let lp = self.loop_labels(label, else_then);
+                if self.is_simple(cond) {
+                    let cond = self.expr(cond);
+                    let mut head = Code::new("while ($.truthy(");
+                    head.append(cond);
+                    head.push(")) {");
+                    self.loop_head(&lp, span, head);
+                } else {
+                    self.loop_head(&lp, span, Code::new("for (;;) {"));
+                    self.enter();
+                    let cond = self.expr(cond);
+                    let mut check = Code::new("if (!$.truthy(");
+                    check.append(cond);
+                    check.push(")) break;");
+                    self.line(check);
+                    self.leave();
+                }
+                let exit = lp.exit.is_some();
+                self.loops.push(lp);
+                self.branch(then, &Dest::Discard);
+                self.loops.pop();
+                self.close("}");
+                if exit {
+                    self.branch(else_then, &Dest::Discard);
+                    self.close("}");
+                }
+                self.finish(Code::new("null"), dest);
+            }
+
+            ExprKind::For { iter, var: name, source, then, else_then, label } => {
+                let lp = self.loop_labels(label, else_then);
+                let items = match source {
+                    Some(source) => {
+                        let mut code = Code::new("$.each(");
+                        code.append(self.expr(source));
+                        code.push(&format!(", {})", self.at(span)));
+                        code
+                    }
+                    None => Code::new(format!("$.times({}n)", iter)),
+                };
+                let mut head = Code::new(format!("for (let {} of ", var(name)));
+                head.append(items);
+                head.push(") {");
+                self.loop_head(&lp, span, head);
+                let exit = lp.exit.is_some();
+                self.loops.push(lp);
+                self.enter();
+                self.declared.last_mut().unwrap().insert(name.clone());
+                self.branch(then, &Dest::Discard);
+                self.leave();
+                self.loops.pop();
+                self.close("}");
+                if exit {
+                    self.branch(else_then, &Dest::Discard);
+                    self.close("}");
+                }
+                self.finish(Code::new("null"), dest);
+            }
+
+            ExprKind::Define { var: name, constant: true, val } => {
+                let value = self.expr(val);
+                let mut line = Code::at(span);
+                if self.consts.iter().any(|scope| scope.contains(name)) {
+                    line.push(&format!("throw $.error({}, {});", js_string(&format!("cannot redefine constant `${}`", name)), self.at(span)));
+                    self.line(line);
+                    return;
+                }
+                self.consts.last_mut().unwrap().insert(name.clone());
+                line.push(&format!("const {} = $.freeze(", constant(name)));
+                line.append(value);
+                line.push(");");
+                self.line(line);
+                self.finish(Code::new("null"), dest);
+            }
+
+            ExprKind::Define { var: name, val, .. } => {
+                let value = self.expr(val);
+                let assign = self.module.resolution.uses.contains_key(&span);
+                let mut line = Code::at(span).tap(&format!("{} = ", self.bind(name, assign)));
+                line.append(value);
+                line.push(";");
+                self.line(line);
+                self.finish(Code::new("null"), dest);
+            }
+
+            ExprKind::Function { name, params, body } => {
+                let generator = body.contains_yield();
+                let names: Vec<String> = params.iter().map(|p| var(p)).collect();
+                let target = self.bind(name, false);
+                let head = Code::at(span).tap(&format!(
+                    "{} = $.railway({}, function{} ({}) {{",
+                    target,
+                    js_string(name),
+                    if generator { "*" } else { "" },
+                    names.join(", ")
+                ));
+                self.function(head, params, body, generator);
+                self.close("});");
+                self.finish(Code::new("null"), dest);
+            }
+
+            ExprKind::Generator(body) => {
+                let head = Self::prefix(dest, span).tap("$.block(function* () {");
+                self.function(head, &[], body, true);
+                self.close("});");
+            }
+
+            ExprKind::Return(value) => {
+                if !self.function {
+                    let line = Code::at(span).tap(&format!("throw $.error(\"`return` outside of a function\", {});", self.at(span)));
+                    self.line(line);
+                    return;
+                }
+                let value = match value {
+                    Some(value) => self.expr(value),
+                    None => Code::new("null"),
+                };
+                let mut line = Code::at(span).tap("return ");
+                line.append(value);
+                line.push(";");
+                self.line(line);
+            }
+
+            ExprKind::Throw(value) => {
+                let value = self.expr(value);
+                let mut line = Code::at(span).tap("throw $.thrown(");
+                line.append(value);
+                line.push(&format!(", {});", self.at(span)));
+                self.line(line);
+            }
+
+            ExprKind::Break(label) | ExprKind::Continue(label) => {
+                let is_break = matches!(expr.kind, ExprKind::Break(_));
+                let keyword = if is_break { "break" } else { "continue" };
+                let line = match self.target(label) {
+                    Some(i) => {
+                        let lp = &self.loops[i];
+                        let innermost = i + 1 == self.loops.len();
+                        let target = match (is_break, &lp.exit, &lp.name) {
+                            (true, Some(exit), _) => Some(exit.clone()),
+                            (_, _, Some(name)) if !innermost => Some(name.clone()),
+                            _ => None,
+                        };
+                        match target {
+                            Some(target) => format!("{} {};", keyword, target),
+                            None => format!("{};", keyword),
+                        }
+                    }
+                    None => format!("throw $.error(\"`{}` outside of a loop\", {});", keyword, self.at(span)),
+                };
+                self.line(Code::at(span).tap(&line));
+            }
+
+            ExprKind::Try { attempt, binding, catch, finally } => {
+                self.open(Code::at(span).tap("try {"));
+                self.branch(attempt, dest);
+                if let Some(catch) = catch {
+                    self.reopen("} catch (e) {");
+                    self.enter();
+                    match binding {
+                        Some(name) => {
+                            let target = self.bind(name, false);
+                            self.line(Code::new(format!("{} = $.caught(e);", target)));
+                        }
+                        None => self.line(Code::new("$.caught(e);")),
+                    }
+                    self.body(catch, dest);
+                    self.leave();
+                }
+                if let Some(finally) = finally {
+                    self.reopen("} finally {");
+                    self.branch(finally, &Dest::Discard);
+                }
+                self.close("}");
+            }
+
+            ExprKind::Yield(inner) if self.generator && matches!(dest, Dest::Discard) => {
+                let value = self.expr(inner);
+                let mut line = Code::at(span).tap("yield ");
+                line.append(value);
+                line.push(";");
+                self.line(line);
+            }
+
+            ExprKind::Export(inner) => self.stmt(inner, dest),
+
+            ExprKind::Import(_) => self.finish(Code::new("null"), dest),
+
+            ExprKind::Error => {
+                let line = Code::at(span).tap(&format!("throw $.error(\"cannot run code that failed to parse\", {});", self.at(span)));
+                self.line(line);
+            }
+
+            _ => {
+                let value = self.expr(expr);
+                self.finish(value, dest);
+            }
+        }
+    }
+}
+
+fn deps(module: &Module) -> Vec<usize> {
+    let mut deps: Vec<usize> = module.imports.iter().map(|(_, dep)| *dep).collect();
+    deps.sort();
+    deps.dedup();
+    deps
+}
+
+fn module(loader: &Loader, id: usize, out: &mut Writer) {
+    let module = &loader.modules[&id];
+    let deps = deps(module);
+
+    let mut t = Transpiler {
+        module,
+        out,
+        operators: HashMap::new(),
+        declared: vec![HashSet::new()],
+        consts: vec![HashSet::new()],
+        loops: Vec::new(),
+        counter: 0,
+        function: false,
+        generator: false,
+    };
+
+    t.out.raw(&format!("\n// {}\n", loader.sources[id].name));
+    let params: Vec<String> = deps.iter().map(|d| format!("m_{}", d)).collect();
+    t.open(Code::new(format!("function module_{}({}) {{", id, params.join(", "))));
+
+    // the module's own operators win over imported ones of the same name, as in the interpreter
+    let mut locals: Vec<_> = module.operators.values().collect();
+    locals.sort_by_key(|op| op.body.span.start);
+    for op in &locals {
+        let name = t.fresh("op_");
+        t.operators.insert(op.op.clone(), (op.kind.clone(), name));
+    }
+
+    for (import, dep) in &module.imports {
+        let available = &loader.modules[dep];
+        let names = import.names.as_ref().unwrap_or(&available.exports);
+        for name in names {
+            match name.kind {
+                ImportKind::Variable => {
+                    let target = t.bind(&name.name, false);
+                    t.line(Code::at(name.span).tap(&format!("{} = m_{}.vars[{}];", target, dep, js_string(&name.name))));
+                }
+                ImportKind::Const => {
+                    if t.consts[0].insert(name.name.clone()) {
+                        t.line(Code::at(name.span).tap(&format!("const {} = m_{}.consts[{}];", constant(&name.name), dep, js_string(&name.name))));
+                    }
+                }
+                ImportKind::Operator => {
+                    if t.operators.contains_key(&name.name) {
+                        continue;
+                    }
+                    if let Some(def) = available.exported_ops.iter().find(|d| d.op == name.name) {
+                        let local = t.fresh("op_");
+                        t.line(Code::at(name.span).tap(&format!("const {} = m_{}.operators[{}];", local, dep, js_string(&name.name))));
+                        t.operators.insert(name.name.clone(), (def.kind.clone(), local));
+                    }
+                }
+            }
+        }
+    }
+
+    for op in &locals {
+        let name = t.operators[&op.op].1.clone();
+        let params: Vec<String> = op.params.iter().map(|p| var(p)).collect();
+        let head = Code::at(op.body.span).tap(&format!("const {} = $.operator({}, function ({}) {{", name, js_string(&op.op), params.join(", ")));
+        t.function(head, &op.params, &op.body, false);
+        t.close("});");
+    }
+
+    t.line(Code::new("let value = null;"));
+    match &module.ast.kind {
+        ExprKind::Block(items) => t.statements(items, &Dest::Assign("value".to_string())),
+        _ => t.stmt(&module.ast, &Dest::Assign("value".to_string())),
+    }
+
+    let mut vars = Vec::new();
+    let mut consts = Vec::new();
+    let mut operators = Vec::new();
+    for name in &module.exports {
+        match name.kind {
+            ImportKind::Variable => vars.push(format!("{}: {}", name.name, var(&name.name))),
+            ImportKind::Const => consts.push(format!("{}: {}", name.name, constant(&name.name))),
+            ImportKind::Operator => {
+                if let Some((_, local)) = t.operators.get(&name.name) {
+                    operators.push(format!("{}: {}", js_string(&name.name), local));
+                }
+            }
+        }
+    }
+    let table = |entries: Vec<String>| match entries.is_empty() {
+        true => "{}".to_string(),
+        false => format!("{{ {} }}", entries.join(", ")),
+    };
+    t.line(Code::new(format!(
+        "return {{ value, vars: {}, consts: {}, operators: {} }};",
+        table(vars),
+        table(consts),
+        table(operators)
+    )));
+    t.close("}");
+}
+
+const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn vlq(out: &mut String, value: i64) {
+    let mut rest = if value < 0 { ((-value) << 1) | 1 } else { value << 1 } as u64;
+    loop {
+        let mut digit = (rest & 31) as usize;
+        rest >>= 5;
+        if rest > 0 {
+            digit |= 32;
+        }
+        out.push(BASE64[digit] as char);
+        if rest == 0 {
+            break;
+        }
+    }
+}
+
+fn mappings(mut list: Vec<Mapping>) -> String {
+    list.sort_by_key(|m| (m.line, m.col));
+    list.dedup_by_key(|m| (m.line, m.col));
+
+    let mut out = String::new();
+    let (mut line, mut col, mut source, mut src_line, mut src_col) = (0, 0, 0, 0, 0);
+    let mut first = true;
+    for m in list {
+        while line < m.line {
+            out.push(';');
+            line += 1;
+            col = 0;
+            first = true;
+        }
+        if !first {
+            out.push(',');
+        }
+        first = false;
+        vlq(&mut out, m.col as i64 - col as i64);
+        vlq(&mut out, m.source as i64 - source as i64);
+        vlq(&mut out, m.src_line as i64 - src_line as i64);
+        vlq(&mut out, m.src_col as i64 - src_col as i64);
+        (col, source, src_line, src_col) = (m.col, m.source, m.src_line, m.src_col);
+    }
+    out
+}
+
+// bundle the entry module and everything it imports into one script, plus a source map for it
+pub fn transpile(loader: &Loader, entry: usize, output: &Path) -> Output {
+    let ids = loader.order(entry);
+
+    let dir = output.parent().unwrap_or(Path::new(""));
+    let sources: Vec<String> = ids
+        .iter()
+        .map(|id| {
+            let name = &loader.sources[*id].name;
+            Path::new(name).strip_prefix(dir).map(|p| p.display().to_string()).unwrap_or_else(|_| name.clone())
+        })
+        .collect();
+
+    let mut out = Writer { files: ids.iter().enumerate().map(|(i, id)| (*id, i)).collect(), ..Default::default() };
+    out.raw("\"use strict\";\n");
+    out.raw(RUNTIME);
+    let names: Vec<String> = sources.iter().map(|s| js_string(s)).collect();
+    out.raw(&format!("$.sources = [{}];\n", names.join(", ")));
+
+    for &id in &ids {
+        module(loader, id, &mut out);
+    }
+
+    out.raw("\n$.main(() => {\n");
+    for &id in &ids {
+        let deps: Vec<String> = deps(&loader.modules[&id]).iter().map(|d| format!("m_{}", d)).collect();
+        out.raw(&format!("  const m_{} = module_{}({});\n", id, id, deps.join(", ")));
+    }
+    out.raw(&format!("  return m_{}.value;\n}});\n", entry));
+
+    let file = output.file_name().map(|f| f.to_string_lossy().to_string()).unwrap_or_default();
+    out.raw(&format!("//# sourceMappingURL={}.map\n", file));
+
+    let contents: Vec<String> = ids.iter().map(|id| js_string(&loader.sources[*id].text)).collect();
+    let map = format!(
+        "{{\"version\":3,\"file\":{},\"sources\":[{}],\"sourcesContent\":[{}],\"names\":[],\"mappings\":\"{}\"}}\n",
+        js_string(&file),
+        names.join(","),
+        contents.join(","),
+        mappings(out.mappings)
+    );
+    Output { code: out.code, map }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // every segment as (generated line, generated column, source, source line, source column), all from 0
+    fn decode(mappings: &str) -> Vec<[i64; 5]> {
+        let mut segments = Vec::new();
+        let mut fields = [0i64; 5];
+        for (line, text) in mappings.split(';').enumerate() {
+            fields[1] = 0;
+            for segment in text.split(',').filter(|s| !s.is_empty()) {
+                let (mut value, mut shift, mut field) = (0i64, 0, 1);
+                for c in segment.bytes() {
+                    let digit = BASE64.iter().position(|&b| b == c).unwrap() as i64;
+                    value |= (digit & 31) << shift;
+                    shift += 5;
+                    if digit & 32 == 0 {
+                        fields[field] += if value & 1 == 1 { -(value >> 1) } else { value >> 1 };
+                        (value, shift, field) = (0, 0, field + 1);
+                    }
+                }
+                assert_eq!(field, 5, "segment `{}` has four fields", segment);
+                fields[0] = line as i64;
+                segments.push(fields);
+            }
+        }
+        segments
+    }
+
+    #[test]
+    fn the_source_map_leads_back_to_the_script() {
+        let dir = std::env::temp_dir().join(format!("vita-map-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("map.vit");
+        fs::write(&path, "print(1)\nI would love to own a plot of land in the 1800s called £x and lease it to 2 owners\nprint(£x / 0)\n").unwrap();
+        let mut loader = Loader::new();
+        let entry = loader.load(&path, None).unwrap();
+        let output = transpile(&loader, entry, &dir.join("map.js"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(output.map.contains("\"sources\":[\"map.vit\"]"), "{}", output.map);
+        let mappings = output.map.split("\"mappings\":\"").nth(1).and_then(|m| m.split('"').next()).unwrap();
+        let segments = decode(mappings);
+        let (line, text) = output.code.lines().enumerate().find(|(_, l)| l.contains("$.binary(\"/\"")).unwrap();
+        let col = text.find("$.binary").unwrap() as i64;
+        let found = segments.iter().find(|s| s[0] == line as i64 && s[1] == col);
+        // `£x / 0` starts on line 3, column 7
+        assert_eq!(found, Some(&[line as i64, col, 0, 2, 6]), "no segment for `{}` in {:?}", text, segments);
+    }
+}
@@ -0,0 +1,531 @@
+// runtime for scripts transpiled by `vita js`; values and errors behave as they do in the interpreter
+const $ = (() => {
+  const MAX_CALL_DEPTH = 1000;
+  const I64_MIN = -(2n ** 63n);
+  const I64_MAX = 2n ** 63n - 1n;
+  const U32_MAX = 2n ** 32n - 1n;
+  const DONE = Symbol("done");
+  const GeneratorFunction = Object.getPrototypeOf(function* () {}).constructor;
+
+  const frozen = new WeakSet();
+  let depth = 0;
+
+  class Range {
+    constructor(from, to) {
+      this.from = from;
+      this.to = to;
+    }
+  }
+
+  class Railway {
+    constructor(name, fn) {
+      this.name = name;
+      this.fn = fn;
+      this.arity = fn.length;
+      this.generator = fn instanceof GeneratorFunction;
+    }
+  }
+
+  class Operator {
+    constructor(name, fn) {
+      this.name = name;
+      this.fn = fn;
+    }
+  }
+
+  class Generator {
+    constructor(name, iterator) {
+      this.name = name;
+      this.iterator = iterator;
+      this.state = "ready";
+    }
+  }
+
+  // `at` is [source, line, column], with source indexing `sources`
+  class VitaError extends Error {
+    constructor(kind, message, at) {
+      super(message);
+      this.kind = kind;
+      this.at = at;
+      this.trace = [];
+    }
+  }
+
+  const rt = { sources: [], VitaError };
+
+  function error(message, at) {
+    return new VitaError("RuntimeError", message, at);
+  }
+
+  function overflow(op) {
+    return `arithmetic overflow in \`${op}\``;
+  }
+
+  function noRealResult(op) {
+    return `\`${op}\` has no real result`;
+  }
+
+  function typeName(v) {
+    if (v === null) return "nothing";
+    if (typeof v === "boolean") return "bool";
+    if (typeof v === "bigint" || typeof v === "number") return "number";
+    if (typeof v === "string") return "string";
+    if (Array.isArray(v)) return "array";
+    if (v instanceof Range) return "range";
+    if (v instanceof Railway) return "function";
+    if (v instanceof Generator) return "generator";
+    return "error";
+  }
+
+  function truthy(v) {
+    if (v === null) return false;
+    if (typeof v === "boolean") return v;
+    if (typeof v === "bigint") return v !== 0n;
+    if (typeof v === "number") return v !== 0;
+    if (typeof v === "string" || Array.isArray(v)) return v.length > 0;
+    if (v instanceof Range) return v.from < v.to;
+    return true;
+  }
+
+  // floats print the way Rust's `{:?}` does: always with a fraction or an exponent
+  function showFloat(n) {
+    if (Number.isNaN(n)) return "NaN";
+    if (!Number.isFinite(n)) return n > 0 ? "inf" : "-inf";
+    if (n === 0) return Object.is(n, -0) ? "-0.0" : "0.0";
+    const abs = Math.abs(n);
+    if (abs >= 1e-4 && abs < 1e16) {
+      const text = String(n);
+      return Number.isInteger(n) ? `${text}.0` : text;
+    }
+    return n.toExponential().replace("e+", "e");
+  }
+
+  function show(v) {
+    if (v === null) return "nothing";
+    if (typeof v === "number") return showFloat(v);
+    if (typeof v !== "object") return String(v);
+    if (Array.isArray(v)) return `[${v.map(show).join(", ")}]`;
+    if (v instanceof Range) return `${v.from}..${v.to}`;
+    if (v instanceof Railway) return `<railway ${v.name}>`;
+    if (v instanceof Generator) return `<generator ${v.name}>`;
+    return `${v.kind}: ${v.message}`;
+  }
+
+  function equal(l, r) {
+    if (Array.isArray(l) && Array.isArray(r)) {
+      return l.length === r.length && l.every((x, i) => equal(x, r[i]));
+    }
+    if (l instanceof Range && r instanceof Range) return l.from === r.from && l.to === r.to;
+    if (typeof l === "bigint" && typeof r === "bigint") return l === r;
+    const a = asFloat(l);
+    const b = asFloat(r);
+    if (a !== undefined && b !== undefined) return a === b;
+    return l === r;
+  }
+
+  function asFloat(v) {
+    if (typeof v === "bigint") return Number(v);
+    if (typeof v === "number") return v;
+    return undefined;
+  }
+
+  function int(op, n) {
+    if (n < I64_MIN || n > I64_MAX) throw overflow(op);
+    return n;
+  }
+
+  function float(op, n) {
+    if (Number.isNaN(n)) throw noRealResult(op);
+    if (!Number.isFinite(n)) throw overflow(op);
+    return n;
+  }
+
+  function operands(op, l, r) {
+    if (typeof l === "bigint" && typeof r === "bigint") return [l, r];
+    const a = asFloat(l);
+    const b = asFloat(r);
+    if (a === undefined || b === undefined) {
+      throw `cannot apply \`${op}\` to ${typeName(l)} and ${typeName(r)}`;
+    }
+    return [a, b];
+  }
+
+  function pow(a, b) {
+    if (b > U32_MAX) throw overflow("^");
+    if (a !== 0n && a !== 1n && a !== -1n && b >= 64n) throw overflow("^");
+    return int("^", a ** b);
+  }
+
+  function tetrate(a, b) {
+    if (typeof a === "bigint") {
+      let height = b;
+      if (height < 0n) throw "cannot tetrate to a negative height";
+      if ((a === 0n || a === -1n) && height > 3n) height = 2n + (height % 2n);
+      let result = 1n;
+      for (let i = 0n; i < height; i++) {
+        if (result < 0n || result > U32_MAX) throw overflow("^^");
+        if (a !== 0n && a !== 1n && a !== -1n && result >= 64n) throw overflow("^^");
+        const next = a ** result;
+        if (next < I64_MIN || next > I64_MAX) throw overflow("^^");
+        if (next === result) break;
+        result = next;
+      }
+      return result;
+    }
+    if (!Number.isInteger(b)) throw "tetration height must be a whole number";
+    if (b < 0) throw "cannot tetrate to a negative height";
+    let result = 1.0;
+    for (let i = 0; i < b; i++) {
+      const next = Math.pow(a, result);
+      if (Number.isNaN(next)) throw noRealResult("^^");
+      if (!Number.isFinite(next)) throw overflow("^^");
+      if (next === result) break;
+      result = next;
+    }
+    return result;
+  }
+
+  function arithmetic(op, l, r) {
+    const [a, b] = operands(op, l, r);
+    if (typeof a === "bigint") {
+      switch (op) {
+        case "+": return int(op, a + b);
+        case "-": return int(op, a - b);
+        case "*": return int(op, a * b);
+        case "/":
+          if (b === 0n) throw "division by zero";
+          if (a % b !== 0n) return float(op, Number(a) / Number(b));
+          return int(op, a / b);
+        case "^":
+          if (b < 0n) {
+            if (a === 0n) throw "division by zero";
+            return float(op, Math.pow(Number(a), Number(b)));
+          }
+          return pow(a, b);
+        case "^^": return tetrate(a, b);
+        default: throw overflow(op);
+      }
+    }
+    switch (op) {
+      case "+": return float(op, a + b);
+      case "-": return float(op, a - b);
+      case "*": return float(op, a * b);
+      case "/":
+        if (b === 0) throw "division by zero";
+        return float(op, a / b);
+      case "^":
+        if (a === 0 && b < 0) throw "division by zero";
+        return float(op, Math.pow(a, b));
+      case "^^": return tetrate(a, b);
+      default: throw `unknown binary operator \`${op}\``;
+    }
+  }
+
+  function compare(op, l, r) {
+    let a = l;
+    let b = r;
+    if (typeof l !== "string" || typeof r !== "string") [a, b] = operands(op, l, r);
+    switch (op) {
+      case "<": return a < b;
+      case ">": return a > b;
+      case "≤": return a <= b;
+      default: return a >= b;
+    }
+  }
+
+  function binaryOp(op, l, r) {
+    switch (op) {
+      case "+":
+        if (typeof l === "string" && typeof r === "string") return l + r;
+        if (Array.isArray(l) && Array.isArray(r)) return l.concat(r);
+        return arithmetic(op, l, r);
+      case "-": case "*": case "/": case "^": case "^^":
+        return arithmetic(op, l, r);
+      case "<": case ">": case "≤": case "≥":
+        return compare(op, l, r);
+      case "..":
+        if (typeof l === "bigint" && typeof r === "bigint") return new Range(l, r);
+        throw `cannot make a range from ${typeName(l)} to ${typeName(r)}`;
+      case "=":
+        return equal(l, r);
+      default:
+        throw `unknown binary operator \`${op}\``;
+    }
+  }
+
+  function unaryOp(op, v) {
+    switch (op) {
+      case "!": return !truthy(v);
+      case "?": return truthy(v);
+      case "-":
+        if (typeof v === "bigint") return int(op, -v);
+        if (typeof v === "number") return -v;
+        throw `cannot apply \`-\` to ${typeName(v)}`;
+      case "++":
+        if (typeof v === "bigint") return int(op, v + 1n);
+        if (typeof v === "number") return float(op, v + 1);
+        throw `cannot apply \`++\` to ${typeName(v)}`;
+      default:
+        throw `unknown unary operator \`${op}\``;
+    }
+  }
+
+  // the helpers above throw bare messages; this gives them the location of the expression
+  function at(f, where) {
+    try {
+      return f();
+    } catch (e) {
+      if (typeof e === "string") throw error(e, where);
+      throw e;
+    }
+  }
+
+  rt.binary = (op, l, r, where) => at(() => binaryOp(op, l, r), where);
+  rt.unary = (op, v, where) => at(() => unaryOp(op, v), where);
+  rt.truthy = truthy;
+  rt.show = show;
+
+  rt.interpolate = (parts) => parts.map(show).join("");
+
+  function index(target, i) {
+    if (target instanceof VitaError && typeof i === "string") {
+      switch (i) {
+        case "kind": return target.kind;
+        case "message": return target.message;
+        case "line": return BigInt(target.at[1]);
+        case "column": return BigInt(target.at[2]);
+        default: throw `errors have no \`${i}\`; try \`kind\`, \`message\`, \`line\` or \`column\``;
+      }
+    }
+    if (typeof i !== "bigint") throw `cannot index with ${typeName(i)}`;
+    let items;
+    if (Array.isArray(target)) items = target;
+    else if (typeof target === "string") items = Array.from(target);
+    else throw `cannot index into ${typeName(target)}`;
+    const len = BigInt(items.length);
+    const resolved = i < 0n ? len + i : i;
+    if (resolved < 0n || resolved >= len) {
+      throw `index ${i} is out of range for ${Array.isArray(target) ? "an array" : "a string"} of length ${len}`;
+    }
+    return items[Number(resolved)];
+  }
+
+  function slice(target, from, to) {
+    let items;
+    if (Array.isArray(target)) items = target;
+    else if (typeof target === "string") items = Array.from(target);
+    else throw `cannot slice ${typeName(target)}`;
+    const len = BigInt(items.length);
+    const bound = (v, otherwise) => {
+      if (v === undefined) return otherwise;
+      if (typeof v !== "bigint") throw `cannot index with ${typeName(v)}`;
+      const resolved = v < 0n ? len + v : v;
+      return resolved < 0n ? 0n : resolved > len ? len : resolved;
+    };
+    const start = bound(from, 0n);
+    const end = bound(to, len);
+    const part = items.slice(Number(start), Number(end > start ? end : start));
+    return Array.isArray(target) ? part : part.join("");
+  }
+
+  rt.index = (target, i, where) => at(() => index(target, i), where);
+  rt.slice = (target, from, to, where) => at(() => slice(target, from, to), where);
+
+  rt.freeze = (value) => {
+    if (Array.isArray(value) && !frozen.has(value)) {
+      frozen.add(value);
+      value.forEach(rt.freeze);
+    }
+    return value;
+  };
+
+  rt.railway = (name, fn) => new Railway(name, fn);
+  rt.operator = (name, fn) => new Operator(name, fn);
+  rt.block = (fn) => new Generator("block", fn());
+
+  function traced(e, name, where) {
+    if (e instanceof VitaError) e.trace.push([name, where]);
+    return e;
+  }
+
+  function invoke(f, args, where) {
+    if (args.length !== f.arity) {
+      throw error(`\`${f.name}\` takes ${f.arity} argument(s) but ${args.length} were given`, where);
+    }
+    if (depth >= MAX_CALL_DEPTH) {
+      throw error(`too many nested calls (last was to \`${f.name}\`)`, where);
+    }
+    if (f.generator) return new Generator(f.name, f.fn(...args));
+    depth++;
+    try {
+      return f.fn(...args);
+    } catch (e) {
+      throw traced(e, f.name, where);
+    } finally {
+      depth--;
+    }
+  }
+
+  function resume(gen, where) {
+    if (gen.state === "done") return DONE;
+    if (gen.state === "running") throw error(`generator \`${gen.name}\` is already running`, where);
+    if (depth >= MAX_CALL_DEPTH) throw error(`too many nested calls (last was to \`${gen.name}\`)`, where);
+    gen.state = "running";
+    depth++;
+    let step;
+    try {
+      step = gen.iterator.next();
+    } catch (e) {
+      gen.state = "done";
+      throw traced(e, gen.name, where);
+    } finally {
+      depth--;
+    }
+    gen.state = step.done ? "done" : "ready";
+    return step.done ? DONE : step.value;
+  }
+
+  function builtin(name, args, where) {
+    if ((name === "push" || name === "pop") && frozen.has(args[0])) {
+      throw error(`cannot \`${name}\` on an array held by a constant`, where);
+    }
+    const expect = (count) => {
+      if (args.length !== count) throw `\`${name}\` takes ${count} argument(s) but ${args.length} were given`;
+    };
+    return at(() => {
+      switch (name) {
+        case "print":
+          console.log(args.map(show).join(" "));
+          return null;
+        case "len": {
+          expect(1);
+          const v = args[0];
+          if (Array.isArray(v)) return BigInt(v.length);
+          if (typeof v === "string") return BigInt(Array.from(v).length);
+          if (v instanceof Range) return v.to > v.from ? v.to - v.from : 0n;
+          throw `\`len\` expects an array, a string or a range, not ${typeName(v)}`;
+        }
+        case "push":
+          if (args.length === 0) throw "`push` needs an array to push onto";
+          if (!Array.isArray(args[0])) throw `\`push\` expects an array, not ${typeName(args[0])}`;
+          args[0].push(...args.slice(1));
+          return args[0];
+        case "pop":
+          expect(1);
+          if (!Array.isArray(args[0])) throw `\`pop\` expects an array, not ${typeName(args[0])}`;
+          if (args[0].length === 0) throw "cannot `pop` from an empty array";
+          return args[0].pop();
+        case "concat":
+          return [].concat(...args.map((v) => (Array.isArray(v) ? v : [v])));
+        case "next":
+          if (args.length !== 1) throw `\`next\` takes 1 argument(s) but ${args.length} were given`;
+          if (!(args[0] instanceof Generator)) throw `\`next\` expects a generator, not ${typeName(args[0])}`;
+          return resume(args[0], where);
+        default:
+          throw `unknown function \`${name}\``;
+      }
+    }, where);
+  }
+
+  rt.builtin = (name, args, where) => {
+    const value = builtin(name, args, where);
+    return value === DONE ? null : value;
+  };
+
+  // a name bound to a railway calls it; anything else falls back to the builtin of that name
+  rt.call = (callee, name, args, where) => {
+    if (callee instanceof Railway) return invoke(callee, args, where);
+    return rt.builtin(name, args, where);
+  };
+
+  rt.apply = (op, args, where) => {
+    try {
+      return op.fn(...args);
+    } catch (e) {
+      throw traced(e, op.name, where);
+    }
+  };
+
+  rt.thrown = (value, where) => {
+    if (value instanceof VitaError) {
+      const copy = new VitaError(value.kind, value.message, value.at);
+      copy.trace = value.trace.slice();
+      return copy;
+    }
+    if (Array.isArray(value) && value.length === 2 && typeof value[0] === "string") {
+      return new VitaError(value[0], show(value[1]), where);
+    }
+    return new VitaError("Error", show(value), where);
+  };
+
+  rt.error = error;
+
+  // only errors raised by the script are catchable, not faults in the generated code
+  rt.caught = (e) => {
+    if (!(e instanceof VitaError)) throw e;
+    return e;
+  };
+
+  // `lolsie` over a computed source; arrays are read live so pushing while looping extends the loop
+  rt.each = function* (source, where) {
+    if (typeof source === "string") source = Array.from(source);
+    if (typeof source === "number") {
+      if (!Number.isInteger(source) || Math.abs(source) >= 2 ** 63) throw error(`cannot loop ${showFloat(source)} times`, where);
+      source = BigInt(source);
+    }
+    if (source instanceof Generator) {
+      for (;;) {
+        const value = resume(source, where);
+        if (value === DONE) return;
+        yield value;
+      }
+    }
+    if (Array.isArray(source)) {
+      for (let i = 0; i < source.length; i++) yield source[i];
+      return;
+    }
+    if (typeof source === "bigint") {
+      for (let i = 0n; i < source; i++) yield i;
+      return;
+    }
+    if (source instanceof Range) {
+      for (let i = source.from; i < source.to; i++) yield i;
+      return;
+    }
+    throw error(`cannot loop over ${typeName(source)}`, where);
+  };
+
+  rt.times = function* (count) {
+    for (let i = 0n; i < count; i++) yield i;
+  };
+
+  function sameFrame([a, x], [b, y]) {
+    return a === b && x[0] === y[0] && x[1] === y[1] && x[2] === y[2];
+  }
+
+  function report(e) {
+    const message = e.kind === "RuntimeError" ? e.message : `${e.kind}: ${e.message}`;
+    const lines = [`error[E0301]: ${message}`, ` --> ${rt.sources[e.at[0]]}:${e.at[1]}:${e.at[2]}`];
+    for (let i = 0; i < e.trace.length; ) {
+      const [name, where] = e.trace[i];
+      let repeats = 0;
+      for (i++; i < e.trace.length && sameFrame(e.trace[i], e.trace[i - 1]); i++) repeats++;
+      lines.push(`  = note: in \`${name}\`, called at ${where[1]}:${where[2]}`);
+      if (repeats > 0) lines.push(`  = note: … repeated ${repeats} more time${repeats === 1 ? "" : "s"}`);
+    }
+    console.error(lines.join("\n"));
+  }
+
+  rt.main = (run) => {
+    try {
+      const value = run();
+      if (value !== null) console.log(show(value));
+    } catch (e) {
+      if (!(e instanceof VitaError)) throw e;
+      report(e);
+      if (typeof process !== "undefined") process.exitCode = 1;
+    }
+  };
+
+  return rt;
+})();
@@ -1,16 +1,19 @@
 use std::sync::LazyLock;
 
+use crate::diagnostics::Diagnostic;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum TokenType {
     Define, Assign, EndOfAssign,
+    Function, Params, EndOfFunction, Return,
     If, Else, ElseIf,
     For, While, Break,
-    Import, ImportAll,
+    Import, ImportAll, Export,
     BinaryOperator,
-    LeftParen, RightParen,
-    Variable, Const, String, Comma,
+    LeftParen, RightParen, LeftBracket, RightBracket, Colon,
+    Variable, Const, String, QuotedString, Number, Comma, Label,
     Indent, Dedent, Newline, LeftCurly, RightCurly,
-    EOF, Continue, Yield, Try, Catch,
+    EOF, Continue, Yield, Try, Catch, Throw, Finally,
     Comment, BlockCommentStart, BlockCommentEnd
 }
 
@@ -26,10 +29,15 @@ static KEYWORDS: LazyLock<Vec<(&'static str, TokenType)>> = LazyLock::new(|| {
     let mut v= vec![
         ("I would love to own a plot of land in the 1800s called", TokenType::Define),
         ("and lease it to", TokenType::Assign),
+        ("I would love to build a railway called", TokenType::Function),
+        ("and let it carry", TokenType::Params),
+        ("passengers", TokenType::EndOfFunction),
+        ("return", TokenType::Return),
         ("sweet but stout", TokenType::ElseIf),
         ("American", TokenType::ImportAll),
         ("owners", TokenType::EndOfAssign),
         ("scammy", TokenType::Import),
+        ("for sale", TokenType::Export),
         ("sweet", TokenType::If),
         ("stout", TokenType::Else),
         ("lolsie", TokenType::For),
@@ -39,6 +47,8 @@ static KEYWORDS: LazyLock<Vec<(&'static str, TokenType)>> = LazyLock::new(|| {
         ("anywho", TokenType::Yield),
         ("sir, would there happen to be any extension work?", TokenType::Try),
         ("yay, homework!", TokenType::Catch),
+        ("the dog ate my homework", TokenType::Throw),
+        ("the bell rings", TokenType::Finally),
         ("europe ->", TokenType::Comment),
         ("asia ->", TokenType::BlockCommentStart),
         ("<- asia", TokenType::BlockCommentEnd)
@@ -49,22 +59,92 @@ static KEYWORDS: LazyLock<Vec<(&'static str, TokenType)>> = LazyLock::new(|| {
     return v;
 });
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+impl TokenType {
+    pub fn describe(&self) -> String {
+        if let Some((kw, _)) = KEYWORDS.iter().find(|(_, ty)| ty == self) {
+            return format!("`{}`", kw);
+        }
+
+        match self {
+            TokenType::LeftParen => "`(`".to_string(),
+            TokenType::RightParen => "`)`".to_string(),
+            TokenType::LeftCurly => "`{`".to_string(),
+            TokenType::RightCurly => "`}`".to_string(),
+            TokenType::LeftBracket => "`[`".to_string(),
+            TokenType::RightBracket => "`]`".to_string(),
+            TokenType::Colon => "`:`".to_string(),
+            TokenType::Comma => "`,`".to_string(),
+            TokenType::Newline => "a newline".to_string(),
+            TokenType::Indent => "an indent".to_string(),
+            TokenType::Dedent => "the end of an indented block".to_string(),
+            TokenType::EOF => "the end of the file".to_string(),
+            TokenType::BinaryOperator => "an operator".to_string(),
+            TokenType::Variable => "a variable".to_string(),
+            TokenType::Const => "a constant".to_string(),
+            TokenType::Label => "a loop label".to_string(),
+            TokenType::String => "a string".to_string(),
+            TokenType::QuotedString => "a quoted string".to_string(),
+            TokenType::Number => "a number".to_string(),
+            other => format!("{}", other),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StringPart {
+    Literal(String),
+    Variable(String, Span),
+    Const(String, Span)
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum TokenValue {
     Char(char),
     Str(String),
-    Num(usize)
+    Num(usize),
+    Int(i64),
+    Float(f64),
+    Parts(Vec<StringPart>)
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub col: usize,
+    pub file: usize
+}
+
+impl Span {
+    pub fn to(self, other: Span) -> Span {
+        let (first, last) = if self.start <= other.start { (self, other) } else { (other, self) };
+        Span {
+            start: first.start,
+            end: first.end.max(last.end),
+            line: first.line,
+            col: first.col,
+            file: first.file
+        }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.col)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Token {
     pub _type: TokenType,
-    pub value: Option<TokenValue>
+    pub value: Option<TokenValue>,
+    pub span: Span
 }
 
 impl Token {
-    pub fn new(_type: TokenType, value: Option<TokenValue>) -> Self {
-        Self {_type, value}
+    pub fn new(_type: TokenType, value: Option<TokenValue>, span: Span) -> Self {
+        Self {_type, value, span}
     }
 }
 
@@ -73,7 +153,20 @@ impl fmt::Display for TokenValue {
         match self {
             TokenValue::Char(c) => write!(f, "{}", c),
             TokenValue::Str(s) => write!(f, "{}", s),
-            TokenValue::Num(n) => write!(f, "{}", n)
+            TokenValue::Num(n) => write!(f, "{}", n),
+            TokenValue::Int(n) => write!(f, "{}", n),
+            TokenValue::Float(n) => write!(f, "{:?}", n),
+            TokenValue::Parts(parts) => {
+                write!(f, "\"")?;
+                for part in parts {
+                    match part {
+                        StringPart::Literal(s) => write!(f, "{}", s.escape_default())?,
+                        StringPart::Variable(name, _) => write!(f, "£{}", name)?,
+                        StringPart::Const(name, _) => write!(f, "${}", name)?
+                    }
+                }
+                write!(f, "\"")
+            }
         }
     }
 }
@@ -81,15 +174,248 @@ impl fmt::Display for TokenValue {
 impl fmt::Display for Token {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match &self.value {
-            Some(v) => write!(f, "Type: {}, Value: {}", self._type, v),
-            None => write!(f, "Type: {}, Value: None", self._type),
+            Some(v) => write!(f, "Type: {}, Value: {}, At: {}", self._type, v, self.span),
+            None => write!(f, "Type: {}, Value: None, At: {}", self._type, self.span),
         }
     }
 }
 
 static OPERATORS: &[&str] = &["^", "*", "/", "+", "-", "<", ">", "=", "≥", "≤"];
 
-fn consume_until_newline(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) -> String {
+#[derive(Clone)]
+struct Cursor<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    offset: usize,
+    line: usize,
+    col: usize,
+    file: usize
+}
+
+impl<'a> Cursor<'a> {
+    fn new(src: &'a str, file: usize) -> Self {
+        Self { chars: src.chars().peekable(), offset: 0, line: 1, col: 1, file }
+    }
+
+    fn peek(&mut self) -> Option<&char> {
+        self.chars.peek()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        self.offset += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+            self.col = 1;
+        } else {
+            self.col += 1;
+        }
+        Some(c)
+    }
+
+    fn position(&self) -> Span {
+        Span { start: self.offset, end: self.offset, line: self.line, col: self.col, file: self.file }
+    }
+
+    fn span_from(&self, start: Span) -> Span {
+        Span { end: self.offset, ..start }
+    }
+}
+
+fn lex_number(chars: &mut Cursor<'_>, first: char, start: Span) -> Result<Token, Diagnostic> {
+    let mut digits = String::new();
+    let mut radix = 10;
+    let mut is_float = false;
+
+    let take_digits = |chars: &mut Cursor<'_>, digits: &mut String, radix: u32| {
+        while let Some(&c) = chars.peek() {
+            if c == '_' {
+                chars.next();
+            } else if c.is_digit(radix) {
+                digits.push(c);
+                chars.next();
+            } else {
+                break;
+            }
+        }
+    };
+
+    let prefix = if first == '0' { chars.peek().copied() } else { None };
+    match prefix {
+        Some('x') | Some('X') => radix = 16,
+        Some('b') | Some('B') => radix = 2,
+        Some('o') | Some('O') => radix = 8,
+        _ => digits.push(first),
+    }
+
+    if radix != 10 {
+        chars.next();
+        take_digits(chars, &mut digits, radix);
+        if digits.is_empty() {
+            return Err(Diagnostic::error("E0003", "number literal has no digits")
+                .with_primary(chars.span_from(start), "expected digits after the base prefix"));
+        }
+    } else {
+        take_digits(chars, &mut digits, 10);
+
+        let mut lookahead = chars.chars.clone();
+        lookahead.next();
+        if chars.peek() == Some(&'.') && matches!(lookahead.peek(), Some(c) if c.is_ascii_digit()) {
+            is_float = true;
+            digits.push('.');
+            chars.next();
+            take_digits(chars, &mut digits, 10);
+        }
+
+        if matches!(chars.peek(), Some('e') | Some('E')) {
+            let mut lookahead = chars.chars.clone();
+            lookahead.next();
+            if matches!(lookahead.peek(), Some('+') | Some('-')) {
+                lookahead.next();
+            }
+            if matches!(lookahead.peek(), Some(c) if c.is_ascii_digit()) {
+                is_float = true;
+                digits.push('e');
+                chars.next();
+                if let Some(&sign) = chars.peek() {
+                    if sign == '+' || sign == '-' {
+                        digits.push(sign);
+                        chars.next();
+                    }
+                }
+                take_digits(chars, &mut digits, 10);
+            }
+        }
+    }
+
+    if matches!(chars.peek(), Some(c) if c.is_alphanumeric() || *c == '_') {
+        let number = chars.span_from(start);
+        let bad_start = chars.position();
+        chars.next();
+        return Err(Diagnostic::error("E0003", "invalid digit in number literal")
+            .with_primary(chars.span_from(bad_start), "unexpected character")
+            .with_secondary(number, "in this number"));
+    }
+
+    let span = chars.span_from(start);
+    let value = if is_float {
+        match digits.parse::<f64>() {
+            Ok(f) => TokenValue::Float(f),
+            Err(_) => return Err(Diagnostic::error("E0003", "invalid floating-point literal").with_primary(span, "here")),
+        }
+    } else {
+        match i64::from_str_radix(&digits, radix) {
+            Ok(n) => TokenValue::Int(n),
+            Err(_) => {
+                return Err(Diagnostic::error("E0004", "integer literal is too large")
+                    .with_primary(span, "does not fit in a 64-bit integer")
+                    .with_note("write it with a decimal point or an exponent to get a floating-point number"));
+            }
+        }
+    };
+
+    Ok(Token::new(TokenType::Number, Some(value), span))
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+fn lex_escape(chars: &mut Cursor<'_>, start: Span) -> Result<char, Diagnostic> {
+    let escape_start = start;
+    let c = match chars.next() {
+        Some(c) => c,
+        None => {
+            return Err(Diagnostic::error("E0005", "unterminated string literal")
+                .with_primary(chars.span_from(escape_start), "the file ends inside this escape"));
+        }
+    };
+
+    let escaped = match c {
+        'n' => '\n',
+        't' => '\t',
+        'r' => '\r',
+        '0' => '\0',
+        '\\' | '"' | '\'' | '$' | '£' | '€' => c,
+        'u' => {
+            if chars.next() != Some('{') {
+                return Err(Diagnostic::error("E0006", "invalid unicode escape")
+                    .with_primary(chars.span_from(escape_start), "expected `{` after `\\u`"));
+            }
+            let mut hex = String::new();
+            loop {
+                match chars.next() {
+                    Some('}') => break,
+                    Some(h) if h.is_ascii_hexdigit() && hex.len() < 6 => hex.push(h),
+                    _ => {
+                        return Err(Diagnostic::error("E0006", "invalid unicode escape")
+                            .with_primary(chars.span_from(escape_start), "expected up to six hex digits and a closing `}`"));
+                    }
+                }
+            }
+            match u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+                Some(ch) => ch,
+                None => {
+                    return Err(Diagnostic::error("E0006", "invalid unicode escape")
+                        .with_primary(chars.span_from(escape_start), format!("`{}` is not a unicode scalar value", hex)));
+                }
+            }
+        }
+        other => {
+            return Err(Diagnostic::error("E0006", format!("unknown escape sequence `\\{}`", other))
+                .with_primary(chars.span_from(escape_start), "not a valid escape")
+                .with_note("valid escapes are \\n, \\t, \\r, \\0, \\\\, \\\", \\', \\$, \\£, \\€ and \\u{...}"));
+        }
+    };
+
+    Ok(escaped)
+}
+
+fn lex_string(chars: &mut Cursor<'_>, start: Span) -> Result<Token, Diagnostic> {
+    let mut parts = Vec::new();
+    let mut literal = String::new();
+
+    loop {
+        let char_start = chars.position();
+        let c = match chars.next() {
+            Some(c) => c,
+            None => {
+                return Err(Diagnostic::error("E0005", "unterminated string literal")
+                    .with_primary(Span { end: start.start + 1, ..start }, "string starts here")
+                    .with_note("strings are closed with a matching `\"`"));
+            }
+        };
+
+        match c {
+            '"' => break,
+            '\\' => literal.push(lex_escape(chars, char_start)?),
+            '£' | '€' | '$' if matches!(chars.peek(), Some(&n) if is_name_char(n)) => {
+                let mut name = String::new();
+                while let Some(&next_c) = chars.peek() {
+                    if !is_name_char(next_c) {
+                        break;
+                    }
+                    name.push(next_c);
+                    chars.next();
+                }
+
+                if !literal.is_empty() {
+                    parts.push(StringPart::Literal(std::mem::take(&mut literal)));
+                }
+                let span = chars.span_from(char_start);
+                parts.push(if c == '$' { StringPart::Const(name, span) } else { StringPart::Variable(name, span) });
+            }
+            other => literal.push(other),
+        }
+    }
+
+    if !literal.is_empty() || parts.is_empty() {
+        parts.push(StringPart::Literal(literal));
+    }
+
+    Ok(Token::new(TokenType::QuotedString, Some(TokenValue::Parts(parts)), chars.span_from(start)))
+}
+
+fn consume_until_newline(chars: &mut Cursor<'_>) -> String {
     let mut s = String::new();
     while let Some(&c) = chars.peek() {
         if c == '\n' {
@@ -101,11 +427,11 @@ fn consume_until_newline(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) -
     s
 }
 
-fn consume_until(chars: &mut std::iter::Peekable<std::str::Chars<'_>>, end: &str) -> String {
+fn consume_until(chars: &mut Cursor<'_>, end: &str) -> Option<String> {
     let mut s = String::new();
 
     loop {
-        let mut it = chars.clone();
+        let mut it = chars.chars.clone();
         let mut matched = true;
 
         for ec in end.chars() {
@@ -119,31 +445,36 @@ fn consume_until(chars: &mut std::iter::Peekable<std::str::Chars<'_>>, end: &str
         }
 
         if matched {
-            for _ in 0..end.len() {
+            for _ in 0..end.chars().count() {
                 chars.next();
             }
-            break;
+            return Some(s);
         }
 
         match chars.next() {
             Some(c) => s.push(c),
-            None => break,
+            None => return None,
         }
     }
-
-    s
 }
 
-pub fn tokenize(src: &String) -> Vec<Token> {
+pub fn tokenize(src: &str, file: usize) -> Result<Vec<Token>, Diagnostic> {
     let mut tokens = Vec::<Token>::new();
     let mut indent_stack = vec![0];
 
-    let mut chars = src.chars().peekable();
+    let mut chars = Cursor::new(src, file);
+
+    loop {
+        let start = chars.position();
+        let c = match chars.next() {
+            Some(c) => c,
+            None => break,
+        };
 
-    while let Some(c) = chars.next() {
         match c {
             '\n' => {
-                tokens.push(Token::new(TokenType::Newline, Some(TokenValue::Str("\\n".to_string()))));
+                tokens.push(Token::new(TokenType::Newline, Some(TokenValue::Str("\\n".to_string())), chars.span_from(start)));
+                let indent_start = chars.position();
                 let mut indent = 0;
                 while let Some(&next_c) = chars.peek() {
                     match next_c {
@@ -157,11 +488,17 @@ pub fn tokenize(src: &String) -> Vec<Token> {
 
                 if indent > current_indent {
                     indent_stack.push(indent);
-                    tokens.push(Token::new(TokenType::Indent, Some(TokenValue::Str("INDENT".to_string()))));
+                    tokens.push(Token::new(TokenType::Indent, Some(TokenValue::Str("INDENT".to_string())), chars.span_from(indent_start)));
                 } else if indent < current_indent {
                     while indent < *indent_stack.last().unwrap() {
                         indent_stack.pop();
-                        tokens.push(Token::new(TokenType::Dedent, Some(TokenValue::Str("DEDENT".to_string()))));
+                        tokens.push(Token::new(TokenType::Dedent, Some(TokenValue::Str("DEDENT".to_string())), chars.span_from(indent_start)));
+                    }
+
+                    if indent != *indent_stack.last().unwrap() {
+                        return Err(Diagnostic::error("E0002", "inconsistent indentation")
+                            .with_primary(chars.span_from(indent_start), "this line does not line up with any enclosing block")
+                            .with_note(format!("expected an indent of {} columns", indent_stack.last().unwrap())));
                     }
                 }
 
@@ -169,11 +506,19 @@ pub fn tokenize(src: &String) -> Vec<Token> {
             }
             ' ' | '\t' | '\r' => continue,
 
-            '(' => tokens.push(Token::new(TokenType::LeftParen, Some(TokenValue::Char('(')))),
-            ')' => tokens.push(Token::new(TokenType::RightParen, Some(TokenValue::Char(')')))),
-            '{' => tokens.push(Token::new(TokenType::LeftCurly, Some(TokenValue::Char('{')))),
-            '}' => tokens.push(Token::new(TokenType::RightCurly, Some(TokenValue::Char('}')))),
-            ',' => tokens.push(Token::new(TokenType::Comma, Some(TokenValue::Char(',')))),
+            '(' => tokens.push(Token::new(TokenType::LeftParen, Some(TokenValue::Char('(')), chars.span_from(start))),
+            ')' => tokens.push(Token::new(TokenType::RightParen, Some(TokenValue::Char(')')), chars.span_from(start))),
+            '{' => tokens.push(Token::new(TokenType::LeftCurly, Some(TokenValue::Char('{')), chars.span_from(start))),
+            '}' => tokens.push(Token::new(TokenType::RightCurly, Some(TokenValue::Char('}')), chars.span_from(start))),
+            ',' => tokens.push(Token::new(TokenType::Comma, Some(TokenValue::Char(',')), chars.span_from(start))),
+            '[' => tokens.push(Token::new(TokenType::LeftBracket, Some(TokenValue::Char('[')), chars.span_from(start))),
+            ']' => tokens.push(Token::new(TokenType::RightBracket, Some(TokenValue::Char(']')), chars.span_from(start))),
+            ':' => tokens.push(Token::new(TokenType::Colon, Some(TokenValue::Char(':')), chars.span_from(start))),
+            // a range is always exactly `..`, so `0..-3` ends at -3 instead of using an operator `..-`
+            '.' if chars.peek() == Some(&'.') => {
+                chars.next();
+                tokens.push(Token::new(TokenType::BinaryOperator, Some(TokenValue::Str("..".to_string())), chars.span_from(start)));
+            }
             op if OPERATORS.contains(&op.to_string().as_str()) => {
                 let mut value = String::new();
                 value.push(op.to_string().chars().next().unwrap());
@@ -185,9 +530,13 @@ pub fn tokenize(src: &String) -> Vec<Token> {
                         break;
                     }
                 }
-                tokens.push(Token::new(TokenType::BinaryOperator, Some(TokenValue::Str(value))));
+                tokens.push(Token::new(TokenType::BinaryOperator, Some(TokenValue::Str(value)), chars.span_from(start)));
             },
 
+            '0'..='9' => tokens.push(lex_number(&mut chars, c, start)?),
+
+            '"' => tokens.push(lex_string(&mut chars, start)?),
+
             '$' => {
                 let mut value = String::new();
                 while let Some(&next_c) = chars.peek() {
@@ -198,7 +547,20 @@ pub fn tokenize(src: &String) -> Vec<Token> {
                         break;
                     }
                 }
-                tokens.push(Token::new(TokenType::Const, Some(TokenValue::Str(value))));
+                tokens.push(Token::new(TokenType::Const, Some(TokenValue::Str(value)), chars.span_from(start)));
+            }
+
+            '@' => {
+                let mut value = String::new();
+                while let Some(&next_c) = chars.peek() {
+                    if next_c.is_ascii_alphanumeric() || next_c == '_' {
+                        value.push(next_c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                tokens.push(Token::new(TokenType::Label, Some(TokenValue::Str(value)), chars.span_from(start)));
             }
 
             '£' | '€' => {
@@ -211,7 +573,7 @@ pub fn tokenize(src: &String) -> Vec<Token> {
                         break;
                     }
                 }
-                tokens.push(Token::new(TokenType::Variable, Some(TokenValue::Str(value))));
+                tokens.push(Token::new(TokenType::Variable, Some(TokenValue::Str(value)), chars.span_from(start)));
             }
             _ => {
                 let mut matched: Option<TokenType> = None;
@@ -236,7 +598,7 @@ pub fn tokenize(src: &String) -> Vec<Token> {
                         if ok {
                             matched = Some(ty.clone());
                             keyword = kw;
-                            matched_len = kw.len() - 1;
+                            matched_len = kw.chars().count() - 1;
                             break;
                         }
                     }
@@ -253,16 +615,25 @@ pub fn tokenize(src: &String) -> Vec<Token> {
                             tokens.push(Token::new(
                                 TokenType::Comment,
                                 Some(TokenValue::Str(text)),
+                                chars.span_from(start),
                             ));
                         }
 
                         TokenType::BlockCommentStart => {
-                            let text = consume_until(&mut chars, "<- asia");
+                            let text = match consume_until(&mut chars, "<- asia") {
+                                Some(text) => text,
+                                None => {
+                                    return Err(Diagnostic::error("E0001", "unterminated block comment")
+                                        .with_primary(Span { end: start.start + keyword.len(), ..start }, "comment starts here")
+                                        .with_note("block comments are closed with `<- asia`"));
+                                }
+                            };
                             tokens.push(Token::new(
                                 TokenType::BlockCommentStart,
                                 Some(TokenValue::Str(text)),
+                                chars.span_from(start),
                             ));
-                            tokens.push(Token::new(TokenType::BlockCommentEnd, None));
+                            tokens.push(Token::new(TokenType::BlockCommentEnd, None, chars.span_from(start)));
                         }
 
                         TokenType::For => {
@@ -274,35 +645,46 @@ pub fn tokenize(src: &String) -> Vec<Token> {
                                 it += 1;
                                 chars.next();
                             }
-                            tokens.push(Token::new(TokenType::For, Some(TokenValue::Num(it))));
+                            tokens.push(Token::new(TokenType::For, Some(TokenValue::Num(it)), chars.span_from(start)));
                         }
 
                         _ => {
                             tokens.push(Token::new(
                                 token_type,
                                 Some(TokenValue::Str(keyword.to_string())),
+                                chars.span_from(start),
                             ));
                         }
                     }
                 } else {
                     let mut value = String::new();
-                    value.push(c);
+                    let mut escaped = c == '\\';
+                    if !escaped {
+                        value.push(c);
+                    }
 
                     while let Some(&next_c) = chars.peek() {
-                        if (next_c.is_whitespace() || "(){},+-*/$£".contains(next_c)) && c != '\\' {
+                        if escaped {
+                            escaped = false;
+                        } else if next_c == '\\' {
+                            escaped = true;
+                            chars.next();
+                            continue;
+                        } else if next_c.is_whitespace() || "(){}[]:,+-*/$£€\"".contains(next_c) {
                             break;
                         }
                         value.push(next_c);
                         chars.next();
                     }
 
-                    tokens.push(Token::new(TokenType::String, Some(TokenValue::Str(value))));
+                    tokens.push(Token::new(TokenType::String, Some(TokenValue::Str(value)), chars.span_from(start)));
                 }
             }
         }
     }
 
-    tokens.push(Token::new(TokenType::EOF, Some(TokenValue::Str("EOF".to_string()))));
+    let end = chars.position();
+    tokens.push(Token::new(TokenType::EOF, Some(TokenValue::Str("EOF".to_string())), end));
 
-    return tokens
+    return Ok(tokens)
 }
@@ -1,46 +1,243 @@
 use std::env;
-use std::fmt;
-use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::Path;
+use std::process;
+use std::thread;
+mod diagnostics;
 mod lexer;
 mod parser;
 mod first_pass;
+mod interpreter;
+mod module;
+mod semantic;
+mod resolver;
+mod types;
+mod bytecode;
+mod vm;
+mod optimize;
+mod js;
+mod c;
+mod wat;
+mod wasm;
+#[cfg(test)]
+mod tests;
+#[cfg(test)]
+mod wasm_exec;
 
-#[derive(Debug)]
-struct Context();
+use diagnostics::Diagnostic;
 
-impl fmt::Display for Context {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        return write!(f, "There is Context. Definitely.");
+const STACK_SIZE: usize = 512 * 1024 * 1024;
+
+struct Flags {
+    show_ast: bool,
+    dump_opt: bool,
+    use_vm: bool,
+}
+
+fn report(diagnostic: &Diagnostic, loader: &module::Loader) {
+    let file = diagnostic.primary_span().map(|s| s.file).unwrap_or(0);
+    let colour = io::stderr().is_terminal();
+    match loader.source(file) {
+        Some(source) => eprint!("{}", diagnostic.render(&source.text, &source.name, colour)),
+        None => eprint!("{}", diagnostic.render("", "", colour)),
     }
 }
 
-fn context() -> Result<(), Context> {
-    return Err(Context());
+fn load(loader: &mut module::Loader, script_name: &str, flags: &Flags) -> Result<usize, Vec<Diagnostic>> {
+    let entry = loader.load(Path::new(script_name), None);
+
+    if flags.show_ast {
+        let mut ids: Vec<&usize> = loader.modules.keys().collect();
+        ids.sort();
+        for id in ids {
+            let module = &loader.modules[id];
+            println!("Module: {}", loader.sources[*id].name);
+            println!("Operators: {:?}", module.operators);
+            println!("AST: {:?}", module.ast);
+            let mut uses: Vec<&lexer::Span> = module.resolution.uses.keys().collect();
+            uses.sort_by_key(|s| s.start);
+            for span in uses {
+                if let Some(def) = module.resolution.definition_of(*span) {
+                    println!("Use at {}:{} -> `{}` ({:?}) at {}:{}", span.line, span.col, def.name, def.kind, def.span.line, def.span.col);
+                }
+            }
+        }
+    }
+
+    let entry = match entry {
+        Some(entry) if !loader.errors.iter().any(Diagnostic::is_error) => entry,
+        _ => return Err(std::mem::take(&mut loader.errors)),
+    };
+    for warning in std::mem::take(&mut loader.errors) {
+        report(&warning, loader);
+    }
+    Ok(entry)
 }
 
-pub fn fail() -> ! {
-    if let Err(e) = context() {
-        eprintln!("\x1b[31m{}\x1b[0m", e);
+// everything but `check` runs on the folded program; checking it would miss code in pruned branches
+fn fold(loader: &mut module::Loader, flags: &Flags) {
+    optimize::run(loader);
+    if flags.dump_opt {
+        let mut ids: Vec<&usize> = loader.modules.keys().collect();
+        ids.sort();
+        for id in ids {
+            let module = &loader.modules[id];
+            println!("Module: {}", loader.sources[*id].name);
+            for operator in module.operators.values() {
+                println!("Operator {}: {:?}", operator.op, operator.body);
+            }
+            println!("Optimised AST: {:?}", module.ast);
+        }
     }
-    std::process::exit(1);
 }
 
-impl std::error::Error for Context {}
+fn run(loader: &mut module::Loader, script_name: &str, flags: &Flags) -> Result<interpreter::Value, Vec<Diagnostic>> {
+    let entry = load(loader, script_name, flags)?;
+    fold(loader, flags);
+    if flags.use_vm {
+        return vm::run(loader, entry);
+    }
+    module::run(loader, entry).map_err(|e| vec![e.into()])
+}
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
+fn disasm(loader: &mut module::Loader, script_name: &str, flags: &Flags) -> Result<(), Vec<Diagnostic>> {
+    let entry = load(loader, script_name, flags)?;
+    fold(loader, flags);
+    let mut programs = std::collections::HashMap::new();
+    vm::compile_all(loader, entry, &mut programs);
+
+    let mut ids: Vec<&usize> = programs.keys().collect();
+    ids.sort();
+    for id in ids {
+        let program = &programs[id];
+        println!("Module: {}", loader.sources[*id].name);
+        print!("{}", bytecode::disassemble(&program.main));
+        for function in &program.functions {
+            print!("{}", bytecode::disassemble(function));
+        }
+    }
+    Ok(())
+}
+
+// write `script.js` and its source map next to the script
+fn transpile(loader: &mut module::Loader, script_name: &str, flags: &Flags) -> Result<(), Vec<Diagnostic>> {
+    let entry = load(loader, script_name, flags)?;
+    fold(loader, flags);
+    let output = Path::new(script_name).with_extension("js");
+    let js = js::transpile(loader, entry, &output);
+    let map = output.with_extension("js.map");
+    for (path, text) in [(&output, &js.code), (&map, &js.map)] {
+        if let Err(e) = std::fs::write(path, text) {
+            return Err(vec![Diagnostic::error("E0801", format!("cannot write `{}`: {}", path.display(), e))]);
+        }
+    }
+    Ok(())
+}
 
-    let script_name = &args[1];
+// write `script.c` next to the script and compile it into an executable named after the script
+fn build(loader: &mut module::Loader, script_name: &str, flags: &Flags) -> Result<(), Vec<Diagnostic>> {
+    let entry = load(loader, script_name, flags)?;
+    fold(loader, flags);
+    let code = c::generate(loader, entry);
+    let script = Path::new(script_name);
+    let source = script.with_extension("c");
+    if let Err(e) = std::fs::write(&source, code) {
+        return Err(vec![Diagnostic::error("E0801", format!("cannot write `{}`: {}", source.display(), e))]);
+    }
+    let mut output = script.with_extension("");
+    if output == script {
+        output = script.with_extension("out");
+    }
+    c::compile(&source, &output).map_err(|e| vec![e])
+}
 
-    let script = fs::read_to_string(script_name);
+// write `script.wat` and `script.wasm` next to the script
+fn assemble(loader: &mut module::Loader, script_name: &str, flags: &Flags) -> Result<(), Vec<Diagnostic>> {
+    let entry = load(loader, script_name, flags)?;
+    fold(loader, flags);
+    let output = wasm::compile(loader, entry)?;
+    let script = Path::new(script_name);
+    for (path, bytes) in [(script.with_extension("wat"), output.text.as_bytes()), (script.with_extension("wasm"), &output.binary)] {
+        if let Err(e) = std::fs::write(&path, bytes) {
+            return Err(vec![Diagnostic::error("E0801", format!("cannot write `{}`: {}", path.display(), e))]);
+        }
+    }
+    Ok(())
+}
 
-    let tokens = lexer::tokenize(&script.unwrap());
-    let (tokens, precedence_map, operator_defs) = first_pass::run(tokens);
+// type check without running anything, printing what was inferred for the entry module
+fn check(loader: &mut module::Loader, script_name: &str, flags: &Flags) -> Result<(), Vec<Diagnostic>> {
+    let entry = load(loader, script_name, flags)?;
+    let report = types::check(loader, entry);
+    if !report.errors.is_empty() {
+        return Err(report.errors);
+    }
+    for (name, ty) in report.signatures {
+        println!("{}: {}", name, ty);
+    }
+    Ok(())
+}
 
-    // for token in &tokens {
-    //     println!("{}", token);
-    // }
+fn main() {
+    let args: Vec<String> = env::args().collect();
 
-    let ast = parser::Parser::new(tokens, 0, precedence_map, operator_defs).parse();
-    println!("AST: {:?}", ast);
+    let flags = Flags {
+        show_ast: args.iter().any(|a| a == "--ast"),
+        dump_opt: args.iter().any(|a| a == "--dump-opt"),
+        use_vm: args.iter().any(|a| a == "--vm"),
+    };
+    let mut positional = args.iter().skip(1).filter(|a| !a.starts_with("--")).peekable();
+    let command = match positional.peek().map(|a| a.as_str()) {
+        Some("run") | Some("check") | Some("disasm") | Some("js") | Some("build") | Some("wasm") => positional.next().unwrap().clone(),
+        _ => "run".to_string(),
+    };
+    let script_name = match positional.next() {
+        Some(name) => name,
+        None => {
+            eprintln!("usage: {} [run | check | disasm | js | build | wasm] <script.vit> [--ast] [--dump-opt] [--vm]", args.first().map(String::as_str).unwrap_or("vita"));
+            process::exit(2);
+        }
+    };
+
+    // recursion in scripts recurses in the evaluator too, so give it more room than the main thread has
+    let script_name = script_name.clone();
+    let worker = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || {
+            let mut loader = module::Loader::new();
+            let result = match command.as_str() {
+                "check" => check(&mut loader, &script_name, &flags).map(|_| interpreter::Value::Null),
+                "build" => build(&mut loader, &script_name, &flags).map(|_| interpreter::Value::Null),
+                "wasm" => assemble(&mut loader, &script_name, &flags).map(|_| interpreter::Value::Null),
+                "js" => transpile(&mut loader, &script_name, &flags).map(|_| interpreter::Value::Null),
+                "disasm" => disasm(&mut loader, &script_name, &flags).map(|_| interpreter::Value::Null),
+                _ => run(&mut loader, &script_name, &flags),
+            };
+            match result {
+                Ok(interpreter::Value::Null) => 0,
+                Ok(value) => {
+                    println!("{}", value);
+                    0
+                }
+                Err(diagnostics) => {
+                    for diagnostic in &diagnostics {
+                        report(diagnostic, &loader);
+                    }
+                    1
+                }
+            }
+        });
+
+    let code = worker.and_then(|w| w.join().map_err(|_| io::Error::other("interpreter panicked")));
+    match code {
+        Ok(code) => process::exit(code),
+        Err(e) => {
+            if io::stderr().is_terminal() {
+                eprintln!("\x1b[31merror\x1b[0m: {}", e);
+            } else {
+                eprintln!("error: {}", e);
+            }
+            process::exit(1);
+        }
+    }
 }
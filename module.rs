@@ -0,0 +1,231 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::diagnostics::Diagnostic;
+use crate::first_pass::{self, OperatorDef};
+use crate::interpreter::{Exports, Interpreter, RuntimeError, Value};
+use crate::lexer::{self, Span};
+use crate::parser::{self, Expr, ExprKind, Import, ImportKind, ImportName, OperatorFn};
+use crate::resolver::{self, Resolution};
+use crate::semantic;
+
+pub struct Source {
+    pub name: String,
+    pub text: String,
+}
+
+pub struct Module {
+    pub ast: Expr,
+    pub operators: HashMap<String, OperatorFn>,
+    pub exported_ops: Vec<OperatorDef>,
+    pub exports: Vec<ImportName>,
+    pub imports: Vec<(Import, usize)>,
+    pub resolution: Resolution,
+}
+
+#[derive(Default)]
+pub struct Loader {
+    pub sources: Vec<Source>,
+    pub modules: HashMap<usize, Module>,
+    by_path: HashMap<PathBuf, usize>,
+    loading: Vec<(PathBuf, usize)>,
+    pub errors: Vec<Diagnostic>,
+}
+
+impl Loader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn source(&self, file: usize) -> Option<&Source> {
+        self.sources.get(file)
+    }
+
+    // `entry` and every module it imports, each one after everything it depends on
+    pub fn order(&self, entry: usize) -> Vec<usize> {
+        fn visit(loader: &Loader, id: usize, seen: &mut HashSet<usize>, ids: &mut Vec<usize>) {
+            if !seen.insert(id) {
+                return;
+            }
+            for (_, dep) in &loader.modules[&id].imports {
+                visit(loader, *dep, seen, ids);
+            }
+            ids.push(id);
+        }
+
+        let mut ids = Vec::new();
+        visit(self, entry, &mut HashSet::new(), &mut ids);
+        ids
+    }
+
+    pub fn load(&mut self, path: &Path, from: Option<Span>) -> Option<usize> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) => {
+                let mut error = Diagnostic::error("E0401", format!("cannot read `{}`: {}", path.display(), e));
+                if let Some(span) = from {
+                    error = error.with_primary(span, "imported here");
+                }
+                self.errors.push(error);
+                return None;
+            }
+        };
+        let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+
+        if let Some(&id) = self.by_path.get(&canonical) {
+            return Some(id);
+        }
+
+        if let Some(i) = self.loading.iter().position(|(p, _)| *p == canonical) {
+            let mut chain: Vec<String> = self.loading[i..]
+                .iter()
+                .map(|(_, id)| format!("`{}`", self.sources[*id].name))
+                .collect();
+            chain.push(format!("`{}`", self.sources[self.loading[i].1].name));
+
+            let mut error = Diagnostic::error("E0402", format!("circular import of `{}`", path.display()))
+                .with_note(format!("the import cycle is {}", chain.join(" -> ")));
+            if let Some(span) = from {
+                error = error.with_primary(span, "this import closes the cycle");
+            }
+            self.errors.push(error);
+            return None;
+        }
+
+        let id = self.sources.len();
+        self.sources.push(Source { name: path.display().to_string(), text });
+        self.loading.push((canonical.clone(), id));
+
+        let module = self.parse_module(id, path);
+
+        self.loading.pop();
+        let module = module?;
+        self.by_path.insert(canonical, id);
+        self.modules.insert(id, module);
+        Some(id)
+    }
+
+    fn parse_module(&mut self, id: usize, path: &Path) -> Option<Module> {
+        let tokens = match lexer::tokenize(&self.sources[id].text, id).and_then(first_pass::run) {
+            Ok(result) => result,
+            Err(e) => {
+                self.errors.push(e);
+                return None;
+            }
+        };
+        let (tokens, precedence_map, operator_defs) = tokens;
+        let exported_ops: Vec<OperatorDef> = operator_defs.iter().filter(|d| d.exported).cloned().collect();
+
+        let mut parser = parser::Parser::new(tokens, 0, precedence_map, operator_defs);
+        let dir = path.parent().unwrap_or(Path::new("."));
+
+        let mut imports = Vec::new();
+        let mut imported = Vec::new();
+        for import in parser.parse_imports() {
+            let dep = match self.load(&dir.join(&import.path), Some(import.path_span)) {
+                Some(dep) => dep,
+                None => continue,
+            };
+
+            let available = &self.modules[&dep];
+            imported.extend(match &import.names {
+                None => available.exports.clone(),
+                Some(names) => names.clone(),
+            });
+            let ops: Vec<OperatorDef> = match &import.names {
+                None => available.exported_ops.clone(),
+                Some(names) => {
+                    for name in names {
+                        if !available.exports.iter().any(|e| e.kind == name.kind && e.name == name.name) {
+                            self.errors.push(
+                                Diagnostic::error("E0403", format!("`{}` has no `{}` for sale", import.path, display_name(name)))
+                                    .with_primary(name.span, "not exported")
+                                    .with_note("only definitions marked `for sale` can be imported"),
+                            );
+                        }
+                    }
+                    available
+                        .exported_ops
+                        .iter()
+                        .filter(|d| names.iter().any(|n| n.kind == ImportKind::Operator && n.name == d.op))
+                        .cloned()
+                        .collect()
+                }
+            };
+            parser.import_operators(&ops);
+            imports.push((import, dep));
+        }
+
+        let (operators, errors) = parser.parse_operators();
+        self.errors.extend(errors);
+        let (ast, errors) = parser.parse();
+        self.errors.extend(errors);
+
+        self.errors.extend(semantic::check(&ast));
+        for op in operators.values() {
+            self.errors.extend(semantic::check(&op.body));
+        }
+        let bodies: Vec<(&[String], &Expr)> = operators.values().map(|op| (op.params.as_slice(), &op.body)).collect();
+        let (resolution, errors) = resolver::resolve(&ast, &imported, &bodies);
+        self.errors.extend(errors);
+
+        let mut exports: Vec<ImportName> = exported_ops
+            .iter()
+            .map(|d| ImportName { kind: ImportKind::Operator, name: d.op.clone(), span: d.span })
+            .collect();
+        if let ExprKind::Block(exprs) = &ast.kind {
+            for expr in exprs {
+                if let ExprKind::Export(inner) = &expr.kind {
+                    match &inner.kind {
+                        ExprKind::Define { var, constant, .. } => exports.push(ImportName {
+                            kind: if *constant { ImportKind::Const } else { ImportKind::Variable },
+                            name: var.clone(),
+                            span: inner.span,
+                        }),
+                        ExprKind::Function { name, .. } => exports.push(ImportName {
+                            kind: ImportKind::Variable,
+                            name: name.clone(),
+                            span: inner.span,
+                        }),
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        Some(Module { ast, operators, exported_ops, exports, imports, resolution })
+    }
+}
+
+fn display_name(name: &ImportName) -> String {
+    match name.kind {
+        ImportKind::Variable => format!("£{}", name.name),
+        ImportKind::Const => format!("${}", name.name),
+        ImportKind::Operator => name.name.clone(),
+    }
+}
+
+pub fn run(loader: &mut Loader, entry: usize) -> Result<Value, RuntimeError> {
+    let mut done = HashMap::new();
+    run_module(loader, entry, &mut done)
+}
+
+fn run_module(loader: &mut Loader, id: usize, done: &mut HashMap<usize, Exports>) -> Result<Value, RuntimeError> {
+    let deps: Vec<usize> = loader.modules[&id].imports.iter().map(|(_, dep)| *dep).collect();
+    for dep in deps {
+        if !done.contains_key(&dep) {
+            run_module(loader, dep, done)?;
+        }
+    }
+
+    let module = loader.modules.get_mut(&id).unwrap();
+    let mut interpreter = Interpreter::new(std::mem::take(&mut module.operators));
+    for (import, dep) in &module.imports {
+        interpreter.import(&done[dep], import.names.as_deref());
+    }
+
+    let value = interpreter.run(&module.ast)?;
+    done.insert(id, interpreter.exports(&module.exports));
+    Ok(value)
+}
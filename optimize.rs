@@ -0,0 +1,198 @@
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use crate::interpreter::{self, Value};
+use crate::module::Loader;
+use crate::parser::{Expr, ExprKind, ImportKind, Number};
+
+struct Optimizer<'a> {
+    consts: Vec<HashMap<String, Value>>,
+    // operators a module defines or imports replace the built-in ones, so they never fold
+    user_ops: &'a HashSet<String>,
+}
+
+// folds what can be worked out before running in every loaded module
+pub fn run(loader: &mut Loader) {
+    let ids: Vec<usize> = loader.modules.keys().copied().collect();
+    for id in ids {
+        let module = &loader.modules[&id];
+        let mut user_ops: HashSet<String> = module.operators.keys().cloned().collect();
+        for (import, dep) in &module.imports {
+            let dep = &loader.modules[dep];
+            user_ops.extend(
+                dep.exported_ops
+                    .iter()
+                    .filter(|d| import.names.as_ref().is_none_or(|names| names.iter().any(|n| n.kind == ImportKind::Operator && n.name == d.op)))
+                    .map(|d| d.op.clone()),
+            );
+        }
+
+        let module = loader.modules.get_mut(&id).unwrap();
+        let mut optimizer = Optimizer { consts: vec![HashMap::new()], user_ops: &user_ops };
+        match &mut module.ast.kind {
+            ExprKind::Block(items) => {
+                for item in items {
+                    optimizer.visit(item);
+                }
+            }
+            _ => optimizer.visit(&mut module.ast),
+        }
+
+        // operator bodies only see the module's constants once it has run, so none are propagated into them
+        for operator in module.operators.values_mut() {
+            let mut optimizer = Optimizer { consts: vec![HashMap::new()], user_ops: &user_ops };
+            optimizer.visit(&mut operator.body);
+        }
+    }
+}
+
+fn literal(value: &Value) -> Option<ExprKind> {
+    match value {
+        Value::Int(n) => Some(ExprKind::Number(Number::Int(*n))),
+        Value::Float(n) => Some(ExprKind::Number(Number::Float(*n))),
+        Value::Str(s) => Some(ExprKind::String(s.clone())),
+        _ => None,
+    }
+}
+
+impl Optimizer<'_> {
+    fn lookup(&self, name: &str) -> Option<Value> {
+        self.consts.iter().rev().find_map(|scope| scope.get(name).cloned())
+    }
+
+    // the value of an expression that can be worked out without running anything;
+    // errors such as a division by zero are left for the program to raise
+    fn value(&self, expr: &Expr) -> Option<Value> {
+        match &expr.kind {
+            ExprKind::Number(Number::Int(n)) => Some(Value::Int(*n)),
+            ExprKind::Number(Number::Float(n)) => Some(Value::Float(*n)),
+            ExprKind::String(s) => Some(Value::Str(s.clone())),
+            ExprKind::Const(name) => self.lookup(name),
+            ExprKind::Binary { left, op, right } if !self.user_ops.contains(op) => {
+                interpreter::binary_op(op, &self.value(left)?, &self.value(right)?).ok()
+            }
+            ExprKind::Unary { oper, op } if !self.user_ops.contains(op) => {
+                interpreter::unary_op(op, &self.value(oper)?).ok()
+            }
+            _ => None,
+        }
+    }
+
+    fn fold(&self, expr: &mut Expr) {
+        if let Some(kind) = self.value(expr).as_ref().and_then(literal) {
+            expr.kind = kind;
+        }
+    }
+
+    fn visit_rc(&mut self, body: &mut Rc<Expr>) {
+        if let Some(body) = Rc::get_mut(body) {
+            self.visit(body);
+        }
+    }
+
+    fn visit(&mut self, expr: &mut Expr) {
+        match &mut expr.kind {
+            ExprKind::Const(_) => self.fold(expr),
+
+            ExprKind::Binary { left, right, .. } => {
+                self.visit(left);
+                self.visit(right);
+                self.fold(expr);
+            }
+
+            ExprKind::Unary { oper, op } => {
+                self.visit(oper);
+                // `!!x` is `?x`, and a `?` or `!` straight after `!` or `?` only needs the outer one's sense
+                if let ExprKind::Unary { oper: inner, op: inner_op } = &mut oper.kind {
+                    let outer = op.as_str();
+                    if matches!(outer, "!" | "?") && matches!(inner_op.as_str(), "!" | "?") && !self.user_ops.contains(outer) && !self.user_ops.contains(inner_op.as_str()) {
+                        let negated = (outer == "!") != (inner_op == "!");
+                        let inner = std::mem::replace(&mut **inner, Expr::new(ExprKind::Error, expr.span));
+                        let op = if negated { "!" } else { "?" };
+                        expr.kind = ExprKind::Unary { oper: Box::new(inner), op: op.to_string() };
+                    }
+                }
+                self.fold(expr);
+            }
+
+            ExprKind::Interpolated(parts) => {
+                for part in parts.iter_mut() {
+                    self.visit(part);
+                }
+                let values: Option<Vec<Value>> = parts.iter().map(|p| self.value(p)).collect();
+                if let Some(values) = values {
+                    expr.kind = ExprKind::String(values.iter().map(|v| v.to_string()).collect());
+                }
+            }
+
+            ExprKind::If { cond, then, else_then } => {
+                self.visit(cond);
+                self.visit(then);
+                self.visit(else_then);
+                if let Some(value) = self.value(cond) {
+                    let taken = if value.is_truthy() { then } else { else_then };
+                    let taken = std::mem::replace(&mut **taken, Expr::new(ExprKind::Error, expr.span));
+                    *expr = taken;
+                }
+            }
+
+            ExprKind::Define { var, constant: true, val } => {
+                self.visit(val);
+                if let Some(value) = self.value(val).filter(|v| literal(v).is_some()) {
+                    if let Some(scope) = self.consts.last_mut() {
+                        scope.insert(var.clone(), value);
+                    }
+                }
+            }
+
+            ExprKind::Block(items) => {
+                self.consts.push(HashMap::new());
+                for item in items {
+                    self.visit(item);
+                }
+                self.consts.pop();
+            }
+
+            ExprKind::Function { body, .. } | ExprKind::Generator(body) => self.visit_rc(body),
+
+            ExprKind::Export(inner) => self.visit(inner),
+
+            _ => {
+                for child in children_mut(expr) {
+                    self.visit(child);
+                }
+            }
+        }
+    }
+}
+
+fn children_mut(expr: &mut Expr) -> Vec<&mut Expr> {
+    match &mut expr.kind {
+        ExprKind::Array(items) => items.iter_mut().collect(),
+        ExprKind::Func { args, .. } => args.iter_mut().collect(),
+        ExprKind::Index { target, index } => vec![target, index],
+        ExprKind::Slice { target, from, to } => {
+            let mut children = vec![&mut **target];
+            children.extend(from.as_deref_mut());
+            children.extend(to.as_deref_mut());
+            children
+        }
+        ExprKind::While { cond, then, else_then, .. } => vec![cond, then, else_then],
+        ExprKind::For { source, then, else_then, .. } => {
+            let mut children: Vec<&mut Expr> = source.as_deref_mut().into_iter().collect();
+            children.push(then);
+            children.push(else_then);
+            children
+        }
+        ExprKind::Define { val, .. } => vec![val],
+        ExprKind::Return(value) => value.as_deref_mut().into_iter().collect(),
+        ExprKind::Yield(inner) | ExprKind::Throw(inner) => vec![inner],
+        ExprKind::Try { attempt, catch, finally, .. } => {
+            let mut children = vec![&mut **attempt];
+            children.extend(catch.as_deref_mut());
+            children.extend(finally.as_deref_mut());
+            children
+        }
+        _ => vec![],
+    }
+}
@@ -1,10 +1,79 @@
-use crate::lexer;
+use crate::diagnostics::Diagnostic;
+use crate::lexer::{self, Span};
+use crate::first_pass::{Associativity, Precedence, PrecedenceMap};
 use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
 
 #[derive(Debug)]
-pub enum Expr {
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+
+    pub fn children(&self) -> Vec<&Expr> {
+        match &self.kind {
+            ExprKind::String(_) | ExprKind::Number(_) | ExprKind::Variable(_) | ExprKind::Const(_) => vec![],
+            ExprKind::Import(_) | ExprKind::Break(_) | ExprKind::Continue(_) | ExprKind::Error => vec![],
+            ExprKind::Interpolated(items) | ExprKind::Array(items) | ExprKind::Block(items) => items.iter().collect(),
+            ExprKind::Func { args, .. } => args.iter().collect(),
+            ExprKind::Binary { left, right, .. } => vec![left, right],
+            ExprKind::Unary { oper, .. } => vec![oper],
+            ExprKind::Index { target, index } => vec![target, index],
+            ExprKind::Slice { target, from, to } => {
+                let mut children = vec![&**target];
+                children.extend(from.as_deref());
+                children.extend(to.as_deref());
+                children
+            }
+            ExprKind::If { cond, then, else_then } => vec![cond, then, else_then],
+            ExprKind::While { cond, then, else_then, .. } => vec![cond, then, else_then],
+            ExprKind::For { source, then, else_then, .. } => {
+                let mut children: Vec<&Expr> = source.as_deref().into_iter().collect();
+                children.push(then);
+                children.push(else_then);
+                children
+            }
+            ExprKind::Define { val, .. } => vec![val],
+            ExprKind::Function { body, .. } | ExprKind::Generator(body) => vec![body],
+            ExprKind::Return(value) => value.as_deref().into_iter().collect(),
+            ExprKind::Export(inner) | ExprKind::Yield(inner) => vec![inner],
+            ExprKind::Try { attempt, catch, finally, .. } => {
+                let mut children = vec![&**attempt];
+                children.extend(catch.as_deref());
+                children.extend(finally.as_deref());
+                children
+            }
+            ExprKind::Throw(value) => vec![value],
+        }
+    }
+
+    // functions and generator blocks are their own generators, so they don't count
+    pub fn contains_yield(&self) -> bool {
+        match &self.kind {
+            ExprKind::Yield(_) => true,
+            ExprKind::Function { .. } | ExprKind::Generator(_) => false,
+            _ => self.children().into_iter().any(Expr::contains_yield),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Number {
+    Int(i64),
+    Float(f64)
+}
+
+#[derive(Debug)]
+pub enum ExprKind {
     String(String),
-    Array(Vec<String>),
+    Interpolated(Vec<Expr>),
+    Number(Number),
+    Array(Vec<Expr>),
     Variable(String),
     Const(String),
 
@@ -24,6 +93,17 @@ pub enum Expr {
         args: Vec<Expr>
     },
 
+    Index {
+        target: Box<Expr>,
+        index: Box<Expr>
+    },
+
+    Slice {
+        target: Box<Expr>,
+        from: Option<Box<Expr>>,
+        to: Option<Box<Expr>>
+    },
+
     If {
         cond: Box<Expr>,
         then: Box<Expr>,
@@ -33,50 +113,109 @@ pub enum Expr {
     While {
         cond: Box<Expr>,
         then: Box<Expr>,
-        else_then: Box<Expr>
+        else_then: Box<Expr>,
+        label: Option<String>
     },
 
     For {
         iter: usize,
         var: String,
+        source: Option<Box<Expr>>,
         then: Box<Expr>,
-        else_then: Box<Expr>
+        else_then: Box<Expr>,
+        label: Option<String>
     },
 
     Define {
         var: String,
+        constant: bool,
         val: Box<Expr>,
     },
 
+    Function {
+        name: String,
+        params: Vec<String>,
+        body: Rc<Expr>
+    },
+
+    Return(Option<Box<Expr>>),
+
+    Import(Import),
+
+    Export(Box<Expr>),
+
     Try {
         attempt: Box<Expr>,
-        catch: Box<Expr>
+        binding: Option<String>,
+        catch: Option<Box<Expr>>,
+        finally: Option<Box<Expr>>
     },
 
+    Throw(Box<Expr>),
+
     Yield(Box<Expr>),
 
-    Break(),
+    Generator(Rc<Expr>),
+
+    Break(Option<String>),
+
+    Continue(Option<String>),
 
-    Block(Vec<Expr>)
+    Block(Vec<Expr>),
+
+    Error
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportKind {
+    Variable,
+    Const,
+    Operator
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportName {
+    pub kind: ImportKind,
+    pub name: String,
+    pub span: Span
+}
+
+#[derive(Debug, Clone)]
+pub struct Import {
+    pub path: String,
+    pub path_span: Span,
+    pub names: Option<Vec<ImportName>>
+}
+
+#[derive(Debug)]
+pub struct OperatorFn {
+    pub op: String,
+    pub kind: crate::first_pass::OperatorKind,
+    pub params: Vec<String>,
+    pub body: Expr
 }
 
+type ParseResult = Result<Expr, Diagnostic>;
+
 pub struct Parser {
     tokens: Vec<lexer::Token>,
     pos: usize,
-    precedence_map: HashMap<String, usize>,
+    precedence_map: PrecedenceMap,
     prefix_ops: HashSet<String>,
     postfix_ops: HashSet<String>,
+    operator_defs: Vec<crate::first_pass::OperatorDef>,
+    errors: Vec<Diagnostic>,
 }
 
 impl Parser {
     pub fn new(
         tokens: Vec<lexer::Token>,
         pos: usize,
-        precedence_map: HashMap<String, usize>,
+        precedence_map: PrecedenceMap,
         operator_defs: Vec<crate::first_pass::OperatorDef>,
     ) -> Self {
         let mut prefix_ops: HashSet<String> =
-            ["!", "?"].iter().map(|s| s.to_string()).collect();
+            ["!", "?", "-"].iter().map(|s| s.to_string()).collect();
         let mut postfix_ops: HashSet<String> =
             ["!", "?"].iter().map(|s| s.to_string()).collect();
 
@@ -100,15 +239,18 @@ impl Parser {
             precedence_map,
             prefix_ops,
             postfix_ops,
+            operator_defs,
+            errors: Vec::new(),
         }
     }
 
-    fn precedence(&self, token: &lexer::Token) -> usize {
+    fn precedence(&self, token: &lexer::Token) -> Precedence {
         let v = &token.value;
+        let fallback = Precedence { level: 0, assoc: Associativity::Left };
 
         match v {
-            Some(lexer::TokenValue::Str(s)) =>  return *self.precedence_map.get(s.as_str()).unwrap_or(&0),
-            _ => crate::fail()
+            Some(lexer::TokenValue::Str(s)) =>  return *self.precedence_map.get(s.as_str()).unwrap_or(&fallback),
+            _ => fallback
         }
     }
 
@@ -122,22 +264,164 @@ impl Parser {
         token
     }
 
-    fn expect(&mut self, types: &[lexer::TokenType]) -> lexer::Token {
-        let token = match self.advance(){ Some(t) => t, None => crate::fail(), };
+    fn current_span(&self) -> Span {
+        match self.tokens.get(self.pos).or(self.tokens.last()) {
+            Some(t) => t.span,
+            None => Span::default(),
+        }
+    }
+
+    fn prev_span(&self) -> Span {
+        match self.pos.checked_sub(1).and_then(|i| self.tokens.get(i)) {
+            Some(t) => t.span,
+            None => self.current_span(),
+        }
+    }
+
+    fn span_from(&self, start: Span) -> Span {
+        start.to(self.prev_span())
+    }
+
+    fn unexpected(&self, token: &lexer::Token, expected: &str) -> Diagnostic {
+        Diagnostic::error("E0201", format!("expected {}, found {}", expected, token._type.describe()))
+            .with_primary(token.span, format!("expected {} here", expected))
+    }
+
+    fn expect(&mut self, types: &[lexer::TokenType]) -> Result<lexer::Token, Diagnostic> {
+        let token = match self.advance() {
+            Some(t) => t,
+            None => {
+                return Err(Diagnostic::error("E0202", "unexpected end of file")
+                    .with_primary(self.prev_span(), "the file ends here"));
+            }
+        };
         if !types.iter().any(|t| *t == token._type) {
-            panic!(
-                "parse error at pos {}: expected one of {:?}, got {:?}",
-                self.pos.saturating_sub(1),
-                types,
-                token._type
-            );
+            let expected: Vec<String> = types.iter().map(|t| t.describe()).collect();
+            return Err(self.unexpected(&token, &expected.join(" or ")));
+        }
+        Ok(token)
+    }
+
+    pub fn import_operators(&mut self, defs: &[crate::first_pass::OperatorDef]) {
+        for def in defs {
+            if self.operator_defs.iter().any(|local| local.op == def.op) {
+                continue;
+            }
+
+            self.precedence_map.insert(def.op.clone(), Precedence { level: def.precedence, assoc: def.assoc });
+            match def.kind {
+                crate::first_pass::OperatorKind::Prefix => {
+                    self.prefix_ops.insert(def.op.clone());
+                }
+                crate::first_pass::OperatorKind::Postfix => {
+                    self.postfix_ops.insert(def.op.clone());
+                }
+                crate::first_pass::OperatorKind::Binary => {}
+            }
         }
-        token
     }
 
-    pub fn parse(&mut self) -> Expr {
+    pub fn parse_imports(&mut self) -> Vec<Import> {
+        let saved = self.pos;
+        let mut imports = Vec::new();
+
+        for i in 0..self.tokens.len() {
+            let all = match self.tokens[i]._type {
+                lexer::TokenType::Import => false,
+                lexer::TokenType::ImportAll => true,
+                _ => continue,
+            };
+            let start = self.tokens[i].span;
+            self.pos = i + 1;
+            // malformed imports are reported again by `parse`, so only the good ones matter here
+            if let Ok(Expr { kind: ExprKind::Import(import), .. }) = self.parse_import(start, all) {
+                imports.push(import);
+            }
+        }
+
+        self.pos = saved;
+        imports
+    }
+
+    pub fn parse_operators(&mut self) -> (HashMap<String, OperatorFn>, Vec<Diagnostic>) {
+        let mut operators = HashMap::new();
+        let mut errors = Vec::new();
+
+        for def in &self.operator_defs {
+            let body_tokens = match &def.func {
+                Some(tokens) => tokens.clone(),
+                None => {
+                    errors.push(Diagnostic::error("E0104", format!("operator `{}` has no body", def.op))
+                        .with_primary(def.span, "defined here")
+                        .with_note("operators are defined as `{{body}, precedence, kind, associativity}`"));
+                    continue;
+                }
+            };
+
+            let mut sub = Parser::new(body_tokens, 0, self.precedence_map.clone(), self.operator_defs.clone());
+            let (body, body_errors) = sub.parse();
+            errors.extend(body_errors);
+
+            let body = match body.kind {
+                ExprKind::Block(mut exprs) if exprs.len() == 1 => exprs.remove(0),
+                other => Expr::new(other, body.span),
+            };
+
+            operators.insert(def.op.clone(), OperatorFn {
+                op: def.op.clone(),
+                kind: def.kind.clone(),
+                params: def.kind.params().iter().map(|p| p.to_string()).collect(),
+                body,
+            });
+        }
+
+        (operators, errors)
+    }
+
+    fn synchronize(&mut self) {
+        let mut depth = 0usize;
+
+        while let Some(token) = self.peek() {
+            match token._type {
+                lexer::TokenType::EOF => return,
+                lexer::TokenType::LeftCurly | lexer::TokenType::Indent => depth += 1,
+                lexer::TokenType::RightCurly | lexer::TokenType::Dedent if depth > 0 => depth -= 1,
+                lexer::TokenType::Dedent => return,
+                lexer::TokenType::RightCurly | lexer::TokenType::Newline | lexer::TokenType::EndOfAssign | lexer::TokenType::EndOfFunction if depth == 0 => {
+                    self.advance();
+                    return;
+                }
+                _ => {}
+            }
+            self.advance();
+        }
+    }
+
+    fn recover(&mut self, error: Diagnostic, start_pos: usize) -> Expr {
+        let start = self.tokens.get(start_pos).map(|t| t.span).unwrap_or_else(|| self.current_span());
+        self.errors.push(error);
+        self.synchronize();
+
+        if self.pos == start_pos && !matches!(self.peek(), Some(t) if matches!(t._type, lexer::TokenType::EOF | lexer::TokenType::Dedent)) {
+            self.advance();
+        }
+
+        Expr::new(ExprKind::Error, self.span_from(start))
+    }
+
+    fn parse_statement(&mut self) -> Expr {
+        let start_pos = self.pos;
+        match self.parse_list() {
+            Ok(expr) => expr,
+            Err(error) => self.recover(error, start_pos),
+        }
+    }
+
+    pub fn parse(&mut self) -> (Expr, Vec<Diagnostic>) {
         // eprintln!("[DEBUG] Starting parse with {} tokens", self.tokens.len());
+        let start = self.current_span();
         let mut exprs = Vec::new();
+        let mut stray_indents = 0usize;
 
         while let Some(token) = self.peek() {
             match token._type {
@@ -147,29 +431,101 @@ impl Parser {
                     self.advance();
                 }
 
-                lexer::TokenType::Indent | lexer::TokenType::Dedent => {
-                    crate::fail();
+                lexer::TokenType::Indent => {
+                    let span = token.span;
+                    stray_indents += 1;
+                    self.errors.push(Diagnostic::error("E0203", "unexpected indentation")
+                        .with_primary(span, "indented blocks must follow a `{`"));
+                    self.advance();
                 }
 
-                _ => exprs.push(self.parse_expr()),
+                lexer::TokenType::Dedent => {
+                    if stray_indents == 0 {
+                        self.errors.push(Diagnostic::error("E0203", "unexpected indentation")
+                            .with_primary(token.span, "this dedent does not close any block"));
+                    }
+                    stray_indents = stray_indents.saturating_sub(1);
+                    self.advance();
+                }
+
+                lexer::TokenType::Export => {
+                    let expr = self.parse_export();
+                    exprs.push(expr);
+                }
+
+                _ => {
+                    let expr = self.parse_statement();
+                    exprs.push(expr);
+                }
             }
         }
 
         // eprintln!("[DEBUG] Parse complete, {} expressions", exprs.len());
-        Expr::Block(exprs)
+        let ast = Expr::new(ExprKind::Block(exprs), start.to(self.current_span()));
+        (ast, std::mem::take(&mut self.errors))
     }
 
-    fn parse_expr(&mut self) -> Expr {
+    fn parse_export(&mut self) -> Expr {
+        let start = self.current_span();
+        self.advance();
+        let inner = self.parse_statement();
+
+        if !matches!(inner.kind, ExprKind::Define { .. } | ExprKind::Function { .. } | ExprKind::Error) {
+            self.errors.push(Diagnostic::error("E0207", "only definitions can be put up `for sale`")
+                .with_primary(inner.span, "this is not a definition")
+                .with_secondary(start, "exported here"));
+        }
+
+        let span = start.to(inner.span);
+        Expr::new(ExprKind::Export(Box::new(inner)), span)
+    }
+
+    fn parse_list(&mut self) -> ParseResult {
+        let first = self.parse_expr()?;
+        if !matches!(self.peek(), Some(t) if t._type == lexer::TokenType::Comma) {
+            return Ok(first);
+        }
+
+        let start = first.span;
+        let mut items = vec![first];
+        while matches!(self.peek(), Some(t) if t._type == lexer::TokenType::Comma) {
+            self.advance();
+            items.push(self.parse_expr()?);
+        }
+
+        Ok(Expr::new(ExprKind::Array(items), self.span_from(start)))
+    }
+
+    fn parse_expr(&mut self) -> ParseResult {
         // eprintln!("[DEBUG] parse_expr with {}", self.tokens.get(self.pos).unwrap());
-        self.parse_binary(0)
+        let expr = self.parse_binary(0)?;
+
+        if matches!(self.peek(), Some(t) if t._type == lexer::TokenType::While) {
+            self.advance();
+            self.expect(&[lexer::TokenType::LeftCurly])?;
+            let body = self.parse_block()?;
+            let else_then = self.parse_loop_else()?;
+            let span = self.span_from(expr.span);
+            return Ok(Expr::new(
+                ExprKind::While {
+                    cond: Box::new(expr),
+                    then: Box::new(body),
+                    else_then: Box::new(else_then),
+                    label: None,
+                },
+                span,
+            ));
+        }
+
+        Ok(expr)
     }
 
-    fn parse_binary(&mut self, min_prec: usize) -> Expr {
+    fn parse_binary(&mut self, min_prec: usize) -> ParseResult {
         // eprintln!("[DEBUG] parse_binary(min_prec={}) with {}", min_prec, self.tokens.get(self.pos).unwrap());
-        let mut left = self.parse_prefix();
+        let mut left = self.parse_prefix()?;
 
         while let Some(token) = self.peek() {
-            
+
             if token._type != lexer::TokenType::BinaryOperator {
                 // eprintln!("[DEBUG] Not a binary operator, breaking");
                 break;
@@ -182,38 +538,73 @@ impl Parser {
             }
 
             let prec = self.precedence(token);
-            if prec < min_prec {
-                // eprintln!("[DEBUG] Precedence {} < {}, breaking", prec, min_prec);
+            if prec.level < min_prec {
+                // eprintln!("[DEBUG] Precedence {} < {}, breaking", prec.level, min_prec);
                 break;
             }
 
             let op_token = self.advance().unwrap();
             // eprintln!("[DEBUG] Processing binary operator: {:?}", op_token);
-            let right = self.parse_binary(prec + 1);
+            let next_min = match prec.assoc {
+                Associativity::Right => prec.level,
+                Associativity::Left | Associativity::None => prec.level + 1,
+            };
+            let right = self.parse_binary(next_min)?;
 
-            left = Expr::Binary {
-                left: Box::new(left),
-                op: match &op_token.value {
-                    Some(lexer::TokenValue::Str(s)) => s.clone(),
-                    _ => crate::fail(),
+            if prec.assoc == Associativity::None {
+                self.check_non_associative(&op_token, prec);
+            }
+
+            let span = left.span.to(right.span);
+            left = Expr::new(
+                ExprKind::Binary {
+                    left: Box::new(left),
+                    op: token_op(&op_token).unwrap_or_default(),
+                    right: Box::new(right),
                 },
-                right: Box::new(right),
-            };
+                span,
+            );
+        }
+
+        Ok(left)
+    }
+
+    fn check_non_associative(&mut self, op_token: &lexer::Token, prec: Precedence) {
+        let next = match self.peek() {
+            Some(t) if t._type == lexer::TokenType::BinaryOperator => t.clone(),
+            _ => return,
+        };
+        if matches!(token_op(&next), Some(op) if self.postfix_ops.contains(&op)) {
+            return;
         }
 
-        left
+        if self.precedence(&next) == prec {
+            let first = token_op(op_token).unwrap_or_default();
+            let second = token_op(&next).unwrap_or_default();
+            self.errors.push(
+                Diagnostic::error("E0205", format!("`{}` and `{}` cannot be chained", first, second))
+                    .with_primary(next.span, "second operator here")
+                    .with_secondary(op_token.span, "first operator here")
+                    .with_note("these operators are non-associative; add parentheses to say which comparison happens first"),
+            );
+        }
     }
 
-    fn parse_prefix(&mut self) -> Expr {
+    fn parse_prefix(&mut self) -> ParseResult {
         if let Some(token) = self.peek() {
             if let Some(op) = token_op(token) {
                 if self.prefix_ops.contains(&op) {
+                    let start = token.span;
                     self.advance();
-                    let expr = self.parse_prefix();
-                    return Expr::Unary {
-                        oper: Box::new(expr),
-                        op,
-                    };
+                    let expr = self.parse_prefix()?;
+                    let span = start.to(expr.span);
+                    return Ok(Expr::new(
+                        ExprKind::Unary {
+                            oper: Box::new(expr),
+                            op,
+                        },
+                        span,
+                    ));
                 }
             }
         }
@@ -221,8 +612,8 @@ impl Parser {
         self.parse_postfix()
     }
 
-    fn parse_postfix(&mut self) -> Expr {
-        let mut expr = self.parse_primary();
+    fn parse_postfix(&mut self) -> ParseResult {
+        let mut expr = self.parse_primary()?;
 
         loop {
             let token = match self.peek() {
@@ -234,104 +625,140 @@ impl Parser {
                 lexer::TokenType::BinaryOperator => {
                     if let Some(op) = token_op(token) {
                         if self.postfix_ops.contains(&op) {
+                            let span = expr.span.to(token.span);
                             self.advance();
-                            expr = Expr::Unary {
-                                oper: Box::new(expr),
-                                op,
-                            };
+                            expr = Expr::new(
+                                ExprKind::Unary {
+                                    oper: Box::new(expr),
+                                    op,
+                                },
+                                span,
+                            );
                             continue;
                         }
                     }
                     break;
                 }
 
-                lexer::TokenType::While => {
+                lexer::TokenType::LeftBracket => {
                     self.advance();
-                    let body = self.parse_block();
-                    expr = Expr::While {
-                        cond: Box::new(expr),
-                        then: Box::new(body),
-                        else_then: Box::new(Expr::Block(vec![])),
-                    };
+                    expr = self.parse_subscript(expr)?;
                 }
 
                 _ => break,
             }
         }
 
-        expr
+        Ok(expr)
     }
 
-    fn parse_primary(&mut self) -> Expr {
+    fn parse_subscript(&mut self, target: Expr) -> ParseResult {
+        let is = |p: &Self, ty: lexer::TokenType| matches!(p.peek(), Some(t) if t._type == ty);
+
+        let from = if is(self, lexer::TokenType::Colon) {
+            None
+        } else {
+            Some(Box::new(self.parse_expr()?))
+        };
+
+        let kind = if is(self, lexer::TokenType::Colon) {
+            self.advance();
+            let to = if is(self, lexer::TokenType::RightBracket) {
+                None
+            } else {
+                Some(Box::new(self.parse_expr()?))
+            };
+            ExprKind::Slice { target: Box::new(target), from, to }
+        } else {
+            match from {
+                Some(index) => ExprKind::Index { target: Box::new(target), index },
+                None => ExprKind::Slice { target: Box::new(target), from: None, to: None },
+            }
+        };
+
+        self.expect(&[lexer::TokenType::RightBracket])?;
+        let start = match &kind {
+            ExprKind::Index { target, .. } | ExprKind::Slice { target, .. } => target.span,
+            _ => self.prev_span(),
+        };
+        Ok(Expr::new(kind, self.span_from(start)))
+    }
+
+    fn parse_primary(&mut self) -> ParseResult {
         // eprintln!("[DEBUG] parse_primary with {}", self.tokens.get(self.pos).unwrap());
-        match self.advance() {
+        let start = self.current_span();
+        let kind = match self.advance() {
             Some(lexer::Token {
                 _type: lexer::TokenType::Const,
                 value: Some(lexer::TokenValue::Str(s)),
+                ..
             }) => {
                 // eprintln!("[DEBUG] Parsed constant: {}", s);
-                Expr::Const(s)
+                ExprKind::Const(s)
             },
 
             Some(lexer::Token {
                 _type: lexer::TokenType::Variable,
                 value: Some(lexer::TokenValue::Str(s)),
+                span,
             }) => {
                 // eprintln!("[DEBUG] Parsed variable: {}", s);
-                Expr::Variable(s)
+                match self.peek() {
+                    // only `£f(` with no gap is a call, so `sweet £x (...)` still reads as a condition
+                    Some(t) if t._type == lexer::TokenType::LeftParen && t.span.start == span.end => {
+                        self.advance();
+                        let args = self.parse_args()?;
+                        ExprKind::Func { name: s, args }
+                    }
+                    _ => ExprKind::Variable(s)
+                }
             },
 
             Some(lexer::Token {
                 _type: lexer::TokenType::String,
                 value: Some(lexer::TokenValue::Str(s)),
+                ..
             }) => {
                 match self.peek() {
                     Some(lexer::Token {_type: lexer::TokenType::LeftParen, ..}) => {
                         self.advance();
-                        let mut args = Vec::new();
-                        
-                        while let Some(token) = self.peek() {
-                            if token._type == lexer::TokenType::RightParen {
-                                break;
-                            }
-                            if token._type == lexer::TokenType::Comma {
-                                self.advance();
-                                continue;
-                            }
-                            args.push(self.parse_expr());
-                        }
-                        
-                        self.expect(&[lexer::TokenType::RightParen]);
-                        Expr::Func {
+                        let args = self.parse_args()?;
+                        ExprKind::Func {
                             name: s,
                             args,
                         }
                     },
-                    Some(lexer::Token {_type: lexer::TokenType::Comma, .. }) => {
-                        // eprintln!("[DEBUG] Decided string was part of array");
-                        let mut array = Vec::<String>::new();
-                        array.push(s);
-                        while self.peek().unwrap()._type == lexer::TokenType::Comma {
-                            self.expect(&[lexer::TokenType::Comma]);
-                            match self.advance() {
-                                Some(lexer:: Token {_type: lexer::TokenType::String, value: Some(lexer::TokenValue::Str(v))}) => {
-                                    array.push(v);
-                                },
-                                _ => crate::fail()
-                            }
-                        }
-                        Expr::Array(array)
-                    },
-                    _ => Expr::String(s)
+                    _ => ExprKind::String(s)
                 }
             },
 
+            Some(lexer::Token {
+                _type: lexer::TokenType::QuotedString,
+                value: Some(lexer::TokenValue::Parts(parts)),
+                span,
+            }) => {
+                return Ok(quoted_string(parts, span));
+            },
+
+            Some(lexer::Token {
+                _type: lexer::TokenType::Number,
+                value: Some(value),
+                span,
+            }) => {
+                let number = match value {
+                    lexer::TokenValue::Float(f) => Number::Float(f),
+                    lexer::TokenValue::Int(n) => Number::Int(n),
+                    _ => Number::Int(0),
+                };
+                return Ok(Expr::new(ExprKind::Number(number), span));
+            },
+
             Some(lexer::Token {
                 _type: lexer::TokenType::If,
                 ..
             }) => {
                 // eprintln!("[DEBUG] Parsing if expression");
-                self.parse_if()
+                return self.parse_if(start);
             },
 
             Some(lexer::Token {
@@ -339,7 +766,7 @@ impl Parser {
                 ..
             }) => {
                 // eprintln!("[DEBUG] Parsing for expression");
-                self.parse_for()
+                return self.parse_for(start);
             },
 
             Some(lexer::Token {
@@ -347,29 +774,126 @@ impl Parser {
                 ..
             }) => {
                 // eprintln!("[DEBUG] Parsing try expression");
-                self.parse_try()
+                return self.parse_try(start);
             },
 
+            Some(lexer::Token {
+                _type: lexer::TokenType::Throw,
+                ..
+            }) => return self.parse_throw(start),
+
             Some(lexer::Token {
                 _type: lexer::TokenType::Yield,
                 ..
             }) => {
                 // eprintln!("[DEBUG] Parsing yield expression");
-                self.parse_yield()
+                return self.parse_yield(start);
+            },
+
+            Some(lexer::Token {
+                _type: lexer::TokenType::Function,
+                ..
+            }) => {
+                return self.parse_function(start);
+            },
+
+            Some(lexer::Token {
+                _type: lexer::TokenType::Return,
+                ..
+            }) => {
+                let value = match self.peek() {
+                    Some(t) if matches!(
+                        t._type,
+                        lexer::TokenType::Newline
+                            | lexer::TokenType::RightCurly
+                            | lexer::TokenType::Dedent
+                            | lexer::TokenType::EndOfFunction
+                            | lexer::TokenType::EOF
+                    ) => None,
+                    _ => Some(Box::new(self.parse_list()?)),
+                };
+                ExprKind::Return(value)
             },
 
             Some(lexer::Token {
                 _type: lexer::TokenType::Break,
                 ..
-            }) => Expr::Break(),
+            }) => ExprKind::Break(self.parse_target_label()),
+
+            Some(lexer::Token {
+                _type: lexer::TokenType::Continue,
+                ..
+            }) => ExprKind::Continue(self.parse_target_label()),
+
+            Some(lexer::Token {
+                _type: lexer::TokenType::Label,
+                value: Some(lexer::TokenValue::Str(name)),
+                span,
+            }) => {
+                let mut expr = self.parse_expr()?;
+                match &mut expr.kind {
+                    ExprKind::While { label, .. } | ExprKind::For { label, .. } => *label = Some(name),
+                    _ => {
+                        return Err(Diagnostic::error("E0209", format!("`@{}` does not label a loop", name))
+                            .with_primary(span, "label here")
+                            .with_secondary(expr.span, "this is not a `lolsie` or `yarp'` loop"));
+                    }
+                }
+                expr.span = span.to(expr.span);
+                return Ok(expr);
+            }
+
+            Some(lexer::Token {
+                _type: lexer::TokenType::Import,
+                ..
+            }) => return self.parse_import(start, false),
+
+            Some(lexer::Token {
+                _type: lexer::TokenType::ImportAll,
+                ..
+            }) => return self.parse_import(start, true),
+
+            Some(lexer::Token {
+                _type: lexer::TokenType::Export,
+                span,
+                ..
+            }) => {
+                return Err(Diagnostic::error("E0207", "`for sale` is only allowed at the top level of a module")
+                    .with_primary(span, "cannot export from here"));
+            }
 
             Some(lexer::Token {
                 _type: lexer::TokenType::LeftParen,
                 ..
             }) => {
-                let expr = self.parse_expr();
-                self.expect(&[lexer::TokenType::RightParen]);
-                expr
+                let expr = self.parse_list()?;
+                self.expect(&[lexer::TokenType::RightParen])?;
+                return Ok(expr);
+            }
+
+            Some(lexer::Token {
+                _type: lexer::TokenType::LeftBracket,
+                ..
+            }) => {
+                let mut items = Vec::new();
+                loop {
+                    while matches!(self.peek(), Some(t) if t._type == lexer::TokenType::Newline) {
+                        self.advance();
+                    }
+                    if matches!(self.peek(), Some(t) if t._type == lexer::TokenType::RightBracket) {
+                        break;
+                    }
+                    items.push(self.parse_expr()?);
+                    while matches!(self.peek(), Some(t) if t._type == lexer::TokenType::Newline) {
+                        self.advance();
+                    }
+                    if !matches!(self.peek(), Some(t) if t._type == lexer::TokenType::Comma) {
+                        break;
+                    }
+                    self.advance();
+                }
+                self.expect(&[lexer::TokenType::RightBracket])?;
+                ExprKind::Array(items)
             }
 
             Some(lexer::Token {
@@ -377,152 +901,384 @@ impl Parser {
                 ..
             }) => {
                 // eprintln!("[DEBUG] Parsing block");
-                self.parse_block()
+                let block = self.parse_block()?;
+                if block.contains_yield() {
+                    let span = block.span;
+                    return Ok(Expr::new(ExprKind::Generator(Rc::new(block)), span));
+                }
+                return Ok(block);
             },
 
             Some(lexer::Token { _type: lexer::TokenType::Define, .. }) => {
-                let var_token = self.expect(&[lexer::TokenType::Variable, lexer::TokenType::Const]);
+                let var_token = self.expect(&[lexer::TokenType::Variable, lexer::TokenType::Const])?;
+                let constant = var_token._type == lexer::TokenType::Const;
                 let var = match var_token.value {
                     Some(lexer::TokenValue::Str(s)) => s,
-                    _ => crate::fail()
+                    _ => String::new()
                 };
-                self.expect(&[lexer::TokenType::Assign]);
+                self.expect(&[lexer::TokenType::Assign])?;
 
-                let val = self.parse_expr();
-                self.expect(&[lexer::TokenType::EndOfAssign]);
-                Expr::Define {var, val: Box::new(val) }
+                let val = self.parse_list()?;
+                self.expect(&[lexer::TokenType::EndOfAssign])?;
+                ExprKind::Define {var, constant, val: Box::new(val) }
             },
 
-            Some(tok) => panic!(
-                "unexpected token {:?} at pos {} with value {:?}",
-                tok._type,
-                self.pos - 1,
-                tok.value
-            ),
-            None => crate::fail(),
+            Some(tok) => return Err(self.unexpected(&tok, "an expression")),
+            None => {
+                return Err(Diagnostic::error("E0202", "unexpected end of file")
+                    .with_primary(self.prev_span(), "expected an expression"));
+            }
+        };
+
+        Ok(Expr::new(kind, self.span_from(start)))
+    }
+
+    fn parse_target_label(&mut self) -> Option<String> {
+        match self.peek() {
+            Some(lexer::Token { _type: lexer::TokenType::Label, value: Some(lexer::TokenValue::Str(name)), .. }) => {
+                let name = name.clone();
+                self.advance();
+                Some(name)
+            }
+            _ => None,
         }
     }
 
-    fn parse_block(&mut self) -> Expr {
+    fn parse_args(&mut self) -> Result<Vec<Expr>, Diagnostic> {
+        let mut args = Vec::new();
+
+        while let Some(token) = self.peek() {
+            if token._type == lexer::TokenType::RightParen {
+                break;
+            }
+            if token._type == lexer::TokenType::Comma {
+                self.advance();
+                continue;
+            }
+            args.push(self.parse_expr()?);
+        }
+
+        self.expect(&[lexer::TokenType::RightParen])?;
+        Ok(args)
+    }
+
+    fn parse_block(&mut self) -> ParseResult {
         // eprintln!("[DEBUG] parse_block with {}", self.tokens.get(self.pos).unwrap());
+        let start = self.prev_span();
         if let Some(token) = self.peek() {
-            
+
             if token._type == lexer::TokenType::RightCurly {
                 self.advance();
-                return Expr::Block(vec![]);
+                return Ok(Expr::new(ExprKind::Block(vec![]), self.span_from(start)));
             }
-            
+
             if token._type != lexer::TokenType::Newline {
-                let expr = self.parse_expr();
-                self.expect(&[lexer::TokenType::RightCurly]);
-                return Expr::Block(vec![expr]);
+                let expr = self.parse_expr()?;
+                self.expect(&[lexer::TokenType::RightCurly])?;
+                return Ok(Expr::new(ExprKind::Block(vec![expr]), self.span_from(start)));
             }
         }
-        self.expect(&[lexer::TokenType::Newline]);
-        self.expect(&[lexer::TokenType::Indent]);
+        self.expect(&[lexer::TokenType::Newline])?;
+        self.expect(&[lexer::TokenType::Indent])?;
 
         let mut exprs = Vec::new();
 
         while let Some(token) = self.peek() {
-            
-            if token._type == lexer::TokenType::Dedent {
-                
+
+            if matches!(token._type, lexer::TokenType::Dedent | lexer::TokenType::EOF) {
+
                 break;
             }
 
-            if token._type == lexer::TokenType::Newline {
+            if matches!(token._type, lexer::TokenType::Newline | lexer::TokenType::Comment | lexer::TokenType::BlockCommentStart | lexer::TokenType::BlockCommentEnd) {
                 self.advance();
                 continue;
             }
 
-            exprs.push(self.parse_expr());
+            exprs.push(self.parse_statement());
         }
 
-        self.expect(&[lexer::TokenType::Dedent]);
+        self.expect(&[lexer::TokenType::Dedent])?;
 
         if matches!(self.peek(), Some(t) if t._type == lexer::TokenType::Newline) {
             self.advance();
         }
 
-        self.expect(&[lexer::TokenType::RightCurly]);
+        self.expect(&[lexer::TokenType::RightCurly])?;
 
         // eprintln!("[DEBUG] Block complete with {} expressions", exprs.len());
-        Expr::Block(exprs)
+        Ok(Expr::new(ExprKind::Block(exprs), self.span_from(start)))
     }
 
-    fn parse_if(&mut self) -> Expr {
+    fn parse_if(&mut self, start: Span) -> ParseResult {
         // eprintln!("[DEBUG] parse_if with {}", self.tokens.get(self.pos).unwrap());
-        let cond = self.parse_expr();
-        self.expect(&[lexer::TokenType::LeftCurly]);
-        let then_branch = self.parse_block();
+        let cond = self.parse_expr()?;
+        self.expect(&[lexer::TokenType::LeftCurly])?;
+        let then_branch = self.parse_block()?;
 
         let else_branch = if let Some(token) = self.peek() {
             if token._type == lexer::TokenType::Else {
                 self.advance();
-                self.expect(&[lexer::TokenType::LeftCurly]);
-                self.parse_block()
+                self.expect(&[lexer::TokenType::LeftCurly])?;
+                self.parse_block()?
+            } else if token._type == lexer::TokenType::ElseIf {
+                // `sweet but stout` is just a nested `sweet` in the else branch
+                let else_if = token.span;
+                self.advance();
+                self.parse_if(else_if)?
             } else {
-                Expr::Block(vec![])
+                Expr::new(ExprKind::Block(vec![]), self.prev_span())
             }
         } else {
-            Expr::Block(vec![])
+            Expr::new(ExprKind::Block(vec![]), self.prev_span())
         };
 
-        Expr::If {
-            cond: Box::new(cond),
-            then: Box::new(then_branch),
-            else_then: Box::new(else_branch),
-        }
+        Ok(Expr::new(
+            ExprKind::If {
+                cond: Box::new(cond),
+                then: Box::new(then_branch),
+                else_then: Box::new(else_branch),
+            },
+            self.span_from(start),
+        ))
     }
 
-    fn parse_for(&mut self) -> Expr {
+    fn parse_for(&mut self, start: Span) -> ParseResult {
         // eprintln!("[DEBUG] parse_for with {}", self.tokens.get(self.pos).unwrap());
         let token = self.tokens.get(self.pos - 1).unwrap();
+        let token_span = token.span;
         let iter = match &token.value {            Some(lexer::TokenValue::Num(n)) => *n,            Some(lexer::TokenValue::Str(s)) => {
-                
-                s.parse().unwrap_or_else(|_| crate::fail())
+
+                match s.parse() {
+                    Ok(n) => n,
+                    Err(_) => {
+                        return Err(Diagnostic::error("E0204", "invalid loop count")
+                            .with_primary(token.span, "the number of iterations is the number of trailing `s`"));
+                    }
+                }
             }
-            _ => crate::fail(),
+            _ => 0,
         };
 
-        let var = match self.expect(&[lexer::TokenType::Variable]).value {
+        let var = match self.expect(&[lexer::TokenType::Variable])?.value {
             Some(lexer::TokenValue::Str(s)) => {
-                
+
                 s
             }
-            _ => crate::fail(),
+            _ => String::new(),
         };
 
-        self.expect(&[lexer::TokenType::LeftCurly]);
-        let body = self.parse_block();
+        let source = if matches!(self.peek(), Some(t) if t._type == lexer::TokenType::LeftCurly) {
+            None
+        } else {
+            let source = self.parse_expr()?;
+            if iter > 0 {
+                self.errors.push(Diagnostic::error("E0210", "a `lolsie` loop cannot have both a count and something to loop over")
+                    .with_primary(source.span, "loops over this")
+                    .with_secondary(token_span, "already counts to this many")
+                    .with_note("drop the trailing `s`, or use a number here instead"));
+            }
+            Some(Box::new(source))
+        };
+
+        self.expect(&[lexer::TokenType::LeftCurly])?;
+        let body = self.parse_block()?;
+        let else_then = self.parse_loop_else()?;
+        let span = self.span_from(start);
+
+        Ok(Expr::new(
+            ExprKind::For {
+                iter,
+                var,
+                source,
+                then: Box::new(body),
+                else_then: Box::new(else_then),
+                label: None,
+            },
+            span,
+        ))
+    }
 
-        Expr::For {
-            iter,
-            var,
-            then: Box::new(body),
-            else_then: Box::new(Expr::Block(vec![])),
+    // `stout` after a loop runs when the loop finishes without `jump off the bandwagon`
+    fn parse_loop_else(&mut self) -> ParseResult {
+        if matches!(self.peek(), Some(t) if t._type == lexer::TokenType::Else) {
+            self.advance();
+            self.expect(&[lexer::TokenType::LeftCurly])?;
+            return self.parse_block();
+        }
+        Ok(Expr::new(ExprKind::Block(vec![]), self.prev_span()))
+    }
+
+    fn parse_function(&mut self, start: Span) -> ParseResult {
+        let name = match self.expect(&[lexer::TokenType::String])?.value {
+            Some(lexer::TokenValue::Str(s)) => s,
+            _ => String::new(),
+        };
+
+        let mut params = Vec::new();
+        if matches!(self.peek(), Some(t) if t._type == lexer::TokenType::Params) {
+            self.advance();
+            loop {
+                let param = self.expect(&[lexer::TokenType::Variable])?;
+                if let Some(lexer::TokenValue::Str(s)) = param.value {
+                    if params.contains(&s) {
+                        self.errors.push(Diagnostic::error("E0206", format!("parameter `£{}` is listed more than once", s))
+                            .with_primary(param.span, "duplicate parameter"));
+                    }
+                    params.push(s);
+                }
+                if !matches!(self.peek(), Some(t) if t._type == lexer::TokenType::Comma) {
+                    break;
+                }
+                self.advance();
+            }
         }
+
+        self.expect(&[lexer::TokenType::LeftCurly])?;
+        let body = self.parse_block()?;
+        self.expect(&[lexer::TokenType::EndOfFunction])?;
+
+        Ok(Expr::new(
+            ExprKind::Function {
+                name,
+                params,
+                body: Rc::new(body),
+            },
+            self.span_from(start),
+        ))
+    }
+
+    fn parse_import(&mut self, start: Span, all: bool) -> ParseResult {
+        let path_token = self.expect(&[lexer::TokenType::QuotedString])?;
+        let path = match &path_token.value {
+            Some(lexer::TokenValue::Parts(parts)) => match parts.as_slice() {
+                [] => String::new(),
+                [lexer::StringPart::Literal(s)] => s.clone(),
+                _ => {
+                    return Err(Diagnostic::error("E0208", "import paths cannot be interpolated")
+                        .with_primary(path_token.span, "expected a plain path"));
+                }
+            },
+            _ => String::new(),
+        };
+
+        let names = if all {
+            None
+        } else {
+            let mut names = Vec::new();
+            loop {
+                let token = self.expect(&[
+                    lexer::TokenType::Variable,
+                    lexer::TokenType::Const,
+                    lexer::TokenType::String,
+                    lexer::TokenType::BinaryOperator,
+                ])?;
+                let (kind, name) = match (&token._type, &token.value) {
+                    (lexer::TokenType::Const, Some(lexer::TokenValue::Str(s))) => (ImportKind::Const, s.clone()),
+                    (lexer::TokenType::BinaryOperator, _) => (ImportKind::Operator, token_op(&token).unwrap_or_default()),
+                    (_, Some(lexer::TokenValue::Str(s))) => (ImportKind::Variable, s.clone()),
+                    _ => (ImportKind::Variable, String::new()),
+                };
+                names.push(ImportName { kind, name, span: token.span });
+
+                if !matches!(self.peek(), Some(t) if t._type == lexer::TokenType::Comma) {
+                    break;
+                }
+                self.advance();
+            }
+            Some(names)
+        };
+
+        Ok(Expr::new(
+            ExprKind::Import(Import { path, path_span: path_token.span, names }),
+            self.span_from(start),
+        ))
     }
 
-    fn parse_try(&mut self) -> Expr {
+    fn parse_try(&mut self, start: Span) -> ParseResult {
         // eprintln!("[DEBUG] parse_try with {}", self.tokens.get(self.pos).unwrap());
-        let attempt = self.parse_expr();
-        self.expect(&[lexer::TokenType::Catch]);
-        let catch = self.parse_expr();
+        let is = |p: &Self, offset: usize, ty: lexer::TokenType| matches!(p.tokens.get(p.pos + offset), Some(t) if t._type == ty);
+        let attempt = self.parse_body()?;
 
-        Expr::Try {
-            attempt: Box::new(attempt),
-            catch: Box::new(catch),
+        let mut binding = None;
+        let mut catch = None;
+        if is(self, 0, lexer::TokenType::Catch) {
+            self.advance();
+            // `yay, homework! £err {` binds the error, a lone `£fallback` is still just the value to use
+            if is(self, 0, lexer::TokenType::Variable) && is(self, 1, lexer::TokenType::LeftCurly) {
+                if let Some(lexer::TokenValue::Str(name)) = self.advance().and_then(|t| t.value) {
+                    binding = Some(name);
+                }
+            }
+            catch = Some(Box::new(self.parse_body()?));
         }
+
+        let finally = if is(self, 0, lexer::TokenType::Finally) {
+            self.advance();
+            self.expect(&[lexer::TokenType::LeftCurly])?;
+            Some(Box::new(self.parse_block()?))
+        } else {
+            None
+        };
+
+        if catch.is_none() && finally.is_none() {
+            let found = self.peek().cloned();
+            let expected = format!("{} or {}", lexer::TokenType::Catch.describe(), lexer::TokenType::Finally.describe());
+            return Err(match found {
+                Some(token) => self.unexpected(&token, &expected),
+                None => Diagnostic::error("E0202", "unexpected end of file").with_primary(self.prev_span(), format!("expected {}", expected)),
+            });
+        }
+
+        Ok(Expr::new(
+            ExprKind::Try {
+                attempt: Box::new(attempt),
+                binding,
+                catch,
+                finally,
+            },
+            self.span_from(start),
+        ))
     }
 
-    fn parse_yield(&mut self) -> Expr {
+    // braces after `try` or `catch` are the body itself, never a block generator of their own
+    fn parse_body(&mut self) -> ParseResult {
+        if matches!(self.peek(), Some(t) if t._type == lexer::TokenType::LeftCurly) {
+            self.advance();
+            return self.parse_block();
+        }
+        self.parse_expr()
+    }
+
+    fn parse_throw(&mut self, start: Span) -> ParseResult {
+        let value = self.parse_list()?;
+        Ok(Expr::new(ExprKind::Throw(Box::new(value)), self.span_from(start)))
+    }
+
+    fn parse_yield(&mut self, start: Span) -> ParseResult {
         // eprintln!("[DEBUG] parse_yield with {}", self.tokens.get(self.pos).unwrap());
-        let expr = self.parse_expr();
-        Expr::Yield(Box::new(expr))
+        let expr = self.parse_list()?;
+        Ok(Expr::new(ExprKind::Yield(Box::new(expr)), self.span_from(start)))
     }
 }
 
+fn quoted_string(parts: Vec<lexer::StringPart>, span: Span) -> Expr {
+    if let [lexer::StringPart::Literal(s)] = parts.as_slice() {
+        return Expr::new(ExprKind::String(s.clone()), span);
+    }
+
+    let parts = parts
+        .into_iter()
+        .map(|part| match part {
+            lexer::StringPart::Literal(s) => Expr::new(ExprKind::String(s), span),
+            lexer::StringPart::Variable(name, span) => Expr::new(ExprKind::Variable(name), span),
+            lexer::StringPart::Const(name, span) => Expr::new(ExprKind::Const(name), span),
+        })
+        .collect();
+
+    Expr::new(ExprKind::Interpolated(parts), span)
+}
+
 fn token_op(token: &lexer::Token) -> Option<String> {
     match (&token._type, &token.value) {
         (lexer::TokenType::BinaryOperator, Some(lexer::TokenValue::Str(s))) => Some(s.clone()),
@@ -532,3 +1288,60 @@ fn token_op(token: &lexer::Token) -> Option<String> {
         _ => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::first_pass;
+
+    fn parse_source(src: &str) -> Expr {
+        let (tokens, precedence_map, operator_defs) = lexer::tokenize(src, 0).and_then(first_pass::run).unwrap();
+        let (ast, errors) = Parser::new(tokens, 0, precedence_map, operator_defs).parse();
+        assert!(errors.is_empty(), "{:?}", errors);
+        ast
+    }
+
+    fn string_in(block: &Expr) -> &str {
+        match &block.kind {
+            ExprKind::Block(items) => match items.as_slice() {
+                [Expr { kind: ExprKind::String(s), .. }] => s,
+                other => panic!("expected one string in the block, got {:?}", other),
+            },
+            other => panic!("expected a block, got {:?}", other),
+        }
+    }
+
+    // the operator each `sweet` tests and the string its branch gives, then what the `stout` gives
+    fn chain(src: &str) -> (Vec<(String, String)>, String) {
+        let ast = parse_source(src);
+        let mut expr = match &ast.kind {
+            ExprKind::Block(items) => &items[0],
+            _ => &ast,
+        };
+        let mut branches = Vec::new();
+        while let ExprKind::If { cond, then, else_then } = &expr.kind {
+            match &cond.kind {
+                ExprKind::Binary { op, .. } => branches.push((op.clone(), string_in(then).to_string())),
+                other => panic!("expected a comparison, got {:?}", other),
+            }
+            expr = else_then;
+        }
+        (branches, string_in(expr).to_string())
+    }
+
+    fn expected() -> (Vec<(String, String)>, String) {
+        (vec![("<".to_string(), "negative".to_string()), ("=".to_string(), "zero".to_string())], "positive".to_string())
+    }
+
+    #[test]
+    fn else_if_chain_on_one_line() {
+        let src = "sweet £n < 0 { \"negative\" } sweet but stout £n = 0 { \"zero\" } stout { \"positive\" }\n";
+        assert_eq!(chain(src), expected());
+    }
+
+    #[test]
+    fn else_if_chain_with_indented_blocks() {
+        let src = "sweet £n < 0 {\n    \"negative\"\n} sweet but stout £n = 0 {\n    \"zero\"\n} stout {\n    \"positive\"\n}\n";
+        assert_eq!(chain(src), expected());
+    }
+}
@@ -0,0 +1,302 @@
+use std::collections::HashMap;
+
+use crate::diagnostics::Diagnostic;
+use crate::interpreter::BUILTINS;
+use crate::lexer::Span;
+use crate::parser::{Expr, ExprKind, ImportKind, ImportName};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefKind {
+    Variable,
+    Const,
+    Function,
+    Parameter,
+    LoopVariable,
+    CatchBinding,
+    Import,
+}
+
+#[derive(Debug, Clone)]
+pub struct Definition {
+    pub name: String,
+    pub kind: DefKind,
+    pub span: Span,
+}
+
+// every `£x`, `$x` and call that names something, keyed by the span of the use
+#[derive(Debug, Default)]
+pub struct Resolution {
+    pub definitions: Vec<Definition>,
+    pub uses: HashMap<Span, usize>,
+}
+
+impl Resolution {
+    pub fn definition_of(&self, use_span: Span) -> Option<&Definition> {
+        self.uses.get(&use_span).map(|&id| &self.definitions[id])
+    }
+}
+
+#[derive(Default)]
+struct Scope {
+    vars: HashMap<String, usize>,
+    consts: HashMap<String, usize>,
+}
+
+struct Resolver {
+    scopes: Vec<Scope>,
+    resolution: Resolution,
+    errors: Vec<Diagnostic>,
+}
+
+pub fn resolve(ast: &Expr, imported: &[ImportName], operators: &[(&[String], &Expr)]) -> (Resolution, Vec<Diagnostic>) {
+    let mut resolver = Resolver {
+        scopes: vec![Scope::default()],
+        resolution: Resolution::default(),
+        errors: Vec::new(),
+    };
+
+    for name in imported {
+        match name.kind {
+            ImportKind::Variable => resolver.declare(&name.name, false, DefKind::Import, name.span),
+            ImportKind::Const => resolver.declare(&name.name, true, DefKind::Import, name.span),
+            ImportKind::Operator => {}
+        }
+    }
+
+    match &ast.kind {
+        ExprKind::Block(items) => resolver.visit_statements(items),
+        _ => resolver.visit(ast),
+    }
+
+    // operator bodies run in the module's global scope, so they see everything defined at the top
+    for (params, body) in operators {
+        resolver.scopes.push(Scope::default());
+        for param in params.iter() {
+            resolver.declare(param, false, DefKind::Parameter, body.span);
+        }
+        resolver.visit(body);
+        resolver.scopes.pop();
+    }
+
+    (resolver.resolution, resolver.errors)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let next = (row[j + 1] + 1).min(row[j] + 1).min(prev + usize::from(ca != *cb));
+            prev = row[j + 1];
+            row[j + 1] = next;
+        }
+    }
+    row[b.len()]
+}
+
+impl Resolver {
+    fn lookup(&self, name: &str, constant: bool) -> Option<usize> {
+        self.scopes.iter().rev().find_map(|scope| {
+            let table = if constant { &scope.consts } else { &scope.vars };
+            table.get(name).copied()
+        })
+    }
+
+    fn declare(&mut self, name: &str, constant: bool, kind: DefKind, span: Span) {
+        let sigil = if constant { "$" } else { "£" };
+        let current = self.scopes.len() - 1;
+        let previous = {
+            let scope = &self.scopes[current];
+            let table = if constant { &scope.consts } else { &scope.vars };
+            table.get(name).copied()
+        };
+
+        if let Some(prev) = previous {
+            let first = &self.resolution.definitions[prev];
+            if kind == DefKind::Function && first.kind == DefKind::Function {
+                self.errors.push(
+                    Diagnostic::error("E0507", format!("railway `{}` is defined more than once in the same block", name))
+                        .with_primary(span, "defined again here")
+                        .with_secondary(first.span, "first defined here"),
+                );
+            }
+        } else if kind != DefKind::Import {
+            let outer = self.scopes[..current].iter().rev().find_map(|scope| {
+                let table = if constant { &scope.consts } else { &scope.vars };
+                table.get(name).copied()
+            });
+            if let Some(outer) = outer {
+                let outer_span = self.resolution.definitions[outer].span;
+                self.errors.push(
+                    Diagnostic::warning("W0501", format!("`{}{}` shadows an outer definition", sigil, name))
+                        .with_primary(span, "this definition hides the outer one")
+                        .with_secondary(outer_span, "outer definition here"),
+                );
+            }
+        }
+
+        let id = self.resolution.definitions.len();
+        self.resolution.definitions.push(Definition { name: name.to_string(), kind, span });
+        let scope = &mut self.scopes[current];
+        let table = if constant { &mut scope.consts } else { &mut scope.vars };
+        table.insert(name.to_string(), id);
+    }
+
+    fn suggestion(&self, name: &str, constant: bool) -> Option<String> {
+        let limit = (name.chars().count() / 3).max(1);
+        self.scopes
+            .iter()
+            .flat_map(|scope| if constant { scope.consts.keys() } else { scope.vars.keys() })
+            .map(|candidate| (edit_distance(name, candidate), candidate))
+            .filter(|(d, _)| *d <= limit)
+            .min()
+            .map(|(_, candidate)| candidate.clone())
+    }
+
+    fn use_name(&mut self, name: &str, constant: bool, span: Span) {
+        if let Some(id) = self.lookup(name, constant) {
+            self.resolution.uses.insert(span, id);
+            return;
+        }
+
+        let sigil = if constant { "$" } else { "£" };
+        let what = if constant { "constant" } else { "variable" };
+        let mut error = Diagnostic::error("E0506", format!("undefined {} `{}{}`", what, sigil, name))
+            .with_primary(span, "not defined in this scope");
+        if let Some(candidate) = self.suggestion(name, constant) {
+            error = error.with_note(format!("did you mean `{}{}`?", sigil, candidate));
+        }
+        self.errors.push(error);
+    }
+
+    fn visit_function(&mut self, params: &[String], body: &Expr, span: Span) {
+        self.scopes.push(Scope::default());
+        for param in params {
+            self.declare(param, false, DefKind::Parameter, span);
+        }
+        self.visit(body);
+        self.scopes.pop();
+    }
+
+    // railways are hoisted so they can call each other, and their bodies are resolved once
+    // the whole block is known because they only run after being called
+    fn visit_statements(&mut self, items: &[Expr]) {
+        let function = |item: &Expr| -> Option<(String, Span)> {
+            match &item.kind {
+                ExprKind::Function { name, .. } => Some((name.clone(), item.span)),
+                ExprKind::Export(inner) => match &inner.kind {
+                    ExprKind::Function { name, .. } => Some((name.clone(), inner.span)),
+                    _ => None,
+                },
+                _ => None,
+            }
+        };
+
+        for item in items {
+            if let Some((name, span)) = function(item) {
+                self.declare(&name, false, DefKind::Function, span);
+            }
+        }
+
+        let mut pending = Vec::new();
+        for item in items {
+            let inner = match &item.kind {
+                ExprKind::Export(inner) => inner,
+                _ => item,
+            };
+            match &inner.kind {
+                ExprKind::Function { params, body, .. } => pending.push((params, body, inner.span)),
+                _ => self.visit(item),
+            }
+        }
+
+        for (params, body, span) in pending {
+            self.visit_function(params, body, span);
+        }
+    }
+
+    fn visit(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Variable(name) => self.use_name(name, false, expr.span),
+
+            ExprKind::Const(name) => self.use_name(name, true, expr.span),
+
+            ExprKind::Func { name, args } => {
+                for arg in args {
+                    self.visit(arg);
+                }
+                match self.lookup(name, false) {
+                    Some(id) => {
+                        self.resolution.uses.insert(expr.span, id);
+                    }
+                    None if BUILTINS.contains(&name.as_str()) => {}
+                    None => {
+                        let mut error = Diagnostic::error("E0506", format!("unknown railway `{}`", name))
+                            .with_primary(expr.span, "not defined in this scope");
+                        if let Some(candidate) = self.suggestion(name, false) {
+                            error = error.with_note(format!("did you mean `{}`?", candidate));
+                        }
+                        self.errors.push(error);
+                    }
+                }
+            }
+
+            ExprKind::Block(items) => {
+                self.scopes.push(Scope::default());
+                self.visit_statements(items);
+                self.scopes.pop();
+            }
+
+            ExprKind::Define { var, constant, val } => {
+                self.visit(val);
+                if *constant {
+                    self.declare(var, true, DefKind::Const, expr.span);
+                } else if let Some(id) = self.lookup(var, false) {
+                    // defining a `£` name that is already visible assigns to it
+                    self.resolution.uses.insert(expr.span, id);
+                } else {
+                    self.declare(var, false, DefKind::Variable, expr.span);
+                }
+            }
+
+            ExprKind::Function { name, params, body } => {
+                self.declare(name, false, DefKind::Function, expr.span);
+                self.visit_function(params, body, expr.span);
+            }
+
+            ExprKind::For { var, source, then, else_then, .. } => {
+                if let Some(source) = source {
+                    self.visit(source);
+                }
+                self.scopes.push(Scope::default());
+                self.declare(var, false, DefKind::LoopVariable, expr.span);
+                self.visit(then);
+                self.scopes.pop();
+                self.visit(else_then);
+            }
+
+            ExprKind::Try { attempt, binding, catch, finally } => {
+                self.visit(attempt);
+                if let Some(catch) = catch {
+                    self.scopes.push(Scope::default());
+                    if let Some(name) = binding {
+                        self.declare(name, false, DefKind::CatchBinding, catch.span);
+                    }
+                    self.visit(catch);
+                    self.scopes.pop();
+                }
+                if let Some(finally) = finally {
+                    self.visit(finally);
+                }
+            }
+
+            _ => {
+                for child in expr.children() {
+                    self.visit(child);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+
+use crate::diagnostics::Diagnostic;
+use crate::lexer::Span;
+use crate::parser::{Expr, ExprKind};
+
+struct Checker {
+    loops: Vec<(Option<String>, Span)>,
+    // inside a generator, and every enclosing node so far is one a generator can pause in
+    resumable: Option<bool>,
+    // inside a `try`, `catch` or `the bell rings` of that generator, which it cannot pause in
+    trying: bool,
+    consts: Vec<HashMap<String, Span>>,
+    errors: Vec<Diagnostic>,
+}
+
+pub fn check(ast: &Expr) -> Vec<Diagnostic> {
+    let mut checker = Checker { loops: Vec::new(), resumable: None, trying: false, consts: Vec::new(), errors: Vec::new() };
+    checker.visit(ast);
+    checker.errors
+}
+
+impl Checker {
+    fn loop_control(&mut self, keyword: &str, label: &Option<String>, span: Span) {
+        if self.loops.is_empty() {
+            self.errors.push(
+                Diagnostic::error("E0501", format!("`{}` outside of a loop", keyword))
+                    .with_primary(span, "not inside a `lolsie` or `yarp'` loop"),
+            );
+            return;
+        }
+
+        if let Some(name) = label {
+            if !self.loops.iter().any(|(l, _)| l.as_ref() == Some(name)) {
+                self.errors.push(
+                    Diagnostic::error("E0502", format!("no enclosing loop is labelled `@{}`", name))
+                        .with_primary(span, "unknown label"),
+                );
+            }
+        }
+    }
+
+    fn enter_loop(&mut self, label: &Option<String>, span: Span) {
+        if let Some(name) = label {
+            if let Some((_, outer)) = self.loops.iter().find(|(l, _)| l.as_ref() == Some(name)) {
+                self.errors.push(
+                    Diagnostic::error("E0503", format!("label `@{}` is already used by an enclosing loop", name))
+                        .with_primary(span, "this loop reuses it")
+                        .with_secondary(*outer, "first used here"),
+                );
+            }
+        }
+        self.loops.push((label.clone(), span));
+    }
+
+    // a `$` name is fixed for as long as it is in scope, so any visible definition counts
+    fn define_const(&mut self, name: &str, span: Span) {
+        if let Some(first) = self.consts.iter().rev().find_map(|scope| scope.get(name)) {
+            self.errors.push(
+                Diagnostic::error("E0505", format!("constant `${}` is defined more than once", name))
+                    .with_primary(span, "redefined here")
+                    .with_secondary(*first, "first defined here")
+                    .with_note("use a `£` variable if the value needs to change"),
+            );
+            return;
+        }
+        if let Some(scope) = self.consts.last_mut() {
+            scope.insert(name.to_string(), span);
+        }
+    }
+
+    fn visit_value(&mut self, expr: &Expr) {
+        let saved = self.resumable;
+        self.resumable = saved.map(|_| false);
+        self.visit(expr);
+        self.resumable = saved;
+    }
+
+    fn visit_generator(&mut self, body: &Expr, generator: bool) {
+        let loops = std::mem::take(&mut self.loops);
+        let saved = std::mem::replace(&mut self.resumable, generator.then_some(true));
+        let trying = std::mem::replace(&mut self.trying, false);
+        self.visit(body);
+        self.trying = trying;
+        self.resumable = saved;
+        self.loops = loops;
+    }
+
+    fn visit(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::String(_) | ExprKind::Number(_) | ExprKind::Variable(_) | ExprKind::Const(_) => {}
+            ExprKind::Import(_) | ExprKind::Error => {}
+
+            ExprKind::Block(items) => {
+                self.consts.push(HashMap::new());
+                for item in items {
+                    self.visit(item);
+                }
+                self.consts.pop();
+            }
+
+            ExprKind::Define { var, constant, val } => {
+                self.visit_value(val);
+                if *constant {
+                    self.define_const(var, expr.span);
+                }
+            }
+
+            ExprKind::If { cond, then, else_then } => {
+                self.visit_value(cond);
+                self.visit(then);
+                self.visit(else_then);
+            }
+
+            ExprKind::While { cond, then, else_then, label } => {
+                self.visit_value(cond);
+                self.enter_loop(label, expr.span);
+                self.visit(then);
+                self.loops.pop();
+                self.visit(else_then);
+            }
+
+            ExprKind::For { source, then, else_then, label, .. } => {
+                if let Some(source) = source {
+                    self.visit_value(source);
+                }
+                self.enter_loop(label, expr.span);
+                self.visit(then);
+                self.loops.pop();
+                self.visit(else_then);
+            }
+
+            // a function body cannot break out of the loop it was defined in
+            ExprKind::Function { body, .. } => self.visit_generator(body, body.contains_yield()),
+
+            ExprKind::Generator(body) => self.visit_generator(body, true),
+
+            ExprKind::Yield(inner) => {
+                match self.resumable {
+                    Some(true) => {}
+                    Some(false) if self.trying => self.errors.push(
+                        Diagnostic::error("E0504", "`anywho` can only be used as a statement in a generator")
+                            .with_primary(expr.span, "cannot pause inside a `try`")
+                            .with_note("a generator cannot pause until the `try` and whatever catches or cleans up after it have finished"),
+                    ),
+                    Some(false) => self.errors.push(
+                        Diagnostic::error("E0504", "`anywho` can only be used as a statement in a generator")
+                            .with_primary(expr.span, "cannot pause here")
+                            .with_note("put the `anywho` on its own line inside the block, `sweet` or loop"),
+                    ),
+                    None => self.errors.push(
+                        Diagnostic::error("E0504", "`anywho` can only be used as a statement in a generator")
+                            .with_primary(expr.span, "not inside a generator")
+                            .with_note("a railway or `{ }` block that uses `anywho` is a generator"),
+                    ),
+                }
+                self.visit_value(inner);
+            }
+
+            ExprKind::Try { .. } => {
+                let trying = self.trying;
+                self.trying = trying || self.resumable.is_some();
+                for child in expr.children() {
+                    self.visit_value(child);
+                }
+                self.trying = trying;
+            }
+
+            ExprKind::Export(inner) => self.visit(inner),
+
+            ExprKind::Break(label) => self.loop_control("jump off the bandwagon", label, expr.span),
+
+            ExprKind::Continue(label) => self.loop_control("get back to work boy", label, expr.span),
+
+            _ => {
+                for child in expr.children() {
+                    self.visit_value(child);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,315 @@
+// golden tests: every script in `samples/` has to print the same thing with
+// every backend and with folding on or off, and that has to match its `.out` file
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+
+use crate::diagnostics::Diagnostic;
+use crate::interpreter::{self, Value};
+use crate::wasm_exec::{self, Trap};
+use crate::{c, js, module, optimize, types, vm, wasm, STACK_SIZE};
+
+#[derive(Debug, Clone, Copy)]
+pub enum Backend {
+    Interpreter,
+    Vm,
+    Wasm,
+    C,
+    Js,
+}
+
+pub fn samples() -> Vec<PathBuf> {
+    scripts("samples")
+}
+
+pub fn scripts(dir: &str) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .expect("tests run from the repository root")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|e| e == "vit"))
+        .collect();
+    paths.sort();
+    paths
+}
+
+// one line per error, with where it was raised and the trace under it
+pub fn summary(diagnostic: &Diagnostic) -> String {
+    let mut out = format!("{}[{}]", diagnostic.severity, diagnostic.code);
+    if let Some(span) = diagnostic.primary_span() {
+        out += &format!(" at {}:{}", span.line, span.col);
+    }
+    out += &format!(": {}\n", diagnostic.message);
+    for note in &diagnostic.notes {
+        out += &format!("  = note: {}\n", note);
+    }
+    out
+}
+
+// the compiled backends print runtime errors the way `Diagnostic::render` does, without the source
+// lines; this gives them the shape `summary` does so they can be compared with the interpreter
+pub fn reported(stderr: &str) -> String {
+    let mut out = String::new();
+    let mut lines = stderr.lines().peekable();
+    while let Some(line) = lines.next() {
+        if line.starts_with("  = note: ") {
+            out += &format!("{}\n", line);
+            continue;
+        }
+        let (head, message) = line.split_once(": ").unwrap_or((line, ""));
+        let at = lines.next_if(|l| l.starts_with(" --> ")).map(|l| {
+            let mut parts = l.rsplitn(3, ':');
+            let (col, line) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+            format!(" at {}:{}", line, col)
+        });
+        out += &format!("{}{}: {}\n", head, at.unwrap_or_default(), message);
+    }
+    out
+}
+
+fn available(program: &str) -> bool {
+    Command::new(program).arg("--version").output().is_ok_and(|o| o.status.success())
+}
+
+// `$CC`, or `cc` when it is not set, as `vita build` would use
+pub fn has_c_compiler() -> bool {
+    available(&std::env::var("CC").unwrap_or_else(|_| "cc".to_string()))
+}
+
+pub fn has_node() -> bool {
+    available("node")
+}
+
+fn scratch(backend: &str, path: &Path) -> (PathBuf, String) {
+    let stem = path.file_stem().unwrap().to_string_lossy().into_owned();
+    let dir = std::env::temp_dir().join(format!("vita-{}-{}-{}", backend, std::process::id(), stem));
+    fs::create_dir_all(&dir).unwrap();
+    (dir, stem)
+}
+
+fn output(run: std::process::Output) -> String {
+    String::from_utf8_lossy(&run.stdout).into_owned() + &reported(&String::from_utf8_lossy(&run.stderr))
+}
+
+// build the script as `vita build` would, somewhere of its own, and run what comes out
+fn native(loader: &module::Loader, entry: usize, path: &Path) -> String {
+    let (dir, stem) = scratch("c", path);
+    let (source, binary) = (dir.join(format!("{}.c", stem)), dir.join(&stem));
+    fs::write(&source, c::generate(loader, entry)).unwrap();
+    if let Err(e) = c::compile(&source, &binary) {
+        panic!("{} does not compile to C: {}", path.display(), summary(&e));
+    }
+    let out = output(Command::new(&binary).output().unwrap());
+    fs::remove_dir_all(&dir).unwrap();
+    out
+}
+
+// the same for `vita js`, run by node
+fn script(loader: &module::Loader, entry: usize, path: &Path) -> String {
+    let (dir, stem) = scratch("js", path);
+    let file = dir.join(format!("{}.js", stem));
+    fs::write(&file, js::transpile(loader, entry, &file).code).unwrap();
+    let out = output(Command::new("node").arg(&file).output().unwrap());
+    fs::remove_dir_all(&dir).unwrap();
+    out
+}
+
+// on a thread of its own so it gets the stack `main` would give it and a fresh print buffer
+pub fn on_big_stack<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    thread::Builder::new().stack_size(STACK_SIZE).spawn(f).unwrap().join().unwrap()
+}
+
+// what running the script printed, then the value it finished with or the errors it stopped on
+pub fn execute(path: &Path, backend: Backend, fold: bool) -> String {
+    let path = path.to_path_buf();
+    on_big_stack(move || {
+        let mut loader = module::Loader::new();
+        let result = match loader.load(&path, None) {
+            Some(entry) if !loader.errors.iter().any(Diagnostic::is_error) => {
+                if fold {
+                    optimize::run(&mut loader);
+                }
+                match backend {
+                    Backend::Interpreter => module::run(&mut loader, entry).map_err(|e| vec![e.into()]),
+                    Backend::Vm => vm::run(&loader, entry),
+                    Backend::Wasm => wasm::compile(&loader, entry).and_then(|output| match wasm_exec::run(&output.binary) {
+                        Ok(value) => Ok(value),
+                        Err(Trap::Fail(e)) => Err(vec![e.into()]),
+                        Err(Trap::Wasm(message)) => panic!("{} traps in WebAssembly: {}", path.display(), message),
+                    }),
+                    Backend::C => return native(&loader, entry, &path),
+                    Backend::Js => return script(&loader, entry, &path),
+                }
+            }
+            _ => Err(std::mem::take(&mut loader.errors)),
+        };
+
+        let mut out = interpreter::OUTPUT.with(|o| o.take());
+        match result {
+            Ok(Value::Null) => {}
+            Ok(value) => out += &format!("{}\n", value),
+            Err(diagnostics) => {
+                for diagnostic in &diagnostics {
+                    out += &summary(diagnostic);
+                }
+            }
+        }
+        out
+    })
+}
+
+// what `vita check` finds wrong with the script, warnings included, before anything is folded away
+pub fn diagnose(path: &Path) -> String {
+    let path = path.to_path_buf();
+    on_big_stack(move || {
+        let mut loader = module::Loader::new();
+        let entry = loader.load(&path, None);
+        let mut diagnostics = std::mem::take(&mut loader.errors);
+        if let Some(entry) = entry.filter(|_| !diagnostics.iter().any(Diagnostic::is_error)) {
+            diagnostics.extend(types::check(&loader, entry).errors);
+        }
+        diagnostics.iter().map(summary).collect()
+    })
+}
+
+// `VITA_BLESS=1` rewrites the `.out` files from the interpreter instead of checking them;
+// `vita build` and `vita js` always fold, so C and JavaScript only run folded, and only when
+// there is a C compiler or node to run them with
+#[test]
+fn samples_match_golden_output() {
+    let bless = std::env::var_os("VITA_BLESS").is_some();
+    let mut runs = vec![(Backend::Interpreter, true), (Backend::Interpreter, false), (Backend::Vm, true), (Backend::Vm, false)];
+    if has_c_compiler() {
+        runs.push((Backend::C, true));
+    } else {
+        eprintln!("no C compiler, so the C backend is not checked");
+    }
+    if has_node() {
+        runs.push((Backend::Js, true));
+    } else {
+        eprintln!("no node, so the JavaScript backend is not checked");
+    }
+    let mut failures = Vec::new();
+    for path in samples() {
+        let golden = path.with_extension("out");
+        let reference = execute(&path, Backend::Interpreter, true);
+        if bless {
+            fs::write(&golden, &reference).unwrap();
+        }
+        let expected = fs::read_to_string(&golden).unwrap_or_default();
+
+        for &(backend, fold) in &runs {
+            let got = execute(&path, backend, fold);
+            if got != expected {
+                failures.push(format!("{} ({:?}, folding {}):\n--- expected\n{}--- got\n{}", path.display(), backend, if fold { "on" } else { "off" }, expected, got));
+            }
+        }
+    }
+    assert!(failures.is_empty(), "{} run(s) differ from their golden output\n\n{}", failures.len(), failures.join("\n"));
+}
+
+// the scripts in `samples/diagnostics/` are there for what `vita check` says about them,
+// which has to match their `.out` files; `VITA_BLESS=1` rewrites those too
+#[test]
+fn diagnostics_match_golden_output() {
+    let bless = std::env::var_os("VITA_BLESS").is_some();
+    let mut failures = Vec::new();
+    for path in scripts("samples/diagnostics") {
+        let golden = path.with_extension("out");
+        let got = diagnose(&path);
+        if bless {
+            fs::write(&golden, &got).unwrap();
+        }
+        let expected = fs::read_to_string(&golden).unwrap_or_default();
+        if got != expected {
+            failures.push(format!("{}:\n--- expected\n{}--- got\n{}", path.display(), expected, got));
+        }
+    }
+    assert!(failures.is_empty(), "{} script(s) differ from their golden diagnostics\n\n{}", failures.len(), failures.join("\n"));
+}
+
+// the checker mustn't turn down a script that runs, even one that stops on an error (E0301) later
+#[test]
+fn check_accepts_every_sample_that_runs() {
+    let mut failures = Vec::new();
+    for path in samples() {
+        let golden = fs::read_to_string(path.with_extension("out")).unwrap_or_default();
+        if golden.lines().any(|line| line.starts_with("error[") && !line.starts_with("error[E0301]")) {
+            continue;
+        }
+        let found = diagnose(&path);
+        if found.lines().any(|line| line.starts_with("error[")) {
+            failures.push(format!("{}:\n{}", path.display(), found));
+        }
+    }
+    assert!(failures.is_empty(), "{} sample(s) fail `vita check`\n\n{}", failures.len(), failures.join("\n"));
+}
+
+// the script at the root of the repository uses the `++` it defines on items of the constant array
+#[test]
+fn test_vit_runs_everywhere() {
+    let path = Path::new("test.vit");
+    let expected = "2 [1, 2, 3]\n3 [1, 2, 3]\n4 [1, 2, 3]\n4\n5\n6\n7\n8\n9\n10\n";
+    let mut runs = vec![(Backend::Interpreter, true), (Backend::Interpreter, false), (Backend::Vm, true), (Backend::Vm, false)];
+    if has_c_compiler() {
+        runs.push((Backend::C, true));
+    }
+    if has_node() {
+        runs.push((Backend::Js, true));
+    }
+    for (backend, fold) in runs {
+        assert_eq!(execute(path, backend, fold), expected, "{:?}, folding {}", backend, if fold { "on" } else { "off" });
+    }
+}
+
+// the WebAssembly backend only takes part of the language, so the samples it turns down are skipped
+#[test]
+fn webassembly_matches_golden_output() {
+    let mut ran = 0;
+    let mut failures = Vec::new();
+    for path in samples() {
+        let expected = fs::read_to_string(path.with_extension("out")).unwrap_or_default();
+        for fold in [true, false] {
+            let got = execute(&path, Backend::Wasm, fold);
+            if got.contains("error[E0804]") {
+                continue;
+            }
+            ran += 1;
+            if got != expected {
+                failures.push(format!("{} (folding {}):\n--- expected\n{}--- got\n{}", path.display(), if fold { "on" } else { "off" }, expected, got));
+            }
+        }
+    }
+    assert!(ran > 0, "no sample compiles to WebAssembly");
+    assert!(failures.is_empty(), "{} run(s) differ from their golden output\n\n{}", failures.len(), failures.join("\n"));
+}
+
+// none of the samples WebAssembly runs stop on an error, so these check how it reports them
+#[test]
+fn webassembly_errors_match_the_interpreter() {
+    let scripts = [
+        "I would love to build a railway called inner and let it carry £x {\n    return £x + (1 = 1)\n} passengers\nI would love to build a railway called outer and let it carry £x {\n    inner(£x * 2)\n} passengers\nprint(outer(3))\n",
+        "print(-(1 = 1))\n",
+        "lolsie £i 2.5 { print(£i) }\n",
+        "lolsie £i 1 = 1 { print(£i) }\n",
+        "I would love to own a plot of land in the 1800s called £big and lease it to 9223372036854775807 owners\nprint(£big + 1)\n",
+        "I would love to own a plot of land in the 1800s called £n and lease it to -8 owners\nprint(£n ^ 0.5)\n",
+        "I would love to own a plot of land in the 1800s called £z and lease it to 0 owners\nprint(1 / £z)\n",
+        "I would love to build a railway called early {\n    £late\n} passengers\nearly()\nI would love to own a plot of land in the 1800s called £late and lease it to 1 owners\n",
+        "I would love to build a railway called down and let it carry £n {\n    down(£n + 1)\n} passengers\ndown(0)\n",
+    ];
+    let dir = std::env::temp_dir().join(format!("vita-wasm-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let mut failures = Vec::new();
+    for (i, script) in scripts.iter().enumerate() {
+        let path = dir.join(format!("error{}.vit", i));
+        fs::write(&path, script).unwrap();
+        let expected = execute(&path, Backend::Interpreter, false);
+        let got = execute(&path, Backend::Wasm, false);
+        if got != expected {
+            failures.push(format!("{}--- expected\n{}--- got\n{}", script, expected, got));
+        }
+    }
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(failures.is_empty(), "{} script(s) fail differently in WebAssembly\n\n{}", failures.len(), failures.join("\n"));
+}
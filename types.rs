@@ -0,0 +1,892 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::diagnostics::Diagnostic;
+use crate::first_pass::OperatorKind;
+use crate::lexer::Span;
+use crate::module::Loader;
+use crate::parser::{Expr, ExprKind, ImportKind, ImportName, OperatorFn};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Var(usize),
+    Number,
+    String,
+    Bool,
+    Null,
+    Range,
+    Error,
+    // only known at run time, e.g. the items of an array that mixes kinds of value
+    Any,
+    Array(Box<Type>),
+    Function(Vec<Type>, Box<Type>),
+    Generator(Box<Type>),
+}
+
+impl Type {
+    fn show(&self, names: &mut Vec<usize>) -> String {
+        match self {
+            Type::Var(v) => {
+                let i = names.iter().position(|n| n == v).unwrap_or_else(|| {
+                    names.push(*v);
+                    names.len() - 1
+                });
+                let letter = (b'a' + (i % 26) as u8) as char;
+                if i < 26 {
+                    format!("'{}", letter)
+                } else {
+                    format!("'{}{}", letter, i / 26)
+                }
+            }
+            Type::Number => "number".to_string(),
+            Type::String => "string".to_string(),
+            Type::Bool => "bool".to_string(),
+            Type::Null => "nothing".to_string(),
+            Type::Range => "range".to_string(),
+            Type::Error => "error".to_string(),
+            Type::Any => "any".to_string(),
+            Type::Array(item) => format!("[{}]", item.show(names)),
+            Type::Function(params, ret) => {
+                let params: Vec<String> = params.iter().map(|p| p.show(names)).collect();
+                format!("({}) -> {}", params.join(", "), ret.show(names))
+            }
+            Type::Generator(item) => format!("generator of {}", item.show(names)),
+        }
+    }
+
+    fn free_vars(&self, out: &mut Vec<usize>) {
+        match self {
+            Type::Var(v) if !out.contains(v) => out.push(*v),
+            Type::Array(item) | Type::Generator(item) => item.free_vars(out),
+            Type::Function(params, ret) => {
+                for p in params {
+                    p.free_vars(out);
+                }
+                ret.free_vars(out);
+            }
+            _ => {}
+        }
+    }
+
+    fn substitute(&self, map: &HashMap<usize, Type>) -> Type {
+        match self {
+            Type::Var(v) => map.get(v).cloned().unwrap_or(Type::Var(*v)),
+            Type::Array(item) => Type::Array(Box::new(item.substitute(map))),
+            Type::Generator(item) => Type::Generator(Box::new(item.substitute(map))),
+            Type::Function(params, ret) => Type::Function(
+                params.iter().map(|p| p.substitute(map)).collect(),
+                Box::new(ret.substitute(map)),
+            ),
+            other => other.clone(),
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.show(&mut Vec::new()))
+    }
+}
+
+// a type with some of its variables free to be anything at each use
+#[derive(Debug, Clone)]
+pub struct Scheme {
+    vars: Vec<usize>,
+    ty: Type,
+}
+
+impl Scheme {
+    fn mono(ty: Type) -> Self {
+        Scheme { vars: Vec::new(), ty }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct TypeExports {
+    vars: HashMap<String, Scheme>,
+    consts: HashMap<String, Scheme>,
+    operators: HashMap<String, (OperatorKind, Scheme)>,
+}
+
+#[derive(Default)]
+struct Scope {
+    vars: HashMap<String, Scheme>,
+    consts: HashMap<String, Scheme>,
+}
+
+struct Function {
+    ret: Type,
+    returns: bool,
+    yields: Option<Type>,
+}
+
+struct Checker {
+    bindings: Vec<Option<Type>>,
+    // variables bound so far, in order, so a failed unification can be undone
+    trail: Vec<usize>,
+    scopes: Vec<Scope>,
+    operators: HashMap<String, (OperatorKind, Scheme)>,
+    functions: Vec<Function>,
+    errors: Vec<Diagnostic>,
+}
+
+// signatures of the entry module's top-level railways and operators, for `vita check`
+pub struct Report {
+    pub signatures: Vec<(String, String)>,
+    pub errors: Vec<Diagnostic>,
+}
+
+pub fn check(loader: &Loader, entry: usize) -> Report {
+    let mut done = HashMap::new();
+    let mut report = Report { signatures: Vec::new(), errors: Vec::new() };
+    check_module(loader, entry, &mut done, &mut report, true);
+    report
+}
+
+fn check_module(loader: &Loader, id: usize, done: &mut HashMap<usize, TypeExports>, report: &mut Report, entry: bool) {
+    let module = &loader.modules[&id];
+    for (_, dep) in &module.imports {
+        if !done.contains_key(dep) {
+            check_module(loader, *dep, done, report, false);
+        }
+    }
+
+    let mut checker = Checker {
+        bindings: Vec::new(),
+        trail: Vec::new(),
+        scopes: vec![Scope::default()],
+        operators: HashMap::new(),
+        functions: Vec::new(),
+        errors: Vec::new(),
+    };
+    for (import, dep) in &module.imports {
+        checker.import(&done[dep], import.names.as_deref());
+    }
+
+    let mut ops: Vec<&OperatorFn> = module.operators.values().collect();
+    ops.sort_by_key(|op| op.body.span.start);
+    for op in &ops {
+        let ty = checker.infer_operator(op);
+        let scheme = checker.generalize(&ty, None);
+        checker.operators.insert(op.op.clone(), (op.kind.clone(), scheme));
+    }
+
+    if let ExprKind::Block(items) = &module.ast.kind {
+        checker.infer_statements(items);
+    } else {
+        checker.infer(&module.ast);
+    }
+
+    if entry {
+        for op in &ops {
+            let (_, scheme) = &checker.operators[&op.op];
+            report.signatures.push((format!("operator {}", op.op), checker.zonk(&scheme.ty).to_string()));
+        }
+        if let ExprKind::Block(items) = &module.ast.kind {
+            for item in items {
+                let inner = match &item.kind {
+                    ExprKind::Export(inner) => inner,
+                    _ => item,
+                };
+                if let ExprKind::Function { name, .. } = &inner.kind {
+                    if let Some(scheme) = checker.scopes[0].vars.get(name) {
+                        report.signatures.push((format!("railway {}", name), checker.zonk(&scheme.ty).to_string()));
+                    }
+                }
+            }
+        }
+    }
+
+    let exports = checker.exports(&module.exports);
+    report.errors.append(&mut checker.errors);
+    done.insert(id, exports);
+}
+
+fn describe(ty: &Type) -> &'static str {
+    match ty {
+        Type::Var(_) => "anything",
+        Type::Number => "number",
+        Type::String => "string",
+        Type::Bool => "bool",
+        Type::Null => "nothing",
+        Type::Range => "range",
+        Type::Error => "error",
+        Type::Any => "anything",
+        Type::Array(_) => "array",
+        Type::Function(..) => "railway",
+        Type::Generator(_) => "generator",
+    }
+}
+
+impl Checker {
+    fn fresh(&mut self) -> Type {
+        self.bindings.push(None);
+        Type::Var(self.bindings.len() - 1)
+    }
+
+    fn resolve(&self, ty: &Type) -> Type {
+        match ty {
+            Type::Var(v) => match &self.bindings[*v] {
+                Some(bound) => self.resolve(bound),
+                None => ty.clone(),
+            },
+            other => other.clone(),
+        }
+    }
+
+    fn zonk(&self, ty: &Type) -> Type {
+        match self.resolve(ty) {
+            Type::Array(item) => Type::Array(Box::new(self.zonk(&item))),
+            Type::Generator(item) => Type::Generator(Box::new(self.zonk(&item))),
+            Type::Function(params, ret) => Type::Function(
+                params.iter().map(|p| self.zonk(p)).collect(),
+                Box::new(self.zonk(&ret)),
+            ),
+            other => other,
+        }
+    }
+
+    fn occurs(&self, v: usize, ty: &Type) -> bool {
+        match self.resolve(ty) {
+            Type::Var(w) => v == w,
+            Type::Array(item) | Type::Generator(item) => self.occurs(v, &item),
+            Type::Function(params, ret) => params.iter().any(|p| self.occurs(v, p)) || self.occurs(v, &ret),
+            _ => false,
+        }
+    }
+
+    fn unify(&mut self, a: &Type, b: &Type) -> bool {
+        match (self.resolve(a), self.resolve(b)) {
+            (Type::Var(v), Type::Var(w)) if v == w => true,
+            (Type::Var(v), other) | (other, Type::Var(v)) => {
+                if self.occurs(v, &other) {
+                    return false;
+                }
+                self.bindings[v] = Some(other);
+                self.trail.push(v);
+                true
+            }
+            (Type::Any, _) | (_, Type::Any) => true,
+            (Type::Array(a), Type::Array(b)) | (Type::Generator(a), Type::Generator(b)) => self.unify(&a, &b),
+            (Type::Function(pa, ra), Type::Function(pb, rb)) => {
+                pa.len() == pb.len() && pa.iter().zip(&pb).all(|(a, b)| self.unify(a, b)) && self.unify(&ra, &rb)
+            }
+            (a, b) => a == b,
+        }
+    }
+
+    // unify, or leave the bindings exactly as they were if the two can't agree
+    fn try_unify(&mut self, a: &Type, b: &Type) -> bool {
+        let mark = self.trail.len();
+        if self.unify(a, b) {
+            return true;
+        }
+        for v in self.trail.drain(mark..) {
+            self.bindings[v] = None;
+        }
+        false
+    }
+
+    fn expect(&mut self, expected: &Type, found: &Type, span: Span) {
+        if self.try_unify(expected, found) {
+            return;
+        }
+
+        let (expected, found) = (self.zonk(expected), self.zonk(found));
+        let mut names = Vec::new();
+        let (expected_text, found_text) = (expected.show(&mut names), found.show(&mut names));
+        self.errors.push(
+            Diagnostic::error("E0601", format!("mismatched types: expected `{}`, found `{}`", expected_text, found_text))
+                .with_primary(span, format!("this is {}", describe(&found))),
+        );
+    }
+
+    fn expect_args(&mut self, name: &str, count: usize, args: &[Expr], span: Span) -> bool {
+        if args.len() == count {
+            return true;
+        }
+        self.errors.push(
+            Diagnostic::error("E0602", format!("`{}` takes {} argument(s) but {} were given", name, count, args.len()))
+                .with_primary(span, "wrong number of arguments"),
+        );
+        false
+    }
+
+    fn not_applicable(&mut self, what: &str, ty: &Type, span: Span) {
+        let ty = self.zonk(ty);
+        self.errors.push(
+            Diagnostic::error("E0603", format!("cannot {} {}", what, describe(&ty)))
+                .with_primary(span, format!("this is {}", ty)),
+        );
+    }
+
+    fn instantiate(&mut self, scheme: &Scheme) -> Type {
+        let map: HashMap<usize, Type> = scheme.vars.iter().map(|v| (*v, self.fresh())).collect();
+        self.zonk(&scheme.ty).substitute(&map)
+    }
+
+    // quantify over everything not pinned down by a binding still in scope; `skip` is the
+    // railway being generalized, whose own monomorphic placeholder shouldn't count
+    fn generalize(&self, ty: &Type, skip: Option<&str>) -> Scheme {
+        let ty = self.zonk(ty);
+        let mut env = Vec::new();
+        let innermost = self.scopes.len() - 1;
+        for (depth, scope) in self.scopes.iter().enumerate() {
+            let vars = scope.vars.iter().filter(|(name, _)| depth != innermost || skip != Some(name.as_str()));
+            for scheme in vars.map(|(_, s)| s).chain(scope.consts.values()) {
+                let mut vars = Vec::new();
+                self.zonk(&scheme.ty).free_vars(&mut vars);
+                env.extend(vars.into_iter().filter(|v| !scheme.vars.contains(v)));
+            }
+        }
+        for function in &self.functions {
+            self.zonk(&function.ret).free_vars(&mut env);
+            if let Some(y) = &function.yields {
+                self.zonk(y).free_vars(&mut env);
+            }
+        }
+
+        let mut vars = Vec::new();
+        ty.free_vars(&mut vars);
+        vars.retain(|v| !env.contains(v));
+        Scheme { vars, ty }
+    }
+
+    fn lookup(&self, name: &str, constant: bool) -> Option<Scheme> {
+        self.scopes.iter().rev().find_map(|scope| {
+            let table = if constant { &scope.consts } else { &scope.vars };
+            table.get(name).cloned()
+        })
+    }
+
+    // a variable given another kind of value can hold anything from then on
+    fn widen(&mut self, name: &str) {
+        if let Some(scope) = self.scopes.iter_mut().rev().find(|scope| scope.vars.contains_key(name)) {
+            scope.vars.insert(name.to_string(), Scheme::mono(Type::Any));
+        }
+    }
+
+    fn bind(&mut self, name: &str, constant: bool, scheme: Scheme) {
+        let scope = self.scopes.last_mut().unwrap();
+        let table = if constant { &mut scope.consts } else { &mut scope.vars };
+        table.insert(name.to_string(), scheme);
+    }
+
+    fn import(&mut self, exports: &TypeExports, names: Option<&[ImportName]>) {
+        let wanted = |kind: ImportKind, name: &str| {
+            names.is_none_or(|names| names.iter().any(|n| n.kind == kind && n.name == name))
+        };
+        for (name, scheme) in &exports.vars {
+            if wanted(ImportKind::Variable, name) {
+                let ty = self.instantiate(scheme);
+                let scheme = self.generalize(&ty, None);
+                self.bind(name, false, scheme);
+            }
+        }
+        for (name, scheme) in &exports.consts {
+            if wanted(ImportKind::Const, name) {
+                let ty = self.instantiate(scheme);
+                let scheme = self.generalize(&ty, None);
+                self.bind(name, true, scheme);
+            }
+        }
+        for (op, (kind, scheme)) in &exports.operators {
+            if wanted(ImportKind::Operator, op) && !self.operators.contains_key(op) {
+                let ty = self.instantiate(scheme);
+                let scheme = self.generalize(&ty, None);
+                self.operators.insert(op.clone(), (kind.clone(), scheme));
+            }
+        }
+    }
+
+    // once a module is done nothing else can constrain it, so every leftover variable is generic
+    fn exports(&self, names: &[ImportName]) -> TypeExports {
+        let close = |scheme: &Scheme| {
+            let ty = self.zonk(&scheme.ty);
+            let mut vars = Vec::new();
+            ty.free_vars(&mut vars);
+            Scheme { vars, ty }
+        };
+        let globals = &self.scopes[0];
+        let mut exports = TypeExports::default();
+        for name in names {
+            match name.kind {
+                ImportKind::Variable => {
+                    if let Some(s) = globals.vars.get(&name.name) {
+                        exports.vars.insert(name.name.clone(), close(s));
+                    }
+                }
+                ImportKind::Const => {
+                    if let Some(s) = globals.consts.get(&name.name) {
+                        exports.consts.insert(name.name.clone(), close(s));
+                    }
+                }
+                ImportKind::Operator => {
+                    if let Some((kind, s)) = self.operators.get(&name.name) {
+                        exports.operators.insert(name.name.clone(), (kind.clone(), close(s)));
+                    }
+                }
+            }
+        }
+        exports
+    }
+
+    fn infer_operator(&mut self, op: &OperatorFn) -> Type {
+        self.scopes.push(Scope::default());
+        let params: Vec<Type> = op.params.iter().map(|_| self.fresh()).collect();
+        for (name, ty) in op.params.iter().zip(&params) {
+            self.bind(name, false, Scheme::mono(ty.clone()));
+        }
+        let ret = self.infer_body(&op.body, false);
+        self.scopes.pop();
+        Type::Function(params, Box::new(ret))
+    }
+
+    // a railway with a `return` gives back what it returns; one without gives back its last value
+    fn infer_body(&mut self, body: &Expr, generator: bool) -> Type {
+        let ret = self.fresh();
+        let yields = if generator { Some(self.fresh()) } else { None };
+        self.functions.push(Function { ret: ret.clone(), returns: false, yields });
+        let value = match &body.kind {
+            ExprKind::Block(items) => self.infer_statements(items),
+            _ => self.infer(body),
+        };
+        let function = self.functions.pop().unwrap();
+        if let Some(yields) = function.yields {
+            return Type::Generator(Box::new(yields));
+        }
+        if !function.returns {
+            self.expect(&ret, &value, body.span);
+        }
+        ret
+    }
+
+    fn infer_function(&mut self, name: &str, params: &[String], body: &Expr) {
+        let own = match self.scopes.last().unwrap().vars.get(name) {
+            Some(scheme) if scheme.vars.is_empty() => scheme.ty.clone(),
+            _ => {
+                let ty = self.fresh();
+                self.bind(name, false, Scheme::mono(ty.clone()));
+                ty
+            }
+        };
+
+        self.scopes.push(Scope::default());
+        let param_types: Vec<Type> = params.iter().map(|_| self.fresh()).collect();
+        for (param, ty) in params.iter().zip(&param_types) {
+            self.bind(param, false, Scheme::mono(ty.clone()));
+        }
+        let ret = self.infer_body(body, body.contains_yield());
+        self.scopes.pop();
+
+        // the name may have held something else before the railway was defined
+        let ty = Type::Function(param_types, Box::new(ret));
+        self.try_unify(&own, &ty);
+        let scheme = self.generalize(&ty, Some(name));
+        self.bind(name, false, scheme);
+    }
+
+    // railways are visible to the whole block so they can call each other
+    fn infer_statements(&mut self, items: &[Expr]) -> Type {
+        for item in items {
+            let inner = match &item.kind {
+                ExprKind::Export(inner) => inner,
+                _ => item,
+            };
+            if let ExprKind::Function { name, .. } = &inner.kind {
+                let ty = self.fresh();
+                self.bind(name, false, Scheme::mono(ty));
+            }
+        }
+
+        let mut last = Type::Null;
+        for item in items {
+            last = self.infer(item);
+        }
+        last
+    }
+
+    fn item_type(&mut self, source: &Type, span: Span) -> Type {
+        match self.resolve(source) {
+            Type::Number | Type::Range => Type::Number,
+            Type::String => Type::String,
+            Type::Array(item) | Type::Generator(item) => *item,
+            Type::Var(_) => self.fresh(),
+            Type::Any => Type::Any,
+            other => {
+                self.not_applicable("loop over", &other, span);
+                self.fresh()
+            }
+        }
+    }
+
+    fn infer_builtin(&mut self, name: &str, args: &[Expr], types: &[Type], span: Span) -> Type {
+        match name {
+            "print" => Type::Null,
+            "len" => {
+                if self.expect_args(name, 1, args, span) {
+                    match self.resolve(&types[0]) {
+                        Type::Array(_) | Type::String | Type::Range | Type::Var(_) | Type::Any => {}
+                        other => self.not_applicable("take the `len` of", &other, args[0].span),
+                    }
+                }
+                Type::Number
+            }
+            "push" => {
+                let item = self.fresh();
+                let array = Type::Array(Box::new(item.clone()));
+                match args.first() {
+                    Some(first) => self.expect(&array, &types[0], first.span),
+                    None => {
+                        self.expect_args(name, 1, args, span);
+                    }
+                }
+                for (arg, ty) in args.iter().zip(types).skip(1) {
+                    self.expect(&item, ty, arg.span);
+                }
+                array
+            }
+            "pop" => {
+                let item = self.fresh();
+                if self.expect_args(name, 1, args, span) {
+                    self.expect(&Type::Array(Box::new(item.clone())), &types[0], args[0].span);
+                }
+                item
+            }
+            // arrays are spliced in and anything else is added as one item
+            "concat" => {
+                let item = self.fresh();
+                for (arg, ty) in args.iter().zip(types) {
+                    match self.resolve(ty) {
+                        Type::Array(inner) => self.expect(&item, &inner, arg.span),
+                        other => self.expect(&item, &other, arg.span),
+                    }
+                }
+                Type::Array(Box::new(item))
+            }
+            "next" => {
+                let item = self.fresh();
+                if self.expect_args(name, 1, args, span) {
+                    self.expect(&Type::Generator(Box::new(item.clone())), &types[0], args[0].span);
+                }
+                item
+            }
+            _ => self.fresh(),
+        }
+    }
+
+    // check each argument against its parameter so a mismatch points at the argument
+    fn apply(&mut self, f: &Type, args: Vec<(Type, Span)>, span: Span) -> Type {
+        match self.resolve(f) {
+            Type::Function(params, ret) if params.len() == args.len() => {
+                for (param, (arg, arg_span)) in params.iter().zip(&args) {
+                    self.expect(param, arg, *arg_span);
+                }
+                *ret
+            }
+            _ => {
+                let ret = self.fresh();
+                let types = args.into_iter().map(|(t, _)| t).collect();
+                self.expect(f, &Type::Function(types, Box::new(ret.clone())), span);
+                ret
+            }
+        }
+    }
+
+    fn infer_binary(&mut self, op: &str, left: &Expr, right: &Expr, span: Span) -> Type {
+        let l = self.infer(left);
+        let r = self.infer(right);
+
+        if let Some((OperatorKind::Binary, scheme)) = self.operators.get(op).cloned() {
+            let f = self.instantiate(&scheme);
+            return self.apply(&f, vec![(l, left.span), (r, right.span)], span);
+        }
+
+        match op {
+            "+" => {
+                self.expect(&l, &r, right.span);
+                match self.resolve(&l) {
+                    Type::Number | Type::String | Type::Array(_) | Type::Var(_) | Type::Any => {}
+                    other => self.not_applicable("apply `+` to", &other, left.span),
+                }
+                l
+            }
+            "-" | "*" | "/" | "^" | "^^" => {
+                self.expect(&Type::Number, &l, left.span);
+                self.expect(&Type::Number, &r, right.span);
+                Type::Number
+            }
+            "<" | ">" | "≤" | "≥" => {
+                self.expect(&l, &r, right.span);
+                match self.resolve(&l) {
+                    Type::Number | Type::String | Type::Var(_) | Type::Any => {}
+                    other => self.not_applicable(&format!("compare with `{}`", op), &other, left.span),
+                }
+                Type::Bool
+            }
+            ".." => {
+                self.expect(&Type::Number, &l, left.span);
+                self.expect(&Type::Number, &r, right.span);
+                Type::Range
+            }
+            "=" => {
+                self.expect(&l, &r, right.span);
+                Type::Bool
+            }
+            _ => self.fresh(),
+        }
+    }
+
+    fn infer_unary(&mut self, op: &str, oper: &Expr, span: Span) -> Type {
+        let t = self.infer(oper);
+
+        if let Some((kind, scheme)) = self.operators.get(op).cloned() {
+            if kind != OperatorKind::Binary {
+                let f = self.instantiate(&scheme);
+                return self.apply(&f, vec![(t, oper.span)], span);
+            }
+        }
+
+        match op {
+            "!" | "?" => Type::Bool,
+            "-" | "++" => {
+                self.expect(&Type::Number, &t, oper.span);
+                Type::Number
+            }
+            _ => self.fresh(),
+        }
+    }
+
+    fn infer_scoped(&mut self, expr: &Expr, bindings: Vec<(String, Type)>) -> Type {
+        self.scopes.push(Scope::default());
+        for (name, ty) in bindings {
+            self.bind(&name, false, Scheme::mono(ty));
+        }
+        let ty = match &expr.kind {
+            ExprKind::Block(items) => self.infer_statements(items),
+            _ => self.infer(expr),
+        };
+        self.scopes.pop();
+        ty
+    }
+
+    fn infer(&mut self, expr: &Expr) -> Type {
+        let span = expr.span;
+        match &expr.kind {
+            ExprKind::String(_) => Type::String,
+
+            ExprKind::Interpolated(parts) => {
+                for part in parts {
+                    self.infer(part);
+                }
+                Type::String
+            }
+
+            ExprKind::Number(_) => Type::Number,
+
+            // items that don't agree widen the array to one of anything
+            ExprKind::Array(items) => {
+                let mut item = self.fresh();
+                for expr in items {
+                    let ty = self.infer(expr);
+                    if !self.try_unify(&item, &ty) {
+                        item = Type::Any;
+                    }
+                }
+                Type::Array(Box::new(item))
+            }
+
+            // undefined names are the resolver's business
+            ExprKind::Variable(name) => match self.lookup(name, false) {
+                Some(scheme) => self.instantiate(&scheme),
+                None => self.fresh(),
+            },
+
+            ExprKind::Const(name) => match self.lookup(name, true) {
+                Some(scheme) => self.instantiate(&scheme),
+                None => self.fresh(),
+            },
+
+            ExprKind::Binary { left, op, right } => self.infer_binary(op, left, right, span),
+
+            ExprKind::Unary { oper, op } => self.infer_unary(op, oper, span),
+
+            ExprKind::Func { name, args } => {
+                let types: Vec<Type> = args.iter().map(|a| self.infer(a)).collect();
+                match self.lookup(name, false) {
+                    Some(scheme) => {
+                        let f = self.instantiate(&scheme);
+                        if let Type::Function(params, _) = self.resolve(&f) {
+                            if !self.expect_args(name, params.len(), args, span) {
+                                return self.fresh();
+                            }
+                        }
+                        let args = types.into_iter().zip(args.iter().map(|a| a.span)).collect();
+                        self.apply(&f, args, span)
+                    }
+                    None => self.infer_builtin(name, args, &types, span),
+                }
+            }
+
+            ExprKind::Index { target, index } => {
+                let t = self.infer(target);
+                let i = self.infer(index);
+                match self.resolve(&t) {
+                    Type::String => {
+                        self.expect(&Type::Number, &i, index.span);
+                        Type::String
+                    }
+                    Type::Error => {
+                        self.expect(&Type::String, &i, index.span);
+                        match &index.kind {
+                            ExprKind::String(field) if field == "line" || field == "column" => Type::Number,
+                            ExprKind::String(field) if field == "kind" || field == "message" => Type::String,
+                            _ => self.fresh(),
+                        }
+                    }
+                    _ => {
+                        let item = self.fresh();
+                        self.expect(&Type::Array(Box::new(item.clone())), &t, target.span);
+                        self.expect(&Type::Number, &i, index.span);
+                        item
+                    }
+                }
+            }
+
+            ExprKind::Slice { target, from, to } => {
+                let t = self.infer(target);
+                for bound in [from, to].into_iter().flatten() {
+                    let b = self.infer(bound);
+                    self.expect(&Type::Number, &b, bound.span);
+                }
+                if self.resolve(&t) != Type::String {
+                    let item = self.fresh();
+                    self.expect(&Type::Array(Box::new(item)), &t, target.span);
+                }
+                t
+            }
+
+            // a branch's value only matters if both branches agree on it
+            ExprKind::If { cond, then, else_then } => {
+                self.infer(cond);
+                let a = self.infer(then);
+                let b = self.infer(else_then);
+                let has_else = !matches!(&else_then.kind, ExprKind::Block(items) if items.is_empty());
+                if has_else && self.try_unify(&a, &b) {
+                    a
+                } else {
+                    self.fresh()
+                }
+            }
+
+            ExprKind::While { cond, then, else_then, .. } => {
+                self.infer(cond);
+                self.infer(then);
+                self.infer(else_then);
+                Type::Null
+            }
+
+            ExprKind::For { var, source, then, else_then, .. } => {
+                let item = match source {
+                    Some(source) => {
+                        let t = self.infer(source);
+                        self.item_type(&t, source.span)
+                    }
+                    None => Type::Number,
+                };
+                self.infer_scoped(then, vec![(var.clone(), item)]);
+                self.infer(else_then);
+                Type::Null
+            }
+
+            ExprKind::Define { var, constant, val } => {
+                let t = self.infer(val);
+                if *constant {
+                    let scheme = self.generalize(&t, None);
+                    self.bind(var, true, scheme);
+                } else {
+                    match self.lookup(var, false) {
+                        Some(scheme) => {
+                            let existing = self.instantiate(&scheme);
+                            if !self.try_unify(&existing, &t) {
+                                self.widen(var);
+                            }
+                        }
+                        None => self.bind(var, false, Scheme::mono(t)),
+                    }
+                }
+                Type::Null
+            }
+
+            ExprKind::Function { name, params, body } => {
+                self.infer_function(name, params, body);
+                Type::Null
+            }
+
+            ExprKind::Return(value) => {
+                let t = match value {
+                    Some(value) => self.infer(value),
+                    None => Type::Null,
+                };
+                if let Some(function) = self.functions.last_mut() {
+                    function.returns = true;
+                    if function.yields.is_none() {
+                        let ret = function.ret.clone();
+                        self.expect(&ret, &t, span);
+                    }
+                }
+                self.fresh()
+            }
+
+            ExprKind::Import(_) => Type::Null,
+
+            ExprKind::Export(inner) => self.infer(inner),
+
+            ExprKind::Try { attempt, binding, catch, finally } => {
+                let a = self.infer(attempt);
+                let result = match catch {
+                    Some(catch) => {
+                        let bindings = binding.iter().map(|b| (b.clone(), Type::Error)).collect();
+                        let c = self.infer_scoped(catch, bindings);
+                        if self.try_unify(&a, &c) {
+                            a
+                        } else {
+                            self.fresh()
+                        }
+                    }
+                    None => a,
+                };
+                if let Some(finally) = finally {
+                    self.infer(finally);
+                }
+                result
+            }
+
+            ExprKind::Throw(value) => {
+                self.infer(value);
+                self.fresh()
+            }
+
+            ExprKind::Yield(inner) => {
+                let t = self.infer(inner);
+                if let Some(y) = self.functions.last().and_then(|f| f.yields.clone()) {
+                    if !self.try_unify(&y, &t) {
+                        self.functions.last_mut().unwrap().yields = Some(Type::Any);
+                    }
+                }
+                t
+            }
+
+            ExprKind::Generator(body) => self.infer_body(body, true),
+
+            ExprKind::Break(_) | ExprKind::Continue(_) | ExprKind::Error => self.fresh(),
+
+            ExprKind::Block(items) => {
+                self.scopes.push(Scope::default());
+                let t = self.infer_statements(items);
+                self.scopes.pop();
+                t
+            }
+        }
+    }
+}
@@ -0,0 +1,667 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::bytecode::{self, Chunk, Op, OperatorSource, Program};
+use crate::diagnostics::Diagnostic;
+use crate::interpreter::{self, RuntimeError, Value, MAX_CALL_DEPTH};
+use crate::lexer::Span;
+use crate::module::Loader;
+use crate::parser::ImportKind;
+
+struct Scope {
+    slots: Vec<Option<Value>>,
+    parent: Option<Env>,
+}
+
+type Env = Rc<RefCell<Scope>>;
+
+fn new_env(size: u16, parent: Option<Env>) -> Env {
+    Rc::new(RefCell::new(Scope { slots: vec![None; size as usize], parent }))
+}
+
+fn read(env: &Env, depth: u16, slot: u16) -> Option<Value> {
+    let scope = env.borrow();
+    match depth {
+        0 => scope.slots[slot as usize].clone(),
+        _ => read(scope.parent.as_ref().expect("scope depth resolved at compile time"), depth - 1, slot),
+    }
+}
+
+fn write(env: &Env, depth: u16, slot: u16, value: Value) {
+    if depth == 0 {
+        env.borrow_mut().slots[slot as usize] = Some(value);
+        return;
+    }
+    let scope = env.borrow();
+    write(scope.parent.as_ref().expect("scope depth resolved at compile time"), depth - 1, slot, value);
+}
+
+// the all-integer cases of the built-in operators; anything else, overflow included, follows the interpreter
+fn int_binary(op: Op, a: i64, b: i64) -> Option<Value> {
+    match op {
+        Op::Add => a.checked_add(b).map(Value::Int),
+        Op::Sub => a.checked_sub(b).map(Value::Int),
+        Op::Mul => a.checked_mul(b).map(Value::Int),
+        Op::Lt => Some(Value::Bool(a < b)),
+        Op::Gt => Some(Value::Bool(a > b)),
+        Op::Le => Some(Value::Bool(a <= b)),
+        Op::Ge => Some(Value::Bool(a >= b)),
+        Op::Eq => Some(Value::Bool(a == b)),
+        Op::Range => Some(Value::Range(a, b)),
+        _ => None,
+    }
+}
+
+// the compiled functions and operator table of one module, shared by its closures
+struct Unit {
+    functions: Vec<Rc<Chunk>>,
+    operators: RefCell<Vec<Rc<Closure>>>,
+}
+
+pub struct Closure {
+    chunk: Rc<Chunk>,
+    env: Env,
+    unit: Rc<Unit>,
+}
+
+impl Closure {
+    pub fn name(&self) -> &str {
+        &self.chunk.name
+    }
+}
+
+impl std::fmt::Debug for Closure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Compiled({}/{})", self.chunk.name, self.chunk.arity)
+    }
+}
+
+#[derive(Default)]
+struct Exports {
+    vars: HashMap<String, Value>,
+    consts: HashMap<String, Value>,
+    operators: HashMap<String, Rc<Closure>>,
+}
+
+struct CallFrame {
+    closure: Rc<Closure>,
+    ip: usize,
+    env: Env,
+    base: usize,
+    iters: usize,
+    span: Span,
+    resumed: Option<Resumed>,
+}
+
+// a frame running a generator; `exit` is where the `lolsie` pulling from it goes once it is done,
+// and without one it was `next` that asked
+struct Resumed {
+    generator: Rc<RefCell<Generator>>,
+    exit: Option<usize>,
+}
+
+#[derive(PartialEq)]
+enum State {
+    Ready,
+    Running,
+    Done,
+}
+
+// A paused generator is a call frame put aside: its scope, the instruction after the `anywho`,
+// and whatever it had on the stack and in loops at the time.
+pub struct Generator {
+    closure: Rc<Closure>,
+    env: Env,
+    ip: usize,
+    stack: Vec<Value>,
+    iters: Vec<Iter>,
+    state: State,
+}
+
+impl Generator {
+    pub fn name(&self) -> &str {
+        &self.closure.chunk.name
+    }
+}
+
+impl std::fmt::Debug for Generator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "CompiledGenerator({})", self.name())
+    }
+}
+
+struct Handler {
+    frame: usize,
+    addr: usize,
+    stack: usize,
+    iters: usize,
+    env: Env,
+}
+
+struct Iter {
+    source: Option<Value>,
+    index: usize,
+    count: usize,
+}
+
+#[derive(Default)]
+struct Vm {
+    stack: Vec<Value>,
+    frames: Vec<CallFrame>,
+    handlers: Vec<Handler>,
+    iters: Vec<Iter>,
+}
+
+pub fn compile_all(loader: &Loader, id: usize, programs: &mut HashMap<usize, Program>) {
+    for (_, dep) in &loader.modules[&id].imports {
+        if !programs.contains_key(dep) {
+            compile_all(loader, *dep, programs);
+        }
+    }
+    programs.entry(id).or_insert_with(|| bytecode::compile(loader, id));
+}
+
+pub fn run(loader: &Loader, entry: usize) -> Result<Value, Vec<Diagnostic>> {
+    let mut programs = HashMap::new();
+    compile_all(loader, entry, &mut programs);
+
+    let mut vm = Vm::default();
+    let mut done = HashMap::new();
+    vm.run_module(loader, &programs, entry, &mut done).map_err(|e| vec![e.into()])
+}
+
+fn loop_source(value: Value, span: Span) -> Result<Value, RuntimeError> {
+    match value {
+        Value::Str(s) => Ok(Value::array(s.chars().map(|c| Value::Str(c.to_string())).collect())),
+        Value::Float(n) if n.fract() == 0.0 && n.abs() < i64::MAX as f64 => Ok(Value::Int(n as i64)),
+        Value::Float(n) => Err(RuntimeError::new(format!("cannot loop {} times", n), span)),
+        other => Ok(other),
+    }
+}
+
+fn next_item(iter: &Iter, span: Span) -> Result<Option<Value>, RuntimeError> {
+    let index = iter.index as i64;
+    match &iter.source {
+        Some(Value::Array(items)) => Ok(items.borrow().get(iter.index).cloned()),
+        Some(Value::Int(n)) => Ok((index < *n).then_some(Value::Int(index))),
+        Some(Value::Range(from, to)) => Ok(from.checked_add(index).filter(|i| i < to).map(Value::Int)),
+        Some(other) => Err(RuntimeError::new(format!("cannot loop over {}", other.type_name()), span)),
+        None => Ok((index < iter.count as i64).then_some(Value::Int(index))),
+    }
+}
+
+impl Vm {
+    fn run_module(
+        &mut self,
+        loader: &Loader,
+        programs: &HashMap<usize, Program>,
+        id: usize,
+        done: &mut HashMap<usize, Exports>,
+    ) -> Result<Value, RuntimeError> {
+        for (_, dep) in &loader.modules[&id].imports {
+            if !done.contains_key(dep) {
+                self.run_module(loader, programs, *dep, done)?;
+            }
+        }
+
+        let program = &programs[&id];
+        let globals = new_env(program.main.slots, None);
+        for (dep, kind, name, slot) in &program.imports {
+            let exports = &done[dep];
+            let value = match kind {
+                ImportKind::Const => exports.consts.get(name),
+                _ => exports.vars.get(name),
+            };
+            if let Some(value) = value {
+                if *kind == ImportKind::Const {
+                    value.freeze();
+                }
+                globals.borrow_mut().slots[*slot as usize] = Some(value.clone());
+            }
+        }
+
+        let unit = Rc::new(Unit { functions: program.functions.clone(), operators: RefCell::new(Vec::new()) });
+        let operators = program
+            .operators
+            .iter()
+            .map(|(_, source)| match source {
+                OperatorSource::Local(i) => Rc::new(Closure {
+                    chunk: program.functions[*i as usize].clone(),
+                    env: globals.clone(),
+                    unit: unit.clone(),
+                }),
+                OperatorSource::Imported(dep, name) => done[dep].operators[name].clone(),
+            })
+            .collect();
+        *unit.operators.borrow_mut() = operators;
+
+        let main = Rc::new(Closure { chunk: program.main.clone(), env: globals.clone(), unit: unit.clone() });
+        let value = self.execute(main)?;
+
+        let mut exports = Exports::default();
+        for (kind, name, slot) in &program.exports {
+            if let Some(value) = globals.borrow().slots[*slot as usize].clone() {
+                match kind {
+                    ImportKind::Const => exports.consts.insert(name.clone(), value),
+                    _ => exports.vars.insert(name.clone(), value),
+                };
+            }
+        }
+        for def in &loader.modules[&id].exported_ops {
+            if let Some(i) = program.operators.iter().position(|(op, _)| *op == def.op) {
+                exports.operators.insert(def.op.clone(), unit.operators.borrow()[i].clone());
+            }
+        }
+        done.insert(id, exports);
+        Ok(value)
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("stack balanced at compile time")
+    }
+
+    fn take(&mut self, count: usize) -> Vec<Value> {
+        let at = self.stack.len() - count;
+        self.stack.split_off(at)
+    }
+
+    fn execute(&mut self, main: Rc<Closure>) -> Result<Value, RuntimeError> {
+        let entry = self.frames.len();
+        self.frames.push(CallFrame {
+            env: main.env.clone(),
+            closure: main,
+            ip: 0,
+            base: self.stack.len(),
+            iters: self.iters.len(),
+            span: Span::default(),
+            resumed: None,
+        });
+
+        loop {
+            match self.run(entry) {
+                Ok(value) => return Ok(value),
+                Err(e) => self.recover(e, entry)?,
+            }
+        }
+    }
+
+    // hand the error to the innermost `naur` still running, unwinding calls on the way
+    fn recover(&mut self, mut e: RuntimeError, entry: usize) -> Result<(), RuntimeError> {
+        loop {
+            let current = self.frames.len() - 1;
+            if self.handlers.last().is_some_and(|h| h.frame == current) {
+                let handler = self.handlers.pop().unwrap();
+                self.stack.truncate(handler.stack);
+                self.iters.truncate(handler.iters);
+                let frame = self.frames.last_mut().unwrap();
+                frame.env = handler.env;
+                frame.ip = handler.addr;
+                self.stack.push(Value::Error(Rc::new(e)));
+                return Ok(());
+            }
+
+            let frame = self.frames.pop().unwrap();
+            self.stack.truncate(frame.base);
+            self.iters.truncate(frame.iters);
+            if let Some(resumed) = frame.resumed {
+                resumed.generator.borrow_mut().state = State::Done;
+            }
+            if current == entry {
+                return Err(e);
+            }
+            e.trace.push((frame.closure.chunk.name.clone(), frame.span));
+        }
+    }
+
+    // true when it pushed a frame, false when the call made a generator and left it on the stack
+    fn call(&mut self, closure: Rc<Closure>, args: Vec<Value>, span: Span) -> Result<bool, RuntimeError> {
+        // the module's own frame is not a call
+        if self.frames.len() > MAX_CALL_DEPTH {
+            return Err(RuntimeError::new(format!("too many nested calls (last was to `{}`)", closure.chunk.name), span));
+        }
+        let env = new_env(closure.chunk.slots, Some(closure.env.clone()));
+        for (slot, arg) in env.borrow_mut().slots.iter_mut().zip(args) {
+            *slot = Some(arg);
+        }
+        if closure.chunk.generator {
+            let generator = Generator { closure, env, ip: 0, stack: Vec::new(), iters: Vec::new(), state: State::Ready };
+            self.stack.push(Value::CompiledGenerator(Rc::new(RefCell::new(generator))));
+            return Ok(false);
+        }
+        self.frames.push(CallFrame {
+            closure,
+            ip: 0,
+            env,
+            base: self.stack.len(),
+            iters: self.iters.len(),
+            span,
+            resumed: None,
+        });
+        Ok(true)
+    }
+
+    // true when it pushed the generator's frame back, false when it had already finished
+    fn resume(&mut self, generator: &Rc<RefCell<Generator>>, exit: Option<usize>, span: Span) -> Result<bool, RuntimeError> {
+        let mut g = generator.borrow_mut();
+        match g.state {
+            State::Done => return Ok(false),
+            State::Running => return Err(RuntimeError::new(format!("generator `{}` is already running", g.name()), span)),
+            State::Ready => {}
+        }
+        if self.frames.len() > MAX_CALL_DEPTH {
+            return Err(RuntimeError::new(format!("too many nested calls (last was to `{}`)", g.name()), span));
+        }
+        g.state = State::Running;
+
+        let base = self.stack.len();
+        let iters = self.iters.len();
+        self.stack.append(&mut g.stack);
+        self.iters.append(&mut g.iters);
+        self.frames.push(CallFrame {
+            closure: g.closure.clone(),
+            ip: g.ip,
+            env: g.env.clone(),
+            base,
+            iters,
+            span,
+            resumed: Some(Resumed { generator: generator.clone(), exit }),
+        });
+        Ok(true)
+    }
+
+    // true when it pushed a frame for a railway, false when a builtin already left its result
+    fn call_value(&mut self, callee: Value, name: &str, args: Vec<Value>, span: Span) -> Result<bool, RuntimeError> {
+        if let Value::Compiled(closure) = callee {
+            if args.len() != closure.chunk.arity {
+                return Err(RuntimeError::new(
+                    format!("`{}` takes {} argument(s) but {} were given", closure.chunk.name, closure.chunk.arity, args.len()),
+                    span,
+                ));
+            }
+            return self.call(closure, args, span);
+        }
+
+        if matches!(name, "push" | "pop") && args.first().is_some_and(Value::is_frozen) {
+            return Err(RuntimeError::new(format!("cannot `{}` on an array held by a constant", name), span));
+        }
+        if name == "next" {
+            let message = match args.as_slice() {
+                [Value::CompiledGenerator(generator)] => {
+                    if self.resume(generator, None, span)? {
+                        return Ok(true);
+                    }
+                    self.stack.push(Value::Null);
+                    return Ok(false);
+                }
+                [other] => format!("`next` expects a generator, not {}", other.type_name()),
+                _ => format!("`next` takes 1 argument(s) but {} were given", args.len()),
+            };
+            return Err(RuntimeError::new(message, span));
+        }
+        let value = interpreter::call_builtin(name, args).map_err(|m| RuntimeError::new(m, span))?;
+        self.stack.push(value);
+        Ok(false)
+    }
+
+    // a call pushed a frame above the one that was running; remember where that one stopped
+    fn suspend(&mut self, ip: usize, env: Env) {
+        let at = self.frames.len() - 2;
+        let caller = &mut self.frames[at];
+        caller.ip = ip;
+        caller.env = env;
+    }
+
+    // runs the innermost frame until the module's own frame returns, holding its chunk and scope
+    // until a call or return moves to another frame
+    fn run(&mut self, entry: usize) -> Result<Value, RuntimeError> {
+        loop {
+            let frame = self.frames.last().unwrap();
+            let closure = frame.closure.clone();
+            let chunk = &*closure.chunk;
+            let mut env = frame.env.clone();
+            let mut ip = frame.ip;
+
+            loop {
+                let op = chunk.code[ip];
+                let span = chunk.spans[ip];
+                ip += 1;
+
+                match op {
+                    Op::Constant(i) => self.stack.push(chunk.constants[i as usize].clone()),
+
+                    Op::Null => self.stack.push(Value::Null),
+
+                    Op::Pop => {
+                        self.pop();
+                    }
+
+                    Op::Array(n) => {
+                        let items = self.take(n as usize);
+                        self.stack.push(Value::array(items));
+                    }
+
+                    Op::Interpolate(n) => {
+                        let parts = self.take(n as usize);
+                        let text: String = parts.iter().map(|v| v.to_string()).collect();
+                        self.stack.push(Value::Str(text));
+                    }
+
+                    Op::GetVar(depth, slot, name) | Op::GetConst(depth, slot, name) => match read(&env, depth, slot) {
+                        Some(value) => self.stack.push(value),
+                        None => {
+                            let sigil = if matches!(op, Op::GetConst(..)) { "constant `$" } else { "variable `£" };
+                            return Err(RuntimeError::new(format!("undefined {}{}`", sigil, chunk.names[name as usize]), span));
+                        }
+                    },
+
+                    Op::GetCallee(depth, slot) => self.stack.push(read(&env, depth, slot).unwrap_or(Value::Null)),
+
+                    Op::SetVar(depth, slot) => {
+                        let value = self.pop();
+                        write(&env, depth, slot, value);
+                    }
+
+                    Op::DefineConst(slot, name) => {
+                        let value = self.pop();
+                        if env.borrow().slots[slot as usize].is_some() {
+                            return Err(RuntimeError::new(format!("cannot redefine constant `${}`", chunk.names[name as usize]), span));
+                        }
+                        value.freeze();
+                        env.borrow_mut().slots[slot as usize] = Some(value);
+                    }
+
+                    Op::Add | Op::Sub | Op::Mul | Op::Div | Op::Pow | Op::Tetrate | Op::Lt | Op::Gt | Op::Le | Op::Ge | Op::Eq | Op::Range => {
+                        let r = self.pop();
+                        let l = self.stack.last_mut().expect("stack balanced at compile time");
+                        let fast = match (&*l, &r) {
+                            (Value::Int(a), Value::Int(b)) => int_binary(op, *a, *b),
+                            _ => None,
+                        };
+                        *l = match fast {
+                            Some(value) => value,
+                            None => interpreter::binary_op(op.symbol(), l, &r).map_err(|m| RuntimeError::new(m, span))?,
+                        };
+                    }
+
+                    Op::Not => {
+                        let v = self.stack.last_mut().expect("stack balanced at compile time");
+                        *v = Value::Bool(!v.is_truthy());
+                    }
+
+                    Op::Truthy => {
+                        let v = self.stack.last_mut().expect("stack balanced at compile time");
+                        *v = Value::Bool(v.is_truthy());
+                    }
+
+                    Op::Neg | Op::Inc => {
+                        let v = self.stack.last_mut().expect("stack balanced at compile time");
+                        *v = interpreter::unary_op(op.symbol(), v).map_err(|m| RuntimeError::new(m, span))?;
+                    }
+
+                    Op::Operator(index, argc) => {
+                        let operator = closure.unit.operators.borrow()[index as usize].clone();
+                        let args = self.take(argc as usize);
+                        if self.call(operator, args, span)? {
+                            self.suspend(ip, env);
+                            break;
+                        }
+                    }
+
+                    Op::Call(name, argc) => {
+                        let args = self.take(argc as usize);
+                        let callee = self.pop();
+                        if self.call_value(callee, &chunk.names[name as usize], args, span)? {
+                            self.suspend(ip, env);
+                            break;
+                        }
+                    }
+
+                    Op::Index => {
+                        let index = self.pop();
+                        let target = self.pop();
+                        let value = interpreter::index_value(&target, &index).map_err(|m| RuntimeError::new(m, span))?;
+                        self.stack.push(value);
+                    }
+
+                    Op::Slice(from, to) => {
+                        let to = if to { Some(self.pop()) } else { None };
+                        let from = if from { Some(self.pop()) } else { None };
+                        let target = self.pop();
+                        let value = interpreter::slice_value(&target, from.as_ref(), to.as_ref()).map_err(|m| RuntimeError::new(m, span))?;
+                        self.stack.push(value);
+                    }
+
+                    Op::Jump(addr) => ip = addr as usize,
+
+                    Op::JumpIfFalse(addr) => {
+                        if !self.pop().is_truthy() {
+                            ip = addr as usize;
+                        }
+                    }
+
+                    Op::PushScope(size) => env = new_env(size, Some(env)),
+
+                    Op::PopScope => {
+                        let parent = env.borrow().parent.clone().expect("scopes balanced at compile time");
+                        env = parent;
+                    }
+
+                    Op::Closure(i) => {
+                        let function = Closure { chunk: closure.unit.functions[i as usize].clone(), env: env.clone(), unit: closure.unit.clone() };
+                        self.stack.push(Value::Compiled(Rc::new(function)));
+                    }
+
+                    Op::Generator(i) => {
+                        let function = Closure { chunk: closure.unit.functions[i as usize].clone(), env: env.clone(), unit: closure.unit.clone() };
+                        self.call(Rc::new(function), Vec::new(), span)?;
+                    }
+
+                    Op::Return => {
+                        let value = self.pop();
+                        let frame = self.frames.pop().unwrap();
+                        self.stack.truncate(frame.base);
+                        self.iters.truncate(frame.iters);
+                        let depth = self.frames.len();
+                        while self.handlers.last().is_some_and(|h| h.frame >= depth) {
+                            self.handlers.pop();
+                        }
+                        if depth == entry {
+                            return Ok(value);
+                        }
+                        match frame.resumed {
+                            // a finished generator ends the `lolsie` over it, or gives `next` nothing
+                            Some(resumed) => {
+                                resumed.generator.borrow_mut().state = State::Done;
+                                match resumed.exit {
+                                    Some(exit) => {
+                                        self.iters.pop();
+                                        self.frames.last_mut().unwrap().ip = exit;
+                                    }
+                                    None => self.stack.push(Value::Null),
+                                }
+                            }
+                            None => self.stack.push(value),
+                        }
+                        break;
+                    }
+
+                    Op::Yield => {
+                        let value = self.pop();
+                        let frame = self.frames.pop().unwrap();
+                        let resumed = frame.resumed.expect("`anywho` only compiles into generators");
+                        let mut g = resumed.generator.borrow_mut();
+                        g.stack = self.stack.split_off(frame.base);
+                        g.stack.push(Value::Null);
+                        g.iters = self.iters.split_off(frame.iters);
+                        g.env = env;
+                        g.ip = ip;
+                        g.state = State::Ready;
+                        self.stack.push(value);
+                        break;
+                    }
+
+                    Op::Throw => {
+                        let value = self.pop();
+                        return Err(RuntimeError::thrown(value, span));
+                    }
+
+                    Op::Rethrow => match self.pop() {
+                        Value::Error(e) => return Err((*e).clone()),
+                        other => return Err(RuntimeError::thrown(other, span)),
+                    },
+
+                    Op::Try(addr) => {
+                        let handler = Handler {
+                            frame: self.frames.len() - 1,
+                            addr: addr as usize,
+                            stack: self.stack.len(),
+                            iters: self.iters.len(),
+                            env: env.clone(),
+                        };
+                        self.handlers.push(handler);
+                    }
+
+                    Op::EndTry => {
+                        self.handlers.pop();
+                    }
+
+                    Op::IterStart(count, has_source) => {
+                        let source = if has_source { Some(loop_source(self.pop(), span)?) } else { None };
+                        self.iters.push(Iter { source, index: 0, count: count as usize });
+                    }
+
+                    Op::IterNext(exit) => {
+                        let iter = self.iters.last_mut().unwrap();
+                        if let Some(Value::CompiledGenerator(generator)) = &iter.source {
+                            let generator = generator.clone();
+                            if self.resume(&generator, Some(exit as usize), span)? {
+                                self.suspend(ip, env);
+                                break;
+                            }
+                            self.iters.pop();
+                            ip = exit as usize;
+                            continue;
+                        }
+                        match next_item(iter, span)? {
+                            Some(item) => {
+                                iter.index += 1;
+                                self.stack.push(item);
+                            }
+                            None => {
+                                self.iters.pop();
+                                ip = exit as usize;
+                            }
+                        }
+                    }
+
+                    Op::IterEnd => {
+                        self.iters.pop();
+                    }
+
+                    Op::Fail(message) => return Err(RuntimeError::new(chunk.names[message as usize].clone(), span)),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,703 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::diagnostics::Diagnostic;
+use crate::lexer::Span;
+use crate::module::{Loader, Module};
+use crate::parser::{Expr, ExprKind, Number};
+use crate::resolver::DefKind;
+use crate::wat;
+
+// the module imports everything that touches the outside from the host as `vita.*`:
+//   print_value(tag, bits) and print_string(pointer, length) write without a newline,
+//   fail(message, length, line, column) reports a runtime error and does not return,
+//   fail_types and fail_value first fill the `{}`s in the message with type names or a value,
+//   pow(x, y) is `f64::powf`.
+// `main` runs the script and returns its value. While a message is being reported, `depth`
+// says how many calls are under way and the start of `memory` holds four i32s per call:
+// the railway's name (pointer and length), then the line and column it was called from
+const RUNTIME: &str = include_str!("wasm_runtime.wat");
+
+// call records sit below this, the strings the module needs from here on
+const DATA: usize = 16384;
+const PAGE: usize = 65536;
+
+pub struct Output {
+    pub text: String,
+    pub binary: Vec<u8>,
+}
+
+fn definitions(module: &Module) -> HashMap<(Span, String), usize> {
+    module
+        .resolution
+        .definitions
+        .iter()
+        .enumerate()
+        .filter(|(_, def)| def.kind != DefKind::Import)
+        .map(|(id, def)| ((def.span, def.name.clone()), id))
+        .collect()
+}
+
+fn ident(name: &str) -> String {
+    name.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect()
+}
+
+fn arithmetic(op: &str) -> Option<&'static str> {
+    Some(match op {
+        "+" => "$add",
+        "-" => "$sub",
+        "*" => "$mul",
+        "/" => "$div",
+        "^" => "$power",
+        _ => return None,
+    })
+}
+
+// what an ordering from `$compare` has to be for each comparison to hold
+fn comparison(op: &str) -> Option<&'static str> {
+    Some(match op {
+        "<" => "i32.const -1\ni32.eq",
+        ">" => "i32.const 1\ni32.eq",
+        "≤" => "i32.const 1\ni32.lt_s",
+        "≥" => "i32.const 2\ni32.lt_u",
+        _ => return None,
+    })
+}
+
+struct Railway {
+    name: String,
+    arity: usize,
+    func: String,
+    defined: String,
+}
+
+struct Loop {
+    label: Option<String>,
+    next: String,
+    exit: String,
+}
+
+#[derive(Default)]
+struct Function {
+    name: String,
+    head: Vec<String>,
+    locals: Vec<String>,
+    declared: HashSet<String>,
+    lines: Vec<String>,
+    indent: usize,
+    loops: Vec<Loop>,
+    railway: bool,
+}
+
+struct Compiler<'a> {
+    module: &'a Module,
+    defs: HashMap<(Span, String), usize>,
+    globals: HashSet<usize>,
+    railways: HashMap<usize, Railway>,
+    consts: Vec<HashSet<String>>,
+    data: Vec<u8>,
+    strings: HashMap<Vec<u8>, usize>,
+    counter: usize,
+    function: Function,
+    functions: Vec<String>,
+    errors: Vec<Diagnostic>,
+}
+
+impl<'a> Compiler<'a> {
+    // definitions made straight in the module's block become globals, and its railways functions
+    fn scan(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Define { var, val, .. } => {
+                if !self.module.resolution.uses.contains_key(&expr.span) {
+                    if let Some(&id) = self.defs.get(&(expr.span, var.clone())) {
+                        self.globals.insert(id);
+                    }
+                }
+                self.scan(val);
+            }
+            ExprKind::Function { name, params, .. } => {
+                if let Some(&id) = self.defs.get(&(expr.span, name.clone())) {
+                    let railway = Railway {
+                        name: name.clone(),
+                        arity: params.len(),
+                        func: format!("$f{}_{}", id, ident(name)),
+                        defined: format!("$f{}_{}_defined", id, ident(name)),
+                    };
+                    self.railways.insert(id, railway);
+                }
+            }
+            ExprKind::Block(_) | ExprKind::Generator(_) => {}
+            _ => {
+                for child in expr.children() {
+                    self.scan(child);
+                }
+            }
+        }
+    }
+
+    fn line(&mut self, text: impl AsRef<str>) {
+        let indent = "  ".repeat(self.function.indent + 2);
+        for line in text.as_ref().lines() {
+            self.function.lines.push(format!("{}{}", indent, line));
+        }
+    }
+
+    fn open(&mut self, text: impl AsRef<str>) {
+        self.line(text);
+        self.function.indent += 1;
+    }
+
+    fn close(&mut self) {
+        self.function.indent -= 1;
+        self.line("end");
+    }
+
+    fn reopen(&mut self, text: &str) {
+        self.function.indent -= 1;
+        self.line(text);
+        self.function.indent += 1;
+    }
+
+    fn fresh(&mut self, prefix: &str) -> String {
+        self.counter += 1;
+        format!("${}{}", prefix, self.counter)
+    }
+
+    fn local(&mut self, name: &str, ty: &str) {
+        if self.function.declared.insert(name.to_string()) {
+            self.function.locals.push(format!("(local {} {})", name, ty));
+        }
+    }
+
+    // a pair of locals that can hold one value
+    fn temp(&mut self) -> String {
+        let name = self.fresh("t");
+        self.local(&format!("{}_t", name), "i32");
+        self.local(&format!("{}_b", name), "i64");
+        name
+    }
+
+    fn string(&mut self, bytes: &[u8]) -> String {
+        let next = DATA + self.data.len();
+        let at = *self.strings.entry(bytes.to_vec()).or_insert(next);
+        if at == next {
+            self.data.extend_from_slice(bytes);
+        }
+        format!("i32.const {}\ni32.const {}", at, bytes.len())
+    }
+
+    fn at(&self, span: Span) -> String {
+        format!("i32.const {}\ni32.const {}", span.line, span.col)
+    }
+
+    fn null(&mut self) {
+        self.line("i32.const 0\ni64.const 0");
+    }
+
+    fn fail(&mut self, message: &str, span: Span) {
+        let message = self.string(message.as_bytes());
+        let at = self.at(span);
+        self.line(format!("{}\n{}\ncall $fail\nunreachable", message, at));
+    }
+
+    fn unsupported(&mut self, what: &str, span: Span) {
+        self.errors.push(
+            Diagnostic::error("E0804", format!("{} are not supported by the WebAssembly backend", what))
+                .with_primary(span, "cannot compile this")
+                .with_note("the WebAssembly backend compiles numbers, `sweet`, loops and railways; run the script with `vita run` for the rest"),
+        );
+        self.null();
+    }
+
+    fn def(&self, span: Span, name: &str) -> Option<usize> {
+        self.defs.get(&(span, name.to_string())).copied()
+    }
+
+    // the two places, tag then bits, a definition keeps its value in
+    fn slots(&mut self, id: usize) -> (String, String) {
+        let name = ident(&self.module.resolution.definitions[id].name);
+        if self.globals.contains(&id) {
+            return (format!("$g{}_{}_t", id, name), format!("$g{}_{}_b", id, name));
+        }
+        let (tag, bits) = (format!("$v{}_{}_t", id, name), format!("$v{}_{}_b", id, name));
+        self.local(&tag, "i32");
+        self.local(&bits, "i64");
+        (tag, bits)
+    }
+
+    fn get(&mut self, id: usize) -> (String, String) {
+        let (tag, bits) = self.slots(id);
+        let op = if self.globals.contains(&id) { "global" } else { "local" };
+        (format!("{}.get {}", op, tag), format!("{}.get {}", op, bits))
+    }
+
+    // pops a value off the stack into a definition
+    fn set(&mut self, id: usize) {
+        let (tag, bits) = self.slots(id);
+        let op = if self.globals.contains(&id) { "global" } else { "local" };
+        self.line(format!("{}.set {}\n{}.set {}", op, bits, op, tag));
+    }
+
+    fn statements(&mut self, items: &'a [Expr]) {
+        match items.split_last() {
+            Some((last, rest)) => {
+                for item in rest {
+                    self.expr(item);
+                    self.line("drop\ndrop");
+                }
+                self.expr(last);
+            }
+            None => self.null(),
+        }
+    }
+
+    fn block(&mut self, items: &'a [Expr]) {
+        self.consts.push(HashSet::new());
+        self.statements(items);
+        self.consts.pop();
+    }
+
+    // leaves the value of `expr` on the stack as a tag and its bits
+    fn expr(&mut self, expr: &'a Expr) {
+        let span = expr.span;
+        match &expr.kind {
+            ExprKind::Number(Number::Int(n)) => self.line(format!("i32.const 2\ni64.const {}", n)),
+
+            ExprKind::Number(Number::Float(n)) => self.line(format!("i32.const 3\nf64.const {:?}\ni64.reinterpret_f64", n)),
+
+            ExprKind::Variable(name) | ExprKind::Const(name) => {
+                let Some(&id) = self.module.resolution.uses.get(&span) else {
+                    let sigil = if matches!(expr.kind, ExprKind::Const(_)) { '$' } else { '£' };
+                    let what = if sigil == '$' { "constant" } else { "variable" };
+                    self.fail(&format!("undefined {} `{}{}`", what, sigil, name), span);
+                    return;
+                };
+                if self.railways.contains_key(&id) {
+                    return self.unsupported("railways used as values", span);
+                }
+                let (tag, bits) = self.get(id);
+                self.line(format!("{}\n{}", tag, bits));
+                // a railway can run before the module has given its globals a value
+                if self.function.railway && self.globals.contains(&id) {
+                    let sigil = if matches!(expr.kind, ExprKind::Const(_)) { "constant `$" } else { "variable `£" };
+                    let message = self.string(format!("undefined {}{}`", sigil, name).as_bytes());
+                    let at = self.at(span);
+                    self.line(format!("{}\n{}\ncall $defined", message, at));
+                }
+            }
+
+            ExprKind::Binary { left, op, right } => {
+                if self.module.operators.contains_key(op) {
+                    return self.unsupported("operators defined in the script", span);
+                }
+                if op == ".." {
+                    return self.unsupported("ranges outside of `lolsie`", span);
+                }
+                if op == "^^" {
+                    return self.unsupported("tetrations", span);
+                }
+                self.expr(left);
+                self.expr(right);
+                let at = self.at(span);
+                if let Some(helper) = arithmetic(op) {
+                    self.line(format!("{}\ncall {}", at, helper));
+                } else if let Some(test) = comparison(op) {
+                    let message = self.string(format!("cannot apply `{}` to {{}} and {{}}", op).as_bytes());
+                    self.line(format!("{}\n{}\ncall $compare\n{}\ncall $bool", message, at, test));
+                } else if op == "=" {
+                    self.line("call $equal\ncall $bool");
+                } else {
+                    self.fail(&format!("unknown binary operator `{}`", op), span);
+                }
+            }
+
+            ExprKind::Unary { oper, op } => {
+                if self.module.operators.contains_key(op) {
+                    return self.unsupported("operators defined in the script", span);
+                }
+                match op.as_str() {
+                    "-" => {
+                        self.expr(oper);
+                        let at = self.at(span);
+                        self.line(format!("{}\ncall $neg", at));
+                    }
+                    "!" => {
+                        self.expr(oper);
+                        self.line("call $truthy\ni32.eqz\ncall $bool");
+                    }
+                    "?" => {
+                        self.expr(oper);
+                        self.line("call $truthy\ncall $bool");
+                    }
+                    _ => self.unsupported(&format!("`{}` operators", op), span),
+                }
+            }
+
+            ExprKind::Func { name, args } => self.call(name, args, span),
+
+            ExprKind::If { cond, then, else_then } => {
+                self.expr(cond);
+                self.line("call $truthy");
+                self.open("if (result i32 i64)");
+                self.branch(then);
+                self.reopen("else");
+                self.branch(else_then);
+                self.close();
+            }
+
+            ExprKind::While { cond, then, else_then, label } => {
+                let next = self.enter_loop(label);
+                let (done, top) = (self.fresh("done"), self.fresh("top"));
+                self.open(format!("block {}", done));
+                self.open(format!("loop {}", top));
+                self.expr(cond);
+                self.line(format!("call $truthy\ni32.eqz\nbr_if {}", done));
+                self.open(format!("block {}", next));
+                self.branch(then);
+                self.line("drop\ndrop");
+                self.close();
+                self.line(format!("br {}", top));
+                self.close();
+                self.close();
+                self.leave_loop(else_then);
+            }
+
+            ExprKind::For { iter, var, source, then, else_then, label } => {
+                let (index, end) = (self.fresh("i"), self.fresh("end"));
+                self.local(&index, "i64");
+                self.local(&end, "i64");
+                match source.as_deref() {
+                    Some(Expr { kind: ExprKind::Binary { left, op, right }, span: range })
+                        if op == ".." && !self.module.operators.contains_key(op) =>
+                    {
+                        self.expr(left);
+                        self.expr(right);
+                        let at = self.at(*range);
+                        self.line(format!("{}\ncall $range\nlocal.set {}\nlocal.set {}", at, end, index));
+                    }
+                    Some(source) => {
+                        self.expr(source);
+                        let at = self.at(span);
+                        self.line(format!("{}\ncall $times\nlocal.set {}\ni64.const 0\nlocal.set {}", at, end, index));
+                    }
+                    None => self.line(format!("i64.const {}\nlocal.set {}\ni64.const 0\nlocal.set {}", iter, end, index)),
+                }
+                let next = self.enter_loop(label);
+                let (done, top) = (self.fresh("done"), self.fresh("top"));
+                self.open(format!("block {}", done));
+                self.open(format!("loop {}", top));
+                self.line(format!("local.get {}\nlocal.get {}\ni64.ge_s\nbr_if {}", index, end, done));
+                self.consts.push(HashSet::new());
+                if let Some(id) = self.def(span, var) {
+                    self.line(format!("i32.const 2\nlocal.get {}", index));
+                    self.set(id);
+                }
+                self.open(format!("block {}", next));
+                self.branch(then);
+                self.line("drop\ndrop");
+                self.close();
+                self.consts.pop();
+                self.line(format!("local.get {}\ni64.const 1\ni64.add\nlocal.set {}\nbr {}", index, index, top));
+                self.close();
+                self.close();
+                self.leave_loop(else_then);
+            }
+
+            ExprKind::Define { var, constant: true, val } => {
+                self.expr(val);
+                if self.consts.iter().any(|scope| scope.contains(var)) {
+                    return self.fail(&format!("cannot redefine constant `${}`", var), span);
+                }
+                self.consts.last_mut().unwrap().insert(var.clone());
+                match self.def(span, var) {
+                    Some(id) => self.set(id),
+                    None => self.line("drop\ndrop"),
+                }
+                self.null();
+            }
+
+            ExprKind::Define { var, val, .. } => {
+                self.expr(val);
+                let id = match self.module.resolution.uses.get(&span) {
+                    Some(&id) => Some(id),
+                    None => self.def(span, var),
+                };
+                match id {
+                    Some(id) if self.railways.contains_key(&id) => return self.unsupported("railways used as values", span),
+                    Some(id) => self.set(id),
+                    None => self.line("drop\ndrop"),
+                }
+                self.null();
+            }
+
+            ExprKind::Function { name, params, body } => {
+                if body.contains_yield() {
+                    return self.unsupported("generator railways", span);
+                }
+                let Some(id) = self.def(span, name).filter(|id| self.railways.contains_key(id)) else {
+                    return self.unsupported("railways inside blocks and other railways", span);
+                };
+                self.railway(id, params, span, body);
+                let defined = self.railways[&id].defined.clone();
+                self.line(format!("i32.const 1\nglobal.set {}", defined));
+                self.null();
+            }
+
+            ExprKind::Return(value) => {
+                match value {
+                    Some(value) => self.expr(value),
+                    None => self.null(),
+                }
+                if self.function.railway {
+                    self.line("return");
+                } else {
+                    self.line("drop\ndrop");
+                    self.fail("`return` outside of a function", span);
+                }
+            }
+
+            ExprKind::Break(label) | ExprKind::Continue(label) => {
+                let breaking = matches!(expr.kind, ExprKind::Break(_));
+                let target = match label {
+                    Some(label) => self.function.loops.iter().rev().find(|l| l.label.as_ref() == Some(label)),
+                    None => self.function.loops.last(),
+                };
+                match target {
+                    Some(l) => {
+                        let jump = format!("br {}", if breaking { &l.exit } else { &l.next });
+                        self.line(jump);
+                    }
+                    None if breaking => self.fail("`jump off the bandwagon` outside of a loop", span),
+                    None => self.fail("`get back to work boy` outside of a loop", span),
+                }
+            }
+
+            ExprKind::Yield(_) => self.unsupported("generators", span),
+
+            ExprKind::Export(inner) => self.expr(inner),
+
+            ExprKind::Block(items) => self.block(items),
+
+            ExprKind::String(_) => self.unsupported("strings outside of `print`", span),
+            ExprKind::Interpolated(_) => self.unsupported("interpolated strings", span),
+            ExprKind::Array(_) => self.unsupported("arrays", span),
+            ExprKind::Index { .. } | ExprKind::Slice { .. } => self.unsupported("indexing and slicing", span),
+            ExprKind::Try { .. } | ExprKind::Throw(_) => self.unsupported("errors", span),
+            ExprKind::Generator(_) => self.unsupported("generator blocks", span),
+            ExprKind::Import(_) => self.unsupported("imports", span),
+            ExprKind::Error => self.fail("cannot run code that failed to parse", span),
+        }
+    }
+
+    // the body of an `if` or a loop, which is a scope of its own
+    fn branch(&mut self, expr: &'a Expr) {
+        match &expr.kind {
+            ExprKind::Block(items) => self.block(items),
+            _ => {
+                self.consts.push(HashSet::new());
+                self.expr(expr);
+                self.consts.pop();
+            }
+        }
+    }
+
+    // breaking jumps past the `else`, continuing to the end of the body
+    fn enter_loop(&mut self, label: &Option<String>) -> String {
+        let (exit, next) = (self.fresh("exit"), self.fresh("next"));
+        self.open(format!("block {}", exit));
+        self.function.loops.push(Loop { label: label.clone(), next: next.clone(), exit });
+        next
+    }
+
+    fn leave_loop(&mut self, else_then: &'a Expr) {
+        self.function.loops.pop();
+        self.branch(else_then);
+        self.line("drop\ndrop");
+        self.close();
+        self.null();
+    }
+
+    fn call(&mut self, name: &str, args: &'a [Expr], span: Span) {
+        if let Some(&id) = self.module.resolution.uses.get(&span) {
+            let Some(railway) = self.railways.get(&id) else {
+                return self.unsupported("calls to railways held in variables", span);
+            };
+            let (func, defined, arity) = (railway.func.clone(), railway.defined.clone(), railway.arity);
+            let callee = railway.name.clone();
+            for arg in args {
+                self.expr(arg);
+            }
+            if args.len() != arity {
+                let message = format!("`{}` takes {} argument(s) but {} were given", callee, arity, args.len());
+                return self.fail(&message, span);
+            }
+            let unknown = self.string(format!("unknown function `{}`", callee).as_bytes());
+            let at = self.at(span);
+            self.line(format!("global.get {}\ni32.eqz", defined));
+            self.open("if");
+            self.line(format!("{}\n{}\ncall $fail\nunreachable", unknown, at));
+            self.close();
+            let record = self.string(callee.as_bytes());
+            let deep = self.string(format!("too many nested calls (last was to `{}`)", callee).as_bytes());
+            self.line(format!("{}\n{}\n{}\ncall $enter\ncall {}\ncall $leave", record, at, deep, func));
+            return;
+        }
+
+        match name {
+            "print" => {
+                // every argument is worked out before anything is printed
+                let mut values = Vec::new();
+                for arg in args {
+                    match &arg.kind {
+                        ExprKind::String(s) => values.push(Err(s.as_bytes())),
+                        _ => {
+                            self.expr(arg);
+                            let temp = self.temp();
+                            self.line(format!("local.set {}_b\nlocal.set {}_t", temp, temp));
+                            values.push(Ok(temp));
+                        }
+                    }
+                }
+                for (i, value) in values.into_iter().enumerate() {
+                    if i > 0 {
+                        let space = self.string(b" ");
+                        self.line(format!("{}\ncall $print_string", space));
+                    }
+                    match value {
+                        Ok(temp) => self.line(format!("local.get {}_t\nlocal.get {}_b\ncall $print_value", temp, temp)),
+                        Err(bytes) => {
+                            let text = self.string(bytes);
+                            self.line(format!("{}\ncall $print_string", text));
+                        }
+                    }
+                }
+                let newline = self.string(b"\n");
+                self.line(format!("{}\ncall $print_string", newline));
+                self.null();
+            }
+            "len" | "push" | "pop" | "concat" | "next" => self.unsupported(&format!("calls to `{}`", name), span),
+            _ => {
+                for arg in args {
+                    self.expr(arg);
+                }
+                self.fail(&format!("unknown function `{}`", name), span);
+            }
+        }
+    }
+
+    fn railway(&mut self, id: usize, params: &[String], span: Span, body: &'a Expr) {
+        let func = self.railways[&id].func.clone();
+        let outer = std::mem::replace(
+            &mut self.function,
+            Function { name: func.clone(), railway: true, ..Default::default() },
+        );
+        for param in params {
+            if let Some(param) = self.def(span, param) {
+                let (tag, bits) = self.slots(param);
+                self.function.head.push(format!("(param {} i32) (param {} i64)", tag, bits));
+            }
+        }
+        // parameters are declared in the head, not as locals
+        self.function.locals.clear();
+        self.consts.push(HashSet::new());
+        match &body.kind {
+            ExprKind::Block(items) => self.statements(items),
+            _ => self.expr(body),
+        }
+        self.consts.pop();
+        let function = std::mem::replace(&mut self.function, outer);
+        self.functions.push(render(&function));
+    }
+}
+
+fn render(function: &Function) -> String {
+    let mut out = format!("  (func {}", function.name);
+    for param in &function.head {
+        out.push(' ');
+        out.push_str(param);
+    }
+    out.push_str(" (result i32 i64)\n");
+    for local in &function.locals {
+        out.push_str(&format!("    {}\n", local));
+    }
+    for line in &function.lines {
+        out.push_str(line);
+        out.push('\n');
+    }
+    out.push_str("  )\n");
+    out
+}
+
+pub fn compile(loader: &Loader, entry: usize) -> Result<Output, Vec<Diagnostic>> {
+    let module = &loader.modules[&entry];
+    let mut c = Compiler {
+        module,
+        defs: definitions(module),
+        globals: HashSet::new(),
+        railways: HashMap::new(),
+        consts: vec![HashSet::new()],
+        data: Vec::new(),
+        strings: HashMap::new(),
+        counter: 0,
+        function: Function { name: "$main".to_string(), ..Default::default() },
+        functions: Vec::new(),
+        errors: Vec::new(),
+    };
+    let items = match &module.ast.kind {
+        ExprKind::Block(items) => items.as_slice(),
+        _ => std::slice::from_ref(&module.ast),
+    };
+    for item in items {
+        c.scan(item);
+    }
+    c.statements(items);
+    let main = std::mem::take(&mut c.function);
+    c.functions.push(render(&main));
+    if !c.errors.is_empty() {
+        return Err(c.errors);
+    }
+
+    // the runtime's messages go in with the rest of the strings
+    let mut runtime = String::new();
+    let mut rest = RUNTIME;
+    while let Some(start) = rest.find("@\"") {
+        let end = start + 2 + rest[start + 2..].find('"').expect("unterminated string in the runtime");
+        let bytes = wat::unescape(&rest[start + 2..end]).expect("bad string in the runtime");
+        runtime.push_str(&rest[..start]);
+        runtime.push_str(&c.string(&bytes).replace('\n', " "));
+        rest = &rest[end + 1..];
+    }
+    runtime.push_str(rest);
+
+    let mut text = String::from("(module\n");
+    text.push_str(&runtime);
+    text.push('\n');
+    let mut ids: Vec<usize> = c.globals.iter().copied().collect();
+    ids.sort();
+    for id in ids {
+        let name = ident(&module.resolution.definitions[id].name);
+        text.push_str(&format!("  (global $g{}_{}_t (mut i32) (i32.const 4))\n", id, name));
+        text.push_str(&format!("  (global $g{}_{}_b (mut i64) (i64.const 0))\n", id, name));
+    }
+    let mut railways: Vec<&Railway> = c.railways.values().collect();
+    railways.sort_by(|a, b| a.func.cmp(&b.func));
+    for railway in railways {
+        text.push_str(&format!("  (global {} (mut i32) (i32.const 0))\n", railway.defined));
+    }
+    for function in &c.functions {
+        text.push('\n');
+        text.push_str(function);
+    }
+    let pages = (DATA + c.data.len()).div_ceil(PAGE);
+    text.push_str(&format!("\n  (memory (export \"memory\") {})\n", pages));
+    if !c.data.is_empty() {
+        text.push_str(&format!("  (data (i32.const {}) {})\n", DATA, wat::escape(&c.data)));
+    }
+    text.push_str("  (export \"main\" (func $main))\n");
+    text.push_str("  (export \"depth\" (func $trace_depth))\n");
+    text.push_str(")\n");
+
+    match wat::assemble(&text) {
+        Ok(binary) => Ok(Output { text, binary }),
+        Err(e) => Err(vec![Diagnostic::error("E0805", format!("the generated WebAssembly does not assemble: {}", e))]),
+    }
+}
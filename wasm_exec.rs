@@ -0,0 +1,690 @@
+// a small WebAssembly machine for the tests: it decodes the binary `wat` assembles and runs `main`
+// with the `vita.*` imports stubbed out the way a host would, printing into the test buffer
+use std::rc::Rc;
+
+use crate::interpreter::{RuntimeError, Value, OUTPUT};
+use crate::lexer::Span;
+
+pub enum Trap {
+    // a `vita.fail` the script asked for, as the interpreter would have raised it
+    Fail(RuntimeError),
+    // the module itself went wrong
+    Wasm(String),
+}
+
+type Run<T> = Result<T, Trap>;
+
+fn trap<T>(message: impl Into<String>) -> Run<T> {
+    Err(Trap::Wasm(message.into()))
+}
+
+#[derive(Clone, Copy)]
+enum Host {
+    PrintValue,
+    PrintString,
+    Fail,
+    FailTypes,
+    FailValue,
+    Pow,
+}
+
+impl Host {
+    fn params(self) -> usize {
+        match self {
+            Host::PrintValue | Host::PrintString | Host::Pow => 2,
+            Host::Fail => 4,
+            Host::FailTypes | Host::FailValue => 6,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Instr {
+    Unreachable,
+    Nop,
+    Block { results: usize, end: usize },
+    Loop,
+    If { results: usize, otherwise: usize, end: usize },
+    Else { end: usize },
+    End,
+    Br(u32),
+    BrIf(u32),
+    Return,
+    Call(u32),
+    Drop,
+    Select,
+    LocalGet(u32),
+    LocalSet(u32),
+    LocalTee(u32),
+    GlobalGet(u32),
+    GlobalSet(u32),
+    Load(u8, u32),
+    Store(u8, u32),
+    Const(u64),
+    Numeric(u8),
+}
+
+struct Signature {
+    params: usize,
+    results: usize,
+}
+
+struct Function {
+    sig: usize,
+    locals: usize,
+    code: Rc<[Instr]>,
+}
+
+// where a branch to a label goes, and what it keeps of the stack
+#[derive(Clone, Copy)]
+struct Label {
+    target: usize,
+    height: usize,
+    arity: usize,
+    looping: bool,
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl Reader<'_> {
+    fn byte(&mut self) -> Run<u8> {
+        let Some(&b) = self.bytes.get(self.at) else { return trap("unexpected end of the module") };
+        self.at += 1;
+        Ok(b)
+    }
+
+    fn unsigned(&mut self) -> Run<u64> {
+        let (mut n, mut shift) = (0u64, 0);
+        loop {
+            let b = self.byte()?;
+            n |= ((b & 0x7f) as u64) << shift;
+            shift += 7;
+            if b & 0x80 == 0 {
+                return Ok(n);
+            }
+            if shift >= 64 {
+                return trap("integer too long");
+            }
+        }
+    }
+
+    fn u32(&mut self) -> Run<u32> {
+        self.unsigned().map(|n| n as u32)
+    }
+
+    fn signed(&mut self) -> Run<i64> {
+        let (mut n, mut shift) = (0i64, 0);
+        loop {
+            let b = self.byte()?;
+            n |= ((b & 0x7f) as i64) << shift;
+            shift += 7;
+            if b & 0x80 == 0 {
+                if shift < 64 && b & 0x40 != 0 {
+                    n |= -1 << shift;
+                }
+                return Ok(n);
+            }
+            if shift >= 70 {
+                return trap("integer too long");
+            }
+        }
+    }
+
+    fn bytes(&mut self, n: usize) -> Run<&[u8]> {
+        let Some(bytes) = self.bytes.get(self.at..self.at + n) else { return trap("unexpected end of the module") };
+        self.at += n;
+        Ok(bytes)
+    }
+
+    fn name(&mut self) -> Run<String> {
+        let n = self.unsigned()? as usize;
+        Ok(String::from_utf8_lossy(self.bytes(n)?).into_owned())
+    }
+
+    // an `i32.const`, `i64.const` or `f64.const` followed by `end`
+    fn constant(&mut self) -> Run<u64> {
+        let value = match self.byte()? {
+            0x41 => self.signed()? as i32 as u32 as u64,
+            0x42 => self.signed()? as u64,
+            0x44 => u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()),
+            op => return trap(format!("0x{:02x} is not a constant", op)),
+        };
+        if self.byte()? != 0x0b {
+            return trap("a constant expression has to end after its value");
+        }
+        Ok(value)
+    }
+}
+
+struct Machine {
+    types: Vec<Signature>,
+    imports: Vec<Host>,
+    functions: Vec<Function>,
+    globals: Vec<u64>,
+    memory: Vec<u8>,
+    exports: Vec<(String, u32)>,
+    // one of each for all the calls under way, so a call does not have to allocate
+    stack: Vec<u64>,
+    locals: Vec<u64>,
+    labels: Vec<Label>,
+}
+
+impl Machine {
+    fn load(binary: &[u8]) -> Run<Machine> {
+        let mut r = Reader { bytes: binary, at: 0 };
+        if r.bytes(8)? != b"\0asm\x01\0\0\0" {
+            return trap("not a WebAssembly module");
+        }
+        let mut m = Machine {
+            types: Vec::new(),
+            imports: Vec::new(),
+            functions: Vec::new(),
+            globals: Vec::new(),
+            memory: Vec::new(),
+            exports: Vec::new(),
+            stack: Vec::new(),
+            locals: Vec::new(),
+            labels: Vec::new(),
+        };
+        let mut sigs = Vec::new();
+        let mut last = 0;
+        while r.at < binary.len() {
+            let id = r.byte()?;
+            if id <= last {
+                return trap(format!("section {} is out of order", id));
+            }
+            last = id;
+            let size = r.unsigned()? as usize;
+            let end = r.at + size;
+            let count = r.unsigned()?;
+            for _ in 0..count {
+                match id {
+                    1 => {
+                        if r.byte()? != 0x60 {
+                            return trap("expected a function type");
+                        }
+                        let params = r.unsigned()? as usize;
+                        r.bytes(params)?;
+                        let results = r.unsigned()? as usize;
+                        r.bytes(results)?;
+                        m.types.push(Signature { params, results });
+                    }
+                    2 => {
+                        let (module, field) = (r.name()?, r.name()?);
+                        if r.byte()? != 0x00 {
+                            return trap("only functions are imported");
+                        }
+                        r.u32()?;
+                        m.imports.push(match (module.as_str(), field.as_str()) {
+                            ("vita", "print_value") => Host::PrintValue,
+                            ("vita", "print_string") => Host::PrintString,
+                            ("vita", "fail") => Host::Fail,
+                            ("vita", "fail_types") => Host::FailTypes,
+                            ("vita", "fail_value") => Host::FailValue,
+                            ("vita", "pow") => Host::Pow,
+                            _ => return trap(format!("nothing to import as `{}.{}`", module, field)),
+                        });
+                    }
+                    3 => sigs.push(r.unsigned()? as usize),
+                    5 => {
+                        if r.byte()? != 0x00 {
+                            return trap("memories have no maximum");
+                        }
+                        m.memory = vec![0; r.unsigned()? as usize * 65536];
+                    }
+                    6 => {
+                        r.bytes(2)?;
+                        let value = r.constant()?;
+                        m.globals.push(value);
+                    }
+                    7 => {
+                        let name = r.name()?;
+                        let kind = r.byte()?;
+                        let index = r.u32()?;
+                        if kind == 0x00 {
+                            m.exports.push((name, index));
+                        }
+                    }
+                    10 => {
+                        let size = r.unsigned()? as usize;
+                        let body = r.bytes(size)?;
+                        let sig = *sigs.get(m.functions.len()).ok_or(Trap::Wasm("more bodies than functions".into()))?;
+                        let function = m.decode(body, sig)?;
+                        m.functions.push(function);
+                    }
+                    11 => {
+                        if r.byte()? != 0x00 {
+                            return trap("only active data segments are supported");
+                        }
+                        let offset = r.constant()? as u32 as usize;
+                        let n = r.unsigned()? as usize;
+                        let bytes = r.bytes(n)?;
+                        let Some(target) = m.memory.get_mut(offset..offset + n) else { return trap("data segment out of bounds") };
+                        target.copy_from_slice(bytes);
+                    }
+                    _ => return trap(format!("unexpected section {}", id)),
+                }
+            }
+            if r.at != end {
+                return trap(format!("section {} is not the size it says", id));
+            }
+        }
+        if sigs.len() != m.functions.len() {
+            return trap("functions without bodies");
+        }
+        Ok(m)
+    }
+
+    fn block_results(&self, r: &mut Reader) -> Run<usize> {
+        match r.bytes.get(r.at) {
+            Some(0x40) => {
+                r.at += 1;
+                Ok(0)
+            }
+            Some(0x7c..=0x7f) => {
+                r.at += 1;
+                Ok(1)
+            }
+            _ => match self.types.get(r.signed()? as usize) {
+                Some(sig) if sig.params == 0 => Ok(sig.results),
+                _ => trap("bad block type"),
+            },
+        }
+    }
+
+    // instructions with their jumps worked out, so running them never has to search
+    fn decode(&self, body: &[u8], sig: usize) -> Run<Function> {
+        let mut r = Reader { bytes: body, at: 0 };
+        let mut locals = self.types[sig].params;
+        for _ in 0..r.unsigned()? {
+            locals += r.unsigned()? as usize;
+            r.byte()?;
+        }
+        let mut code = Vec::new();
+        let mut open = Vec::new();
+        while r.at < body.len() {
+            let op = r.byte()?;
+            let instr = match op {
+                0x00 => Instr::Unreachable,
+                0x01 => Instr::Nop,
+                0x02 | 0x04 => {
+                    open.push(code.len());
+                    let results = self.block_results(&mut r)?;
+                    if op == 0x02 {
+                        Instr::Block { results, end: 0 }
+                    } else {
+                        Instr::If { results, otherwise: 0, end: 0 }
+                    }
+                }
+                0x03 => {
+                    open.push(code.len());
+                    self.block_results(&mut r)?;
+                    Instr::Loop
+                }
+                0x05 => {
+                    let here = code.len();
+                    let Some(&start) = open.last() else { return trap("`else` outside of an `if`") };
+                    let Instr::If { otherwise, .. } = &mut code[start] else { return trap("`else` outside of an `if`") };
+                    *otherwise = here + 1;
+                    Instr::Else { end: 0 }
+                }
+                0x0b => {
+                    if let Some(start) = open.pop() {
+                        let end = code.len();
+                        let mut after_else = None;
+                        match &mut code[start] {
+                            Instr::Block { end: e, .. } => *e = end,
+                            Instr::If { otherwise, end: e, .. } => {
+                                *e = end;
+                                if *otherwise == 0 {
+                                    *otherwise = end;
+                                } else {
+                                    after_else = Some(*otherwise);
+                                }
+                            }
+                            _ => {}
+                        }
+                        if let Some(Instr::Else { end: e }) = after_else.map(|at| &mut code[at - 1]) {
+                            *e = end;
+                        }
+                    }
+                    Instr::End
+                }
+                0x0c => Instr::Br(r.u32()?),
+                0x0d => Instr::BrIf(r.u32()?),
+                0x0f => Instr::Return,
+                0x10 => Instr::Call(r.u32()?),
+                0x1a => Instr::Drop,
+                0x1b => Instr::Select,
+                0x20 => Instr::LocalGet(r.u32()?),
+                0x21 => Instr::LocalSet(r.u32()?),
+                0x22 => Instr::LocalTee(r.u32()?),
+                0x23 => Instr::GlobalGet(r.u32()?),
+                0x24 => Instr::GlobalSet(r.u32()?),
+                0x28 | 0x29 | 0x2b | 0x36 | 0x37 | 0x39 => {
+                    r.u32()?;
+                    let offset = r.u32()?;
+                    if op < 0x30 {
+                        Instr::Load(op, offset)
+                    } else {
+                        Instr::Store(op, offset)
+                    }
+                }
+                0x41 => Instr::Const(r.signed()? as i32 as u32 as u64),
+                0x42 => Instr::Const(r.signed()? as u64),
+                0x44 => Instr::Const(u64::from_le_bytes(r.bytes(8)?.try_into().unwrap())),
+                0x45..=0xbf => Instr::Numeric(op),
+                _ => return trap(format!("unknown opcode 0x{:02x}", op)),
+            };
+            code.push(instr);
+        }
+        if !open.is_empty() || !matches!(code.last(), Some(Instr::End)) {
+            return trap("a function body has to end with `end`");
+        }
+        Ok(Function { sig, locals, code: code.into() })
+    }
+
+    fn export(&self, name: &str) -> Option<u32> {
+        self.exports.iter().find(|(n, _)| n == name).map(|&(_, i)| i)
+    }
+
+    fn pop(&mut self) -> u64 {
+        self.stack.pop().expect("the module was validated by running it")
+    }
+
+    fn read(&self, at: usize, n: usize) -> Run<&[u8]> {
+        match self.memory.get(at..at + n) {
+            Some(bytes) => Ok(bytes),
+            None => trap(format!("out of bounds memory access at {}", at)),
+        }
+    }
+
+    fn text(&self, at: u64, len: u64) -> Run<String> {
+        Ok(String::from_utf8_lossy(self.read(at as u32 as usize, len as u32 as usize)?).into_owned())
+    }
+
+    fn word(&self, at: usize) -> Run<usize> {
+        Ok(u32::from_le_bytes(self.read(at, 4)?.try_into().unwrap()) as usize)
+    }
+
+    // what a failure looks like once the host has read the calls under way out of memory
+    fn fail(&mut self, message: String, line: u64, col: u64) -> Run<()> {
+        let at = |line: usize, col: usize| Span { line, col, ..Default::default() };
+        let mut e = RuntimeError::new(message, at(line as usize, col as usize));
+        let depth = self.export("depth").ok_or(Trap::Wasm("no `depth` export".into()))?;
+        self.invoke(depth)?;
+        let depth = self.pop() as usize;
+        for call in (0..depth).rev() {
+            let record = call * 16;
+            let name = self.text(self.word(record)? as u64, self.word(record + 4)? as u64)?;
+            e.trace.push((name, at(self.word(record + 8)?, self.word(record + 12)?)));
+        }
+        Err(Trap::Fail(e))
+    }
+
+    fn host(&mut self, host: Host) -> Run<()> {
+        let args = self.stack.split_off(self.stack.len() - host.params());
+        match host {
+            Host::PrintValue => {
+                let text = value(args[0], args[1]).to_string();
+                OUTPUT.with(|o| o.borrow_mut().push_str(&text));
+            }
+            Host::PrintString => {
+                let text = self.text(args[0], args[1])?;
+                OUTPUT.with(|o| o.borrow_mut().push_str(&text));
+            }
+            Host::Fail => {
+                let message = self.text(args[0], args[1])?;
+                self.fail(message, args[2], args[3])?;
+            }
+            Host::FailTypes => {
+                let mut message = self.text(args[0], args[1])?;
+                for tag in [args[2], args[3]] {
+                    if tag as i32 != -1 {
+                        message = message.replacen("{}", value(tag, 0).type_name(), 1);
+                    }
+                }
+                self.fail(message, args[4], args[5])?;
+            }
+            Host::FailValue => {
+                let message = self.text(args[0], args[1])?.replacen("{}", &value(args[2], args[3]).to_string(), 1);
+                self.fail(message, args[4], args[5])?;
+            }
+            Host::Pow => self.stack.push(f64::from_bits(args[0]).powf(f64::from_bits(args[1])).to_bits()),
+        }
+        Ok(())
+    }
+
+    // call a function on the arguments at the top of the stack, which its results replace
+    fn invoke(&mut self, index: u32) -> Run<()> {
+        let index = index as usize;
+        if let Some(&host) = self.imports.get(index) {
+            return self.host(host);
+        }
+        let Some(function) = self.functions.get(index - self.imports.len()) else { return trap(format!("no function {}", index)) };
+        let sig = &self.types[function.sig];
+        let (code, locals, params, results) = (function.code.clone(), function.locals, sig.params, sig.results);
+
+        let frame = self.locals.len();
+        let args = self.stack.len() - params;
+        self.locals.extend(self.stack.drain(args..));
+        self.locals.resize(frame + locals, 0);
+        let outer = self.labels.len();
+        let result = self.execute(&code, frame, results);
+        self.locals.truncate(frame);
+        self.labels.truncate(outer);
+        result
+    }
+
+    fn execute(&mut self, code: &[Instr], frame: usize, results: usize) -> Run<()> {
+        let base = self.stack.len();
+        let outer = self.labels.len();
+        self.labels.push(Label { target: code.len(), height: base, arity: results, looping: false });
+        let mut pc = 0;
+        while pc < code.len() {
+            let instr = code[pc];
+            pc += 1;
+            match instr {
+                Instr::Unreachable => return trap("unreachable"),
+                Instr::Nop => {}
+                Instr::Block { results, end } => self.labels.push(Label { target: end + 1, height: self.stack.len(), arity: results, looping: false }),
+                Instr::Loop => self.labels.push(Label { target: pc, height: self.stack.len(), arity: 0, looping: true }),
+                Instr::If { results, otherwise, end } => {
+                    let cond = self.pop() as u32;
+                    self.labels.push(Label { target: end + 1, height: self.stack.len(), arity: results, looping: false });
+                    if cond == 0 {
+                        pc = otherwise;
+                    }
+                }
+                Instr::Else { end } => pc = end,
+                Instr::End => {
+                    self.labels.pop();
+                }
+                Instr::Br(depth) => pc = self.branch(depth as usize),
+                Instr::BrIf(depth) => {
+                    if self.pop() as u32 != 0 {
+                        pc = self.branch(depth as usize);
+                    }
+                }
+                Instr::Return => pc = self.branch(self.labels.len() - outer - 1),
+                Instr::Call(index) => self.invoke(index)?,
+                Instr::Drop => {
+                    self.pop();
+                }
+                Instr::Select => {
+                    let cond = self.pop() as u32;
+                    let (b, a) = (self.pop(), self.pop());
+                    self.stack.push(if cond != 0 { a } else { b });
+                }
+                Instr::LocalGet(i) => self.stack.push(self.locals[frame + i as usize]),
+                Instr::LocalSet(i) => self.locals[frame + i as usize] = self.pop(),
+                Instr::LocalTee(i) => self.locals[frame + i as usize] = *self.stack.last().unwrap(),
+                Instr::GlobalGet(i) => self.stack.push(self.globals[i as usize]),
+                Instr::GlobalSet(i) => self.globals[i as usize] = self.pop(),
+                Instr::Load(op, offset) => {
+                    let at = self.pop() as u32 as usize + offset as usize;
+                    let n = if op == 0x28 { 4 } else { 8 };
+                    let mut bytes = [0; 8];
+                    bytes[..n].copy_from_slice(self.read(at, n)?);
+                    self.stack.push(u64::from_le_bytes(bytes));
+                }
+                Instr::Store(op, offset) => {
+                    let value = self.pop();
+                    let at = self.pop() as u32 as usize + offset as usize;
+                    let n = if op == 0x36 { 4 } else { 8 };
+                    match self.memory.get_mut(at..at + n) {
+                        Some(target) => target.copy_from_slice(&value.to_le_bytes()[..n]),
+                        None => return trap(format!("out of bounds memory access at {}", at)),
+                    }
+                }
+                Instr::Const(bits) => self.stack.push(bits),
+                Instr::Numeric(op) => self.numeric(op)?,
+            }
+        }
+        let end = self.stack.len() - results;
+        self.stack.drain(base..end);
+        Ok(())
+    }
+
+    // leave the labels up to `depth` with their results, giving back where to carry on
+    fn branch(&mut self, depth: usize) -> usize {
+        let at = self.labels.len() - 1 - depth;
+        let Label { target, height, arity, looping } = self.labels[at];
+        let end = self.stack.len() - arity;
+        self.stack.drain(height..end);
+        self.labels.truncate(at + usize::from(looping));
+        target
+    }
+
+    fn numeric(&mut self, op: u8) -> Run<()> {
+        let i32s = |n: u64| n as u32 as i32;
+        let f = f64::from_bits;
+        let unary = matches!(op, 0x45 | 0x50 | 0x99..=0x9f | 0xa7 | 0xac | 0xad | 0xb0 | 0xb7 | 0xb9 | 0xbd | 0xbf);
+        let b = if unary { 0 } else { self.pop() };
+        let a = self.pop();
+        let bool = |c: bool| c as u64;
+        let i32r = |n: i32| n as u32 as u64;
+        let f64r = |x: f64| x.to_bits();
+        let result = match op {
+            0x45 => bool(a as u32 == 0),
+            0x46 => bool(a as u32 == b as u32),
+            0x47 => bool(a as u32 != b as u32),
+            0x48 => bool(i32s(a) < i32s(b)),
+            0x49 => bool((a as u32) < b as u32),
+            0x4a => bool(i32s(a) > i32s(b)),
+            0x4b => bool(a as u32 > b as u32),
+            0x4c => bool(i32s(a) <= i32s(b)),
+            0x4d => bool(a as u32 <= b as u32),
+            0x4e => bool(i32s(a) >= i32s(b)),
+            0x4f => bool(a as u32 >= b as u32),
+            0x50 => bool(a == 0),
+            0x51 => bool(a == b),
+            0x52 => bool(a != b),
+            0x53 => bool((a as i64) < b as i64),
+            0x54 => bool(a < b),
+            0x55 => bool(a as i64 > b as i64),
+            0x56 => bool(a > b),
+            0x57 => bool(a as i64 <= b as i64),
+            0x58 => bool(a <= b),
+            0x59 => bool(a as i64 >= b as i64),
+            0x5a => bool(a >= b),
+            0x61 => bool(f(a) == f(b)),
+            0x62 => bool(f(a) != f(b)),
+            0x63 => bool(f(a) < f(b)),
+            0x64 => bool(f(a) > f(b)),
+            0x65 => bool(f(a) <= f(b)),
+            0x66 => bool(f(a) >= f(b)),
+            0x6a => i32r(i32s(a).wrapping_add(i32s(b))),
+            0x6b => i32r(i32s(a).wrapping_sub(i32s(b))),
+            0x6c => i32r(i32s(a).wrapping_mul(i32s(b))),
+            0x6d | 0x6f => {
+                if i32s(b) == 0 {
+                    return trap("integer divide by zero");
+                }
+                if op == 0x6d && i32s(a) == i32::MIN && i32s(b) == -1 {
+                    return trap("integer overflow");
+                }
+                i32r(if op == 0x6d { i32s(a) / i32s(b) } else { i32s(a).wrapping_rem(i32s(b)) })
+            }
+            0x71 => i32r(i32s(a) & i32s(b)),
+            0x72 => i32r(i32s(a) | i32s(b)),
+            0x73 => i32r(i32s(a) ^ i32s(b)),
+            0x74 => i32r(i32s(a).wrapping_shl(b as u32)),
+            0x75 => i32r(i32s(a).wrapping_shr(b as u32)),
+            0x76 => (a as u32).wrapping_shr(b as u32) as u64,
+            0x7c => a.wrapping_add(b),
+            0x7d => a.wrapping_sub(b),
+            0x7e => a.wrapping_mul(b),
+            0x7f..=0x82 => {
+                if b == 0 {
+                    return trap("integer divide by zero");
+                }
+                match op {
+                    0x7f if a as i64 == i64::MIN && b as i64 == -1 => return trap("integer overflow"),
+                    0x7f => (a as i64 / b as i64) as u64,
+                    0x80 => a / b,
+                    0x81 => (a as i64).wrapping_rem(b as i64) as u64,
+                    _ => a % b,
+                }
+            }
+            0x83 => a & b,
+            0x84 => a | b,
+            0x85 => a ^ b,
+            0x86 => a.wrapping_shl(b as u32),
+            0x87 => (a as i64).wrapping_shr(b as u32) as u64,
+            0x88 => a.wrapping_shr(b as u32),
+            0x99 => f64r(f(a).abs()),
+            0x9a => f64r(-f(a)),
+            0x9b => f64r(f(a).ceil()),
+            0x9c => f64r(f(a).floor()),
+            0x9d => f64r(f(a).trunc()),
+            0x9e => f64r(f(a).round_ties_even()),
+            0x9f => f64r(f(a).sqrt()),
+            0xa0 => f64r(f(a) + f(b)),
+            0xa1 => f64r(f(a) - f(b)),
+            0xa2 => f64r(f(a) * f(b)),
+            0xa3 => f64r(f(a) / f(b)),
+            0xa4 | 0xa5 if f(a).is_nan() || f(b).is_nan() => f64r(f64::NAN),
+            0xa4 => f64r(f(a).min(f(b))),
+            0xa5 => f64r(f(a).max(f(b))),
+            0xa7 => a as u32 as u64,
+            0xac => i32s(a) as i64 as u64,
+            0xad => a as u32 as u64,
+            0xb0 => {
+                let x = f(a).trunc();
+                if x.is_nan() || !(-9223372036854775808.0..9223372036854775808.0).contains(&x) {
+                    return trap("integer overflow");
+                }
+                x as i64 as u64
+            }
+            0xb7 => f64r(i32s(a) as f64),
+            0xb9 => f64r(a as i64 as f64),
+            0xbd | 0xbf => a,
+            _ => return trap(format!("unknown opcode 0x{:02x}", op)),
+        };
+        self.stack.push(result);
+        Ok(())
+    }
+}
+
+// the interpreter's value for a tag and its bits
+fn value(tag: u64, bits: u64) -> Value {
+    match tag as u32 {
+        1 => Value::Bool(bits != 0),
+        2 => Value::Int(bits as i64),
+        3 => Value::Float(f64::from_bits(bits)),
+        _ => Value::Null,
+    }
+}
+
+// run what `wasm::compile` made from `main` to the value it finishes with
+pub fn run(binary: &[u8]) -> Run<Value> {
+    let mut machine = Machine::load(binary)?;
+    let main = machine.export("main").ok_or(Trap::Wasm("no `main` export".into()))?;
+    machine.invoke(main)?;
+    let bits = machine.pop();
+    Ok(value(machine.pop(), bits))
+}
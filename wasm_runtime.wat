@@ -0,0 +1,729 @@
+  ;; the host prints and reports errors; `fail` and its variants do not return
+  (import "vita" "print_value" (func $print_value (param i32 i64)))
+  (import "vita" "print_string" (func $print_string (param i32 i32)))
+  (import "vita" "fail" (func $fail (param i32 i32 i32 i32)))
+  (import "vita" "fail_types" (func $fail_types (param i32 i32 i32 i32 i32 i32)))
+  (import "vita" "fail_value" (func $fail_value (param i32 i32 i32 i64 i32 i32)))
+  (import "vita" "pow" (func $pow (param f64 f64) (result f64)))
+
+  ;; a value is a tag and 64 bits: 0 nothing, 1 bool, 2 int, 3 float (its bits), 4 not defined yet
+  (global $depth (mut i32) (i32.const 0))
+
+  (func $bool (param $b i32) (result i32 i64)
+    i32.const 1
+    local.get $b
+    i64.extend_i32_u
+  )
+
+  (func $truthy (param $t i32) (param $b i64) (result i32)
+    local.get $t
+    i32.const 3
+    i32.eq
+    if (result i32)
+      local.get $b
+      f64.reinterpret_i64
+      f64.const 0
+      f64.ne
+    else
+      local.get $t
+      i32.const 0
+      i32.ne
+      local.get $b
+      i64.const 0
+      i64.ne
+      i32.and
+    end
+  )
+
+  (func $float (param $t i32) (param $b i64) (result f64)
+    local.get $t
+    i32.const 3
+    i32.eq
+    if (result f64)
+      local.get $b
+      f64.reinterpret_i64
+    else
+      local.get $b
+      f64.convert_i64_s
+    end
+  )
+
+  (func $ints (param $lt i32) (param $rt i32) (result i32)
+    local.get $lt
+    i32.const 2
+    i32.eq
+    local.get $rt
+    i32.const 2
+    i32.eq
+    i32.and
+  )
+
+  (func $numbers (param $lt i32) (param $rt i32) (param $msg i32) (param $len i32) (param $line i32) (param $col i32)
+    local.get $lt
+    i32.const 2
+    i32.sub
+    i32.const 2
+    i32.lt_u
+    local.get $rt
+    i32.const 2
+    i32.sub
+    i32.const 2
+    i32.lt_u
+    i32.and
+    i32.eqz
+    if
+      local.get $msg
+      local.get $len
+      local.get $lt
+      local.get $rt
+      local.get $line
+      local.get $col
+      call $fail_types
+      unreachable
+    end
+  )
+
+  (func $finite (param $f f64) (param $msg i32) (param $len i32) (param $line i32) (param $col i32) (result i32 i64)
+    local.get $f
+    local.get $f
+    f64.sub
+    f64.const 0
+    f64.eq
+    i32.eqz
+    if
+      local.get $msg
+      local.get $len
+      local.get $line
+      local.get $col
+      call $fail
+      unreachable
+    end
+    i32.const 3
+    local.get $f
+    i64.reinterpret_f64
+  )
+
+  (func $defined (param $t i32) (param $b i64) (param $msg i32) (param $len i32) (param $line i32) (param $col i32) (result i32 i64)
+    local.get $t
+    i32.const 4
+    i32.eq
+    if
+      local.get $msg
+      local.get $len
+      local.get $line
+      local.get $col
+      call $fail
+      unreachable
+    end
+    local.get $t
+    local.get $b
+  )
+
+  (func $add (param $lt i32) (param $lb i64) (param $rt i32) (param $rb i64) (param $line i32) (param $col i32) (result i32 i64)
+    (local $r i64)
+    local.get $lt
+    local.get $rt
+    @"cannot apply `+` to {} and {}"
+    local.get $line
+    local.get $col
+    call $numbers
+    local.get $lt
+    local.get $rt
+    call $ints
+    if (result i32 i64)
+      local.get $lb
+      local.get $rb
+      i64.add
+      local.set $r
+      ;; the sum overflowed when it has a different sign from both operands
+      local.get $lb
+      local.get $r
+      i64.xor
+      local.get $rb
+      local.get $r
+      i64.xor
+      i64.and
+      i64.const 0
+      i64.lt_s
+      if
+        @"arithmetic overflow in `+`"
+        local.get $line
+        local.get $col
+        call $fail
+        unreachable
+      end
+      i32.const 2
+      local.get $r
+    else
+      local.get $lt
+      local.get $lb
+      call $float
+      local.get $rt
+      local.get $rb
+      call $float
+      f64.add
+      @"arithmetic overflow in `+`"
+      local.get $line
+      local.get $col
+      call $finite
+    end
+  )
+
+  (func $sub (param $lt i32) (param $lb i64) (param $rt i32) (param $rb i64) (param $line i32) (param $col i32) (result i32 i64)
+    (local $r i64)
+    local.get $lt
+    local.get $rt
+    @"cannot apply `-` to {} and {}"
+    local.get $line
+    local.get $col
+    call $numbers
+    local.get $lt
+    local.get $rt
+    call $ints
+    if (result i32 i64)
+      local.get $lb
+      local.get $rb
+      i64.sub
+      local.set $r
+      ;; the difference overflowed when the operands differ in sign and the result took the second's
+      local.get $lb
+      local.get $rb
+      i64.xor
+      local.get $lb
+      local.get $r
+      i64.xor
+      i64.and
+      i64.const 0
+      i64.lt_s
+      if
+        @"arithmetic overflow in `-`"
+        local.get $line
+        local.get $col
+        call $fail
+        unreachable
+      end
+      i32.const 2
+      local.get $r
+    else
+      local.get $lt
+      local.get $lb
+      call $float
+      local.get $rt
+      local.get $rb
+      call $float
+      f64.sub
+      @"arithmetic overflow in `-`"
+      local.get $line
+      local.get $col
+      call $finite
+    end
+  )
+
+  (func $mul_checked (param $a i64) (param $b i64) (param $msg i32) (param $len i32) (param $line i32) (param $col i32) (result i64)
+    (local $r i64)
+    local.get $a
+    local.get $b
+    i64.mul
+    local.set $r
+    local.get $a
+    i64.eqz
+    if (result i32)
+      i32.const 0
+    else
+      local.get $a
+      i64.const -1
+      i64.eq
+      if (result i32)
+        local.get $b
+        i64.const -9223372036854775808
+        i64.eq
+      else
+        local.get $r
+        local.get $a
+        i64.div_s
+        local.get $b
+        i64.ne
+      end
+    end
+    if
+      local.get $msg
+      local.get $len
+      local.get $line
+      local.get $col
+      call $fail
+      unreachable
+    end
+    local.get $r
+  )
+
+  (func $mul (param $lt i32) (param $lb i64) (param $rt i32) (param $rb i64) (param $line i32) (param $col i32) (result i32 i64)
+    local.get $lt
+    local.get $rt
+    @"cannot apply `*` to {} and {}"
+    local.get $line
+    local.get $col
+    call $numbers
+    local.get $lt
+    local.get $rt
+    call $ints
+    if (result i32 i64)
+      i32.const 2
+      local.get $lb
+      local.get $rb
+      @"arithmetic overflow in `*`"
+      local.get $line
+      local.get $col
+      call $mul_checked
+    else
+      local.get $lt
+      local.get $lb
+      call $float
+      local.get $rt
+      local.get $rb
+      call $float
+      f64.mul
+      @"arithmetic overflow in `*`"
+      local.get $line
+      local.get $col
+      call $finite
+    end
+  )
+
+  (func $div (param $lt i32) (param $lb i64) (param $rt i32) (param $rb i64) (param $line i32) (param $col i32) (result i32 i64)
+    local.get $lt
+    local.get $rt
+    @"cannot apply `/` to {} and {}"
+    local.get $line
+    local.get $col
+    call $numbers
+    local.get $rt
+    local.get $rb
+    call $float
+    f64.const 0
+    f64.eq
+    if
+      @"division by zero"
+      local.get $line
+      local.get $col
+      call $fail
+      unreachable
+    end
+    local.get $lt
+    local.get $rt
+    call $ints
+    if (result i32)
+      ;; ints that do not divide evenly give a float
+      local.get $lb
+      local.get $rb
+      i64.rem_s
+      i64.eqz
+    else
+      i32.const 0
+    end
+    if (result i32 i64)
+      local.get $lb
+      i64.const -9223372036854775808
+      i64.eq
+      local.get $rb
+      i64.const -1
+      i64.eq
+      i32.and
+      if
+        @"arithmetic overflow in `/`"
+        local.get $line
+        local.get $col
+        call $fail
+        unreachable
+      end
+      i32.const 2
+      local.get $lb
+      local.get $rb
+      i64.div_s
+    else
+      local.get $lt
+      local.get $lb
+      call $float
+      local.get $rt
+      local.get $rb
+      call $float
+      f64.div
+      @"arithmetic overflow in `/`"
+      local.get $line
+      local.get $col
+      call $finite
+    end
+  )
+
+  (func $pow_int (param $base i64) (param $e i64) (param $line i32) (param $col i32) (result i64)
+    (local $acc i64)
+    ;; the same steps as Rust's `checked_pow`, so it overflows in the same places
+    local.get $e
+    i64.const 4294967295
+    i64.gt_s
+    if
+      @"arithmetic overflow in `^`"
+      local.get $line
+      local.get $col
+      call $fail
+      unreachable
+    end
+    i64.const 1
+    local.set $acc
+    local.get $e
+    i64.eqz
+    if (result i64)
+      i64.const 1
+    else
+      block $done
+        loop $step
+          local.get $e
+          i64.const 1
+          i64.le_s
+          br_if $done
+          local.get $e
+          i64.const 1
+          i64.and
+          i32.wrap_i64
+          if
+            local.get $acc
+            local.get $base
+            @"arithmetic overflow in `^`"
+            local.get $line
+            local.get $col
+            call $mul_checked
+            local.set $acc
+          end
+          local.get $e
+          i64.const 1
+          i64.shr_u
+          local.set $e
+          local.get $base
+          local.get $base
+          @"arithmetic overflow in `^`"
+          local.get $line
+          local.get $col
+          call $mul_checked
+          local.set $base
+          br $step
+        end
+      end
+      local.get $acc
+      local.get $base
+      @"arithmetic overflow in `^`"
+      local.get $line
+      local.get $col
+      call $mul_checked
+    end
+  )
+
+  (func $power (param $lt i32) (param $lb i64) (param $rt i32) (param $rb i64) (param $line i32) (param $col i32) (result i32 i64)
+    (local $f f64)
+    local.get $lt
+    local.get $rt
+    @"cannot apply `^` to {} and {}"
+    local.get $line
+    local.get $col
+    call $numbers
+    ;; zero to a negative power divides by zero, whatever kind of number either is
+    local.get $lt
+    local.get $lb
+    call $float
+    f64.const 0
+    f64.eq
+    local.get $rt
+    local.get $rb
+    call $float
+    f64.const 0
+    f64.lt
+    i32.and
+    if
+      @"division by zero"
+      local.get $line
+      local.get $col
+      call $fail
+      unreachable
+    end
+    local.get $lt
+    local.get $rt
+    call $ints
+    if (result i32)
+      local.get $rb
+      i64.const 0
+      i64.ge_s
+    else
+      i32.const 0
+    end
+    if (result i32 i64)
+      i32.const 2
+      local.get $lb
+      local.get $rb
+      local.get $line
+      local.get $col
+      call $pow_int
+    else
+      local.get $lt
+      local.get $lb
+      call $float
+      local.get $rt
+      local.get $rb
+      call $float
+      call $pow
+      ;; only NaN is not equal to itself: a negative number to a fractional power
+      local.tee $f
+      local.get $f
+      f64.ne
+      if
+        @"`^` has no real result"
+        local.get $line
+        local.get $col
+        call $fail
+        unreachable
+      end
+      local.get $f
+      @"arithmetic overflow in `^`"
+      local.get $line
+      local.get $col
+      call $finite
+    end
+  )
+
+  ;; -1, 0 or 1, or 2 when a NaN means there is no order
+  (func $compare (param $lt i32) (param $lb i64) (param $rt i32) (param $rb i64) (param $msg i32) (param $len i32) (param $line i32) (param $col i32) (result i32)
+    (local $l f64)
+    (local $r f64)
+    local.get $lt
+    local.get $rt
+    local.get $msg
+    local.get $len
+    local.get $line
+    local.get $col
+    call $numbers
+    local.get $lt
+    local.get $rt
+    call $ints
+    if (result i32)
+      local.get $lb
+      local.get $rb
+      i64.gt_s
+      local.get $lb
+      local.get $rb
+      i64.lt_s
+      i32.sub
+    else
+      local.get $lt
+      local.get $lb
+      call $float
+      local.set $l
+      local.get $rt
+      local.get $rb
+      call $float
+      local.set $r
+      local.get $l
+      local.get $l
+      f64.ne
+      local.get $r
+      local.get $r
+      f64.ne
+      i32.or
+      if (result i32)
+        i32.const 2
+      else
+        local.get $l
+        local.get $r
+        f64.gt
+        local.get $l
+        local.get $r
+        f64.lt
+        i32.sub
+      end
+    end
+  )
+
+  (func $equal (param $lt i32) (param $lb i64) (param $rt i32) (param $rb i64) (result i32)
+    local.get $lt
+    local.get $rt
+    i32.eq
+    local.get $lt
+    i32.const 3
+    i32.ne
+    i32.and
+    if (result i32)
+      local.get $lb
+      local.get $rb
+      i64.eq
+    else
+      local.get $lt
+      i32.const 2
+      i32.sub
+      i32.const 2
+      i32.lt_u
+      local.get $rt
+      i32.const 2
+      i32.sub
+      i32.const 2
+      i32.lt_u
+      i32.and
+      if (result i32)
+        local.get $lt
+        local.get $lb
+        call $float
+        local.get $rt
+        local.get $rb
+        call $float
+        f64.eq
+      else
+        i32.const 0
+      end
+    end
+  )
+
+  (func $neg (param $t i32) (param $b i64) (param $line i32) (param $col i32) (result i32 i64)
+    local.get $t
+    i32.const 2
+    i32.eq
+    if (result i32 i64)
+      local.get $b
+      i64.const -9223372036854775808
+      i64.eq
+      if
+        @"arithmetic overflow in `-`"
+        local.get $line
+        local.get $col
+        call $fail
+        unreachable
+      end
+      i32.const 2
+      i64.const 0
+      local.get $b
+      i64.sub
+    else
+      local.get $t
+      i32.const 3
+      i32.ne
+      if
+        @"cannot apply `-` to {}"
+        local.get $t
+        i32.const -1
+        local.get $line
+        local.get $col
+        call $fail_types
+        unreachable
+      end
+      i32.const 3
+      local.get $b
+      f64.reinterpret_i64
+      f64.neg
+      i64.reinterpret_f64
+    end
+  )
+
+  ;; how many times `lolsie` goes round for a number, which has to be whole
+  (func $times (param $t i32) (param $b i64) (param $line i32) (param $col i32) (result i64)
+    (local $f f64)
+    local.get $t
+    i32.const 2
+    i32.eq
+    if (result i64)
+      local.get $b
+    else
+      local.get $t
+      i32.const 3
+      i32.ne
+      if
+        @"cannot loop over {}"
+        local.get $t
+        i32.const -1
+        local.get $line
+        local.get $col
+        call $fail_types
+        unreachable
+      end
+      local.get $b
+      f64.reinterpret_i64
+      local.tee $f
+      local.get $f
+      f64.trunc
+      f64.ne
+      local.get $f
+      f64.abs
+      f64.const 9223372036854775807
+      f64.lt
+      i32.eqz
+      i32.or
+      if
+        @"cannot loop {} times"
+        local.get $t
+        local.get $b
+        local.get $line
+        local.get $col
+        call $fail_value
+        unreachable
+      end
+      local.get $f
+      i64.trunc_f64_s
+    end
+  )
+
+  (func $range (param $lt i32) (param $lb i64) (param $rt i32) (param $rb i64) (param $line i32) (param $col i32) (result i64 i64)
+    local.get $lt
+    local.get $rt
+    call $ints
+    i32.eqz
+    if
+      @"cannot make a range from {} to {}"
+      local.get $lt
+      local.get $rt
+      local.get $line
+      local.get $col
+      call $fail_types
+      unreachable
+    end
+    local.get $lb
+    local.get $rb
+  )
+
+  ;; each call is recorded so the host can show where an error came through
+  (func $enter (param $name i32) (param $len i32) (param $line i32) (param $col i32) (param $msg i32) (param $mlen i32)
+    (local $at i32)
+    global.get $depth
+    i32.const 1000
+    i32.ge_s
+    if
+      local.get $msg
+      local.get $mlen
+      local.get $line
+      local.get $col
+      call $fail
+      unreachable
+    end
+    global.get $depth
+    i32.const 16
+    i32.mul
+    local.tee $at
+    local.get $name
+    i32.store
+    local.get $at
+    local.get $len
+    i32.store offset=4
+    local.get $at
+    local.get $line
+    i32.store offset=8
+    local.get $at
+    local.get $col
+    i32.store offset=12
+    global.get $depth
+    i32.const 1
+    i32.add
+    global.set $depth
+  )
+
+  (func $leave
+    global.get $depth
+    i32.const 1
+    i32.sub
+    global.set $depth
+  )
+
+  (func $trace_depth (result i32)
+    global.get $depth
+  )
@@ -0,0 +1,794 @@
+use std::collections::HashMap;
+
+// the WebAssembly text `wasm` writes, assembled into the binary format: flat instructions with
+// `$name`d locals, globals, functions and labels, and no folded expressions
+
+enum Sexp {
+    Atom(String),
+    Str(Vec<u8>),
+    List(Vec<Sexp>),
+}
+
+impl Sexp {
+    fn atom(&self) -> Option<&str> {
+        match self {
+            Sexp::Atom(a) => Some(a),
+            _ => None,
+        }
+    }
+
+    fn list(&self) -> Option<&[Sexp]> {
+        match self {
+            Sexp::List(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    // a list whose first word is `head`
+    fn form(&self, head: &str) -> Option<&[Sexp]> {
+        self.list().filter(|items| items.first().and_then(Sexp::atom) == Some(head))
+    }
+}
+
+// the bytes a string literal stands for, without its quotes
+pub fn unescape(text: &str) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push(b'\n'),
+            Some('t') => out.push(b'\t'),
+            Some('r') => out.push(b'\r'),
+            Some('"') => out.push(b'"'),
+            Some('\'') => out.push(b'\''),
+            Some('\\') => out.push(b'\\'),
+            Some(hi) => {
+                let lo = chars.next().ok_or("unfinished escape in string")?;
+                let byte = u8::from_str_radix(&format!("{}{}", hi, lo), 16)
+                    .map_err(|_| format!("unknown escape `\\{}{}` in string", hi, lo))?;
+                out.push(byte);
+            }
+            None => return Err("unfinished escape in string".to_string()),
+        }
+    }
+    Ok(out)
+}
+
+// a string literal holding `bytes`
+pub fn escape(bytes: &[u8]) -> String {
+    let mut out = String::from("\"");
+    for &b in bytes {
+        match b {
+            b'"' | b'\\' => {
+                out.push('\\');
+                out.push(b as char);
+            }
+            0x20..=0x7e => out.push(b as char),
+            _ => out.push_str(&format!("\\{:02x}", b)),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn parse(text: &str) -> Result<Vec<Sexp>, String> {
+    let mut stack: Vec<Vec<Sexp>> = vec![Vec::new()];
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        match c {
+            _ if c.is_whitespace() => {}
+            ';' if chars.peek().map(|&(_, c)| c) == Some(';') => {
+                for (_, c) in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '(' => stack.push(Vec::new()),
+            ')' => {
+                let list = stack.pop().filter(|_| !stack.is_empty()).ok_or("unmatched `)`")?;
+                stack.last_mut().unwrap().push(Sexp::List(list));
+            }
+            '"' => {
+                let mut end = None;
+                let mut escaped = false;
+                for (i, c) in chars.by_ref() {
+                    match c {
+                        '\\' if !escaped => escaped = true,
+                        '"' if !escaped => {
+                            end = Some(i);
+                            break;
+                        }
+                        _ => escaped = false,
+                    }
+                }
+                let end = end.ok_or("unterminated string")?;
+                stack.last_mut().unwrap().push(Sexp::Str(unescape(&text[start + 1..end])?));
+            }
+            _ => {
+                let mut end = text.len();
+                while let Some(&(i, c)) = chars.peek() {
+                    if c.is_whitespace() || c == '(' || c == ')' || c == '"' {
+                        end = i;
+                        break;
+                    }
+                    chars.next();
+                }
+                stack.last_mut().unwrap().push(Sexp::Atom(text[start..end].to_string()));
+            }
+        }
+    }
+    match stack.pop() {
+        Some(items) if stack.is_empty() => Ok(items),
+        _ => Err("unmatched `(`".to_string()),
+    }
+}
+
+fn unsigned(out: &mut Vec<u8>, mut n: u64) {
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn signed(out: &mut Vec<u8>, mut n: i64) {
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if (n == 0 && byte & 0x40 == 0) || (n == -1 && byte & 0x40 != 0) {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn vector(out: &mut Vec<u8>, bytes: &[u8]) {
+    unsigned(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+fn section(out: &mut Vec<u8>, id: u8, count: usize, body: Vec<u8>) {
+    if count == 0 {
+        return;
+    }
+    let mut contents = Vec::new();
+    unsigned(&mut contents, count as u64);
+    contents.extend(body);
+    out.push(id);
+    unsigned(out, contents.len() as u64);
+    out.extend(contents);
+}
+
+fn valtype(word: &str) -> Result<u8, String> {
+    match word {
+        "i32" => Ok(0x7f),
+        "i64" => Ok(0x7e),
+        "f32" => Ok(0x7d),
+        "f64" => Ok(0x7c),
+        _ => Err(format!("unknown value type `{}`", word)),
+    }
+}
+
+fn integer(word: &str) -> Result<i64, String> {
+    let (negative, digits) = match word.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, word),
+    };
+    let digits = digits.replace('_', "");
+    let n = match digits.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => digits.parse::<u64>(),
+    }
+    .map_err(|_| format!("expected a number, found `{}`", word))?;
+    Ok(if negative { (n as i64).wrapping_neg() } else { n as i64 })
+}
+
+fn opcode(word: &str) -> Option<u8> {
+    Some(match word {
+        "unreachable" => 0x00,
+        "nop" => 0x01,
+        "else" => 0x05,
+        "return" => 0x0f,
+        "drop" => 0x1a,
+        "select" => 0x1b,
+        "i32.eqz" => 0x45,
+        "i32.eq" => 0x46,
+        "i32.ne" => 0x47,
+        "i32.lt_s" => 0x48,
+        "i32.lt_u" => 0x49,
+        "i32.gt_s" => 0x4a,
+        "i32.gt_u" => 0x4b,
+        "i32.le_s" => 0x4c,
+        "i32.le_u" => 0x4d,
+        "i32.ge_s" => 0x4e,
+        "i32.ge_u" => 0x4f,
+        "i64.eqz" => 0x50,
+        "i64.eq" => 0x51,
+        "i64.ne" => 0x52,
+        "i64.lt_s" => 0x53,
+        "i64.lt_u" => 0x54,
+        "i64.gt_s" => 0x55,
+        "i64.gt_u" => 0x56,
+        "i64.le_s" => 0x57,
+        "i64.le_u" => 0x58,
+        "i64.ge_s" => 0x59,
+        "i64.ge_u" => 0x5a,
+        "f64.eq" => 0x61,
+        "f64.ne" => 0x62,
+        "f64.lt" => 0x63,
+        "f64.gt" => 0x64,
+        "f64.le" => 0x65,
+        "f64.ge" => 0x66,
+        "i32.add" => 0x6a,
+        "i32.sub" => 0x6b,
+        "i32.mul" => 0x6c,
+        "i32.div_s" => 0x6d,
+        "i32.rem_s" => 0x6f,
+        "i32.and" => 0x71,
+        "i32.or" => 0x72,
+        "i32.xor" => 0x73,
+        "i32.shl" => 0x74,
+        "i32.shr_s" => 0x75,
+        "i32.shr_u" => 0x76,
+        "i64.add" => 0x7c,
+        "i64.sub" => 0x7d,
+        "i64.mul" => 0x7e,
+        "i64.div_s" => 0x7f,
+        "i64.div_u" => 0x80,
+        "i64.rem_s" => 0x81,
+        "i64.rem_u" => 0x82,
+        "i64.and" => 0x83,
+        "i64.or" => 0x84,
+        "i64.xor" => 0x85,
+        "i64.shl" => 0x86,
+        "i64.shr_s" => 0x87,
+        "i64.shr_u" => 0x88,
+        "f64.abs" => 0x99,
+        "f64.neg" => 0x9a,
+        "f64.ceil" => 0x9b,
+        "f64.floor" => 0x9c,
+        "f64.trunc" => 0x9d,
+        "f64.nearest" => 0x9e,
+        "f64.sqrt" => 0x9f,
+        "f64.add" => 0xa0,
+        "f64.sub" => 0xa1,
+        "f64.mul" => 0xa2,
+        "f64.div" => 0xa3,
+        "f64.min" => 0xa4,
+        "f64.max" => 0xa5,
+        "i32.wrap_i64" => 0xa7,
+        "i64.trunc_f64_s" => 0xb0,
+        "i64.extend_i32_s" => 0xac,
+        "i64.extend_i32_u" => 0xad,
+        "f64.convert_i32_s" => 0xb7,
+        "f64.convert_i64_s" => 0xb9,
+        "i64.reinterpret_f64" => 0xbd,
+        "f64.reinterpret_i64" => 0xbf,
+        _ => return None,
+    })
+}
+
+// loads and stores, with the alignment they have when none is written
+fn memory_op(word: &str) -> Option<(u8, u64)> {
+    Some(match word {
+        "i32.load" => (0x28, 2),
+        "i64.load" => (0x29, 3),
+        "f64.load" => (0x2b, 3),
+        "i32.store" => (0x36, 2),
+        "i64.store" => (0x37, 3),
+        "f64.store" => (0x39, 3),
+        _ => return None,
+    })
+}
+
+#[derive(Clone, PartialEq)]
+struct Signature {
+    params: Vec<u8>,
+    results: Vec<u8>,
+}
+
+struct Func<'a> {
+    sig: usize,
+    locals: Vec<u8>,
+    names: HashMap<String, u32>,
+    body: &'a [Sexp],
+}
+
+#[derive(Default)]
+struct Assembler {
+    types: Vec<Signature>,
+    funcs: HashMap<String, u32>,
+    globals: HashMap<String, u32>,
+    memories: HashMap<String, u32>,
+}
+
+impl Assembler {
+    fn signature(&mut self, sig: Signature) -> usize {
+        match self.types.iter().position(|s| *s == sig) {
+            Some(i) => i,
+            None => {
+                self.types.push(sig);
+                self.types.len() - 1
+            }
+        }
+    }
+
+    // a function from its `(param ...)`, `(result ...)` and `(local ...)` lists and the body after them
+    fn header<'a>(&mut self, items: &'a [Sexp]) -> Result<Func<'a>, String> {
+        let mut params = Vec::new();
+        let mut results = Vec::new();
+        let mut locals = Vec::new();
+        let mut used = 0;
+        for item in items {
+            let Some(list) = item.list() else { break };
+            let (head, rest) = match list.split_first() {
+                Some((head, rest)) => (head.atom().unwrap_or(""), rest),
+                None => break,
+            };
+            let target = match head {
+                "param" => &mut params,
+                "result" => &mut results,
+                "local" => &mut locals,
+                _ => break,
+            };
+            match rest.first().and_then(Sexp::atom) {
+                Some(n) if n.starts_with('$') => {
+                    let ty = rest.get(1).and_then(Sexp::atom).ok_or("missing type")?;
+                    target.push((Some(n.to_string()), valtype(ty)?));
+                }
+                _ => {
+                    for ty in rest {
+                        target.push((None, valtype(ty.atom().ok_or("expected a type")?)?));
+                    }
+                }
+            }
+            used += 1;
+        }
+        let mut names = HashMap::new();
+        for (i, (n, _)) in params.iter().chain(locals.iter()).enumerate() {
+            if let Some(n) = n {
+                names.insert(n.clone(), i as u32);
+            }
+        }
+        let sig = self.signature(Signature {
+            params: params.iter().map(|p| p.1).collect(),
+            results: results.iter().map(|r| r.1).collect(),
+        });
+        Ok(Func { sig, locals: locals.iter().map(|l| l.1).collect(), names, body: &items[used..] })
+    }
+
+    fn index(names: &HashMap<String, u32>, word: &str, what: &str) -> Result<u32, String> {
+        if let Some(&i) = names.get(word) {
+            return Ok(i);
+        }
+        word.parse().map_err(|_| format!("unknown {} `{}`", what, word))
+    }
+
+    fn immediate<'a>(items: &'a [Sexp], i: &mut usize, what: &str) -> Result<&'a str, String> {
+        let word = items.get(*i).and_then(Sexp::atom).ok_or(format!("`{}` needs an immediate", what))?;
+        *i += 1;
+        Ok(word)
+    }
+
+    fn code(&mut self, func: &Func, out: &mut Vec<u8>) -> Result<(), String> {
+        let items = func.body;
+        let mut labels: Vec<Option<String>> = vec![None];
+        let mut i = 0;
+        while i < items.len() {
+            let word = items[i].atom().ok_or("expected an instruction")?;
+            i += 1;
+            match word {
+                "block" | "loop" | "if" => {
+                    out.push(match word {
+                        "block" => 0x02,
+                        "loop" => 0x03,
+                        _ => 0x04,
+                    });
+                    let label = match items.get(i).and_then(Sexp::atom) {
+                        Some(l) if l.starts_with('$') => {
+                            i += 1;
+                            Some(l.to_string())
+                        }
+                        _ => None,
+                    };
+                    let mut results = Vec::new();
+                    while let Some(list) = items.get(i).and_then(|s| s.form("result")) {
+                        for ty in &list[1..] {
+                            results.push(valtype(ty.atom().ok_or("expected a type")?)?);
+                        }
+                        i += 1;
+                    }
+                    match results.len() {
+                        0 => out.push(0x40),
+                        1 => out.push(results[0]),
+                        _ => {
+                            let sig = self.signature(Signature { params: Vec::new(), results });
+                            signed(out, sig as i64);
+                        }
+                    }
+                    labels.push(label);
+                }
+                "end" => {
+                    labels.pop();
+                    out.push(0x0b);
+                }
+                "br" | "br_if" => {
+                    let target = Self::immediate(items, &mut i, word)?;
+                    let depth = match labels.iter().rev().position(|l| l.as_deref() == Some(target)) {
+                        Some(depth) => depth as u32,
+                        None => target.parse().map_err(|_| format!("unknown label `{}`", target))?,
+                    };
+                    out.push(if word == "br" { 0x0c } else { 0x0d });
+                    unsigned(out, depth as u64);
+                }
+                "call" => {
+                    let target = Self::immediate(items, &mut i, word)?;
+                    out.push(0x10);
+                    unsigned(out, Self::index(&self.funcs, target, "function")? as u64);
+                }
+                "local.get" | "local.set" | "local.tee" => {
+                    let target = Self::immediate(items, &mut i, word)?;
+                    out.push(match word {
+                        "local.get" => 0x20,
+                        "local.set" => 0x21,
+                        _ => 0x22,
+                    });
+                    unsigned(out, Self::index(&func.names, target, "local")? as u64);
+                }
+                "global.get" | "global.set" => {
+                    let target = Self::immediate(items, &mut i, word)?;
+                    out.push(if word == "global.get" { 0x23 } else { 0x24 });
+                    unsigned(out, Self::index(&self.globals, target, "global")? as u64);
+                }
+                "i32.const" => {
+                    out.push(0x41);
+                    signed(out, integer(Self::immediate(items, &mut i, word)?)? as i32 as i64);
+                }
+                "i64.const" => {
+                    out.push(0x42);
+                    signed(out, integer(Self::immediate(items, &mut i, word)?)?);
+                }
+                "f64.const" => {
+                    let text = Self::immediate(items, &mut i, word)?;
+                    let n: f64 = text.parse().map_err(|_| format!("expected a float, found `{}`", text))?;
+                    out.push(0x44);
+                    out.extend_from_slice(&n.to_le_bytes());
+                }
+                _ => {
+                    if let Some((code, natural)) = memory_op(word) {
+                        let (mut offset, mut align) = (0, natural);
+                        while let Some(arg) = items.get(i).and_then(Sexp::atom) {
+                            if let Some(n) = arg.strip_prefix("offset=") {
+                                offset = integer(n)? as u64;
+                            } else if let Some(n) = arg.strip_prefix("align=") {
+                                align = (integer(n)? as u64).trailing_zeros() as u64;
+                            } else {
+                                break;
+                            }
+                            i += 1;
+                        }
+                        out.push(code);
+                        unsigned(out, align);
+                        unsigned(out, offset);
+                    } else {
+                        out.push(opcode(word).ok_or(format!("unknown instruction `{}`", word))?);
+                    }
+                }
+            }
+        }
+        out.push(0x0b);
+        Ok(())
+    }
+}
+
+fn constant(expr: &[Sexp]) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    let word = expr.first().and_then(Sexp::atom).ok_or("expected a constant expression")?;
+    let value = expr.get(1).and_then(Sexp::atom).ok_or("expected a constant")?;
+    match word {
+        "i32.const" => {
+            out.push(0x41);
+            signed(&mut out, integer(value)? as i32 as i64);
+        }
+        "i64.const" => {
+            out.push(0x42);
+            signed(&mut out, integer(value)?);
+        }
+        "f64.const" => {
+            out.push(0x44);
+            let n: f64 = value.parse().map_err(|_| format!("expected a float, found `{}`", value))?;
+            out.extend_from_slice(&n.to_le_bytes());
+        }
+        _ => return Err(format!("`{}` is not a constant expression", word)),
+    }
+    out.push(0x0b);
+    Ok(out)
+}
+
+pub fn assemble(text: &str) -> Result<Vec<u8>, String> {
+    let top = parse(text)?;
+    let fields = match top.as_slice() {
+        [module] => module.form("module").ok_or("expected a `module`")?,
+        _ => return Err("expected exactly one `module`".to_string()),
+    };
+
+    let mut asm = Assembler::default();
+    let mut imports = Vec::new();
+    let mut funcs = Vec::new();
+    let mut globals = Vec::new();
+    let mut memories = Vec::new();
+    let mut exports = Vec::new();
+    let mut data = Vec::new();
+
+    // functions can be called before they are defined, so name everything first
+    for field in &fields[1..] {
+        let list = field.list().ok_or("expected a module field")?;
+        let head = list.first().and_then(Sexp::atom).unwrap_or("");
+        let label = list.get(1).and_then(Sexp::atom).filter(|n| n.starts_with('$'));
+        match head {
+            "import" => {
+                if !funcs.is_empty() {
+                    return Err("imports have to come before the functions a module defines".to_string());
+                }
+                let func = list.get(3).and_then(|f| f.form("func")).ok_or("only functions can be imported")?;
+                if let Some(n) = func.get(1).and_then(Sexp::atom).filter(|n| n.starts_with('$')) {
+                    asm.funcs.insert(n.to_string(), imports.len() as u32);
+                }
+                imports.push(list);
+            }
+            "func" => {
+                if let Some(n) = label {
+                    asm.funcs.insert(n.to_string(), (imports.len() + funcs.len()) as u32);
+                }
+                funcs.push(list);
+            }
+            "global" => {
+                if let Some(n) = label {
+                    asm.globals.insert(n.to_string(), globals.len() as u32);
+                }
+                globals.push(list);
+            }
+            "memory" => {
+                if let Some(n) = label {
+                    asm.memories.insert(n.to_string(), memories.len() as u32);
+                }
+                memories.push(list);
+            }
+            "export" => exports.push(list),
+            "data" => data.push(list),
+            _ => return Err(format!("unknown module field `{}`", head)),
+        }
+    }
+
+    let mut out = b"\0asm\x01\0\0\0".to_vec();
+    let mut import_section = Vec::new();
+    let mut export_section = Vec::new();
+
+    for list in &imports {
+        let (Some(Sexp::Str(module)), Some(Sexp::Str(field))) = (list.get(1), list.get(2)) else {
+            return Err("an import needs a module and a name".to_string());
+        };
+        let func = list[3].list().unwrap();
+        let skip = if func.get(1).and_then(Sexp::atom).is_some_and(|n| n.starts_with('$')) { 2 } else { 1 };
+        let header = asm.header(&func[skip..])?;
+        vector(&mut import_section, module);
+        vector(&mut import_section, field);
+        import_section.push(0x00);
+        unsigned(&mut import_section, header.sig as u64);
+    }
+
+    let mut function_section = Vec::new();
+    let mut code_section = Vec::new();
+    for list in &funcs {
+        let skip = if list.get(1).and_then(Sexp::atom).is_some_and(|n| n.starts_with('$')) { 2 } else { 1 };
+        let func = asm.header(&list[skip..])?;
+        let mut body = Vec::new();
+        // consecutive locals of one type are declared together
+        let mut runs: Vec<(u32, u8)> = Vec::new();
+        for &ty in &func.locals {
+            match runs.last_mut() {
+                Some((count, last)) if *last == ty => *count += 1,
+                _ => runs.push((1, ty)),
+            }
+        }
+        unsigned(&mut body, runs.len() as u64);
+        for (count, ty) in runs {
+            unsigned(&mut body, count as u64);
+            body.push(ty);
+        }
+        asm.code(&func, &mut body)?;
+        unsigned(&mut function_section, func.sig as u64);
+        unsigned(&mut code_section, body.len() as u64);
+        code_section.extend(body);
+    }
+
+    let mut memory_section = Vec::new();
+    for (i, list) in memories.iter().enumerate() {
+        let mut pages = None;
+        for item in &list[1..] {
+            if let Some(export) = item.form("export") {
+                let Some(Sexp::Str(n)) = export.get(1) else { return Err("an export needs a name".to_string()) };
+                vector(&mut export_section, n);
+                export_section.push(0x02);
+                unsigned(&mut export_section, i as u64);
+            } else if let Some(n) = item.atom().filter(|n| !n.starts_with('$')) {
+                pages = Some(integer(n)? as u64);
+            }
+        }
+        memory_section.push(0x00);
+        unsigned(&mut memory_section, pages.ok_or("a memory needs a size")?);
+    }
+    let memory_exports = memories.iter().flat_map(|m| m.iter()).filter(|s| s.form("export").is_some()).count();
+
+    let mut global_section = Vec::new();
+    for list in &globals {
+        let rest: Vec<&Sexp> = list[1..].iter().filter(|s| !s.atom().is_some_and(|n| n.starts_with('$'))).collect();
+        let (ty, init) = match rest.as_slice() {
+            [ty, init] => (ty, init.list().ok_or("a global needs an initial value")?),
+            _ => return Err("a global needs a type and an initial value".to_string()),
+        };
+        match ty.form("mut") {
+            Some(m) => {
+                global_section.push(valtype(m.get(1).and_then(Sexp::atom).unwrap_or(""))?);
+                global_section.push(0x01);
+            }
+            None => {
+                global_section.push(valtype(ty.atom().unwrap_or(""))?);
+                global_section.push(0x00);
+            }
+        }
+        global_section.extend(constant(init)?);
+    }
+
+    for list in &exports {
+        let (Some(Sexp::Str(n)), Some(target)) = (list.get(1), list.get(2).and_then(Sexp::list)) else {
+            return Err("an export needs a name and what it exports".to_string());
+        };
+        let kind = target.first().and_then(Sexp::atom).unwrap_or("");
+        let word = target.get(1).and_then(Sexp::atom).unwrap_or("");
+        let (code, index) = match kind {
+            "func" => (0x00, Assembler::index(&asm.funcs, word, "function")?),
+            "memory" => (0x02, Assembler::index(&asm.memories, word, "memory")?),
+            "global" => (0x03, Assembler::index(&asm.globals, word, "global")?),
+            _ => return Err(format!("cannot export a `{}`", kind)),
+        };
+        vector(&mut export_section, n);
+        export_section.push(code);
+        unsigned(&mut export_section, index as u64);
+    }
+
+    let mut data_section = Vec::new();
+    for list in &data {
+        let offset = list.get(1).and_then(|o| o.list()).ok_or("a data segment needs an offset")?;
+        let mut bytes = Vec::new();
+        for item in &list[2..] {
+            match item {
+                Sexp::Str(s) => bytes.extend_from_slice(s),
+                _ => return Err("a data segment holds strings".to_string()),
+            }
+        }
+        data_section.push(0x00);
+        data_section.extend(constant(offset)?);
+        vector(&mut data_section, &bytes);
+    }
+
+    let mut type_section = Vec::new();
+    for sig in &asm.types {
+        type_section.push(0x60);
+        vector(&mut type_section, &sig.params);
+        vector(&mut type_section, &sig.results);
+    }
+
+    section(&mut out, 1, asm.types.len(), type_section);
+    section(&mut out, 2, imports.len(), import_section);
+    section(&mut out, 3, funcs.len(), function_section);
+    section(&mut out, 5, memories.len(), memory_section);
+    section(&mut out, 6, globals.len(), global_section);
+    section(&mut out, 7, exports.len() + memory_exports, export_section);
+    section(&mut out, 10, funcs.len(), code_section);
+    section(&mut out, 11, data.len(), data_section);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leb(write: fn(&mut Vec<u8>, i64), n: i64) -> Vec<u8> {
+        let mut out = Vec::new();
+        write(&mut out, n);
+        out
+    }
+
+    // the instructions of a module's one function, which is small enough for every size to take a byte
+    fn body(text: &str) -> Vec<u8> {
+        let binary = assemble(text).unwrap();
+        let mut at = 8;
+        while binary[at] != 10 {
+            at += 2 + binary[at + 1] as usize;
+        }
+        let end = at + 2 + binary[at + 1] as usize;
+        assert_eq!(binary[at + 4], 0, "the function declares no locals");
+        binary[at + 5..end].to_vec()
+    }
+
+    #[test]
+    fn numbers_take_as_few_bytes_as_they_need() {
+        assert_eq!(leb(|out, n| unsigned(out, n as u64), 0), [0x00]);
+        assert_eq!(leb(|out, n| unsigned(out, n as u64), 624485), [0xe5, 0x8e, 0x26]);
+        assert_eq!(leb(signed, -1), [0x7f]);
+        assert_eq!(leb(signed, 63), [0x3f]);
+        assert_eq!(leb(signed, 64), [0xc0, 0x00]);
+        assert_eq!(leb(signed, -65), [0xbf, 0x7f]);
+        assert_eq!(leb(signed, i64::MIN), [0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x7f]);
+    }
+
+    #[test]
+    fn a_module_assembles_byte_for_byte() {
+        assert_eq!(assemble("(module)").unwrap(), b"\0asm\x01\0\0\0");
+        let text = r#"
+            (module
+              (func $add (param $a i32) (param $b i32) (result i32)
+                local.get $a
+                local.get $b
+                i32.add)
+              (export "add" (func $add)))
+        "#;
+        let mut expected = b"\0asm\x01\0\0\0".to_vec();
+        expected.extend([0x01, 0x07, 0x01, 0x60, 0x02, 0x7f, 0x7f, 0x01, 0x7f]);
+        expected.extend([0x03, 0x02, 0x01, 0x00]);
+        expected.extend([0x07, 0x07, 0x01, 0x03, b'a', b'd', b'd', 0x00, 0x00]);
+        expected.extend([0x0a, 0x09, 0x01, 0x07, 0x00, 0x20, 0x00, 0x20, 0x01, 0x6a, 0x0b]);
+        assert_eq!(assemble(text).unwrap(), expected);
+    }
+
+    #[test]
+    fn labels_become_depths() {
+        let code = body("(module (func block $out loop $top br $out br $top br_if 1 end end))");
+        assert_eq!(code, [0x02, 0x40, 0x03, 0x40, 0x0c, 0x01, 0x0c, 0x00, 0x0d, 0x01, 0x0b, 0x0b, 0x0b]);
+    }
+
+    #[test]
+    fn blocks_with_two_results_share_the_function_type() {
+        let code = body("(module (func (result i32 i64) i32.const 0 if (result i32 i64) i32.const 1 i64.const 2 else i32.const 3 i64.const -4 end))");
+        assert_eq!(code, [0x41, 0x00, 0x04, 0x00, 0x41, 0x01, 0x42, 0x02, 0x05, 0x41, 0x03, 0x42, 0x7c, 0x0b, 0x0b]);
+    }
+
+    #[test]
+    fn memory_operations_default_to_their_natural_alignment() {
+        let code = body("(module (memory 1) (func i32.const 0 i64.const 1 i64.store offset=8 i32.const 0 i32.load align=1 drop))");
+        assert_eq!(code, [0x41, 0x00, 0x42, 0x01, 0x37, 0x03, 0x08, 0x41, 0x00, 0x28, 0x00, 0x00, 0x1a, 0x0b]);
+    }
+
+    #[test]
+    fn strings_escape_and_unescape_to_the_same_bytes() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        let literal = escape(&bytes);
+        assert_eq!(unescape(&literal[1..literal.len() - 1]).unwrap(), bytes);
+        assert_eq!(unescape(r#"a\n\t\"\\\41"#).unwrap(), b"a\n\t\"\\A");
+    }
+
+    #[test]
+    fn mistakes_are_reported() {
+        let error = |text: &str| assemble(text).err().unwrap();
+        assert_eq!(error("(module (func i32.frobnicate))"), "unknown instruction `i32.frobnicate`");
+        assert_eq!(error("(module (func block br $nowhere end))"), "unknown label `$nowhere`");
+        assert_eq!(error("(module (func local.get $x))"), "unknown local `$x`");
+        assert_eq!(error("(module (func call $f))"), "unknown function `$f`");
+        assert_eq!(error("(module (func)"), "unmatched `(`");
+        assert_eq!(error("(module))"), "unmatched `)`");
+        assert_eq!(error("(module (func (param $x f16)))"), "unknown value type `f16`");
+        assert_eq!(error("(module (func (export \"f\") nop))"), "expected an instruction");
+    }
+}